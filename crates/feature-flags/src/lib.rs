@@ -38,6 +38,9 @@ pub enum FeatureFlag {
 
     /// Do not try to work around incompatible terminal.
     OmitTermWorkarounds,
+
+    /// Run functions and blocks in the middle of a pipeline in a child fish.
+    ConcurrentPipelines,
 }
 
 struct Features {
@@ -152,6 +155,14 @@ pub struct FeatureMetadata {
         default_value: false,
         read_only: false,
     },
+    FeatureMetadata {
+        flag: FeatureFlag::ConcurrentPipelines,
+        name: L!("concurrent-pipelines"),
+        groups: L!("4.9"),
+        description: L!("functions and blocks in pipelines run concurrently"),
+        default_value: false,
+        read_only: false,
+    },
 ];
 
 thread_local!(
@@ -335,6 +335,139 @@ pub fn string_fuzzy_match_string(
     StringFuzzyMatch::try_create(string, match_against, anchor_start)
 }
 
+/// The result of ranking a fuzzy match, see [`fuzzy_score`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FuzzyScore {
+    /// How well the needle matched; higher is better.
+    pub score: i32,
+    /// The indices of the matched characters in the haystack, in increasing order.
+    pub positions: Vec<usize>,
+}
+
+// Scoring parameters, loosely modeled after fzf.
+const FUZZY_SCORE_MATCH: i32 = 16;
+const FUZZY_SCORE_GAP_START: i32 = -3;
+const FUZZY_SCORE_GAP_EXTENSION: i32 = -1;
+const FUZZY_BONUS_BOUNDARY: i32 = FUZZY_SCORE_MATCH / 2;
+const FUZZY_BONUS_CAMEL: i32 = FUZZY_BONUS_BOUNDARY - 1;
+const FUZZY_BONUS_START: i32 = FUZZY_BONUS_BOUNDARY + 2;
+const FUZZY_BONUS_CONSECUTIVE: i32 = -(FUZZY_SCORE_GAP_START + FUZZY_SCORE_GAP_EXTENSION);
+const FUZZY_BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+const FUZZY_BONUS_SAME_CASE: i32 = 1;
+
+fn is_fuzzy_separator(c: char) -> bool {
+    matches!(c, '/' | '-' | '_' | '.' | ':' | ',' | '=' | '@') || c.is_whitespace()
+}
+
+/// Fold a character for fuzzy comparison: case-insensitive, and `_` matches `-`.
+fn fuzzy_fold(c: char) -> char {
+    if c == '_' {
+        return '-';
+    }
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// The bonus for matching the character at `idx`, based on where it sits in a word.
+fn fuzzy_position_bonus(haystack: &[char], idx: usize) -> i32 {
+    let Some(&prev) = idx.checked_sub(1).map(|p| &haystack[p]) else {
+        return FUZZY_BONUS_START;
+    };
+    let cur = haystack[idx];
+    if is_fuzzy_separator(prev) && !is_fuzzy_separator(cur) {
+        FUZZY_BONUS_BOUNDARY
+    } else if (prev.is_lowercase() && cur.is_uppercase())
+        || (!prev.is_ascii_digit() && cur.is_ascii_digit())
+    {
+        FUZZY_BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+/// Match the characters of `needle` in order, but not necessarily contiguously, against
+/// `haystack`, and rank the best such alignment. Matches at the start of the haystack, at word
+/// boundaries and in consecutive runs score higher; gaps score lower.
+/// Returns None if `needle` is not a subsequence of `haystack` (ignoring case).
+pub fn fuzzy_score(needle: &wstr, haystack: &wstr) -> Option<FuzzyScore> {
+    let needle = needle.as_char_slice();
+    let haystack = haystack.as_char_slice();
+    let (n, m) = (needle.len(), haystack.len());
+    if n == 0 {
+        return Some(FuzzyScore::default());
+    }
+    if n > m {
+        return None;
+    }
+    let bonuses: Vec<i32> = (0..m)
+        .map(|idx| fuzzy_position_bonus(haystack, idx))
+        .collect();
+
+    // scores[i * m + j] is the best score for matching needle[..=i] with needle[i] at haystack[j],
+    // and from[i * m + j] is where needle[i - 1] was matched in that alignment.
+    let mut scores: Vec<Option<i32>> = vec![None; n * m];
+    let mut from = vec![0; n * m];
+    let better = |a: Option<(i32, usize)>, b: Option<(i32, usize)>| match (a, b) {
+        (Some(a), Some(b)) => Some(if b.0 > a.0 { b } else { a }),
+        (a, b) => a.or(b),
+    };
+    for i in 0..n {
+        // The best predecessor in the previous row that leaves a gap before the current column,
+        // including the gap penalty.
+        let mut gapped: Option<(i32, usize)> = None;
+        for j in i..m {
+            if i > 0 && j >= 2 {
+                let extended = gapped.map(|(s, k)| (s + FUZZY_SCORE_GAP_EXTENSION, k));
+                let started =
+                    scores[(i - 1) * m + j - 2].map(|s| (s + FUZZY_SCORE_GAP_START, j - 2));
+                gapped = better(started, extended);
+            }
+            if fuzzy_fold(needle[i]) != fuzzy_fold(haystack[j]) {
+                continue;
+            }
+            let mut bonus = bonuses[j];
+            if i == 0 {
+                bonus *= FUZZY_BONUS_FIRST_CHAR_MULTIPLIER;
+            }
+            if needle[i] == haystack[j] {
+                bonus += FUZZY_BONUS_SAME_CASE;
+            }
+            let here = FUZZY_SCORE_MATCH + bonus;
+            let best = if i == 0 {
+                Some((here, 0))
+            } else {
+                let consecutive = scores[(i - 1) * m + j - 1]
+                    .map(|s| (s + FUZZY_BONUS_CONSECUTIVE + here, j - 1));
+                better(consecutive, gapped.map(|(s, k)| (s + here, k)))
+            };
+            if let Some((score, k)) = best {
+                scores[i * m + j] = Some(score);
+                from[i * m + j] = k;
+            }
+        }
+    }
+
+    // Pick the best end position, preferring the earliest on ties.
+    let mut end: Option<(i32, usize)> = None;
+    for j in n - 1..m {
+        if let Some(score) = scores[(n - 1) * m + j] {
+            if end.is_none_or(|(best, _)| score > best) {
+                end = Some((score, j));
+            }
+        }
+    }
+    let (score, mut j) = end?;
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i * m + j];
+    }
+    Some(FuzzyScore { score, positions })
+}
+
 /// Split a string by runs of any of the separator characters provided in `seps`.
 /// Note the delimiters are the characters in `seps`, not `seps` itself.
 /// `seps` may contain the NUL character.
@@ -546,9 +679,10 @@ pub fn fish_wcwidth_visible(c: char) -> isize {
 #[cfg(test)]
 mod tests {
     use super::{
-        CaseSensitivity, ContainType, LineIterator, count_newlines, ifind, join_strings,
-        split_string_tok, string_fuzzy_match_string, string_prefixes_string_case_insensitive,
-        string_suffixes_string_case_insensitive, trim, trim_in_place,
+        CaseSensitivity, ContainType, LineIterator, count_newlines, fuzzy_score, ifind,
+        join_strings, split_string_tok, string_fuzzy_match_string,
+        string_prefixes_string_case_insensitive, string_suffixes_string_case_insensitive, trim,
+        trim_in_place,
     };
     use fish_widestring::prelude::*;
 
@@ -622,6 +756,34 @@ macro_rules! validate {
         validate!("alpha-b", "alpha b", None);
     }
 
+    #[test]
+    fn test_fuzzy_score() {
+        let positions = |needle: &str, haystack: &str| {
+            fuzzy_score(&WString::from(needle), &WString::from(haystack)).map(|m| m.positions)
+        };
+        let score = |needle: &str, haystack: &str| {
+            fuzzy_score(&WString::from(needle), &WString::from(haystack))
+                .unwrap()
+                .score
+        };
+        assert_eq!(positions("", "abc"), Some(vec![]));
+        assert_eq!(positions("abc", "abc"), Some(vec![0, 1, 2]));
+        assert_eq!(positions("ab", "ba"), None);
+        assert_eq!(positions("abcd", "abc"), None);
+        assert_eq!(positions("FB", "foobar"), Some(vec![0, 3]));
+        assert_eq!(positions("foo_b", "foo-bar"), Some(vec![0, 1, 2, 3, 4]));
+        // Word boundaries are preferred over earlier matches in the middle of a word.
+        assert_eq!(positions("fb", "fxbar-bar"), Some(vec![0, 6]));
+        assert_eq!(positions("gco", "git checkout"), Some(vec![0, 4, 9]));
+
+        // Prefix beats infix, boundaries beat the middle of a word, runs beat gaps.
+        assert!(score("abc", "abc") > score("abc", "xabc"));
+        assert!(score("fb", "foo-bar") > score("fb", "foobar"));
+        assert!(score("bar", "foobar") > score("bar", "xbxaxr"));
+        // Matching case scores a little higher.
+        assert!(score("Foo", "Foo") > score("foo", "Foo"));
+    }
+
     #[test]
     fn test_fuzzy_match() {
         // Check that a string fuzzy match has the expected type and case folding.
@@ -15,6 +15,8 @@ complete -c abbr -f -n $__fish_abbr_not_add_cond -s h -l help -d Help
 complete -c abbr -f -n $__fish_abbr_add_cond -s p -l position -a 'command anywhere' -d 'Expand only as a command, or anywhere' -x
 complete -c abbr -f -n $__fish_abbr_add_cond -s f -l function -d 'Treat expansion argument as a fish function' -xa '(functions)'
 complete -c abbr -f -n $__fish_abbr_add_cond -s r -l regex -d 'Match a regular expression' -x
+complete -c abbr -f -n $__fish_abbr_add_cond -l condition -d 'Expand only if a command succeeds' -x
+complete -c abbr -f -n $__fish_abbr_add_cond -l directory -d 'Expand only in matching directories' -xa '(__fish_complete_directories)'
 complete -c abbr -f -n $__fish_abbr_add_cond -l set-cursor -d 'Position the cursor at % post-expansion'
 
 complete -c abbr -f -n '__fish_seen_subcommand_from -s --show' -l color -d 'When to colorize output' -xa 'always never auto'
@@ -77,5 +77,13 @@ complete --command argparse --short-option S --long-option strict-longopts \
 complete --command argparse --short-option U --long-option unknown-arguments --no-files --require-parameter \
     --arguments "optional required none" \
     --description 'Whether unknown options can have arguments'
+complete --command argparse --long-option subcommand --no-files --require-parameter \
+    --description 'Declare a subcommand'
+complete --command argparse --long-option print-completions \
+    -n '! __fish_seen_argument --long print-help' \
+    --description 'Print completions for the option specs'
+complete --command argparse --long-option print-help \
+    -n '! __fish_seen_argument --long print-completions' \
+    --description 'Print a usage message for the option specs'
 complete --command argparse --short-option s --long-option stop-nonopt \
     --description 'Exit on subcommand'
@@ -0,0 +1,3 @@
+complete -c assert -s h -l help -d 'Display help and exit'
+complete -c assert -s m -l message -x -d 'Message to print if the condition is false'
+complete -c assert -w test
@@ -1,6 +1,7 @@
 set -l bind_optspecs \
     a/all \
     e/erase \
+    fallback \
     M/mode= \
     m/sets-mode= \
     preset \
@@ -23,6 +24,7 @@ complete -c bind -s M -l mode -d 'Specify the bind mode that the bind is used in
 complete -c bind -s m -l sets-mode -d 'Change current mode after bind is executed' -xa '(bind -L)'
 complete -c bind -s L -l list-modes -d 'Display a list of defined bind modes'
 complete -c bind -s s -l silent -d 'Operate silently'
+complete -c bind -l fallback -d 'Bind a command to all unbound keys'
 complete -c bind -l preset -d 'Operate on preset bindings'
 complete -c bind -l user -d 'Operate on user bindings'
 complete -c bind -l color -d 'When to colorize output' -xa 'always never auto'
@@ -24,6 +24,7 @@ complete -c commandline -s S -l search-mode -d "Return true if performing a hist
 complete -c commandline -s P -l paging-mode -d "Return true if showing pager content"
 complete -c commandline -l paging-full-mode -d "Return true if pager is showing all content"
 complete -c commandline -l search-field -d "Operate on the pager search field"
+complete -c commandline -l key-event -d "Print the key that triggered the current binding"
 complete -c commandline -l is-valid -d "Return true if the command line is syntactically valid and complete"
 complete -c commandline -l showing-suggestion -d "Return true if the command line has an autosuggestion"
 
@@ -19,6 +19,8 @@ complete -c complete -l escape -d "Make -C escape special characters"
 complete -c complete -s n -l condition -d "Completion only used if command has zero exit status" -x
 complete -c complete -s w -l wraps -d "Inherit completions from specified command" -xa '(__fish_complete_command)'
 complete -c complete -s k -l keep-order -d "Keep order of arguments instead of sorting alphabetically"
+complete -c complete -l from-argparse -d "Generate completions from a function's argparse call" -xa '(functions --all | string split ", ")'
+complete -c complete -l cache-ttl -x -d "Reuse results of --arguments for this many seconds"
 complete -c complete -l color -d "When to colorize output" -xa "always never auto"
 
 # Deprecated options
@@ -1 +1,3 @@
 complete -c eval -s h -l help -d 'Display help and exit'
+complete -c eval -l restricted -d 'Forbid cd, exec, writing files and universal variables'
+complete -c eval -l allow-command -d 'Allow external command in restricted mode' -x -a '(__fish_complete_command)'
@@ -11,6 +11,8 @@ complete -c fish -l profile-startup -d "Output startup profiling information to
 complete -c fish -s d -l debug -d "Specify debug categories" -x -a "(fish --print-debug-categories | string replace ' ' \t)"
 complete -c fish -s o -l debug-output -d "Where to direct debug output to" -rF
 complete -c fish -s P -l private -d "Do not persist history"
+complete -c fish -l restricted -d "Forbid cd, exec, writing files and universal variables"
+complete -c fish -l allow-command -d "Allow external command in restricted mode" -x -a "(__fish_complete_command)"
 
 function __fish_complete_features
     set -l arg_comma (commandline -tc | string replace -rf '(.*,)[^,]*' '$1' | string replace -r -- '--.*=' '')
@@ -4,10 +4,13 @@ complete -c fish_indent -s c -l check -d 'Do not indent, only return 0 if the co
 complete -c fish_indent -s i -l no-indent -d 'Do not indent output, only reformat into one job per line'
 complete -c fish_indent -l only-indent -d 'Do not reformat, only indent lines'
 complete -c fish_indent -l only-unindent -d 'Do not reformat, only unindent lines'
+complete -c fish_indent -l max-width -x -d 'Break lines wider than the given number of columns'
+complete -c fish_indent -l lines -x -d 'Only reformat the statements on lines START:END'
 complete -c fish_indent -l ansi -d 'Colorize the output using ANSI escape sequences'
 complete -c fish_indent -l html -d 'Output in HTML format'
 complete -c fish_indent -s w -l write -d 'Write to file'
 complete -c fish_indent -s d -l debug -x -d 'Enable debug at specified verbosity level'
 complete -c fish_indent -s o -l debug-output -d "Where to direct debug output to" -rF
 complete -c fish_indent -s D -l debug-stack-frames -x -d 'Specify how many stack frames to display in debug messages'
+complete -c fish_indent -l dump-ast -x -a json -d 'Print the syntax tree in the given format'
 complete -c fish_indent -l dump-parse-tree -d 'Dump information about parsed statements to stderr'
@@ -0,0 +1,4 @@
+complete -c fish_inline_image -s h -l help -d 'Display help and exit'
+complete -c fish_inline_image -s w -l width -x -d 'Number of cells the image occupies'
+complete -c fish_inline_image -s q -l query -d 'Print the image protocols the terminal supports'
+complete -c fish_inline_image -n 'not __fish_seen_argument -s q -l query' -k -xa '(__fish_complete_suffix .png .six .sixel)'
@@ -0,0 +1,3 @@
+complete -c fish_test -s h -l help -d 'Display help and exit'
+complete -c fish_test -s f -l format -x -a 'tap\t"Test Anything Protocol" junit\t"JUnit XML"' -d 'Format of the report'
+complete -c fish_test -k -a '(__fish_complete_suffix .fish)'
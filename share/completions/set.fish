@@ -105,6 +105,11 @@ complete -c set -n "__fish_is_nth_token 1" -s S -l show -d "Show variable"
 complete -c set -n "__fish_is_nth_token 1" -l path -d "Make variable as a path variable"
 complete -c set -n "__fish_is_nth_token 1" -l unpath -d "Make variable not as a path variable"
 complete -c set -n "__fish_is_nth_token 1" -l no-event -d "Don't emit an event"
+complete -c set -n "__fish_is_nth_token 1" -s A -l associative -d "Make variable associative"
+complete -c set -n "__fish_is_nth_token 1" -l keys -d "Print the keys of an associative variable"
+complete -c set -n "__fish_is_nth_token 1" -l computed -d "Compute value from code when expanded"
+complete -c set -n "__fish_is_nth_token 1" -l ttl -x -d "Recompute value after this duration"
+complete -c set -n "__fish_is_nth_token 1" -l invalidate -d "Recompute value on next expansion"
 
 #TODO: add CPP code to generate list of read-only variables and exclude them from the following completions
 
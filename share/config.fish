@@ -60,6 +60,10 @@ if not set -q fish_complete_path
     set -a fish_complete_path $__fish_cache_dir/generated_completions
 end
 
+if not set -q fish_translation_path
+    set fish_translation_path $__fish_config_dir/translations $__fish_sysconf_dir/translations $__fish_user_data_dir/translations $xdg_data_dirs/translations
+end
+
 # Add a handler for when fish_user_path changes, so we can apply the same changes to PATH
 function __fish_reconstruct_path -d "Update PATH when fish_user_paths changes" --on-variable fish_user_paths
     # Deduplicate $fish_user_paths
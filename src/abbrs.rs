@@ -1,15 +1,28 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     sync::{LazyLock, Mutex, MutexGuard},
 };
 
 use crate::prelude::*;
 
 use crate::parse_constants::SourceRange;
+use crate::wildcard::wildcard_match;
+use fish_common::escape;
 use pcre2::utf32::Regex;
 
 static ABBRS: LazyLock<Mutex<AbbreviationSet>> = LazyLock::new(|| Mutex::new(Default::default()));
 
+/// The results of abbreviation conditions, which are evaluated at most once per prompt.
+static CONDITION_CACHE: LazyLock<Mutex<ConditionCache>> =
+    LazyLock::new(|| Mutex::new(ConditionCache::default()));
+
+#[derive(Default)]
+struct ConditionCache {
+    /// Incremented for every prompt, so results for an earlier prompt can be told apart.
+    generation: u64,
+    results: HashMap<WString, bool>,
+}
+
 pub fn with_abbrs<R>(cb: impl FnOnce(&AbbreviationSet) -> R) -> R {
     let abbrs_g = ABBRS.lock().unwrap();
     cb(&abbrs_g)
@@ -61,6 +74,13 @@ pub struct Abbreviation {
     /// If set, then move the cursor to the first instance of this string in the expansion.
     pub set_cursor_marker: Option<WString>,
 
+    /// If set, a fish command which must succeed for the abbreviation to expand.
+    pub condition: Option<WString>,
+
+    /// Wildcard patterns for the directories the abbreviation is valid in, together with their
+    /// subdirectories (or empty if any).
+    pub directories: Vec<WString>,
+
     /// Mark if we came from a universal variable.
     pub from_universal: bool,
 }
@@ -84,6 +104,8 @@ pub fn new(
             replacement_is_function: false,
             position,
             set_cursor_marker: None,
+            condition: None,
+            directories: vec![],
             from_universal,
         }
     }
@@ -93,8 +115,9 @@ pub fn is_regex(&self) -> bool {
         self.regex.is_some()
     }
 
-    // Return true if we match a token at a given position.
-    pub fn matches(&self, token: &wstr, position: Position, command: &wstr) -> bool {
+    // Return true if we match a token at a given position, in the working directory `pwd`.
+    // The condition is not checked here.
+    pub fn matches(&self, token: &wstr, position: Position, command: &wstr, pwd: &wstr) -> bool {
         if !self.matches_position(position) {
             return false;
         }
@@ -103,6 +126,10 @@ pub fn matches(&self, token: &wstr, position: Position, command: &wstr) -> bool
             return false;
         }
 
+        if !self.matches_directory(pwd) {
+            return false;
+        }
+
         match &self.regex {
             Some(r) => r
                 .is_match(token.as_char_slice())
@@ -115,6 +142,32 @@ pub fn matches(&self, token: &wstr, position: Position, command: &wstr) -> bool
     fn matches_position(&self, position: Position) -> bool {
         self.position == Position::Anywhere || self.position == position
     }
+
+    // Return if our condition allows us to expand, see [`abbrs_condition_holds`].
+    fn condition_holds(&self) -> bool {
+        abbrs_condition_holds(self.condition.as_deref())
+    }
+
+    // Return if we expand in the working directory `pwd`, which is in one of our directories or
+    // below it.
+    fn matches_directory(&self, pwd: &wstr) -> bool {
+        if self.directories.is_empty() {
+            return true;
+        }
+        let pwd = if pwd.len() > 1 && pwd.ends_with("/") {
+            pwd.slice_to(pwd.len() - 1)
+        } else {
+            pwd
+        };
+        self.directories.iter().any(|pattern| {
+            let mut subtree = pattern.clone();
+            if !subtree.ends_with("/") {
+                subtree.push('/');
+            }
+            subtree.push('*');
+            wildcard_match(pwd, pattern, false) || wildcard_match(pwd, &subtree, false)
+        })
+    }
 }
 
 /// The result of an abbreviation expansion.
@@ -128,6 +181,10 @@ pub struct Replacer {
 
     /// If set, the cursor should be moved to the first instance of this string in the expansion.
     pub set_cursor_marker: Option<WString>,
+
+    /// If set, a fish command which must succeed for the replacer to be used.
+    /// See [`abbrs_condition_cached`].
+    pub condition: Option<WString>,
 }
 
 pub struct Replacement {
@@ -179,17 +236,25 @@ pub struct AbbreviationSet {
 
 impl AbbreviationSet {
     /// Return the list of replacers for an input token, in priority order.
-    /// The `position` is given to describe where the token was found.
-    pub fn r#match(&self, token: &wstr, position: Position, cmd: &wstr) -> Vec<Replacer> {
+    /// The `position` is given to describe where the token was found, and `pwd` is the working
+    /// directory.
+    pub fn r#match(
+        &self,
+        token: &wstr,
+        position: Position,
+        cmd: &wstr,
+        pwd: &wstr,
+    ) -> Vec<Replacer> {
         let mut result = vec![];
 
         // Later abbreviations take precedence so walk backwards.
         for abbr in self.abbrs.iter().rev() {
-            if abbr.matches(token, position, cmd) {
+            if abbr.matches(token, position, cmd, pwd) {
                 result.push(Replacer {
                     replacement: abbr.replacement.clone(),
                     is_function: abbr.replacement_is_function,
                     set_cursor_marker: abbr.set_cursor_marker.clone(),
+                    condition: abbr.condition.clone(),
                 });
             }
         }
@@ -197,10 +262,56 @@ pub fn r#match(&self, token: &wstr, position: Position, cmd: &wstr) -> Vec<Repla
     }
 
     /// Return whether we would have at least one replacer for a given token.
-    pub fn has_match(&self, token: &wstr, position: Position, cmd: &wstr) -> bool {
+    /// This never runs conditions, so abbreviations whose condition has not been evaluated for
+    /// this prompt yet do not match.
+    pub fn has_match(&self, token: &wstr, position: Position, cmd: &wstr, pwd: &wstr) -> bool {
         self.abbrs
             .iter()
-            .any(|abbr| abbr.matches(token, position, cmd))
+            .any(|abbr| abbr.matches(token, position, cmd, pwd) && abbr.condition_holds())
+    }
+
+    /// Return the name and expansion of an abbreviation whose expansion the command line
+    /// `command` starts with, so it could have been typed as the abbreviation instead.
+    /// The longest expansion is preferred. Like [`has_match`](Self::has_match), this never runs
+    /// conditions.
+    pub fn hint(&self, command: &wstr, pwd: &wstr) -> Option<(WString, WString)> {
+        let leading_spaces = command.chars().take_while(|c| c.is_whitespace()).count();
+        let command = &command[leading_spaces..];
+        let mut result: Option<(WString, WString)> = None;
+        for abbr in &self.abbrs {
+            if abbr.is_regex()
+                || abbr.replacement_is_function
+                || !abbr.commands.is_empty()
+                || !abbr.matches_directory(pwd)
+                || !abbr.condition_holds()
+            {
+                continue;
+            }
+            let expansion = Replacement::new(
+                SourceRange::new(0, 0),
+                abbr.replacement.clone(),
+                abbr.set_cursor_marker.clone(),
+            )
+            .text;
+            // Typing the expansion must have been longer than typing the name.
+            if expansion.len() <= abbr.name.len() || !command.starts_with(&expansion[..]) {
+                continue;
+            }
+            if command
+                .chars()
+                .nth(expansion.len())
+                .is_some_and(|c| !c.is_whitespace())
+            {
+                continue;
+            }
+            if result
+                .as_ref()
+                .is_none_or(|(_, longest)| longest.len() < expansion.len())
+            {
+                result = Some((abbr.name.clone(), expansion));
+            }
+        }
+        result
     }
 
     /// Add an abbreviation. Any abbreviation with the same name is replaced.
@@ -280,17 +391,93 @@ fn on_remove(&mut self, name: &wstr) {
 
 /// Return the list of replacers for an input token, in priority order, using the global set.
 /// The `position` is given to describe where the token was found.
-pub fn abbrs_match(token: &wstr, position: Position, cmd: &wstr) -> Vec<Replacer> {
-    with_abbrs(|set| set.r#match(token, position, cmd))
+pub fn abbrs_match(token: &wstr, position: Position, cmd: &wstr, pwd: &wstr) -> Vec<Replacer> {
+    with_abbrs(|set| set.r#match(token, position, cmd, pwd))
         .into_iter()
         .collect()
 }
 
+/// Return the distinct conditions of all abbreviations.
+pub fn abbrs_conditions() -> Vec<WString> {
+    let mut seen = HashSet::new();
+    with_abbrs(|set| {
+        set.list()
+            .iter()
+            .filter_map(|abbr| abbr.condition.clone())
+            .filter(|condition| seen.insert(condition.clone()))
+            .collect()
+    })
+}
+
+/// Return a script which runs each of `conditions`, and prints a line for each of them, `1` if it
+/// succeeded and `0` otherwise. This is how the conditions are run in the background.
+pub fn abbrs_conditions_script(conditions: &[WString]) -> WString {
+    let mut script = WString::new();
+    for condition in conditions {
+        script.push_str("if eval ");
+        script.push_utfstr(&escape(condition));
+        script.push_str(" </dev/null &>/dev/null\n    echo 1\nelse\n    echo 0\nend\n");
+    }
+    script
+}
+
+/// Record the `output` of [`abbrs_conditions_script`] for `conditions`, unless the results are for
+/// an earlier prompt than `generation`.
+pub fn abbrs_set_condition_results(generation: u64, conditions: Vec<WString>, output: &[WString]) {
+    let mut cache = CONDITION_CACHE.lock().unwrap();
+    if cache.generation != generation {
+        return;
+    }
+    for (condition, result) in conditions.into_iter().zip(output) {
+        cache.results.insert(condition, result == "1");
+    }
+}
+
+/// Return the result of an abbreviation condition if it has already been run for this prompt.
+/// Conditions are run in the background for every prompt; if an abbreviation is expanded before
+/// its condition has finished there, the condition is run right away instead.
+pub fn abbrs_condition_cached(condition: &wstr) -> Option<bool> {
+    CONDITION_CACHE
+        .lock()
+        .unwrap()
+        .results
+        .get(condition)
+        .copied()
+}
+
+/// Return whether an abbreviation with `condition` may expand, which is the case if it has no
+/// condition, or its condition has succeeded for this prompt. Highlighting, the preview and the
+/// expansion of abbreviations all go by this.
+pub fn abbrs_condition_holds(condition: Option<&wstr>) -> bool {
+    condition.is_none_or(|condition| abbrs_condition_cached(condition) == Some(true))
+}
+
+/// Return the generation to pass to [`abbrs_set_condition_results`] for the current prompt.
+pub fn abbrs_condition_generation() -> u64 {
+    CONDITION_CACHE.lock().unwrap().generation
+}
+
+/// Forget the results of abbreviation conditions. This is done for every new prompt.
+/// Return the generation to pass to [`abbrs_set_condition_results`] for this prompt.
+pub fn abbrs_clear_condition_cache() -> u64 {
+    let mut cache = CONDITION_CACHE.lock().unwrap();
+    cache.generation += 1;
+    cache.results.clear();
+    cache.generation
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Abbreviation, Position, abbrs_match, with_abbrs_mut};
+    use super::{
+        Abbreviation, Position, abbrs_clear_condition_cache, abbrs_conditions,
+        abbrs_conditions_script, abbrs_match, abbrs_set_condition_results, with_abbrs,
+        with_abbrs_mut,
+    };
     use crate::editable_line::{Edit, apply_edit};
+    use crate::env::EnvMode;
+    use crate::exec::exec_subshell;
     use crate::highlight::HighlightSpec;
+    use crate::parser::ParserEnvSetMode;
     use crate::prelude::*;
     use crate::reader::reader_expand_abbreviation_at_cursor;
     use crate::tests::prelude::*;
@@ -334,11 +521,11 @@ fn test_abbreviations() {
         // Helper to expand an abbreviation, enforcing we have no more than one result.
         macro_rules! abbr_expand_1 {
             ($token:expr, $position:expr) => {
-                let result = abbrs_match(L!($token), $position, L!(""));
+                let result = abbrs_match(L!($token), $position, L!(""), L!("/"));
                 assert_eq!(result, vec![]);
             };
             ($token:expr, $position:expr, $expected:expr) => {
-                let result = abbrs_match(L!($token), $position, L!(""));
+                let result = abbrs_match(L!($token), $position, L!(""), L!("/"));
                 assert_eq!(
                     result
                         .into_iter()
@@ -427,6 +614,159 @@ macro_rules! validate {
         with_abbrs_mut(|abbrset| abbrset.clear());
     }
 
+    #[test]
+    #[serial]
+    fn test_abbreviation_directories_and_conditions() {
+        test_init();
+        let parser = &mut TestParser::new();
+        with_abbrs_mut(|abbrs| {
+            let mut abbr = Abbreviation::new(
+                L!("b").to_owned(),
+                L!("b").to_owned(),
+                L!("cargo build").to_owned(),
+                Position::Command,
+                false,
+            );
+            abbr.directories = vec![L!("/src/*/rust").to_owned()];
+            abbrs.add(abbr);
+            let mut abbr = Abbreviation::new(
+                L!("t").to_owned(),
+                L!("t").to_owned(),
+                L!("true").to_owned(),
+                Position::Command,
+                false,
+            );
+            abbr.condition = Some(L!("test -n \"$abbr_condition\"").to_owned());
+            abbrs.add(abbr);
+        });
+
+        let replacements = |token: &wstr, pwd: &wstr| {
+            abbrs_match(token, Position::Command, L!(""), pwd)
+                .into_iter()
+                .map(|a| a.replacement)
+                .collect::<Vec<_>>()
+        };
+        let build = vec![L!("cargo build").to_owned()];
+        assert_eq!(replacements(L!("b"), L!("/src/fish/rust")), build);
+        assert_eq!(replacements(L!("b"), L!("/src/fish/rust/")), build);
+        assert_eq!(replacements(L!("b"), L!("/src/fish/rust/src/bin")), build);
+        assert!(replacements(L!("b"), L!("/src/fish/rusty")).is_empty());
+        assert!(replacements(L!("b"), L!("/src/fish")).is_empty());
+
+        // Conditions are run in the background for each prompt. Until their result is known, the
+        // abbreviation does not match, and expanding it runs the condition right away.
+        let conditions = abbrs_conditions();
+        assert_eq!(
+            conditions,
+            vec![L!("test -n \"$abbr_condition\"").to_owned()]
+        );
+        let script = abbrs_conditions_script(&conditions);
+        let generation = abbrs_clear_condition_cache();
+        assert!(!with_abbrs(|set| set.has_match(
+            L!("t"),
+            Position::Command,
+            L!(""),
+            L!("/")
+        )));
+        assert!(reader_expand_abbreviation_at_cursor(L!("t"), 1, parser).is_none());
+
+        let mut output = vec![];
+        exec_subshell(&script, parser, Some(&mut output), false).unwrap();
+        assert_eq!(output, vec![L!("0").to_owned()]);
+        abbrs_set_condition_results(generation, conditions.clone(), &output);
+        assert!(!with_abbrs(|set| set.has_match(
+            L!("t"),
+            Position::Command,
+            L!(""),
+            L!("/")
+        )));
+
+        parser.set_one(
+            L!("abbr_condition"),
+            ParserEnvSetMode::new(EnvMode::GLOBAL),
+            L!("1").to_owned(),
+        );
+        let mut output = vec![];
+        exec_subshell(&script, parser, Some(&mut output), false).unwrap();
+        assert_eq!(output, vec![L!("1").to_owned()]);
+        // Results for an earlier prompt are ignored.
+        let new_generation = abbrs_clear_condition_cache();
+        abbrs_set_condition_results(generation, conditions.clone(), &output);
+        assert!(!with_abbrs(|set| set.has_match(
+            L!("t"),
+            Position::Command,
+            L!(""),
+            L!("/")
+        )));
+        abbrs_set_condition_results(new_generation, conditions, &output);
+        assert!(with_abbrs(|set| set.has_match(
+            L!("t"),
+            Position::Command,
+            L!(""),
+            L!("/")
+        )));
+        let replacement = reader_expand_abbreviation_at_cursor(L!("t"), 1, parser).unwrap();
+        assert_eq!(replacement.text, "true");
+
+        // The result of a condition run for an expansion is kept for this prompt.
+        abbrs_clear_condition_cache();
+        let replacement = reader_expand_abbreviation_at_cursor(L!("t"), 1, parser).unwrap();
+        assert_eq!(replacement.text, "true");
+        assert!(with_abbrs(|set| set.has_match(
+            L!("t"),
+            Position::Command,
+            L!(""),
+            L!("/")
+        )));
+
+        with_abbrs_mut(|abbrset| abbrset.clear());
+    }
+
+    #[test]
+    #[serial]
+    fn test_abbreviation_hint() {
+        test_init();
+        with_abbrs_mut(|abbrs| {
+            abbrs.add(Abbreviation::new(
+                L!("g").to_owned(),
+                L!("g").to_owned(),
+                L!("git").to_owned(),
+                Position::Command,
+                false,
+            ));
+            abbrs.add(Abbreviation::new(
+                L!("gco").to_owned(),
+                L!("gco").to_owned(),
+                L!("git checkout").to_owned(),
+                Position::Command,
+                false,
+            ));
+            let mut abbr = Abbreviation::new(
+                L!("L").to_owned(),
+                L!("L").to_owned(),
+                L!("% | less").to_owned(),
+                Position::Anywhere,
+                false,
+            );
+            abbr.set_cursor_marker = Some(L!("%").to_owned());
+            abbrs.add(abbr);
+
+            let hint = |command: &str| abbrs.hint(&WString::from(command), L!("/"));
+            let name_and_expansion =
+                |name: &str, expansion: &str| Some((WString::from(name), WString::from(expansion)));
+            assert_eq!(
+                hint("git checkout main"),
+                name_and_expansion("gco", "git checkout")
+            );
+            assert_eq!(hint("  git status"), name_and_expansion("g", "git"));
+            assert_eq!(hint("git"), name_and_expansion("g", "git"));
+            assert_eq!(hint("gitk"), None);
+            assert_eq!(hint("echo git"), None);
+            assert_eq!(hint(" | less"), None);
+            abbrs.clear();
+        });
+    }
+
     #[test]
     #[serial]
     fn rename_abbrs() {
@@ -443,6 +783,8 @@ fn rename_abbrs() {
                     replacement_is_function: false,
                     position,
                     set_cursor_marker: None,
+                    condition: None,
+                    directories: vec![],
                     from_universal: false,
                 });
             };
@@ -1486,6 +1486,56 @@ pub fn dump(&self, orig: &wstr) -> WString {
         }
         result
     }
+
+    /// Return a JSON representation of the tree, for use by other programs.
+    /// Each node is an object with its "kind", its "start" and "end" offsets in characters (null
+    /// if unsourced), its "token" type or "keyword" if it is one, and a list of its "children".
+    pub fn dump_json(&self) -> WString {
+        let mut dumper = JsonDumper::default();
+        dumper.visit(self.top.as_node());
+        dumper.result.push('\n');
+        dumper.result
+    }
+}
+
+/// Helper for [`Ast::dump_json`].
+#[derive(Default)]
+struct JsonDumper {
+    result: WString,
+    /// Whether a sibling was already written, so the next node needs a comma.
+    needs_comma: bool,
+}
+
+impl<'a> NodeVisitor<'a> for JsonDumper {
+    fn visit(&mut self, node: &'a dyn Node) {
+        if self.needs_comma {
+            self.result.push(',');
+        }
+        sprintf!(=> &mut self.result, "{\"kind\":\"%s\"", ast_kind_to_string(node.kind()));
+        match node.try_source_range() {
+            Some(range) => {
+                sprintf!(=> &mut self.result, ",\"start\":%u,\"end\":%u", range.start(), range.end());
+            }
+            None => self.result.push_str(",\"start\":null,\"end\":null"),
+        }
+        if let Some(token) = node.as_token() {
+            let token_type = token.token_type().to_wstr();
+            let prefix = L!("ParseTokenType::");
+            let name = if token_type.starts_with(prefix) {
+                token_type.slice_from(prefix.len())
+            } else {
+                token_type
+            };
+            sprintf!(=> &mut self.result, ",\"token\":\"%s\"", name);
+        } else if let Some(keyword) = node.as_keyword() {
+            sprintf!(=> &mut self.result, ",\"keyword\":\"%s\"", keyword.keyword().to_wstr());
+        }
+        self.result.push_str(",\"children\":[");
+        self.needs_comma = false;
+        node.accept(self);
+        self.result.push_str("]}");
+        self.needs_comma = true;
+    }
 }
 
 struct SourceRangeVisitor {
@@ -2838,6 +2888,30 @@ fn test_ast_parse() {
         assert!(!ast.any_error);
     }
 
+    #[test]
+    fn test_ast_dump_json() {
+        let src = L!("echo hi");
+        let ast = ast::parse(src, ParseTreeFlags::default(), None);
+        let json = ast.dump_json();
+        assert!(json.starts_with(
+            r#"{"kind":"job_list","start":0,"end":7,"children":[{"kind":"job_conjunction","start":0,"end":7,"children":["#
+        ));
+        assert!(json.ends_with("]}\n"));
+        let json = json.to_string();
+        assert!(
+            json.contains(r#"{"kind":"token","start":0,"end":4,"token":"string","children":[]}"#)
+        );
+        assert!(json.contains(r#"{"kind":"argument","start":5,"end":7,"children":[]}"#));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+
+        let ast = ast::parse(L!("if true; end"), ParseTreeFlags::default(), None);
+        assert!(
+            ast.dump_json()
+                .to_string()
+                .contains(r#"{"kind":"keyword","start":0,"end":2,"keyword":"if","children":[]}"#)
+        );
+    }
+
     // TODO use 'indoc' but that fails on windows:
     //       0 [main] rustc 550 child_info_fork::abort: address space needed by 'indoc-1058d1a3f55eac1a.dll' (0x400000) is already occupied
     // error: could not exec the linker `x86_64-pc-cygwin-gcc`
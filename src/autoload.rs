@@ -127,6 +127,9 @@ pub fn perform_autoload(path: &AutoloadPath, parser: &mut Parser) {
         // - we source the file.
         // We don't create a buffer or check ifs or create a read_limit
         let prev_statuses = parser.last_statuses();
+        // Autoloaded files are trusted, so the functions they define must not inherit a restriction
+        // from the code that triggered the load. They are still restricted when called from there.
+        let _unrestricted = parser.lift_restriction();
         let mut parser = ScopeGuard::new(parser, |parser| parser.set_last_statuses(prev_statuses));
         match path {
             AutoloadPath::OnDisk(p) => {
@@ -25,7 +25,7 @@
     },
     common::{PACKAGE_NAME, PROFILING_ACTIVE, PROGRAM_NAME},
     env::{EnvMode, EnvStack, Environment as _, Statuses, config_paths::ConfigPaths, env_init},
-    eprintf, err_fmt,
+    eprintf, err_fmt, err_str,
     event::{self, Event},
     fds::heightenize_fd,
     flog::{self, activate_flog_categories_by_pattern, flog, flogf, set_flog_file_fd},
@@ -53,6 +53,7 @@
     wutil::waccess,
 };
 use fish_common::{escape, save_term_foreground_process_group};
+use fish_feature_flags::FeatureFlag;
 use fish_widestring::{bytes2wcstring, osstr2wcstring, wcs2bytes};
 use libc::{STDERR_FILENO, STDIN_FILENO};
 use nix::{
@@ -66,7 +67,10 @@
     ops::ControlFlow,
     os::unix::prelude::*,
     path::Path,
-    sync::{Arc, atomic::Ordering},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 /// container to hold the options specified within the command line
@@ -95,6 +99,10 @@ struct FishCmdOpts {
     is_interactive_session: bool,
     /// Whether to enable private mode.
     enable_private_mode: bool,
+    /// Whether to run the commands, script or interactive session in restricted mode.
+    restricted: bool,
+    /// The external commands allowed in restricted mode.
+    allowed_commands: Vec<WString>,
 }
 
 /// Return a timeval converted to milliseconds.
@@ -201,7 +209,7 @@ fn run_command_list(parser: &mut Parser, cmds: &[OsString]) -> Result<(), libc::
             let _ = parser.eval_parsed_source(&ps, &IoChain::new(), None, BlockType::Top);
             retval = Ok(());
         } else {
-            let backtrace = parser.get_backtrace(&cmd_wcs, &errors);
+            let backtrace = parser.get_backtrace(&cmd_wcs, &errors, isatty(STDERR_FILENO));
             eprintf!("%s", backtrace);
             // XXX: Why is this the return for "unknown command"?
             retval = Err(STATUS_CMD_UNKNOWN);
@@ -217,6 +225,8 @@ fn fish_parse_opt(args: &mut [WString], opts: &mut FishCmdOpts) -> ControlFlow<i
     const RUSAGE_ARG: char = 1 as char;
     const PRINT_DEBUG_CATEGORIES_ARG: char = 2 as char;
     const PROFILE_STARTUP_ARG: char = 3 as char;
+    const RESTRICTED_ARG: char = 4 as char;
+    const ALLOW_COMMAND_ARG: char = 5 as char;
 
     const SHORT_OPTS: &wstr = L!("+hPilNnvc:C:p:d:f:D:o:");
     const LONG_OPTS: &[WOption<'static>] = &[
@@ -239,6 +249,8 @@ fn fish_parse_opt(args: &mut [WString], opts: &mut FishCmdOpts) -> ControlFlow<i
         wopt(L!("profile"), RequiredArgument, 'p'),
         wopt(L!("profile-startup"), RequiredArgument, PROFILE_STARTUP_ARG),
         wopt(L!("private"), NoArgument, 'P'),
+        wopt(L!("restricted"), NoArgument, RESTRICTED_ARG),
+        wopt(L!("allow-command"), RequiredArgument, ALLOW_COMMAND_ARG),
         wopt(L!("help"), NoArgument, 'h'),
         wopt(L!("version"), NoArgument, 'v'),
     ];
@@ -299,6 +311,8 @@ fn fish_parse_opt(args: &mut [WString], opts: &mut FishCmdOpts) -> ControlFlow<i
                 PROFILING_ACTIVE.store(true);
             }
             'P' => opts.enable_private_mode = true,
+            RESTRICTED_ARG => opts.restricted = true,
+            ALLOW_COMMAND_ARG => opts.allowed_commands.push(w.woptarg.unwrap().to_owned()),
             'v' => {
                 printf!("%s\n", localized_version_string(PACKAGE_NAME));
                 return ControlFlow::Break(0);
@@ -333,6 +347,14 @@ fn fish_parse_opt(args: &mut [WString], opts: &mut FishCmdOpts) -> ControlFlow<i
     }
     let optind = w.wopt_index;
 
+    if !opts.allowed_commands.is_empty() && !opts.restricted {
+        err_str!("--allow-command requires --restricted")
+            .cmd(L!("fish"))
+            .append_to_msg('\n')
+            .write_to(&mut OutputStream::Fd(FdOutputStream::new(STDERR_FILENO)));
+        return ControlFlow::Break(1);
+    }
+
     // If our command name begins with a dash that implies we're a login shell.
     opts.is_login |= args[0].char_at(0) == '-';
 
@@ -519,6 +541,18 @@ fn throwing_main() -> i32 {
     // Clear signals in case we were interrupted (#9024).
     signal_clear_cancel();
 
+    // Everything after the config and the --init-command is restricted, if requested.
+    let _restricted = opts
+        .restricted
+        .then(|| parser.push_restriction(std::mem::take(&mut opts.allowed_commands)));
+
+    // With concurrent pipelines, functions and blocks in a pipeline run as `fish -c` in a child
+    // process. Stop once our output pipe breaks, like an external command would.
+    let broken_pipe = (!opts.batch_cmds.is_empty()
+        && fish_feature_flags::feature_test(FeatureFlag::ConcurrentPipelines))
+    .then(|| Arc::new(AtomicBool::new(false)));
+    parser.libdata_mut().broken_pipe = broken_pipe.clone();
+
     if !opts.batch_cmds.is_empty() {
         // Run the commands specified as arguments, if any.
         if get_login() {
@@ -610,7 +644,12 @@ fn throwing_main() -> i32 {
     history::save_all();
 
     // If we deferred a fatal signal, re-raise it now so the parent sees WIFSIGNALED.
-    let exit_sig = reader_exit_signal();
+    // The same goes for a broken output pipe.
+    let exit_sig = if broken_pipe.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+        libc::SIGPIPE
+    } else {
+        reader_exit_signal()
+    };
     if exit_sig != 0 {
         unsafe {
             libc::signal(exit_sig, libc::SIG_DFL);
@@ -5,6 +5,7 @@
     common::valid_func_name,
     env::{EnvMode, EnvStackSetResult},
     err_fmt, err_str,
+    expand::expand_tilde,
     highlight::highlight_and_colorize,
     parser::ParserEnvSetMode,
     re::{regex_make_anchored, to_boxed_chars},
@@ -36,6 +37,8 @@ struct Options {
     commands: Vec<WString>,
     position: Option<Position>,
     set_cursor_marker: Option<WString>,
+    condition: Option<WString>,
+    directories: Vec<WString>,
     args: Vec<WString>,
     color: ColorEnabled,
 }
@@ -90,6 +93,12 @@ fn validate(&mut self) -> Option<Error<'_>> {
         if !self.add && self.set_cursor_marker.is_some() {
             return Some(err_fmt!(OPTION_REQUIRES_ARG, "--set-cursor", "--add"));
         }
+        if !self.add && self.condition.is_some() {
+            return Some(err_fmt!(OPTION_REQUIRES_ARG, "--condition", "--add"));
+        }
+        if !self.add && !self.directories.is_empty() {
+            return Some(err_fmt!(OPTION_REQUIRES_ARG, "--directory", "--add"));
+        }
         if self
             .set_cursor_marker
             .as_ref()
@@ -155,6 +164,14 @@ fn abbr_show(opts: &Options, streams: &mut IoStreams, parser: &mut Parser) -> Bu
                 add_arg(L!("--command"));
                 add_arg(&escape_string(cmd, style));
             }
+            if let Some(ref condition) = abbr.condition {
+                add_arg(L!("--condition"));
+                add_arg(&escape_string(condition, style));
+            }
+            for directory in &abbr.directories {
+                add_arg(L!("--directory"));
+                add_arg(&escape_string(directory, style));
+            }
             add_arg(L!("--"));
             // Literal abbreviations have the name and key as the same.
             // Regex abbreviations have a pattern separate from the name.
@@ -301,7 +318,7 @@ fn abbr_query(opts: &Options) -> BuiltinResult {
 }
 
 // Add a named abbreviation.
-fn abbr_add(opts: &Options, streams: &mut IoStreams) -> BuiltinResult {
+fn abbr_add(opts: &Options, streams: &mut IoStreams, parser: &Parser) -> BuiltinResult {
     let subcmd = L!("--add");
 
     if opts.args.len() < 2 && opts.function.is_none() {
@@ -404,6 +421,17 @@ fn abbr_add(opts: &Options, streams: &mut IoStreams) -> BuiltinResult {
         return Err(STATUS_INVALID_ARGS);
     }
 
+    // Directories are matched against $PWD, so expand a leading tilde now.
+    let directories = opts
+        .directories
+        .iter()
+        .map(|directory| {
+            let mut directory = directory.clone();
+            expand_tilde(&mut directory, parser.vars());
+            directory
+        })
+        .collect();
+
     // Note historically we have allowed overwriting existing abbreviations.
     abbrs::with_abbrs_mut(move |abbrs| {
         abbrs.add(Abbreviation {
@@ -414,6 +442,8 @@ fn abbr_add(opts: &Options, streams: &mut IoStreams) -> BuiltinResult {
             replacement_is_function: opts.function.is_some(),
             position,
             set_cursor_marker: opts.set_cursor_marker.clone(),
+            condition: opts.condition.clone(),
+            directories,
             from_universal: false,
             commands: opts.commands.clone(),
         });
@@ -463,6 +493,8 @@ pub fn abbr(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) ->
     const NON_OPTION_ARGUMENT: char = 1 as char;
     const SET_CURSOR_SHORT: char = 2 as char;
     const RENAME_SHORT: char = 3 as char;
+    const CONDITION_SHORT: char = 4 as char;
+    const DIRECTORY_SHORT: char = 5 as char;
 
     // Note the leading '-' causes wgetopter to return arguments in order, instead of permuting
     // them. We need this behavior for compatibility with pre-builtin abbreviations where options
@@ -480,6 +512,8 @@ pub fn abbr(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) ->
             SET_CURSOR_SHORT,
         ),
         wopt(L!("function"), ArgType::RequiredArgument, 'f'),
+        wopt(L!("condition"), ArgType::RequiredArgument, CONDITION_SHORT),
+        wopt(L!("directory"), ArgType::RequiredArgument, DIRECTORY_SHORT),
         wopt(L!("rename"), ArgType::NoArgument, RENAME_SHORT),
         wopt(L!("erase"), ArgType::NoArgument, 'e'),
         wopt(L!("query"), ArgType::NoArgument, 'q'),
@@ -561,6 +595,16 @@ pub fn abbr(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) ->
                     .insert(w.woptarg.unwrap_or(L!("%")).to_owned());
             }
             'f' => opts.function = w.woptarg.map(ToOwned::to_owned),
+            CONDITION_SHORT => {
+                if opts.condition.is_some() {
+                    err_str!("Cannot specify multiple conditions")
+                        .cmd(CMD)
+                        .finish(streams);
+                    return Err(STATUS_INVALID_ARGS);
+                }
+                opts.condition = w.woptarg.map(ToOwned::to_owned);
+            }
+            DIRECTORY_SHORT => opts.directories.push(w.woptarg.unwrap().to_owned()),
             RENAME_SHORT => opts.rename = true,
             'e' => opts.erase = true,
             'q' => opts.query = true,
@@ -614,8 +658,17 @@ pub fn abbr(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) ->
         return Err(STATUS_INVALID_ARGS);
     }
 
+    // Abbreviations run their functions and conditions later on their own, so restricted code may
+    // not change them.
+    if (opts.add || opts.rename || opts.erase) && parser.is_restricted() {
+        err_str!("changing abbreviations is not allowed in restricted mode")
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_RESTRICTED);
+    }
+
     if opts.add {
-        return abbr_add(&opts, streams);
+        return abbr_add(&opts, streams, parser);
     }
     if opts.show {
         return abbr_show(&opts, streams, parser);
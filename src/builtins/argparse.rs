@@ -1,11 +1,19 @@
 use super::prelude::*;
+use crate::ast::{Kind, Node as _, Traversal};
 use crate::builtins::Error;
-use crate::env::{EnvMode, EnvSetMode, EnvStack};
+use crate::env::EnvMode;
 use crate::exec::exec_subshell;
+use crate::function;
+use crate::io::{IoChain, OutputStream};
+use crate::parse_util::locate_cmdsubst_range;
 use crate::parser::ParserEnvSetMode;
 use crate::wutil::fish_iswalnum;
 use crate::{err_fmt, err_str};
+use fish_common::{
+    EscapeStringStyle, UnescapeFlags, UnescapeStringStyle, escape_string, unescape_string,
+};
 use fish_fluent::localize_fn;
+use fish_widestring::{EXPAND_RESERVED_BASE, EXPAND_RESERVED_END};
 use std::collections::HashMap;
 
 const VAR_NAME_PREFIX: &wstr = L!("_flag_");
@@ -28,6 +36,7 @@ struct OptionSpec<'args> {
     short_flag: char,
     long_flag: &'args wstr,
     validation_command: &'args wstr,
+    description: &'args wstr,
     vals: Vec<WString>,
     short_flag_valid: bool,
     delete: bool,
@@ -48,7 +57,7 @@ fn new(s: char) -> Self {
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 enum UnknownHandling {
     #[default]
     Error,
@@ -62,6 +71,8 @@ struct Options<'args> {
     unknown_arguments: ArgType,
     strict_long_opts: bool,
     print_help: bool,
+    print_completions: bool,
+    print_usage: bool,
     stop_nonopt: bool,
     min_args: usize,
     max_args: usize,
@@ -71,8 +82,20 @@ struct Options<'args> {
     args: Vec<Cow<'args, wstr>>,
     args_opts: Vec<Cow<'args, wstr>>,
     options: HashMap<char, OptionSpec<'args>>,
+    // The short flags of the options, in the order they were given.
+    option_order: Vec<char>,
     long_to_short_flag: HashMap<WString, char>,
     exclusive_flag_sets: Vec<Vec<char>>,
+    subcommands: Vec<Subcommand<'args>>,
+    // The index of the subcommand that was given, if any.
+    subcommand: Option<usize>,
+}
+
+/// A subcommand declared with `--subcommand`, with its own option specs.
+struct Subcommand<'args> {
+    name: &'args wstr,
+    description: &'args wstr,
+    opts: Options<'args>,
 }
 
 impl Options<'_> {
@@ -85,6 +108,11 @@ fn new() -> Self {
     }
 }
 
+/// Values used for long-only options.
+const OPT_PRINT_COMPLETIONS: char = '\x01';
+const OPT_PRINT_HELP: char = '\x02';
+const OPT_SUBCOMMAND: char = '\x03';
+
 const SHORT_OPTIONS: &wstr = L!("+hn:siuU:x:SN:X:");
 const LONG_OPTIONS: &[WOption] = &[
     wopt(L!("stop-nonopt"), ArgType::NoArgument, 's'),
@@ -97,6 +125,13 @@ fn new() -> Self {
     wopt(L!("help"), ArgType::NoArgument, 'h'),
     wopt(L!("min-args"), ArgType::RequiredArgument, 'N'),
     wopt(L!("max-args"), ArgType::RequiredArgument, 'X'),
+    wopt(
+        L!("print-completions"),
+        ArgType::NoArgument,
+        OPT_PRINT_COMPLETIONS,
+    ),
+    wopt(L!("print-help"), ArgType::NoArgument, OPT_PRINT_HELP),
+    wopt(L!("subcommand"), ArgType::RequiredArgument, OPT_SUBCOMMAND),
 ];
 
 // Check if any pair of mutually exclusive options was seen. Note that since every option must have
@@ -220,6 +255,7 @@ fn parse_flag_modifiers<'args>(
         && !s.is_empty()
         && s.char_at(0) != '!'
         && s.char_at(0) != '&'
+        && s.char_at(0) != '"'
     {
         err_fmt!(
             "Implicit int short flag '%c' does not allow modifiers like '%c'",
@@ -255,6 +291,17 @@ fn parse_flag_modifiers<'args>(
         s = s.slice_from(1);
     }
 
+    if s.char_at(0) == '"' {
+        let Some(len) = s.slice_from(1).chars().position(|c| c == '"') else {
+            Error::from(&err_invalid_opt_spec(option_spec, s.char_at(0)))
+                .cmd(&opts.name)
+                .finish(streams);
+            return false;
+        };
+        opt_spec.description = s.slice_from(1).slice_to(len);
+        s = s.slice_from(len + 2);
+    }
+
     if s.char_at(0) == '!' {
         if opt_spec.arg_type == ArgType::NoArgument {
             Error::from(&err_invalid_opt_spec(option_spec, s.char_at(0)))
@@ -362,7 +409,7 @@ fn parse_option_spec_sep<'args>(
             opt_spec.accumulate_args = false;
             i += 1; // the struct is initialized assuming short_flag_valid should be true
         }
-        '!' | '?' | '=' | '&' => {
+        '!' | '?' | '=' | '&' | '"' => {
             // Try to parse any other flag modifiers
             // parse_flag_modifiers assumes opt_spec_str starts where it should, not one earlier
             s = s.slice_from(i);
@@ -453,6 +500,7 @@ fn parse_option_spec<'args>(
     }
 
     // Record our option under its short flag.
+    opts.option_order.push(opt_spec.short_flag);
     opts.options.insert(opt_spec.short_flag, opt_spec);
 
     true
@@ -487,7 +535,25 @@ fn collect_option_specs<'args>(
             break;
         }
 
-        if !parse_option_spec(opts, args[*optind], &mut counter, streams) {
+        // A spec like `add:f/force` belongs to the subcommand `add`.
+        let option_spec = args[*optind];
+        let subcommand = option_spec.find_char(':').and_then(|i| {
+            let index = opts
+                .subcommands
+                .iter()
+                .position(|sub| sub.name == &option_spec[..i])?;
+            Some((index, &option_spec[i + 1..]))
+        });
+        let parsed = match subcommand {
+            Some((index, option_spec)) => parse_option_spec(
+                &mut opts.subcommands[index].opts,
+                option_spec,
+                &mut counter,
+                streams,
+            ),
+            None => parse_option_spec(opts, option_spec, &mut counter, streams),
+        };
+        if !parsed {
             return Err(STATUS_CMD_ERROR);
         }
 
@@ -507,6 +573,42 @@ fn collect_option_specs<'args>(
     Ok(SUCCESS)
 }
 
+/// Parse the argument to `--subcommand`, a name optionally followed by a description in double
+/// quotes.
+fn parse_subcommand<'args>(
+    opts: &mut Options<'args>,
+    cmd: &wstr,
+    spec: &'args wstr,
+    streams: &mut IoStreams,
+) -> bool {
+    let (name, description) = match spec.find_char('"') {
+        Some(i) if spec.len() > i + 1 && spec.ends_with("\"") => {
+            (&spec[..i], &spec[i + 1..spec.len() - 1])
+        }
+        // An unterminated description.
+        Some(_) => (L!(""), L!("")),
+        None => (spec, L!("")),
+    };
+    if name.is_empty() || name.starts_with("-") || name.contains(':') {
+        err_fmt!("Invalid subcommand spec '%s'", spec)
+            .cmd(cmd)
+            .finish(streams);
+        return false;
+    }
+    if opts.subcommands.iter().any(|sub| sub.name == name) {
+        err_fmt!("Subcommand '%s' already defined", name)
+            .cmd(cmd)
+            .finish(streams);
+        return false;
+    }
+    opts.subcommands.push(Subcommand {
+        name,
+        description,
+        opts: Options::new(),
+    });
+    true
+}
+
 fn parse_cmd_opts<'args>(
     opts: &mut Options<'args>,
     optind: &mut usize,
@@ -562,6 +664,13 @@ fn parse_cmd_opts<'args>(
             // definitions we'll parse these strings into a more useful data structure.
             'x' => opts.raw_exclusive_flags.push(w.woptarg.unwrap()),
             'h' => opts.print_help = true,
+            OPT_PRINT_COMPLETIONS => opts.print_completions = true,
+            OPT_PRINT_HELP => opts.print_usage = true,
+            OPT_SUBCOMMAND => {
+                if !parse_subcommand(opts, cmd, w.woptarg.unwrap(), streams) {
+                    return Err(STATUS_INVALID_ARGS);
+                }
+            }
             'N' => {
                 opts.min_args = {
                     let x = fish_wcstol(w.woptarg.unwrap()).unwrap_or(-1);
@@ -615,6 +724,17 @@ fn parse_cmd_opts<'args>(
         }
     }
 
+    if opts.print_completions && opts.print_usage {
+        err_fmt!(
+            Error::COMBO_EXCLUSIVE,
+            "--print-completions",
+            "--print-help"
+        )
+        .cmd(cmd)
+        .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+
     // Imply --unknown-arguments implies --move-unknown, unless --ignore-unknown was given
     if seen_unknown_arguments && opts.unknown_handling == UnknownHandling::Error {
         opts.unknown_handling = UnknownHandling::Move;
@@ -643,6 +763,9 @@ fn parse_cmd_opts<'args>(
             .get_function_name(1)
             .unwrap_or_else(|| L!("argparse").to_owned());
     }
+    for sub in &mut opts.subcommands {
+        sub.opts.name = opts.name.clone() + " " + sub.name;
+    }
 
     *optind = w.wopt_index;
     collect_option_specs(opts, optind, argc, args, streams)
@@ -900,7 +1023,9 @@ fn argparse_parse_flags<'args>(
 
     // "+" means stop at nonopt, "-" means give nonoptions the option character code `1`, and don't
     // reorder.
-    let mut short_options = WString::from(if opts.stop_nonopt { L!("+") } else { L!("-") });
+    // With subcommands, the first non-option argument is the subcommand.
+    let stop_nonopt = opts.stop_nonopt || !opts.subcommands.is_empty();
+    let mut short_options = WString::from(if stop_nonopt { L!("+") } else { L!("-") });
     let mut long_options = vec![];
     populate_option_strings(opts, &mut short_options, &mut long_options);
 
@@ -1093,12 +1218,45 @@ fn argparse_parse_args<'args>(
 
     check_for_mutually_exclusive_flags(opts, streams)?;
 
+    if !opts.subcommands.is_empty() && optind < argc {
+        return argparse_parse_subcommand(opts, &mut args[optind..], parser, streams);
+    }
+
     opts.args
         .extend(args[optind..].iter().map(|&s| Cow::Borrowed(s)));
 
     Ok(SUCCESS)
 }
 
+/// Parse the arguments after a subcommand, where `args[0]` is the subcommand's name, with the
+/// subcommand's own option specs.
+fn argparse_parse_subcommand<'args>(
+    opts: &mut Options<'args>,
+    args: &mut [&'args wstr],
+    parser: &mut Parser,
+    streams: &mut IoStreams,
+) -> BuiltinResult {
+    let Some(index) = opts.subcommands.iter().position(|sub| sub.name == args[0]) else {
+        err_str!(Error::INVALID_SUBCMD)
+            .subcmd(&opts.name, args[0])
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    };
+
+    let sub = &mut opts.subcommands[index].opts;
+    sub.unknown_handling = opts.unknown_handling;
+    sub.unknown_arguments = opts.unknown_arguments;
+    sub.strict_long_opts = opts.strict_long_opts;
+    sub.stop_nonopt = opts.stop_nonopt;
+    let argc = args.len();
+    argparse_parse_args(sub, args, argc, parser, streams)?;
+
+    opts.args.append(&mut sub.args);
+    opts.args_opts.append(&mut sub.args_opts);
+    opts.subcommand = Some(index);
+    Ok(SUCCESS)
+}
+
 fn check_min_max_args_constraints(opts: &Options, streams: &mut IoStreams) -> BuiltinResult {
     let cmd = &opts.name;
 
@@ -1120,7 +1278,26 @@ fn check_min_max_args_constraints(opts: &Options, streams: &mut IoStreams) -> Bu
 }
 
 /// Put the result of parsing the supplied args into the caller environment as local vars.
-fn set_argparse_result_vars(vars: &EnvStack, local_mode: EnvSetMode, opts: Options) {
+/// These are set through the parser, so restricted code can't use them to change exported
+/// variables.
+fn set_argparse_result_vars(parser: &mut Parser, opts: Options) {
+    let local_mode = ParserEnvSetMode::new(EnvMode::LOCAL);
+    set_flag_vars(parser, &opts);
+    if let Some(index) = opts.subcommand {
+        let sub = &opts.subcommands[index];
+        set_flag_vars(parser, &sub.opts);
+        parser.set_one(L!("_subcommand"), local_mode, sub.name.to_owned());
+    }
+
+    let args = opts.args.into_iter().map(|s| s.into_owned()).collect();
+    parser.set_var(L!("argv"), local_mode, args);
+    let args_opts = opts.args_opts.into_iter().map(|s| s.into_owned()).collect();
+    parser.set_var(L!("argv_opts"), local_mode, args_opts);
+}
+
+/// Set the `_flag_` variables for the options that were seen.
+fn set_flag_vars(parser: &mut Parser, opts: &Options) {
+    let local_mode = ParserEnvSetMode::new(EnvMode::LOCAL);
     for opt_spec in opts.options.values() {
         if opt_spec.num_seen == 0 {
             continue;
@@ -1129,7 +1306,7 @@ fn set_argparse_result_vars(vars: &EnvStack, local_mode: EnvSetMode, opts: Optio
         if opt_spec.short_flag_valid {
             let mut var_name = WString::from(VAR_NAME_PREFIX);
             var_name.push(opt_spec.short_flag);
-            vars.set(&var_name, local_mode, opt_spec.vals.clone());
+            parser.set_var(&var_name, local_mode, opt_spec.vals.clone());
         }
 
         if !opt_spec.long_flag.is_empty() {
@@ -1140,14 +1317,300 @@ fn set_argparse_result_vars(vars: &EnvStack, local_mode: EnvSetMode, opts: Optio
                 .chars()
                 .map(|c| if fish_iswalnum(c) { c } else { '_' });
             let var_name_long: WString = VAR_NAME_PREFIX.chars().chain(long_flag).collect();
-            vars.set(&var_name_long, local_mode, opt_spec.vals.clone());
+            parser.set_var(&var_name_long, local_mode, opt_spec.vals.clone());
         }
     }
+}
 
-    let args = opts.args.into_iter().map(|s| s.into_owned()).collect();
-    vars.set(L!("argv"), local_mode, args);
-    let args_opts = opts.args_opts.into_iter().map(|s| s.into_owned()).collect();
-    vars.set(L!("argv_opts"), local_mode, args_opts);
+/// Return the short flags of the options that can't be combined with `opt_spec`.
+fn excluded_flags(opts: &Options, opt_spec: &OptionSpec) -> Vec<char> {
+    let mut result = vec![];
+    for xarg_set in &opts.exclusive_flag_sets {
+        if !xarg_set.contains(&opt_spec.short_flag) {
+            continue;
+        }
+        for &xflag in xarg_set {
+            if xflag != opt_spec.short_flag && !result.contains(&xflag) {
+                result.push(xflag);
+            }
+        }
+    }
+    result
+}
+
+/// Return `complete` commands that offer the options and subcommands in `opts` for the command
+/// `opts.name`.
+fn completion_commands(opts: &Options) -> Vec<WString> {
+    let escape = |s: &wstr| escape_string(s, EscapeStringStyle::default());
+    let name = escape(&opts.name);
+    if opts.subcommands.is_empty() {
+        return option_completion_commands(opts, &name, None);
+    }
+
+    // The options of the command itself are only accepted before the subcommand.
+    let mut result = option_completion_commands(opts, &name, Some(L!("__fish_use_subcommand")));
+    for sub in &opts.subcommands {
+        let mut command = sprintf!(
+            "complete -c %s -f -n __fish_use_subcommand -a %s",
+            name,
+            escape(sub.name)
+        );
+        if !sub.description.is_empty() {
+            command.push_str(" -d ");
+            command.push_utfstr(&escape(sub.description));
+        }
+        result.push(command);
+    }
+    for sub in &opts.subcommands {
+        let condition = L!("__fish_seen_subcommand_from ").to_owned() + &escape(sub.name)[..];
+        result.extend(option_completion_commands(
+            &sub.opts,
+            &name,
+            Some(&escape(&condition)),
+        ));
+    }
+    result
+}
+
+/// Return `complete` commands that offer the options in `opts` for the already escaped command
+/// `name`, if the escaped `condition` holds.
+fn option_completion_commands(
+    opts: &Options,
+    name: &wstr,
+    condition: Option<&wstr>,
+) -> Vec<WString> {
+    let escape = |s: &wstr| escape_string(s, EscapeStringStyle::default());
+    let mut result = vec![];
+    for short in &opts.option_order {
+        let opt_spec = &opts.options[short];
+        if !opt_spec.short_flag_valid && opt_spec.long_flag.is_empty() {
+            continue;
+        }
+
+        let mut command = L!("complete -c ").to_owned();
+        command.push_utfstr(name);
+        if let Some(condition) = condition {
+            command.push_str(" -n ");
+            command.push_utfstr(condition);
+        }
+        if opt_spec.short_flag_valid {
+            command.push_str(" -s ");
+            command.push_utfstr(&escape(&WString::from(&[opt_spec.short_flag][..])));
+        }
+        if !opt_spec.long_flag.is_empty() {
+            command.push_str(" -l ");
+            command.push_utfstr(&escape(opt_spec.long_flag));
+        }
+        if opt_spec.arg_type == ArgType::RequiredArgument {
+            command.push_str(" -r");
+        }
+
+        // Don't offer an option once one that excludes it was given.
+        let mut seen = WString::new();
+        for xflag in excluded_flags(opts, opt_spec) {
+            let xopt_spec = &opts.options[&xflag];
+            if xopt_spec.short_flag_valid {
+                seen.push_str(" -s ");
+                seen.push(xflag);
+            }
+            if !xopt_spec.long_flag.is_empty() {
+                seen.push_str(" -l ");
+                seen.push_utfstr(xopt_spec.long_flag);
+            }
+        }
+        if !seen.is_empty() {
+            command.push_str(" -n ");
+            command.push_utfstr(&escape(
+                &(L!("not __fish_seen_argument").to_owned() + &seen[..]),
+            ));
+        }
+
+        if !opt_spec.description.is_empty() {
+            command.push_str(" -d ");
+            command.push_utfstr(&escape(opt_spec.description));
+        }
+        result.push(command);
+    }
+    result
+}
+
+/// Return a help text listing the options and subcommands in `opts` with their descriptions.
+fn usage(opts: &Options) -> WString {
+    let mut result = wgettext_fmt!("Usage: %s [OPTIONS]", opts.name);
+    if !opts.subcommands.is_empty() {
+        result.push_str(" SUBCOMMAND");
+    }
+    if opts.max_args > 0 {
+        result.push_str(if opts.min_args > 0 {
+            " ARG ..."
+        } else {
+            " [ARG ...]"
+        });
+    }
+    result.push('\n');
+
+    push_usage_table(&mut result, &wgettext!("Options:"), option_usage_rows(opts));
+    let rows = opts
+        .subcommands
+        .iter()
+        .map(|sub| (sub.name.to_owned(), sub.description))
+        .collect();
+    push_usage_table(&mut result, &wgettext!("Subcommands:"), rows);
+    for sub in &opts.subcommands {
+        push_usage_table(
+            &mut result,
+            &wgettext_fmt!("Options for %s:", sub.name),
+            option_usage_rows(&sub.opts),
+        );
+    }
+    result
+}
+
+/// Append a titled table of rows, with their descriptions lined up, to a help text.
+fn push_usage_table(result: &mut WString, title: &wstr, rows: Vec<(WString, &wstr)>) {
+    if rows.is_empty() {
+        return;
+    }
+
+    result.push('\n');
+    result.push_utfstr(title);
+    result.push('\n');
+    let width = rows.iter().map(|(flags, _)| flags.len()).max().unwrap();
+    for (flags, description) in rows {
+        result.push_str("  ");
+        result.push_utfstr(&flags);
+        if !description.is_empty() {
+            result.extend(std::iter::repeat_n(' ', width - flags.len() + 2));
+            result.push_utfstr(description);
+        }
+        result.push('\n');
+    }
+}
+
+/// Return the rows of the options table in the help text, with the flags and description of each
+/// option.
+fn option_usage_rows<'args>(opts: &Options<'args>) -> Vec<(WString, &'args wstr)> {
+    let mut rows = vec![];
+    for short in &opts.option_order {
+        let opt_spec = &opts.options[short];
+        let is_int_flag = opt_spec.short_flag == opts.implicit_int_flag;
+        let value = if is_int_flag {
+            L!("NUMBER")
+        } else {
+            L!("VALUE")
+        };
+
+        let mut flags = WString::new();
+        if is_int_flag {
+            flags.push_str("-NUMBER");
+        } else if opt_spec.short_flag_valid {
+            flags.push('-');
+            flags.push(opt_spec.short_flag);
+        }
+        if !opt_spec.long_flag.is_empty() {
+            if !flags.is_empty() {
+                flags.push_str(", ");
+            } else {
+                // Line long-only options up with the long options of the others.
+                flags.push_str("    ");
+            }
+            flags.push_str("--");
+            flags.push_utfstr(opt_spec.long_flag);
+        }
+        if flags.is_empty() {
+            continue;
+        }
+        let separator = if opt_spec.long_flag.is_empty() {
+            L!(" ")
+        } else {
+            L!("=")
+        };
+        match opt_spec.arg_type {
+            ArgType::RequiredArgument if !is_int_flag || !opt_spec.long_flag.is_empty() => {
+                flags.push_utfstr(separator);
+                flags.push_utfstr(value);
+            }
+            ArgType::OptionalArgument => {
+                flags.push('[');
+                flags.push_utfstr(separator);
+                flags.push_utfstr(value);
+                flags.push(']');
+            }
+            _ => {}
+        }
+        rows.push((flags, opt_spec.description));
+    }
+    rows
+}
+
+/// Return `complete` commands for the options of the first `argparse` call in a function.
+/// Only literal arguments are used, since the function is not run.
+pub fn function_completion_commands(parser: &mut Parser, function_name: &wstr) -> Vec<WString> {
+    let Some(props) = function::get_props(function_name) else {
+        return vec![];
+    };
+    let src = props.func_node.source_str();
+    let Some(statement) = Traversal::new(&*props.func_node).find_map(|node| match node.kind() {
+        Kind::DecoratedStatement(statement) if statement.command.source(src) == "argparse" => {
+            Some(statement)
+        }
+        _ => None,
+    }) else {
+        return vec![];
+    };
+
+    let mut owned_args = vec![L!("argparse").to_owned()];
+    for arg in statement.args_or_redirs.iter().filter(|a| a.is_argument()) {
+        let text = arg.argument().source(src);
+        if !matches!(
+            locate_cmdsubst_range(text, &mut 0, true, None, None),
+            Ok(None)
+        ) {
+            continue;
+        }
+        let Some(arg) = unescape_string(text, UnescapeStringStyle::Script(UnescapeFlags::SPECIAL))
+        else {
+            continue;
+        };
+        if arg
+            .chars()
+            .any(|c| (EXPAND_RESERVED_BASE..=EXPAND_RESERVED_END).contains(&c))
+        {
+            continue;
+        }
+        let is_separator = arg == "--";
+        owned_args.push(arg);
+        if is_separator {
+            break;
+        }
+    }
+    if owned_args.last().is_none_or(|arg| arg != "--") {
+        return vec![];
+    }
+
+    let mut args: Vec<&wstr> = owned_args.iter().map(|s| s.as_utfstr()).collect();
+    let argc = args.len();
+    let mut out = OutputStream::Null;
+    let mut err = OutputStream::Null;
+    let io_chain = IoChain::new();
+    let mut streams = IoStreams::new(&mut out, &mut err, &io_chain);
+    let mut opts = Options::new();
+    let mut optind = 0;
+    if parse_cmd_opts(
+        &mut opts,
+        &mut optind,
+        argc,
+        &mut args,
+        parser,
+        &mut streams,
+    )
+    .is_err()
+        || parse_exclusive_args(&mut opts, &mut streams).is_err()
+    {
+        return vec![];
+    }
+    opts.name = function_name.to_owned();
+    completion_commands(&opts)
 }
 
 /// The argparse builtin. This is explicitly not compatible with the BSD or GNU version of this
@@ -1182,6 +1645,17 @@ pub fn argparse(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]
 
     parse_exclusive_args(&mut opts, streams)?;
 
+    if opts.print_completions {
+        for command in completion_commands(&opts) {
+            streams.out.appendln(&command);
+        }
+        return Ok(SUCCESS);
+    }
+    if opts.print_usage {
+        streams.out.append(&usage(&opts));
+        return Ok(SUCCESS);
+    }
+
     // wgetopt expects the first argument to be the command, and skips it.
     // if optind was 0 we'd already have returned.
     assert!(optind > 0, "Optind is 0?");
@@ -1195,11 +1669,7 @@ pub fn argparse(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]
 
     check_min_max_args_constraints(&opts, streams)?;
 
-    set_argparse_result_vars(
-        parser.vars(),
-        parser.convert_env_set_mode(ParserEnvSetMode::new(EnvMode::LOCAL)),
-        opts,
-    );
+    set_argparse_result_vars(parser, opts);
 
     Ok(SUCCESS)
 }
@@ -0,0 +1,50 @@
+//! Implementation of the assert builtin.
+
+use super::prelude::*;
+use crate::err_fmt;
+use fish_common::escape;
+use fish_wcstringutil::join_strings;
+
+/// The assert builtin. Checks a condition like `test`, and reports it if it does not hold.
+pub fn assert(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) -> BuiltinResult {
+    let cmd = argv[0];
+
+    // Like test, we can't use wgetopt, since the condition may look like options.
+    let mut message = None;
+    let mut optind = 1;
+    while let Some(&arg) = argv.get(optind) {
+        if arg == "-h" || arg == "--help" {
+            builtin_print_help(parser, streams, cmd);
+            return Ok(SUCCESS);
+        } else if (arg == "-m" || arg == "--message") && optind + 1 < argv.len() {
+            message = Some(argv[optind + 1]);
+            optind += 2;
+        } else if arg.starts_with("--message=") {
+            message = Some(arg.slice_from("--message=".len()));
+            optind += 1;
+        } else {
+            if arg == "--" {
+                optind += 1;
+            }
+            break;
+        }
+    }
+
+    let mut test_argv: Vec<&wstr> = vec![cmd];
+    test_argv.extend_from_slice(&argv[optind..]);
+    if super::test::test(parser, streams, &mut test_argv).is_ok() {
+        return Ok(SUCCESS);
+    }
+
+    parser.libdata_mut().assertion_failures += 1;
+    let condition: Vec<WString> = argv[optind..].iter().map(|arg| escape(arg)).collect();
+    err_fmt!(
+        "%s: %s",
+        message.unwrap_or(wgettext!("assertion failed")),
+        join_strings(&condition, ' ')
+    )
+    .cmd(cmd)
+    .stacktrace(parser)
+    .finish(streams);
+    Err(STATUS_CMD_ERROR)
+}
@@ -17,6 +17,9 @@
 
 const DEFAULT_BIND_MODE: &wstr = L!("default");
 
+/// Long-only option for --fallback.
+const FALLBACK_OPTION_CHAR: char = '\x01';
+
 enum BindMode {
     Insert,
     Erase,
@@ -36,6 +39,7 @@ struct Options {
     mode: BindMode,
     bind_mode: Option<WString>,
     sets_bind_mode: Option<WString>,
+    fallback: bool,
     color: ColorEnabled,
 }
 
@@ -53,6 +57,7 @@ fn new() -> Options {
             mode: BindMode::Insert,
             bind_mode: None,
             sets_bind_mode: None,
+            fallback: false,
             color: ColorEnabled::default(),
         }
     }
@@ -73,8 +78,8 @@ fn new() -> BuiltinBind {
         }
     }
 
-    /// Returns a WString for the output line of a bind
-    fn generate_output_string(seq: &[Key], user: bool, bind: &Binding) -> WString {
+    /// Returns the start of the output line of a bind, up to the keys.
+    fn generate_output_prefix(user: bool, bind: &Binding) -> WString {
         let mut out = WString::new();
 
         out.push_str("bind");
@@ -94,6 +99,24 @@ fn generate_output_string(seq: &[Key], user: bool, bind: &Binding) -> WString {
                 out.push_utfstr(&escape(sets_mode));
             }
         }
+        out
+    }
+
+    /// Returns a WString for the output line of a fallback bind.
+    fn generate_fallback_output_string(user: bool, bind: &Binding) -> WString {
+        let mut out = Self::generate_output_prefix(user, bind);
+        out.push_str(" --fallback");
+        for ecmd in &bind.commands {
+            out.push(' ');
+            out.push_utfstr(&escape(ecmd));
+        }
+        out.push('\n');
+        out
+    }
+
+    /// Returns a WString for the output line of a bind
+    fn generate_output_string(seq: &[Key], user: bool, bind: &Binding) -> WString {
+        let mut out = Self::generate_output_prefix(user, bind);
 
         out.push(' ');
         match bind.key_name_style {
@@ -237,6 +260,32 @@ fn list(
         }
     }
 
+    /// List the fallback bindings.
+    /// Returns false if there are none.
+    ///
+    /// If bind_mode is None, then fallbacks from all modes are listed.
+    fn list_fallbacks(
+        &self,
+        bind_mode: Option<&wstr>,
+        user: bool,
+        parser: &mut Parser,
+        streams: &mut IoStreams,
+    ) -> bool {
+        let fallbacks = self.bindings.get_fallbacks(bind_mode, user);
+        for bind in &fallbacks {
+            let out = Self::generate_fallback_output_string(user, bind);
+            if self.opts.color.enabled(streams) {
+                streams.out.append(&bytes2wcstring(&highlight_and_colorize(
+                    &out,
+                    &mut parser.context(),
+                )));
+            } else {
+                streams.out.append(&out);
+            }
+        }
+        !fallbacks.is_empty()
+    }
+
     /// Print all named keys to the string buffer used for standard output.
     fn key_names(&self, streams: &mut IoStreams) {
         let function_keys: Vec<WString> = (1..=MAX_FUNCTION_KEY)
@@ -323,6 +372,11 @@ fn erase(&mut self, seq: &[&wstr], all: bool, user: bool, streams: &mut IoStream
 
         let bind_mode = bind_mode.unwrap_or(DEFAULT_BIND_MODE);
 
+        if self.opts.fallback {
+            self.bindings.erase_fallback(bind_mode, user);
+            return false;
+        }
+
         for s in seq {
             let Some(s) = self.compute_seq(streams, s) else {
                 return true;
@@ -342,7 +396,13 @@ fn insert(
         let argc = argv.len();
         let cmd = argv[0];
         let arg_count = argc - optind;
-        if arg_count < 2 {
+        // A fallback binding has no key sequence, so any argument is a command.
+        let listing = if self.opts.fallback {
+            arg_count == 0
+        } else {
+            arg_count < 2
+        };
+        if listing {
             // If we get both or neither preset/user, we list both.
             if !self.opts.have_preset && !self.opts.have_user {
                 self.opts.preset = true;
@@ -358,15 +418,39 @@ fn insert(
             }
         }
 
+        if self.opts.fallback {
+            let bind_mode = self.opts.bind_mode.as_deref();
+            if arg_count == 0 {
+                let mut found = false;
+                if self.opts.preset {
+                    found |= self.list_fallbacks(bind_mode, false, parser, streams);
+                }
+                if self.opts.user {
+                    found |= self.list_fallbacks(bind_mode, true, parser, streams);
+                }
+                return !found;
+            }
+            self.bindings.set_fallback(
+                argv[optind..].iter().map(|&s| s.to_owned()).collect(),
+                bind_mode.unwrap_or(DEFAULT_BIND_MODE).to_owned(),
+                self.opts.sets_bind_mode.clone(),
+                self.opts.user,
+                parser.current_filename(),
+            );
+            return false;
+        }
+
         if arg_count == 0 {
             // We don't overload this with user and def because we want them to be grouped.
             // First the presets, then the users (because of scrolling).
             let bind_mode = self.opts.bind_mode.as_deref();
             if self.opts.preset {
                 self.list(bind_mode, false, parser, streams);
+                self.list_fallbacks(bind_mode, false, parser, streams);
             }
             if self.opts.user {
                 self.list(bind_mode, true, parser, streams);
+                self.list_fallbacks(bind_mode, true, parser, streams);
             }
         } else if arg_count == 1 {
             let Some(seq) = self.compute_seq(streams, argv[optind]) else {
@@ -451,6 +535,7 @@ fn parse_cmd_opts(
     let long_options: &[WOption] = &[
         wopt(L!("all"), NoArgument, 'a'),
         wopt(L!("erase"), NoArgument, 'e'),
+        wopt(L!("fallback"), NoArgument, FALLBACK_OPTION_CHAR),
         wopt(L!("function-names"), NoArgument, 'f'),
         wopt(L!("help"), NoArgument, 'h'),
         wopt(L!("key"), NoArgument, 'k'),
@@ -483,6 +568,7 @@ fn parse_cmd_opts(
         match c {
             'a' => opts.all = true,
             'e' => opts.mode = BindMode::Erase,
+            FALLBACK_OPTION_CHAR => opts.fallback = true,
             'f' => opts.mode = BindMode::FunctionNames,
             'h' => opts.print_help = true,
             'k' => {
@@ -568,6 +654,21 @@ pub fn bind(
             self.opts.user = true;
         }
 
+        // Bindings run their commands later on their own, so restricted code may not change them.
+        let arg_count = argv.len() - optind;
+        let changes_bindings = match self.opts.mode {
+            BindMode::Erase => true,
+            BindMode::Insert if self.opts.fallback => arg_count > 0,
+            BindMode::Insert => arg_count >= 2,
+            BindMode::KeyNames | BindMode::FunctionNames => false,
+        };
+        if changes_bindings && parser.is_restricted() {
+            err_str!("changing bindings is not allowed in restricted mode")
+                .cmd(cmd)
+                .finish(streams);
+            return Err(STATUS_RESTRICTED);
+        }
+
         match self.opts.mode {
             BindMode::Erase => {
                 // If we get both, we erase both.
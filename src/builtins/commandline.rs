@@ -268,6 +268,7 @@ pub fn commandline(
     let mut search_field_mode = false;
     let mut is_valid = false;
     let mut showing_suggestion = false;
+    let mut key_event_mode = false;
 
     let mut override_buffer = None;
     let mut forward_jump = false;
@@ -309,6 +310,7 @@ pub fn commandline(
         wopt(L!("backward-jump"), ArgType::RequiredArgument, '\x08'),
         wopt(L!("forward-jump-till"), ArgType::RequiredArgument, '\x09'),
         wopt(L!("backward-jump-till"), ArgType::RequiredArgument, '\x0a'),
+        wopt(L!("key-event"), ArgType::NoArgument, '\x0b'),
     ];
 
     let mut w = WGetopter::new(short_options, long_options, args);
@@ -359,6 +361,7 @@ pub fn commandline(
             '\x03' => search_field_mode = true,
             '\x01' => is_valid = true,
             '\x04' => showing_suggestion = true,
+            '\x0b' => key_event_mode = true,
             '\x07' => {
                 forward_jump = true;
                 jump_target = Some(w.woptarg.unwrap().to_owned());
@@ -654,6 +657,26 @@ pub fn commandline(
         };
     }
 
+    if key_event_mode {
+        let Some(key_event) = rstate.key_event else {
+            return Err(STATUS_CMD_ERROR);
+        };
+        let modifiers = key_event.modifiers;
+        let modifier_names: Vec<&wstr> = [
+            (modifiers.sup, L!("super")),
+            (modifiers.ctrl, L!("ctrl")),
+            (modifiers.alt, L!("alt")),
+            (modifiers.shift, L!("shift")),
+        ]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+        .collect();
+        streams.out.appendln(&WString::from(key_event.key));
+        streams.out.appendln(&join_strings(&modifier_names, '-'));
+        streams.out.appendln(key_event.kind.name());
+        return Ok(SUCCESS);
+    }
+
     if selection_start_mode {
         let Some(selection) = rstate.selection else {
             return Err(STATUS_CMD_ERROR);
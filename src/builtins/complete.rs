@@ -3,8 +3,8 @@
     builtins::Error,
     complete::{
         CompleteFlags, CompleteOptionType, CompletionMode, CompletionRequestOptions, complete_add,
-        complete_add_wrapper, complete_print, complete_remove, complete_remove_all,
-        complete_remove_wrapper,
+        complete_add_from_argparse, complete_add_wrapper, complete_print, complete_remove,
+        complete_remove_all, complete_remove_wrapper, complete_set_cache_ttl,
     },
     err_fmt, err_raw, err_str,
     highlight::highlight_and_colorize,
@@ -17,6 +17,7 @@
 use fish_common::{UnescapeFlags, UnescapeStringStyle, unescape_string};
 use fish_wcstringutil::string_suffixes_string;
 use fish_widestring::bytes2wcstring;
+use std::time::Duration;
 
 // builtin_complete_* are a set of rather silly looping functions that make sure that all the proper
 // combinations of complete_add or complete_remove get called. This is needed since complete allows
@@ -239,6 +240,8 @@ fn builtin_complete_print(
 
 /// Values used for long-only options.
 const OPT_ESCAPE: char = '\x01';
+const OPT_CACHE_TTL: char = '\x02';
+const OPT_FROM_ARGPARSE: char = '\x03';
 
 /// The complete builtin. Used for specifying programmable tab-completions. Calls the functions in
 /// complete.rs for any heavy lifting.
@@ -266,6 +269,8 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
     let mut preserve_order = false;
     let mut unescape_output = true;
     let mut color = ColorEnabled::default();
+    let mut cache_ttl = None;
+    let mut from_argparse = vec![];
 
     let short_options: &wstr = L!("a:c:p:s:l:o:d:fFrxeuAn:C::w:hk");
     let long_options: &[WOption] = &[
@@ -289,6 +294,12 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
         wopt(L!("help"), ArgType::NoArgument, 'h'),
         wopt(L!("keep-order"), ArgType::NoArgument, 'k'),
         wopt(L!("escape"), ArgType::NoArgument, OPT_ESCAPE),
+        wopt(L!("cache-ttl"), ArgType::RequiredArgument, OPT_CACHE_TTL),
+        wopt(
+            L!("from-argparse"),
+            ArgType::RequiredArgument,
+            OPT_FROM_ARGPARSE,
+        ),
         wopt(L!("color"), ArgType::RequiredArgument, COLOR_OPTION_CHAR),
     ];
 
@@ -382,6 +393,16 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
             OPT_ESCAPE => {
                 unescape_output = false;
             }
+            OPT_CACHE_TTL => match fish_wcstoul(w.woptarg.unwrap()) {
+                Ok(secs) => cache_ttl = Some(Duration::from_secs(secs)),
+                Err(_) => {
+                    err_fmt!(Error::NOT_NUMBER, w.woptarg.unwrap())
+                        .cmd(cmd)
+                        .finish(streams);
+                    return Err(STATUS_INVALID_ARGS);
+                }
+            },
+            OPT_FROM_ARGPARSE => from_argparse.push(w.woptarg.unwrap().to_owned()),
             'h' => {
                 builtin_print_help(parser, streams, cmd);
                 return Ok(SUCCESS);
@@ -426,6 +447,16 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
         return Err(STATUS_INVALID_ARGS);
     }
 
+    if remove && cache_ttl.is_some() {
+        err_fmt!(
+            Error::INVALID_OPT_COMBO_WITH_CTX,
+            "'--erase' and '--cache-ttl'"
+        )
+        .cmd(cmd)
+        .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+
     if w.wopt_index != argc {
         // Use one left-over arg as the do-complete argument
         // to enable `complete -C "git check"`.
@@ -471,6 +502,29 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
         }
     }
 
+    let defines_option = !gnu_opt.is_empty()
+        || !short_opt.is_empty()
+        || !old_opt.is_empty()
+        || !comp.is_empty()
+        || !desc.is_empty()
+        || !condition.is_empty()
+        || result_mode != CompletionMode::default();
+
+    // Completions run their arguments and conditions later on their own, so restricted code may
+    // not change them.
+    let changes_completions = !path.is_empty()
+        || remove
+        || defines_option
+        || !wrap_targets.is_empty()
+        || cache_ttl.is_some()
+        || !from_argparse.is_empty();
+    if !do_complete && changes_completions && parser.is_restricted() {
+        err_str!("changing completions is not allowed in restricted mode")
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_RESTRICTED);
+    }
+
     if do_complete {
         let have_do_complete_param = do_complete_param.is_some();
         let do_complete_param = match do_complete_param {
@@ -560,19 +614,7 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
 
             parser.libdata_mut().builtin_complete_current_commandline = false;
         }
-    } else if path.is_empty()
-        && gnu_opt.is_empty()
-        && short_opt.is_empty()
-        && old_opt.is_empty()
-        && !remove
-        && comp.is_empty()
-        && desc.is_empty()
-        && condition.is_empty()
-        && wrap_targets.is_empty()
-        && !result_mode.no_files
-        && !result_mode.force_files
-        && !result_mode.requires_param
-    {
+    } else if !changes_completions {
         // No arguments that would add or remove anything specified, so we print the definitions of
         // all matching completions.
         if cmd_to_complete.is_empty() {
@@ -601,24 +643,40 @@ pub fn complete(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]
                 &wrap_targets,
             );
         } else {
-            builtin_complete_add(
-                &cmd_to_complete,
-                &path,
-                &short_opt,
-                &gnu_opt,
-                &old_opt,
-                result_mode,
-                &condition,
-                &comp,
-                &desc,
-                flags,
-            );
+            // A bare `--cache-ttl` only changes the caching of existing completions.
+            if defines_option || cache_ttl.is_none() {
+                builtin_complete_add(
+                    &cmd_to_complete,
+                    &path,
+                    &short_opt,
+                    &gnu_opt,
+                    &old_opt,
+                    result_mode,
+                    &condition,
+                    &comp,
+                    &desc,
+                    flags,
+                );
+            }
+            if let Some(ttl) = cache_ttl {
+                // A TTL of zero disables caching.
+                let ttl = (!ttl.is_zero()).then_some(ttl);
+                for cmd in &cmd_to_complete {
+                    complete_set_cache_ttl(cmd.clone(), false, ttl);
+                }
+                for path in &path {
+                    complete_set_cache_ttl(path.clone(), true, ttl);
+                }
+            }
             // Handle wrap targets (probably empty). We only wrap commands, not paths.
             for wrap_target in wrap_targets {
                 for i in &cmd_to_complete {
                     complete_add_wrapper(i.clone(), wrap_target.clone());
                 }
             }
+            for function in from_argparse {
+                complete_add_from_argparse(function);
+            }
         }
     }
 
@@ -1,18 +1,48 @@
 //! The eval builtin.
 
 use super::prelude::*;
+use crate::err_str;
 use crate::io::IoBufferfill;
 use crate::parser::BlockType;
 use fish_wcstringutil::join_strings;
 use libc::{STDERR_FILENO, STDOUT_FILENO};
 
 pub fn eval(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) -> BuiltinResult {
-    let argc = args.len();
-    if argc <= 1 {
+    // We only recognize the restriction options, anything else is part of the command.
+    let mut restricted = false;
+    let mut allowed = vec![];
+    let mut optind = 1;
+    while let Some(&arg) = args.get(optind) {
+        if arg == "--restricted" {
+            restricted = true;
+            optind += 1;
+        } else if arg == "--allow-command" && optind + 1 < args.len() {
+            allowed.push(args[optind + 1].to_owned());
+            optind += 2;
+        } else if arg.starts_with("--allow-command=") {
+            allowed.push(arg.slice_from("--allow-command=".len()).to_owned());
+            optind += 1;
+        } else {
+            if arg == "--" && optind > 1 {
+                optind += 1;
+            }
+            break;
+        }
+    }
+    if !allowed.is_empty() && !restricted {
+        err_str!("--allow-command requires --restricted")
+            .cmd(args[0])
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+
+    if args.len() <= optind {
         return Ok(SUCCESS);
     }
 
-    let new_cmd = join_strings(&args[1..], ' ');
+    let new_cmd = join_strings(&args[optind..], ' ');
+    let _restricted = restricted.then(|| parser.push_restriction(allowed));
 
     // Copy the full io chain; we may append bufferfills.
     let mut ios = streams.io_chain.clone();
@@ -23,6 +23,7 @@
 };
 use assert_matches::assert_matches;
 use fish_common::{ReadExt as _, UnescapeFlags, UnescapeStringStyle, unescape_string};
+use fish_fallback::fish_wcswidth;
 use fish_wcstringutil::count_preceding_backslashes;
 use fish_wgetopt::{ArgType, WGetopter, WOption, wopt};
 use fish_widestring::{INTERNAL_SEPARATOR, bytes2wcstring, osstr2wcstring, wcs2bytes};
@@ -31,6 +32,7 @@
     fmt::Write as _,
     fs,
     io::{Read, Write as _},
+    ops::{Range, RangeInclusive},
     os::unix::ffi::OsStrExt as _,
 };
 
@@ -973,6 +975,7 @@ enum OutputType {
         PygmentsCsv,
         Check,
         Html,
+        AstJson,
     }
 
     let mut output_type = OutputType::PlainText;
@@ -980,6 +983,8 @@ enum OutputType {
     let mut do_indent = true;
     let mut only_indent = false;
     let mut only_unindent = false;
+    let mut max_width = None;
+    let mut lines = None;
 
     let short_opts: &wstr = L!("+hvwic");
     let long_opts: &[WOption] = &[
@@ -994,6 +999,9 @@ enum OutputType {
         wopt(L!("ansi"), ArgType::NoArgument, '\x02'),
         wopt(L!("pygments"), ArgType::NoArgument, '\x03'),
         wopt(L!("check"), ArgType::NoArgument, 'c'),
+        wopt(L!("max-width"), ArgType::RequiredArgument, '\x06'),
+        wopt(L!("dump-ast"), ArgType::RequiredArgument, '\x07'),
+        wopt(L!("lines"), ArgType::RequiredArgument, '\x08'),
     ];
 
     let mut shim_args: Vec<&wstr> = args.iter().map(|s| s.as_ref()).collect();
@@ -1024,6 +1032,44 @@ enum OutputType {
             '\x02' => output_type = OutputType::Ansi,
             '\x03' => output_type = OutputType::PygmentsCsv,
             'c' => output_type = OutputType::Check,
+            '\x06' => {
+                let arg = w.woptarg.unwrap();
+                max_width = match fish_wcstoi(arg) {
+                    Ok(n) if n > 0 => Some(usize::try_from(n).unwrap()),
+                    _ => {
+                        err_fmt!(Error::NOT_NUMBER, arg)
+                            .cmd(L!("fish_indent"))
+                            .finish(streams);
+                        return Err(STATUS_CMD_ERROR);
+                    }
+                };
+            }
+            '\x07' => {
+                let arg = w.woptarg.unwrap();
+                if arg != "json" {
+                    err_fmt!("%s: invalid AST format", arg)
+                        .cmd(L!("fish_indent"))
+                        .finish(streams);
+                    return Err(STATUS_CMD_ERROR);
+                }
+                output_type = OutputType::AstJson;
+            }
+            '\x08' => {
+                let arg = w.woptarg.unwrap();
+                let Some(range) = parse_line_range(arg) else {
+                    err_fmt!("%s: invalid line range", arg)
+                        .cmd(L!("fish_indent"))
+                        .finish(streams);
+                    return Err(STATUS_CMD_ERROR);
+                };
+                lines = Some(range);
+            }
+            ':' => {
+                err_fmt!(Error::MISSING_OPT_ARG, w.argv[w.wopt_index - 1])
+                    .cmd(L!("fish_indent"))
+                    .finish(streams);
+                return Err(STATUS_CMD_ERROR);
+            }
             ';' => {
                 err_fmt!(Error::UNEXP_OPT_ARG, w.argv[w.wopt_index - 1])
                     .cmd(L!("fish_indent"))
@@ -1042,6 +1088,13 @@ enum OutputType {
 
     let args = &w.argv[w.wopt_index..];
 
+    if output_type == OutputType::File && parser.is_some_and(|parser| parser.is_restricted()) {
+        err_str!("writing files is not allowed in restricted mode")
+            .cmd(L!("fish_indent"))
+            .finish(streams);
+        return Err(STATUS_RESTRICTED);
+    }
+
     let mut retval = 0;
 
     let mut src;
@@ -1095,6 +1148,13 @@ enum OutputType {
             continue;
         }
 
+        if output_type == OutputType::AstJson {
+            let ast = ast::parse(&src, parse_flags(), None);
+            streams.out.append(&ast.dump_json());
+            i += 1;
+            continue;
+        }
+
         let output_wtext = if only_indent || only_unindent {
             let indents = compute_indents(&src);
             if only_indent {
@@ -1132,7 +1192,7 @@ enum OutputType {
                 }
             }
         } else {
-            prettify(streams, &src, do_indent)
+            prettify(streams, &src, do_indent, max_width, lines.clone())
         };
 
         // Maybe colorize.
@@ -1176,7 +1236,7 @@ enum OutputType {
             OutputType::Html => {
                 colored_output = html_colorize(&output_wtext, &colors);
             }
-            OutputType::PygmentsCsv => {
+            OutputType::PygmentsCsv | OutputType::AstJson => {
                 unreachable!()
             }
             OutputType::Check => {
@@ -1262,7 +1322,13 @@ struct TokenRange {
 }
 
 // Entry point for prettification.
-fn prettify(streams: &mut IoStreams, src: &wstr, do_indent: bool) -> WString {
+fn prettify(
+    streams: &mut IoStreams,
+    src: &wstr,
+    do_indent: bool,
+    max_width: Option<usize>,
+    lines: Option<RangeInclusive<usize>>,
+) -> WString {
     if DUMP_PARSE_TREE.load() {
         let flags = ParseTreeFlags {
             leave_unterminated: true,
@@ -1279,11 +1345,310 @@ fn prettify(streams: &mut IoStreams, src: &wstr, do_indent: bool) -> WString {
         metrics.visit(ast.top());
         streams.err.appendln(&format!("{}", metrics));
     }
+    match lines {
+        Some(lines) => prettify_lines(src, do_indent, max_width, lines),
+        None => prettify_source(src, do_indent, max_width),
+    }
+}
+
+fn prettify_source(src: &wstr, do_indent: bool, max_width: Option<usize>) -> WString {
+    let mut output = prettify_once(src, do_indent);
+    if let Some(max_width) = max_width {
+        // Breaking a line changes the indentation of the lines after it, so prettify again and
+        // repeat until there is nothing left to break. Breaks are never removed, so this ends.
+        while let Some(broken) = break_long_lines(&output, max_width) {
+            let reprettified = prettify_once(&broken, do_indent);
+            if reprettified == output {
+                break;
+            }
+            output = reprettified;
+        }
+    }
+    output
+}
+
+/// Parse a 1-based, inclusive line range like `3:10`.
+fn parse_line_range(arg: &wstr) -> Option<RangeInclusive<usize>> {
+    let colon = arg.find_char(':')?;
+    let parse = |s: &wstr| {
+        fish_wcstoi(s)
+            .ok()
+            .filter(|&n| n > 0)
+            .map(|n| usize::try_from(n).unwrap())
+    };
+    let first = parse(arg.slice_to(colon))?;
+    let last = parse(arg.slice_from(colon + 1))?;
+    (first <= last).then_some(first..=last)
+}
+
+/// Prettify only the statements of `src` which overlap the given lines, leaving the rest of it
+/// exactly as it is.
+fn prettify_lines(
+    src: &wstr,
+    do_indent: bool,
+    max_width: Option<usize>,
+    lines: RangeInclusive<usize>,
+) -> WString {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(
+            src.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect();
+    let line_start = |line: usize| line_starts.get(line - 1).copied().unwrap_or(src.len());
+    let selection = line_start(*lines.start())..line_start(*lines.end() + 1);
+
+    let ast = ast::parse(src, parse_flags(), None);
+    let mut statements = SelectedStatements {
+        source: src,
+        selection,
+        regions: vec![],
+    };
+    statements.visit(ast.top());
+
+    let indents = compute_indents(src);
+    let mut output = WString::new();
+    let mut copied_to = 0;
+    for (region, statement_start) in statements.regions {
+        if region.start < copied_to {
+            continue;
+        }
+        output.push_utfstr(&src[copied_to..region.start]);
+        copied_to = region.end;
+
+        // Put the statements into enough blocks to be indented like in their context, and take
+        // them out again afterwards.
+        let level = if do_indent {
+            usize::try_from(indents[statement_start]).unwrap()
+        } else {
+            0
+        };
+        let mut snippet = WString::new();
+        for _ in 0..level {
+            snippet.push_str("begin\n");
+        }
+        snippet.push_utfstr(&src[region.clone()]);
+        if !snippet.ends_with('\n') {
+            snippet.push('\n');
+        }
+        for _ in 0..level {
+            snippet.push_str("end\n");
+        }
+        let pretty = prettify_source(&snippet, do_indent, max_width);
+        let pretty_lines: Vec<&wstr> = pretty.split('\n').collect();
+        // The output ends in a newline, so the last line is empty.
+        for line in &pretty_lines[level..pretty_lines.len() - 1 - level] {
+            output.push_utfstr(line);
+            output.push('\n');
+        }
+        if !src[region].ends_with('\n') {
+            output.pop();
+        }
+    }
+    output.push_utfstr(&src[copied_to..]);
+    output
+}
+
+/// Finds the statements for [`prettify_lines`]: runs of jobs in a job list which overlap the
+/// selection. Blocks that only partly overlap it are searched for smaller statements first.
+struct SelectedStatements<'s> {
+    source: &'s wstr,
+    selection: Range<usize>,
+    /// The whole lines to prettify, and where their first statement starts.
+    regions: Vec<(Range<usize>, usize)>,
+}
+
+impl<'s> SelectedStatements<'s> {
+    /// Record the lines containing a run of statements, unless they contain other code too.
+    fn add_run(&mut self, run: Option<Range<usize>>) {
+        let Some(run) = run else {
+            return;
+        };
+        let chars = self.source.as_char_slice();
+        let start = chars[..run.start]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map_or(0, |i| i + 1);
+        let end = if run.end > 0 && chars[run.end - 1] == '\n' {
+            run.end
+        } else {
+            chars[run.end..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map_or(chars.len(), |i| run.end + i + 1)
+        };
+        let before_is_blank = chars[start..run.start]
+            .iter()
+            .all(|&c| c == ' ' || c == '\t');
+        let after_is_blank_or_comment = chars[run.end..end]
+            .iter()
+            .find(|c| !c.is_whitespace())
+            .is_none_or(|&c| c == '#');
+        if before_is_blank && after_is_blank_or_comment {
+            self.regions.push((start..end, run.start));
+        }
+    }
+}
+
+impl<'a, 's> NodeVisitor<'a> for SelectedStatements<'s> {
+    fn visit(&mut self, node: &'a dyn Node) {
+        let Kind::JobList(jobs) = node.kind() else {
+            node.accept(self);
+            return;
+        };
+        let mut run: Option<Range<usize>> = None;
+        for job in jobs {
+            let Some(range) = job.try_source_range() else {
+                self.add_run(run.take());
+                continue;
+            };
+            let range = range.start()..range.end();
+            if range.start >= self.selection.end || range.end <= self.selection.start {
+                self.add_run(run.take());
+                continue;
+            }
+            if range.start < self.selection.start || range.end > self.selection.end {
+                // Prefer the statements inside a block to the whole block.
+                let mut inner = SelectedStatements {
+                    source: self.source,
+                    selection: self.selection.clone(),
+                    regions: vec![],
+                };
+                job.as_node().accept(&mut inner);
+                if !inner.regions.is_empty() {
+                    self.add_run(run.take());
+                    self.regions.extend(inner.regions);
+                    continue;
+                }
+            }
+            run = Some(match run {
+                Some(run) => run.start..range.end,
+                None => range,
+            });
+        }
+        self.add_run(run);
+    }
+}
+
+fn prettify_once(src: &wstr, do_indent: bool) -> WString {
     let ast = ast::parse(src, parse_flags(), None);
     let mut printer = PrettyPrinter::new(src, &ast, do_indent);
     printer.prettify()
 }
 
+/// The places where a long line may be broken, from most to least preferred.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum LineBreak {
+    /// Before an `and` or `or` job following a semicolon, which becomes a newline.
+    AndOrJob,
+    /// Before `&&` or `||`.
+    Conjunction,
+    /// Before a pipe.
+    Pipe,
+    /// Before an argument.
+    Argument,
+}
+
+/// Insert line breaks into the lines of prettified `src` which are wider than `max_width`, or
+/// return None if there are none to insert. A line is broken at all of its `; and`, `&&` or `|`,
+/// in that order of preference, or else before the last argument that still fits.
+/// The result needs to be prettified again to indent the new lines.
+fn break_long_lines(src: &wstr, max_width: usize) -> Option<WString> {
+    let ast = ast::parse(src, parse_flags(), None);
+    if ast.errored() {
+        return None;
+    }
+
+    // Only break where the pretty printer keeps escaped newlines (or newlines).
+    let mut candidates: Vec<(usize, LineBreak)> = vec![];
+    for node in Traversal::new(ast.top()) {
+        let (range, kind) = match node.kind() {
+            Kind::Argument(arg) => (arg.range(), LineBreak::Argument),
+            Kind::Token(token) => match token.token_type() {
+                ParseTokenType::Pipe => (token.range(), LineBreak::Pipe),
+                ParseTokenType::AndAnd | ParseTokenType::OrOr => {
+                    (token.range(), LineBreak::Conjunction)
+                }
+                _ => continue,
+            },
+            Kind::JobConjunction(conj) => {
+                let Some(decorator) = conj.decorator.as_ref() else {
+                    continue;
+                };
+                (decorator.range(), LineBreak::AndOrJob)
+            }
+            _ => continue,
+        };
+        let Some(range) = range else {
+            continue;
+        };
+        if kind == LineBreak::AndOrJob
+            && src[..range.start()].chars().rev().find(|&c| c != ' ') != Some(';')
+        {
+            continue;
+        }
+        candidates.push((range.start(), kind));
+    }
+    candidates.sort_unstable();
+
+    let width = |s: &wstr| fish_wcswidth(s).unwrap_or(s.len());
+    let trim_end = |s: &wstr| {
+        let spaces = s.chars().rev().take_while(|&c| c == ' ').count();
+        s.slice_to(s.len() - spaces)
+    };
+
+    let mut breaks = vec![];
+    let mut line_start = 0;
+    for line in src.split('\n') {
+        let line_end = line_start + line.len();
+        if width(line) > max_width {
+            // Breaking before the first token of a line would not help.
+            let content_start = line_start + line.chars().take_while(|&c| c == ' ').count();
+            let first = candidates.partition_point(|&(pos, _)| pos <= content_start);
+            let last = candidates.partition_point(|&(pos, _)| pos < line_end);
+            let on_line = &candidates[first..last];
+            match on_line.iter().map(|&(_, kind)| kind).min() {
+                None => (),
+                Some(LineBreak::Argument) => {
+                    // The broken line ends in " \".
+                    let fits = |pos: usize| width(trim_end(&src[line_start..pos])) + 2 <= max_width;
+                    let pos = on_line
+                        .iter()
+                        .map(|&(pos, _)| pos)
+                        .take_while(|&pos| fits(pos))
+                        .last()
+                        .unwrap_or(on_line[0].0);
+                    breaks.push((pos, LineBreak::Argument));
+                }
+                Some(best) => breaks.extend(on_line.iter().filter(|&&(_, kind)| kind == best)),
+            }
+        }
+        line_start = line_end + 1;
+    }
+    if breaks.is_empty() {
+        return None;
+    }
+
+    let mut result = src.to_owned();
+    for &(pos, kind) in breaks.iter().rev() {
+        let start = pos
+            - result[..pos]
+                .chars()
+                .rev()
+                .take_while(|&c| c == ' ')
+                .count();
+        if kind == LineBreak::AndOrJob {
+            // Replace the semicolon.
+            result.replace_range(start - 1..pos, L!("\n"));
+        } else {
+            result.replace_range(start..pos, L!(" \\\n"));
+        }
+    }
+    Some(result)
+}
+
 /// Given a string and list of colors of the same size, return the string with HTML span elements
 /// for the various colors.
 fn html_class_name_for_color(spec: HighlightSpec) -> &'static wstr {
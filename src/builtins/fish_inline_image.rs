@@ -0,0 +1,347 @@
+//! Implementation of the fish_inline_image builtin.
+
+use std::io::Read as _;
+
+use super::prelude::*;
+use crate::{
+    builtins::Error,
+    err_fmt,
+    fds::wopen_cloexec,
+    screen::is_dumb,
+    tty_handoff::{get_cell_size, get_kitty_graphics_capability, get_sixel_capability},
+};
+use fish_widestring::bytes2wcstring;
+use nix::{fcntl::OFlag, sys::stat::Mode};
+
+const SHORT_OPTIONS: &wstr = L!("w:qh");
+const LONG_OPTIONS: &[WOption] = &[
+    wopt(L!("width"), RequiredArgument, 'w'),
+    wopt(L!("query"), NoArgument, 'q'),
+    wopt(L!("help"), NoArgument, 'h'),
+];
+
+/// The number of cells a PNG image occupies if no width is given.
+const DEFAULT_WIDTH: usize = 2;
+
+/// The largest payload the kitty graphics protocol accepts in a single escape sequence.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ImageProtocol {
+    Kitty,
+    Sixel,
+}
+
+impl ImageProtocol {
+    fn name(self) -> &'static wstr {
+        match self {
+            ImageProtocol::Kitty => L!("kitty"),
+            ImageProtocol::Sixel => L!("sixel"),
+        }
+    }
+
+    fn is_supported(self) -> bool {
+        if is_dumb() {
+            return false;
+        }
+        let supported = match self {
+            ImageProtocol::Kitty => get_kitty_graphics_capability(),
+            ImageProtocol::Sixel => get_sixel_capability(),
+        };
+        supported == Some(true)
+    }
+
+    /// The protocol that can display an image file, based on its contents.
+    fn for_image(data: &[u8]) -> Option<Self> {
+        if data.starts_with(PNG_SIGNATURE) {
+            Some(ImageProtocol::Kitty)
+        } else if data.starts_with(b"\x1bP") {
+            Some(ImageProtocol::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+fn base64_encode(data: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize]);
+            } else {
+                result.push(b'=');
+            }
+        }
+    }
+    result
+}
+
+/// Transmit and display a PNG image, scaled to `width` cells of a single row, without moving the
+/// cursor. Responses are suppressed so nothing ends up in the input queue.
+fn kitty_graphics_image(png: &[u8], width: usize) -> Vec<u8> {
+    let payload = base64_encode(png);
+    let mut result = vec![];
+    let mut chunks = payload.chunks(KITTY_CHUNK_SIZE).peekable();
+    let mut first = true;
+    while let Some(chunk) = chunks.next() {
+        let more = u8::from(chunks.peek().is_some());
+        result.extend_from_slice(b"\x1b_G");
+        if first {
+            result.extend_from_slice(format!("a=T,f=100,c={width},r=1,C=1,q=2,").as_bytes());
+            first = false;
+        }
+        result.extend_from_slice(format!("m={more};").as_bytes());
+        result.extend_from_slice(chunk);
+        result.extend_from_slice(b"\x1b\\");
+    }
+    result
+}
+
+/// Return the width and height in pixels that a sixel image declares in its raster attributes,
+/// `"Pan;Pad;Ph;Pv` right after the introducer, if it does.
+fn sixel_size(sixel: &[u8]) -> Option<(usize, usize)> {
+    let data = sixel.strip_prefix(b"\x1bP")?;
+    let start = data.iter().position(|&c| c == b'q')? + 1;
+    let raster = data[start..].strip_prefix(b"\"")?;
+    let end = raster
+        .iter()
+        .position(|&c| !c.is_ascii_digit() && c != b';')
+        .unwrap_or(raster.len());
+    let params: Vec<usize> = raster[..end]
+        .split(|&c| c == b';')
+        .map(|param| std::str::from_utf8(param).ok()?.parse().ok())
+        .collect::<Option<_>>()?;
+    match params[..] {
+        [_, _, width, height] if width > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+/// Return the number of cells a sixel image of `width_px` pixels needs, with cells
+/// `cell_width_px` pixels wide.
+fn sixel_cells(width_px: usize, cell_width_px: usize) -> usize {
+    width_px.div_ceil(cell_width_px)
+}
+
+/// Strip line breaks, which are meaningless inside sixel data but would split the sequence
+/// when it is captured by a command substitution.
+fn sixel_image(sixel: &[u8]) -> Vec<u8> {
+    let mut result: Vec<u8> = sixel
+        .iter()
+        .copied()
+        .filter(|&c| c != b'\n' && c != b'\r')
+        .collect();
+    if !result.ends_with(b"\x1b\\") {
+        result.extend_from_slice(b"\x1b\\");
+    }
+    result
+}
+
+/// Return the escape sequences to draw an image into the next `width` cells.
+fn inline_image(protocol: ImageProtocol, data: &[u8], width: usize) -> Vec<u8> {
+    let mut result = vec![];
+    // Reserve the cells first, so the prompt's width is computed correctly and the spaces
+    // can't overwrite the image afterwards. Then go back and draw it from a saved cursor.
+    result.resize(width, b' ');
+    result.extend_from_slice(format!("\x1b[{width}D\x1b7").as_bytes());
+    match protocol {
+        ImageProtocol::Kitty => result.extend(kitty_graphics_image(data, width)),
+        ImageProtocol::Sixel => result.extend(sixel_image(data)),
+    }
+    result.extend_from_slice(format!("\x1b8\x1b[{width}C").as_bytes());
+    result
+}
+
+pub fn fish_inline_image(
+    parser: &mut Parser,
+    streams: &mut IoStreams,
+    argv: &mut [&wstr],
+) -> BuiltinResult {
+    let cmd = argv[0];
+    let mut width = None;
+    let mut query = false;
+    let mut w = WGetopter::new(SHORT_OPTIONS, LONG_OPTIONS, argv);
+    while let Some(opt) = w.next_opt() {
+        match opt {
+            'w' => {
+                let arg = w.woptarg.unwrap();
+                width = match fish_wcstoi(arg) {
+                    Ok(n) if n > 0 => Some(n.try_into().unwrap()),
+                    _ => {
+                        err_fmt!(Error::NOT_NUMBER, arg)
+                            .cmd(cmd)
+                            .full_trailer(parser)
+                            .finish(streams);
+                        return Err(STATUS_INVALID_ARGS);
+                    }
+                };
+            }
+            'q' => query = true,
+            'h' => {
+                builtin_print_help(parser, streams, cmd);
+                return Ok(SUCCESS);
+            }
+            ':' => {
+                builtin_missing_argument(parser, streams, cmd, None, argv[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            ';' => {
+                builtin_unexpected_argument(parser, streams, cmd, argv[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            '?' => {
+                builtin_unknown_option(parser, streams, cmd, argv[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            _ => panic!("unexpected retval from WGetopter"),
+        }
+    }
+
+    let optind = w.wopt_index;
+    let args = &argv[optind..];
+
+    if query {
+        if !args.is_empty() {
+            err_fmt!(Error::UNEXP_ARG_COUNT, 0, args.len())
+                .cmd(cmd)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+        let mut found = false;
+        for protocol in [ImageProtocol::Kitty, ImageProtocol::Sixel] {
+            if protocol.is_supported() {
+                streams.out.appendln(protocol.name());
+                found = true;
+            }
+        }
+        return if found {
+            Ok(SUCCESS)
+        } else {
+            Err(STATUS_CMD_ERROR)
+        };
+    }
+
+    if args.len() != 1 {
+        err_fmt!(Error::UNEXP_ARG_COUNT, 1, args.len())
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+    let path = args[0];
+
+    let mut data = vec![];
+    let read_result = wopen_cloexec(path, OFlag::O_RDONLY, Mode::empty())
+        .map_err(|err| err.desc().to_owned())
+        .and_then(|mut file| file.read_to_end(&mut data).map_err(|err| err.to_string()));
+    if let Err(err) = read_result {
+        err_fmt!("%s: cannot read image: %s", path, err)
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_CMD_ERROR);
+    }
+
+    let Some(protocol) = ImageProtocol::for_image(&data) else {
+        err_fmt!("%s: unsupported image format, expected PNG or sixel", path)
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_CMD_ERROR);
+    };
+
+    // Quietly fail so callers can fall back to text.
+    if !protocol.is_supported() {
+        return Err(STATUS_CMD_ERROR);
+    }
+
+    // Sixel images are drawn at their own size, so reserve as many cells as they cover.
+    let width = match (width, protocol) {
+        (Some(width), _) => width,
+        (None, ImageProtocol::Kitty) => DEFAULT_WIDTH,
+        (None, ImageProtocol::Sixel) => {
+            let cells = sixel_size(&data)
+                .zip(get_cell_size())
+                .map(|((width_px, _), (cell_width_px, _))| sixel_cells(width_px, cell_width_px));
+            let Some(cells) = cells else {
+                err_fmt!(
+                    "%s: cannot tell how many cells the sixel image covers, use --width",
+                    path
+                )
+                .cmd(cmd)
+                .finish(streams);
+                return Err(STATUS_CMD_ERROR);
+            };
+            cells
+        }
+    };
+
+    streams
+        .out
+        .append(&bytes2wcstring(&inline_image(protocol, &data, width)));
+    Ok(SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ImageProtocol, base64_encode, inline_image, kitty_graphics_image, sixel_cells, sixel_size,
+    };
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), b"");
+        assert_eq!(base64_encode(b"f"), b"Zg==");
+        assert_eq!(base64_encode(b"fo"), b"Zm8=");
+        assert_eq!(base64_encode(b"foo"), b"Zm9v");
+        assert_eq!(base64_encode(b"foobar"), b"Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_kitty_graphics_chunks() {
+        assert_eq!(
+            kitty_graphics_image(b"foo", 3),
+            b"\x1b_Ga=T,f=100,c=3,r=1,C=1,q=2,m=0;Zm9v\x1b\\"
+        );
+        // 3072 bytes make exactly one chunk of 4096 base64 characters.
+        let image = kitty_graphics_image(&[0; 3073], 2);
+        let sequences: Vec<_> = image
+            .split(|&c| c == b'\x1b')
+            .filter(|s| s.starts_with(b"_G"))
+            .collect();
+        assert_eq!(sequences.len(), 2);
+        assert!(sequences[0].starts_with(b"_Ga=T,f=100,c=2,r=1,C=1,q=2,m=1;"));
+        assert!(sequences[1].starts_with(b"_Gm=0;"));
+    }
+
+    #[test]
+    fn test_inline_image() {
+        assert_eq!(
+            ImageProtocol::for_image(b"\x89PNG\r\n\x1a\nrest"),
+            Some(ImageProtocol::Kitty)
+        );
+        assert_eq!(
+            ImageProtocol::for_image(b"\x1bPq#0~\x1b\\"),
+            Some(ImageProtocol::Sixel)
+        );
+        assert_eq!(ImageProtocol::for_image(b"GIF89a"), None);
+        assert_eq!(sixel_size(b"\x1bP0;1q\"1;1;20;12#0~\x1b\\"), Some((20, 12)));
+        assert_eq!(sixel_size(b"\x1bPq\"1;1;9;6\n#0~"), Some((9, 6)));
+        assert_eq!(sixel_size(b"\x1bPq#0~\x1b\\"), None);
+        assert_eq!(sixel_size(b"\x1bPq\"1;1;0;6#0~"), None);
+        assert_eq!(sixel_size(b"\x1bPq\"1;1;20#0~"), None);
+        assert_eq!(sixel_cells(20, 10), 2);
+        assert_eq!(sixel_cells(21, 10), 3);
+        assert_eq!(
+            inline_image(ImageProtocol::Sixel, b"\x1bPq\n#0~\n", 2),
+            b"  \x1b[2D\x1b7\x1bPq#0~\x1b\\\x1b8\x1b[2C"
+        );
+    }
+}
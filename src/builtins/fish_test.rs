@@ -0,0 +1,400 @@
+//! Implementation of the fish_test builtin.
+
+use std::{
+    os::fd::OwnedFd,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::prelude::*;
+use crate::{
+    builtins::Error,
+    env::EnvMode,
+    err_fmt,
+    fds::wopen_cloexec,
+    function,
+    io::{IoBufferfill, IoChain},
+    parser::ParserEnvSetMode,
+    wutil::perror_nix,
+};
+use fish_common::escape;
+use fish_widestring::bytes2wcstring;
+use libc::{STDERR_FILENO, STDOUT_FILENO};
+use nix::{fcntl::OFlag, sys::stat::Mode, unistd::fchdir};
+
+const SHORT_OPTIONS: &wstr = L!("f:h");
+const LONG_OPTIONS: &[WOption] = &[
+    wopt(L!("format"), RequiredArgument, 'f'),
+    wopt(L!("help"), NoArgument, 'h'),
+];
+
+/// Test functions are functions whose name starts with this.
+const TEST_PREFIX: &str = "test_";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ReportFormat {
+    Tap,
+    Junit,
+}
+
+/// The result of running a single test function.
+struct TestResult {
+    name: WString,
+    status: i32,
+    failed_assertions: usize,
+    duration: Duration,
+    stdout: WString,
+    stderr: WString,
+}
+
+impl TestResult {
+    fn passed(&self) -> bool {
+        self.status == 0 && self.failed_assertions == 0
+    }
+}
+
+/// The results of all tests from one file.
+struct TestSuite {
+    name: WString,
+    results: Vec<TestResult>,
+}
+
+/// The working directory and functions from before a test or a file, which
+/// pop_sandbox() does not restore.
+struct SavedState {
+    cwd_fd: Option<Arc<OwnedFd>>,
+    functions: Vec<WString>,
+}
+
+impl SavedState {
+    fn save(parser: &Parser) -> Self {
+        SavedState {
+            cwd_fd: parser.libdata().cwd_fd.clone(),
+            functions: function::get_names(true, parser.vars()),
+        }
+    }
+
+    /// Return the functions that were defined since this was saved.
+    fn new_functions(&self, parser: &Parser) -> Vec<WString> {
+        function::get_names(true, parser.vars())
+            .into_iter()
+            .filter(|name| !self.functions.contains(name))
+            .collect()
+    }
+
+    /// Go back to the saved working directory, and remove the functions defined since.
+    fn restore(self, parser: &mut Parser) {
+        for name in self.new_functions(parser) {
+            function::remove(&name);
+        }
+        if let Some(fd) = &self.cwd_fd {
+            if let Err(err) = fchdir(fd) {
+                perror_nix("fchdir", err);
+            }
+        }
+        parser.libdata_mut().cwd_fd = self.cwd_fd;
+    }
+}
+
+/// Run the test function `name` in its own variable scope, with private history and no
+/// universal variable changes, capturing its output.
+fn run_test(parser: &mut Parser, io_chain: &IoChain, name: &wstr) -> Option<TestResult> {
+    let is_repainting = parser.is_repainting();
+    let saved = SavedState::save(parser);
+    parser.vars().push_sandbox();
+    parser.set_one(
+        L!("fish_history"),
+        ParserEnvSetMode::new(EnvMode::GLOBAL),
+        WString::new(),
+    );
+    let failures_before = parser.libdata().assertion_failures;
+
+    let mut ios = io_chain.clone();
+    let fills = IoBufferfill::create_opts(0, STDOUT_FILENO).and_then(|stdout| {
+        IoBufferfill::create_opts(0, STDERR_FILENO).map(|stderr| (stdout, stderr))
+    });
+    let Ok((stdout_fill, stderr_fill)) = fills else {
+        // We were unable to create a pipe, probably fd exhaustion.
+        parser.vars().pop_sandbox(is_repainting);
+        return None;
+    };
+    ios.push(stdout_fill.clone());
+    ios.push(stderr_fill.clone());
+
+    let start = Instant::now();
+    let res = parser.eval(&escape(name), &ios);
+    let duration = start.elapsed();
+
+    // Note it is important that we hold no other references to the bufferfills here - they need
+    // to deallocate to close.
+    ios.clear();
+    let stdout = bytes2wcstring(&IoBufferfill::finish(stdout_fill).newline_serialized());
+    let stderr = bytes2wcstring(&IoBufferfill::finish(stderr_fill).newline_serialized());
+
+    // A test calling `exit` only ends that test.
+    parser.libdata_mut().exit_current_script = false;
+    let failed_assertions = parser.libdata().assertion_failures - failures_before;
+    saved.restore(parser);
+    parser.vars().pop_sandbox(is_repainting);
+
+    Some(TestResult {
+        name: name.to_owned(),
+        status: res.status.status_value(),
+        failed_assertions,
+        duration,
+        stdout,
+        stderr,
+    })
+}
+
+fn run_tests(
+    parser: &mut Parser,
+    io_chain: &IoChain,
+    names: &[WString],
+) -> Result<Vec<TestResult>, ErrorCode> {
+    let mut results = vec![];
+    for name in names {
+        results.push(run_test(parser, io_chain, name).ok_or(STATUS_CMD_ERROR)?);
+    }
+    Ok(results)
+}
+
+/// Source `path` and run the test functions it defines, in the order they were defined.
+fn run_file(
+    parser: &mut Parser,
+    streams: &mut IoStreams,
+    cmd: &wstr,
+    path: &wstr,
+) -> Result<TestSuite, ErrorCode> {
+    if let Err(err) = wopen_cloexec(path, OFlag::O_RDONLY, Mode::empty()) {
+        err_fmt!("%s: cannot read file: %s", path, err.desc())
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_CMD_ERROR);
+    }
+
+    let is_repainting = parser.is_repainting();
+    let saved = SavedState::save(parser);
+    parser.vars().push_sandbox();
+
+    let mut source_cmd = L!("builtin source ").to_owned();
+    source_cmd.push_utfstr(&escape(path));
+    parser.eval(&source_cmd, streams.io_chain);
+
+    let new_functions = saved.new_functions(parser);
+    let mut tests: Vec<(i32, WString)> = new_functions
+        .iter()
+        .filter(|name| name.starts_with(TEST_PREFIX))
+        .filter_map(|name| {
+            let props = function::get_props(name)?;
+            let file = props.definition_file.as_ref()?;
+            (file.as_utfstr() == path).then(|| (props.definition_lineno(), name.clone()))
+        })
+        .collect();
+    tests.sort();
+    let names: Vec<WString> = tests.into_iter().map(|(_, name)| name).collect();
+
+    let results = run_tests(parser, streams.io_chain, &names);
+
+    // Tests from one file must not leak into the next.
+    saved.restore(parser);
+    parser.vars().pop_sandbox(is_repainting);
+
+    Ok(TestSuite {
+        name: path.to_owned(),
+        results: results?,
+    })
+}
+
+fn push_tap_diagnostics(out: &mut WString, text: &wstr) {
+    for line in text.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        out.push_str("# ");
+        out.push_utfstr(line);
+        out.push('\n');
+    }
+}
+
+fn format_tap(suites: &[TestSuite]) -> WString {
+    let total: usize = suites.iter().map(|suite| suite.results.len()).sum();
+    let mut out = WString::new();
+    out.push_utfstr(&sprintf!("TAP version 13\n1..%d\n", total));
+    let mut number = 0;
+    for suite in suites {
+        for result in &suite.results {
+            number += 1;
+            if result.passed() {
+                out.push_utfstr(&sprintf!("ok %d - %s\n", number, result.name));
+                continue;
+            }
+            out.push_utfstr(&sprintf!("not ok %d - %s\n", number, result.name));
+            if result.status != 0 {
+                out.push_utfstr(&sprintf!("# exit status: %d\n", result.status));
+            }
+            if result.failed_assertions != 0 {
+                out.push_utfstr(&sprintf!(
+                    "# failed assertions: %d\n",
+                    result.failed_assertions
+                ));
+            }
+            push_tap_diagnostics(&mut out, &result.stdout);
+            push_tap_diagnostics(&mut out, &result.stderr);
+        }
+    }
+    out
+}
+
+fn escape_xml(text: &wstr) -> WString {
+    let mut out = WString::new();
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn format_junit(suites: &[TestSuite]) -> WString {
+    let count = |suite: &TestSuite| suite.results.len();
+    let failures = |suite: &TestSuite| suite.results.iter().filter(|r| !r.passed()).count();
+    let mut out = WString::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_utfstr(&sprintf!(
+        "<testsuites tests=\"%d\" failures=\"%d\">\n",
+        suites.iter().map(count).sum::<usize>(),
+        suites.iter().map(failures).sum::<usize>()
+    ));
+    for suite in suites {
+        let suite_name = escape_xml(&suite.name);
+        out.push_utfstr(&sprintf!(
+            "  <testsuite name=\"%s\" tests=\"%d\" failures=\"%d\">\n",
+            suite_name,
+            count(suite),
+            failures(suite)
+        ));
+        for result in &suite.results {
+            out.push_utfstr(&sprintf!(
+                "    <testcase name=\"%s\" classname=\"%s\" time=\"%.3f\">\n",
+                escape_xml(&result.name),
+                suite_name,
+                result.duration.as_secs_f64()
+            ));
+            if !result.passed() {
+                let message = if result.status != 0 {
+                    sprintf!("exit status: %d", result.status)
+                } else {
+                    sprintf!("failed assertions: %d", result.failed_assertions)
+                };
+                out.push_utfstr(&sprintf!(
+                    "      <failure message=\"%s\"/>\n",
+                    escape_xml(&message)
+                ));
+            }
+            if !result.stdout.is_empty() {
+                out.push_utfstr(&sprintf!(
+                    "      <system-out>%s</system-out>\n",
+                    escape_xml(&result.stdout)
+                ));
+            }
+            if !result.stderr.is_empty() {
+                out.push_utfstr(&sprintf!(
+                    "      <system-err>%s</system-err>\n",
+                    escape_xml(&result.stderr)
+                ));
+            }
+            out.push_str("    </testcase>\n");
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+pub fn fish_test(
+    parser: &mut Parser,
+    streams: &mut IoStreams,
+    argv: &mut [&wstr],
+) -> BuiltinResult {
+    let cmd = argv[0];
+    let mut format = ReportFormat::Tap;
+    let mut w = WGetopter::new(SHORT_OPTIONS, LONG_OPTIONS, argv);
+    while let Some(opt) = w.next_opt() {
+        match opt {
+            'f' => {
+                let arg = w.woptarg.unwrap();
+                format = if arg == "tap" {
+                    ReportFormat::Tap
+                } else if arg == "junit" {
+                    ReportFormat::Junit
+                } else {
+                    err_fmt!("%s: invalid report format", arg)
+                        .cmd(cmd)
+                        .full_trailer(parser)
+                        .finish(streams);
+                    return Err(STATUS_INVALID_ARGS);
+                };
+            }
+            'h' => {
+                builtin_print_help(parser, streams, cmd);
+                return Ok(SUCCESS);
+            }
+            ':' => {
+                builtin_missing_argument(parser, streams, cmd, None, argv[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            ';' => {
+                builtin_unexpected_argument(parser, streams, cmd, argv[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            '?' => {
+                builtin_unknown_option(parser, streams, cmd, argv[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            _ => panic!("unexpected retval from WGetopter"),
+        }
+    }
+    let files = &argv[w.wopt_index..];
+
+    let mut suites = vec![];
+    let mut failed = false;
+    if files.is_empty() {
+        // Run the test functions that are already defined.
+        let mut names: Vec<WString> = function::get_names(false, parser.vars())
+            .into_iter()
+            .filter(|name| name.starts_with(TEST_PREFIX))
+            .collect();
+        names.sort();
+        suites.push(TestSuite {
+            name: L!("fish_test").to_owned(),
+            results: run_tests(parser, streams.io_chain, &names)?,
+        });
+    } else {
+        for &path in files {
+            match run_file(parser, streams, cmd, path) {
+                Ok(suite) => suites.push(suite),
+                Err(_) => failed = true,
+            }
+        }
+    }
+
+    streams.out.append(&match format {
+        ReportFormat::Tap => format_tap(&suites),
+        ReportFormat::Junit => format_junit(&suites),
+    });
+
+    let all_passed = suites
+        .iter()
+        .all(|suite| suite.results.iter().all(TestResult::passed));
+    if all_passed && !failed {
+        Ok(SUCCESS)
+    } else {
+        Err(STATUS_CMD_ERROR)
+    }
+}
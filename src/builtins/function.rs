@@ -8,6 +8,7 @@
 use crate::global_safety::RelaxedAtomicBool;
 use crate::parse_execution::varname_error;
 use crate::parse_tree::NodeRef;
+use crate::parser::{RestrictedChange, is_search_path_var};
 use crate::parser_keywords::parser_keywords_is_reserved;
 use crate::proc::{InternalJobId, Pid};
 use crate::signal::RawSignal;
@@ -316,6 +317,24 @@ pub fn function(
         return Ok(SUCCESS);
     }
 
+    if parser.is_restricted() {
+        // Event handlers would run on their own later, so restricted code may not add them.
+        if !opts.events.is_empty() {
+            err_str!("event handlers are not allowed in restricted mode")
+                .cmd(cmd)
+                .finish(streams);
+            return Err(STATUS_RESTRICTED);
+        }
+        // Nor may its functions set search paths for their callers' commands.
+        let mut names = opts.named_arguments.iter().chain(&opts.inherit_vars);
+        if let Some(name) = names.find(|name| is_search_path_var(name)) {
+            err_raw!(RestrictedChange::SearchPath.message(name))
+                .cmd(cmd)
+                .finish(streams);
+            return Err(STATUS_RESTRICTED);
+        }
+    }
+
     // Extract the current filename.
     let definition_file = parser.current_filename.borrow().clone();
 
@@ -346,6 +365,7 @@ pub fn function(
         is_copy: false,
         copy_definition_file: None,
         copy_definition_lineno: None,
+        restricted_commands: parser.restricted_commands(),
     };
 
     // Add the function itself.
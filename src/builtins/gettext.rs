@@ -1,15 +1,100 @@
 use super::prelude::*;
+use cfg_if::cfg_if;
+
+#[derive(Default)]
+struct Options {
+    print_help: bool,
+    domain: Option<WString>,
+}
+
+const SHORT_OPTIONS: &wstr = L!("+d:h");
+const LONG_OPTIONS: &[WOption] = &[
+    wopt(L!("domain"), RequiredArgument, 'd'),
+    wopt(L!("help"), NoArgument, 'h'),
+];
+
+fn parse_options(
+    args: &mut [&wstr],
+    parser: &Parser,
+    streams: &mut IoStreams,
+) -> Result<(Options, usize), ErrorCode> {
+    let cmd = args[0];
+
+    let mut opts = Options::default();
+
+    let mut w = WGetopter::new(SHORT_OPTIONS, LONG_OPTIONS, args);
+
+    while let Some(c) = w.next_opt() {
+        match c {
+            'd' => opts.domain = Some(w.woptarg.unwrap().to_owned()),
+            'h' => opts.print_help = true,
+            ':' => {
+                builtin_missing_argument(parser, streams, cmd, None, args[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            ';' => {
+                builtin_unexpected_argument(parser, streams, cmd, args[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            '?' => {
+                builtin_unknown_option(parser, streams, cmd, args[w.wopt_index - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            _ => panic!("unexpected retval from WGetopter"),
+        }
+    }
+
+    Ok((opts, w.wopt_index))
+}
+
+/// Look up `message` in the catalogs of `domain` from `$fish_translation_path`, falling back to the
+/// untranslated message.
+fn translate_in_domain(parser: &Parser, domain: &wstr, message: &wstr) -> WString {
+    cfg_if! {
+        if #[cfg(feature = "localize-messages")] {
+            use crate::env::Environment as _;
+            let dirs = parser
+                .vars()
+                .get(L!("fish_translation_path"))
+                .map(|var| var.as_list().to_vec())
+                .unwrap_or_default();
+            crate::localization::translate_in_domain(&dirs, domain, message)
+                .unwrap_or_else(|| message.to_owned())
+        } else {
+            let _ = (parser, domain);
+            message.to_owned()
+        }
+    }
+}
 
 /// Used for the fish `_` builtin for requesting translations.
 /// For scripts in `share/`, the corresponding strings are extracted from the scripts using
 /// `cargo xtask gettext update`.
-/// Strings not present in our repo would require a custom MO file for translation to be possible.
-pub fn gettext(_parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) -> BuiltinResult {
-    for arg in &argv[1..] {
-        streams.out.append(
-            crate::localization::LocalizableString::from_external_source((*arg).to_owned())
-                .localize(),
-        );
+/// Other scripts can ship their own catalogs in a directory from `$fish_translation_path` and pass
+/// its name via `--domain`.
+pub fn gettext(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) -> BuiltinResult {
+    let cmd = argv[0];
+    let (opts, optind) = parse_options(argv, parser, streams)?;
+
+    if opts.print_help {
+        builtin_print_help(parser, streams, cmd);
+        return Ok(SUCCESS);
+    }
+
+    for arg in &argv[optind..] {
+        match &opts.domain {
+            Some(domain) => {
+                streams
+                    .out
+                    .append(&translate_in_domain(parser, domain, arg));
+            }
+            None => {
+                streams.out.append(
+                    crate::localization::LocalizableString::from_external_source((*arg).to_owned())
+                        .localize(),
+                );
+            }
+        }
     }
     Ok(SUCCESS)
 }
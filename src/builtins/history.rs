@@ -274,6 +274,18 @@ pub fn history(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr])
     // search term).
     let args = &args[optind..];
 
+    // The history is written to a file, so restricted code may only search it.
+    let writes_history = !matches!(
+        opts.hist_cmd,
+        HistCmd::None | HistCmd::Search | HistCmd::Merge
+    );
+    if writes_history && parser.is_restricted() {
+        err_str!("changing the history is not allowed in restricted mode")
+            .cmd(cmd)
+            .finish(streams);
+        return Err(STATUS_RESTRICTED);
+    }
+
     let mut status = Ok(SUCCESS);
     match opts.hist_cmd {
         HistCmd::None | HistCmd::Search => {
@@ -120,7 +120,8 @@ fn builtin_jobs_print(j: &Job, mode: JobsPrintMode, header: bool, streams: &mut
             }
 
             for p in j.processes() {
-                out += &sprintf!("%s\n", p.argv0().unwrap())[..];
+                // Blocks have no command name.
+                out += &sprintf!("%s\n", p.argv0().unwrap_or(L!("-")))[..];
             }
             streams.out.append(&out);
         }
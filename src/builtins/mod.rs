@@ -2,6 +2,7 @@
 
 pub mod abbr;
 pub mod argparse;
+pub mod assert;
 pub mod bg;
 pub mod bind;
 pub mod block;
@@ -23,7 +24,9 @@
 pub mod r#false;
 pub mod fg;
 pub mod fish_indent;
+pub mod fish_inline_image;
 pub mod fish_key_reader;
+pub mod fish_test;
 pub mod function;
 pub mod functions;
 pub mod r#gettext;
@@ -7,6 +7,7 @@
 
 use super::prelude::*;
 use crate::path::path_apply_working_directory;
+use crate::terminal::{hyperlink_file, hyperlinks_enabled};
 use crate::wutil::{
     INVALID_FILE_ID, file_id_for_path, lwstat, normalize_path, waccess, wbasename, wdirname,
     wrealpath, wstat,
@@ -142,12 +143,25 @@ struct Options<'args> {
 
     all_valid: bool,
     all: bool,
+
+    hyperlinks_valid: bool,
+    /// If set, existing files are written as hyperlinks, relative to this directory.
+    hyperlink_directory: Option<WString>,
 }
 
 #[inline]
 fn path_out(streams: &mut IoStreams, opts: &Options<'_>, s: impl AsRef<wstr>) {
     let s = s.as_ref();
     if !opts.quiet {
+        if let Some(directory) = &opts.hyperlink_directory {
+            if !s.is_empty() && lwstat(&path_apply_working_directory(s, directory)).is_ok() {
+                let link = hyperlink_file(s, s, directory);
+                streams
+                    .out
+                    .append_with_separation(&link, SeparationType::Explicitly, true);
+                return;
+            }
+        }
         if !opts.null_out {
             streams
                 .out
@@ -387,6 +401,14 @@ fn parse_opts<'args>(
         return Err(STATUS_INVALID_ARGS);
     }
 
+    if opts.hyperlinks_valid
+        && !opts.null_out
+        && streams.out_is_terminal()
+        && hyperlinks_enabled(parser.vars())
+    {
+        opts.hyperlink_directory = Some(parser.vars().get_pwd_slash());
+    }
+
     Ok(SUCCESS)
 }
 
@@ -454,7 +476,15 @@ fn path_basename(
 }
 
 fn path_dirname(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) -> BuiltinResult {
-    path_transform(parser, streams, args, |s| wdirname(s).to_owned(), |_| {})
+    path_transform(
+        parser,
+        streams,
+        args,
+        |s| wdirname(s).to_owned(),
+        |opts| {
+            opts.hyperlinks_valid = true;
+        },
+    )
 }
 
 fn normalize_help(path: &wstr) -> WString {
@@ -470,7 +500,9 @@ fn path_normalize(
     streams: &mut IoStreams,
     args: &mut [&wstr],
 ) -> BuiltinResult {
-    path_transform(parser, streams, args, normalize_help, |_| {})
+    path_transform(parser, streams, args, normalize_help, |opts| {
+        opts.hyperlinks_valid = true;
+    })
 }
 
 fn path_mtime(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) -> BuiltinResult {
@@ -629,7 +661,10 @@ fn path_change_extension(
 }
 
 fn path_resolve(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) -> BuiltinResult {
-    let mut opts = Options::default();
+    let mut opts = Options {
+        hyperlinks_valid: true,
+        ..Default::default()
+    };
     let mut optind = 0;
 
     parse_opts(&mut opts, &mut optind, 0, args, parser, streams)?;
@@ -695,6 +730,7 @@ fn path_sort(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) -
     let mut opts = Options {
         reverse_valid: true,
         unique_valid: true,
+        hyperlinks_valid: true,
         ..Default::default()
     };
     let mut optind = 0;
@@ -868,6 +904,7 @@ fn path_filter_maybe_is(
         perms_valid: true,
         invert_valid: true,
         all_valid: true,
+        hyperlinks_valid: true,
         ..Default::default()
     };
     let mut optind = 0;
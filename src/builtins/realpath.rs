@@ -7,6 +7,7 @@
 use crate::err_fmt;
 use crate::{
     path::path_apply_working_directory,
+    terminal::{hyperlink_file, hyperlinks_enabled},
     wutil::{normalize_path, wrealpath},
 };
 
@@ -74,11 +75,18 @@ pub fn realpath(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]
     }
 
     let mut had_error = false;
+    let hyperlinks = streams.out_is_terminal() && hyperlinks_enabled(parser.vars());
 
     for &arg in &args[optind..] {
         if !opts.no_symlinks {
             if let Some(real_path) = wrealpath(arg) {
-                streams.out.append(&real_path);
+                if hyperlinks {
+                    streams
+                        .out
+                        .append(&hyperlink_file(&real_path, &real_path, L!("/")));
+                } else {
+                    streams.out.append(&real_path);
+                }
                 streams.out.append(L!("\n"));
             } else {
                 let errno = errno();
@@ -100,7 +108,14 @@ pub fn realpath(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]
                 } else {
                     path_apply_working_directory(arg, &realpwd)
                 };
-                streams.out.appendln(&normalize_path(&absolute_arg, false));
+                let normalized = normalize_path(&absolute_arg, false);
+                if hyperlinks {
+                    streams
+                        .out
+                        .appendln(&hyperlink_file(&normalized, &normalized, L!("/")));
+                } else {
+                    streams.out.appendln(&normalized);
+                }
             } else {
                 err_fmt!("%s failed: %s", "realpath", errno().to_string())
                     .cmd(cmd)
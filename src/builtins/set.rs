@@ -2,17 +2,26 @@
 use crate::{
     builtins::Error,
     common::valid_var_name,
-    env::{EnvMode, EnvStackSetResult, EnvVar, EnvVarFlags, Environment, INHERITED_VARS},
+    env::{
+        ComputedVar, EnvMode, EnvStackSetResult, EnvVar, EnvVarFlags, Environment, INHERITED_VARS,
+        universal_defaults_path, universal_var_layer, universal_var_metadata,
+    },
+    env_universal_common::UvarLayer,
     err_fmt, err_str,
     event::{self, Event},
     expand::{expand_escape_string, expand_escape_variable},
     history::{History, history_id},
     parse_execution::varname_error,
+    parse_util::unescaped_slice_length,
     parser::ParserEnvSetMode,
     wutil::wcstoi::{self, wcstoi_partial},
 };
 use fish_common::{EscapeFlags, EscapeStringStyle, escape, escape_string, help_section};
-use fish_widestring::ELLIPSIS_CHAR;
+use fish_widestring::{ELLIPSIS_CHAR, cstr2wcstring};
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 struct Options {
@@ -34,6 +43,11 @@ struct Options {
     prepend: bool,
     preserve_failure_exit_status: bool,
     no_event: bool,
+    associative: bool,
+    keys: bool,
+    computed: bool,
+    ttl: Option<Duration>,
+    invalidate: bool,
 }
 
 impl Default for Options {
@@ -57,6 +71,11 @@ fn default() -> Self {
             prepend: false,
             preserve_failure_exit_status: true,
             no_event: false,
+            associative: false,
+            keys: false,
+            computed: false,
+            ttl: None,
+            invalidate: false,
         }
     }
 }
@@ -91,10 +110,14 @@ fn parse(
         const PATH_ARG: char = 1 as char;
         const UNPATH_ARG: char = 2 as char;
         const NO_EVENT_ARG: char = 3 as char;
+        const KEYS_ARG: char = 4 as char;
+        const COMPUTED_ARG: char = 5 as char;
+        const TTL_ARG: char = 6 as char;
+        const INVALIDATE_ARG: char = 7 as char;
         // Variables used for parsing the argument list. This command is atypical in using the "+"
         // (REQUIRE_ORDER) option for flag parsing. This is not typical of most fish commands. It means
         // we stop scanning for flags when the first non-flag argument is seen.
-        const SHORT_OPTS: &wstr = L!("+LSUaefghlnpqux");
+        const SHORT_OPTS: &wstr = L!("+ALSUaefghlnpqux");
         const LONG_OPTS: &[WOption] = &[
             wopt(L!("export"), NoArgument, 'x'),
             wopt(L!("global"), NoArgument, 'g'),
@@ -112,6 +135,11 @@ fn parse(
             wopt(L!("path"), NoArgument, PATH_ARG),
             wopt(L!("unpath"), NoArgument, UNPATH_ARG),
             wopt(L!("no-event"), NoArgument, NO_EVENT_ARG),
+            wopt(L!("associative"), NoArgument, 'A'),
+            wopt(L!("keys"), NoArgument, KEYS_ARG),
+            wopt(L!("computed"), NoArgument, COMPUTED_ARG),
+            wopt(L!("ttl"), RequiredArgument, TTL_ARG),
+            wopt(L!("invalidate"), NoArgument, INVALIDATE_ARG),
             wopt(L!("help"), NoArgument, 'h'),
         ];
 
@@ -120,6 +148,7 @@ fn parse(
         let mut w = WGetopter::new(SHORT_OPTS, LONG_OPTS, args);
         while let Some(c) = w.next_opt() {
             match c {
+                'A' => opts.associative = true,
                 'a' => opts.append = true,
                 'e' => {
                     opts.erase = true;
@@ -143,6 +172,26 @@ fn parse(
                 PATH_ARG => opts.pathvar = true,
                 UNPATH_ARG => opts.unpathvar = true,
                 NO_EVENT_ARG => opts.no_event = true,
+                KEYS_ARG => {
+                    opts.keys = true;
+                    opts.preserve_failure_exit_status = false;
+                }
+                COMPUTED_ARG => opts.computed = true,
+                TTL_ARG => {
+                    let arg = w.woptarg.unwrap();
+                    let Some(ttl) = parse_duration(arg) else {
+                        err_fmt!("%s: invalid duration", arg)
+                            .cmd(cmd)
+                            .full_trailer(parser)
+                            .finish(streams);
+                        return Err(STATUS_INVALID_ARGS);
+                    };
+                    opts.ttl = Some(ttl);
+                }
+                INVALIDATE_ARG => {
+                    opts.invalidate = true;
+                    opts.preserve_failure_exit_status = false;
+                }
                 'U' => opts.universal = true,
                 'L' => opts.shorten_ok = false,
                 'S' => {
@@ -302,6 +351,46 @@ fn validate(
             return Err(STATUS_INVALID_ARGS);
         }
 
+        // --associative only makes sense when setting, and --keys only on its own.
+        if (opts.associative
+            && (opts.erase || opts.list || opts.query || opts.show || opts.prepend))
+            || (opts.keys
+                && (opts.erase
+                    || opts.list
+                    || opts.query
+                    || opts.show
+                    || opts.associative
+                    || opts.append
+                    || opts.prepend))
+        {
+            err_str!(Error::INVALID_OPT_COMBO)
+                .cmd(cmd)
+                .full_trailer(parser)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+
+        // --computed only makes sense when setting, --ttl only with --computed, and --invalidate
+        // only on its own.
+        let other_modes = opts.erase
+            || opts.list
+            || opts.query
+            || opts.show
+            || opts.keys
+            || opts.associative
+            || opts.append
+            || opts.prepend;
+        if (opts.computed && (other_modes || opts.pathvar || opts.invalidate))
+            || (opts.ttl.is_some() && !opts.computed)
+            || (opts.invalidate && other_modes)
+        {
+            err_str!(Error::INVALID_OPT_COMBO)
+                .cmd(cmd)
+                .full_trailer(parser)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+
         if args.len() == optind && opts.erase {
             err_fmt!(Error::MISSING_OPT_ARG, L!("--erase"))
                 .cmd(cmd)
@@ -314,6 +403,28 @@ fn validate(
     }
 }
 
+/// Parse a duration like `30s` for `--ttl`. A number without a unit is in seconds.
+fn parse_duration(arg: &wstr) -> Option<Duration> {
+    if !arg.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut consumed = 0;
+    let amount: u64 = wcstoi_partial(arg, wcstoi::Options::default(), &mut consumed).ok()?;
+    let unit = arg.slice_from(consumed);
+    let seconds = if unit.is_empty() || unit == "s" {
+        amount
+    } else if unit == "ms" {
+        return Some(Duration::from_millis(amount));
+    } else if unit == "m" {
+        amount.checked_mul(60)?
+    } else if unit == "h" {
+        amount.checked_mul(60 * 60)?
+    } else {
+        return None;
+    };
+    Some(Duration::from_secs(seconds))
+}
+
 // Check if we are setting a uvar and a global of the same name exists. See
 // https://github.com/fish-shell/fish-shell/issues/806
 fn warn_if_uvar_shadows_global(
@@ -336,7 +447,14 @@ fn warn_if_uvar_shadows_global(
     }
 }
 
-fn handle_env_return(retval: EnvStackSetResult, cmd: &wstr, key: &wstr, streams: &mut IoStreams) {
+fn handle_env_return(
+    retval: EnvStackSetResult,
+    cmd: &wstr,
+    key: &wstr,
+    mode: ParserEnvSetMode,
+    streams: &mut IoStreams,
+    parser: &Parser,
+) {
     match retval {
         EnvStackSetResult::Ok => (),
         EnvStackSetResult::Perm => {
@@ -360,6 +478,13 @@ fn handle_env_return(retval: EnvStackSetResult, cmd: &wstr, key: &wstr, streams:
             .cmd(cmd)
             .finish(streams);
         }
+        EnvStackSetResult::Restricted => {
+            // The variable is unchanged, so this gives the same answer as when setting it.
+            let restricted = parser
+                .restricted_change(key, mode)
+                .expect("change should have been restricted");
+            err_raw!(restricted.message(key)).cmd(cmd).finish(streams);
+        }
         EnvStackSetResult::NotFound => {
             // Only variable deletion can return a `NotFound` error, but that case is explicitly silenced
             unreachable!("variable not found");
@@ -367,25 +492,28 @@ fn handle_env_return(retval: EnvStackSetResult, cmd: &wstr, key: &wstr, streams:
     }
 }
 
-/// Call vars.set. If this is a path variable, e.g. PATH, validate the elements. On error, print a
-/// description of the problem to stderr.
+/// Call vars.set, or vars.set_map if `map_keys` is given. If this is a path variable, e.g. PATH,
+/// validate the elements. On error, print a description of the problem to stderr.
+#[allow(clippy::too_many_arguments)]
 fn env_set_reporting_errors(
     cmd: &wstr,
     opts: &Options,
     key: &wstr,
     mode: EnvMode,
+    map_keys: Option<Vec<WString>>,
     list: Vec<WString>,
     streams: &mut IoStreams,
     parser: &mut Parser,
 ) -> EnvStackSetResult {
     let mode = ParserEnvSetMode::user(mode);
-    let retval = if opts.no_event {
-        parser.set_var(key, mode, list)
-    } else {
-        parser.set_var_and_fire(key, mode, list)
+    let retval = match (map_keys, opts.no_event) {
+        (None, true) => parser.set_var(key, mode, list),
+        (None, false) => parser.set_var_and_fire(key, mode, list),
+        (Some(keys), true) => parser.set_map(key, mode, keys, list),
+        (Some(keys), false) => parser.set_map_and_fire(key, mode, keys, list),
     };
     // If this returned OK, the parser already fired the event.
-    handle_env_return(retval, cmd, key, streams);
+    handle_env_return(retval, cmd, key, mode, streams, parser);
     retval
 }
 
@@ -414,6 +542,8 @@ struct SplitVar<'a> {
     varname: &'a wstr,
     var: Option<EnvVar>,
     indexes: Vec<isize>,
+    /// For associative variables, the key given in brackets instead of indexes.
+    key: Option<&'a wstr>,
 }
 
 impl<'a> SplitVar<'a> {
@@ -426,7 +556,7 @@ fn varsize(&self) -> usize {
 /// Extract indexes from an argument of the form `var_name[index1 index2...]`.
 /// The argument `arg` is split into a variable name and list of indexes, which is returned by
 /// reference. Indexes are "expanded" in the sense that range expressions .. and negative values are
-/// handled.
+/// handled. If the variable is associative, the text in brackets is a single key instead.
 ///
 /// Returns:
 ///   a split var on success, none() on error, in which case an error will have been printed.
@@ -460,6 +590,14 @@ fn split_var_and_indexes_internal<'a>(
         return Ok(res);
     };
 
+    if res.var.as_ref().is_some_and(EnvVar::is_map) {
+        let rest = arg.slice_from(open_bracket);
+        let slice_len = unescaped_slice_length(rest)
+            .ok_or_else(|| EnvArrayParseError::InvalidIndex(rest.slice_from(1).to_owned()))?;
+        res.key = Some(&rest[1..slice_len - 1]);
+        return Ok(res);
+    }
+
     // We need the length of the array to validate the indexes.
     let len = res
         .var
@@ -554,6 +692,37 @@ fn erased_at_indexes(mut input: Vec<WString>, mut indexes: Vec<isize>) -> Vec<WS
     input
 }
 
+/// Return the keys and values of the associative variable `var` without `key`.
+fn map_removing_key(var: &EnvVar, key: &wstr) -> (Vec<WString>, Vec<WString>) {
+    var.map_keys()
+        .unwrap()
+        .iter()
+        .zip(var.as_list())
+        .filter(|(k, _)| *k != key)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .unzip()
+}
+
+/// Return the keys and values of the associative variable `var` (or an empty map if None), with
+/// each key in `pairs` inserted or updated.
+fn map_setting_keys(var: Option<&EnvVar>, pairs: &[&wstr]) -> (Vec<WString>, Vec<WString>) {
+    let (mut keys, mut values) = match var {
+        Some(var) => (var.map_keys().unwrap().to_vec(), var.as_list().to_vec()),
+        None => (vec![], vec![]),
+    };
+    for pair in pairs.chunks_exact(2) {
+        let (key, value) = (pair[0], pair[1].to_owned());
+        match keys.iter().position(|k| k == key) {
+            Some(idx) => values[idx] = value,
+            None => {
+                keys.push(key.to_owned());
+                values.push(value);
+            }
+        }
+    }
+    (keys, values)
+}
+
 /// Print the names of all environment variables in the scope. It will include the values unless the
 /// `set --names` flag was used.
 fn list(opts: &Options, parser: &Parser, streams: &mut IoStreams) -> BuiltinResult {
@@ -624,7 +793,17 @@ fn query(
             return Err(STATUS_CMD_ERROR);
         };
 
-        if split.indexes.is_empty() {
+        if let Some(key) = split.key {
+            // Increment if the key is missing.
+            if split
+                .var
+                .as_ref()
+                .and_then(|var| var.map_get(key))
+                .is_none()
+            {
+                retval += 1;
+            }
+        } else if split.indexes.is_empty() {
             // No indexes, just increment if our variable is missing.
             if split.var.is_none() {
                 retval += 1;
@@ -667,14 +846,24 @@ fn show_scope(var_name: &wstr, scope: EnvMode, streams: &mut IoStreams, vars: &d
         L!("").to_owned()
     };
     let vals = var.as_list();
-    streams.out.append(&wgettext_fmt!(
-        "$%s: set in %s scope, %s,%s with %d elements",
-        var_name,
-        scope_name,
-        exportv,
-        pathvarv,
-        vals.len()
-    ));
+    if var.is_map() {
+        streams.out.append(&wgettext_fmt!(
+            "$%s: set in %s scope, %s, associative with %d keys",
+            var_name,
+            scope_name,
+            exportv,
+            vals.len()
+        ));
+    } else {
+        streams.out.append(&wgettext_fmt!(
+            "$%s: set in %s scope, %s,%s with %d elements",
+            var_name,
+            scope_name,
+            exportv,
+            pathvarv,
+            vals.len()
+        ));
+    }
     // HACK: PWD can be set, depending on how you ask.
     // For our purposes it's read-only.
     if EnvVar::flags_for(var_name).contains(EnvVarFlags::READ_ONLY) {
@@ -685,6 +874,20 @@ fn show_scope(var_name: &wstr, scope: EnvMode, streams: &mut IoStreams, vars: &d
         streams.out.append('\n');
     }
 
+    if let Some(computed) = var.computed() {
+        let source = escape(computed.source());
+        let line = match computed.ttl() {
+            Some(ttl) => wgettext_fmt!(
+                "$%s: computed by %s, cached for %s",
+                var_name,
+                source,
+                format_duration(ttl)
+            ),
+            None => wgettext_fmt!("$%s: computed by %s", var_name, source),
+        };
+        streams.out.appendln(&line);
+    }
+
     for i in 0..vals.len() {
         if vals.len() > 100 {
             if i == 50 {
@@ -700,12 +903,97 @@ fn show_scope(var_name: &wstr, scope: EnvMode, streams: &mut IoStreams, vars: &d
             value,
             EscapeStringStyle::Script(EscapeFlags::NO_PRINTABLES | EscapeFlags::NO_QUOTED),
         );
-        streams
-            .out
-            .append(&sprintf!("$%s[%d]: |%s|\n", var_name, i + 1, &escaped_val));
+        if let Some(keys) = var.map_keys() {
+            let escaped_key = escape_string(
+                &keys[i],
+                EscapeStringStyle::Script(EscapeFlags::NO_PRINTABLES | EscapeFlags::NO_QUOTED),
+            );
+            streams.out.append(&sprintf!(
+                "$%s[%s]: |%s|\n",
+                var_name,
+                &escaped_key,
+                &escaped_val
+            ));
+        } else {
+            streams
+                .out
+                .append(&sprintf!("$%s[%d]: |%s|\n", var_name, i + 1, &escaped_val));
+        }
+    }
+
+    // Say where a universal value comes from if the system defaults are involved.
+    if scope == EnvMode::UNIVERSAL {
+        let origin = match universal_var_layer(var_name) {
+            Some(UvarLayer::System) => Some(wgettext_fmt!(
+                "$%s: from the system defaults in %s",
+                var_name,
+                universal_defaults_path()
+            )),
+            Some(UvarLayer::UserOverridingSystem) => Some(wgettext_fmt!(
+                "$%s: overrides the system default in %s",
+                var_name,
+                universal_defaults_path()
+            )),
+            Some(UvarLayer::User) | None => None,
+        };
+        if let Some(origin) = origin {
+            streams.out.appendln(&origin);
+        }
+
+        if let Some(metadata) = universal_var_metadata(var_name) {
+            let mtime = format_timestamp(metadata.mtime);
+            let changed = if metadata.host.is_empty() {
+                wgettext_fmt!("$%s: last changed %s", var_name, mtime)
+            } else {
+                wgettext_fmt!("$%s: last changed %s on %s", var_name, mtime, metadata.host)
+            };
+            streams.out.appendln(&changed);
+        }
     }
 }
 
+/// Format a `--ttl` duration, in the largest unit that represents it exactly.
+fn format_duration(duration: Duration) -> WString {
+    let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+    if millis % 1000 != 0 {
+        sprintf!("%dms", millis)
+    } else if millis % (60 * 60 * 1000) == 0 && millis != 0 {
+        sprintf!("%dh", millis / (60 * 60 * 1000))
+    } else if millis % (60 * 1000) == 0 && millis != 0 {
+        sprintf!("%dm", millis / (60 * 1000))
+    } else {
+        sprintf!("%ds", millis / 1000)
+    }
+}
+
+/// Format seconds since the epoch as a local date and time.
+fn format_timestamp(seconds: i64) -> WString {
+    // This warns for musl, but the warning is useless to us - there is nothing we can or should do.
+    #[allow(deprecated)]
+    let Ok(seconds) = libc::time_t::try_from(seconds) else {
+        return seconds.to_wstring();
+    };
+    let mut local_time = MaybeUninit::uninit();
+    if unsafe { libc::localtime_r(&seconds, local_time.as_mut_ptr()) }.is_null() {
+        return seconds.to_wstring();
+    }
+    const MAX_TIMESTAMP_LENGTH: usize = 100;
+    let mut timestamp_str = [0_u8; MAX_TIMESTAMP_LENGTH];
+    if unsafe {
+        libc::strftime(
+            timestamp_str.as_mut_ptr().cast(),
+            MAX_TIMESTAMP_LENGTH,
+            c"%Y-%m-%d %H:%M:%S".as_ptr(),
+            local_time.as_ptr(),
+        )
+    } == 0
+    {
+        return seconds.to_wstring();
+    }
+    // SAFETY: strftime terminates the string with a null byte if it returns nonzero.
+    cstr2wcstring(CStr::from_bytes_until_nul(&timestamp_str).unwrap())
+}
+
 /// Show mode. Show information about the named variable(s).
 fn show(cmd: &wstr, parser: &Parser, streams: &mut IoStreams, args: &[&wstr]) -> BuiltinResult {
     localizable_consts! {
@@ -776,6 +1064,116 @@ fn show(cmd: &wstr, parser: &Parser, streams: &mut IoStreams, args: &[&wstr]) ->
     Ok(SUCCESS)
 }
 
+/// Keys mode. Print the keys of the named variable(s), or the indexes of list variables.
+fn keys(
+    cmd: &wstr,
+    opts: &Options,
+    parser: &Parser,
+    streams: &mut IoStreams,
+    args: &[&wstr],
+) -> BuiltinResult {
+    let mut retval = 0;
+    for arg in args.iter().copied() {
+        if !valid_var_name(arg) {
+            varname_error(cmd, arg).full_trailer(parser).finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+        let Some(var) = parser.vars().getf(arg, opts.env_mode()) else {
+            retval += 1;
+            continue;
+        };
+        match var.map_keys() {
+            Some(keys) => {
+                for key in keys {
+                    streams.out.appendln(key);
+                }
+            }
+            None => {
+                for i in 1..=var.as_list().len() {
+                    streams.out.appendln(&i.to_wstring());
+                }
+            }
+        }
+    }
+    BuiltinResult::from_dynamic(retval)
+}
+
+/// Computed mode. Define a variable whose value is the output of the given code.
+fn computed(
+    cmd: &wstr,
+    opts: &Options,
+    parser: &mut Parser,
+    streams: &mut IoStreams,
+    args: &[&wstr],
+) -> BuiltinResult {
+    if opts.exportv {
+        err_str!("computed variables can not be exported")
+            .cmd(cmd)
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+    if opts.universal {
+        err_str!("computed variables can not be universal")
+            .cmd(cmd)
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+    if args.len() != 2 {
+        err_fmt!(Error::UNEXP_ARG_COUNT, 2, args.len())
+            .cmd(cmd)
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+
+    let varname = args[0];
+    if !valid_var_name(varname) {
+        varname_error(cmd, varname)
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+
+    // Code defined by restricted code stays restricted when it is computed later.
+    let computed = Arc::new(ComputedVar::new(
+        args[1].to_owned(),
+        opts.ttl,
+        parser.restricted_commands(),
+    ));
+    let mode = ParserEnvSetMode::user(opts.env_mode());
+    let retval = parser.set_computed(varname, mode, computed);
+    if retval == EnvStackSetResult::Ok && !opts.no_event {
+        event::fire(parser, Event::variable_set(varname.to_owned()));
+    }
+    handle_env_return(retval, cmd, varname, mode, streams, parser);
+    BuiltinResult::from(retval)
+}
+
+/// Invalidate mode. Make the named computed variables compute their value again on next use.
+fn invalidate(
+    cmd: &wstr,
+    opts: &Options,
+    parser: &Parser,
+    streams: &mut IoStreams,
+    args: &[&wstr],
+) -> BuiltinResult {
+    let mut retval = 0;
+    for arg in args.iter().copied() {
+        if !valid_var_name(arg) {
+            varname_error(cmd, arg).full_trailer(parser).finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+        let var = parser.vars().getf(arg, opts.env_mode());
+        match var.as_ref().and_then(EnvVar::computed) {
+            Some(computed) => computed.invalidate(),
+            None => retval += 1,
+        }
+    }
+    BuiltinResult::from_dynamic(retval)
+}
+
 fn erase(
     cmd: &wstr,
     opts: &Options,
@@ -798,14 +1196,29 @@ fn erase(
                 return Err(STATUS_INVALID_ARGS);
             }
             let retval;
-            if split.indexes.is_empty() {
+            if let Some(key) = split.key {
+                // remove just the specified key of the map
+                let var = split.var.unwrap();
+                let (keys, values) = map_removing_key(&var, key);
+                retval = env_set_reporting_errors(
+                    cmd,
+                    opts,
+                    split.varname,
+                    mode,
+                    Some(keys),
+                    values,
+                    streams,
+                    parser,
+                );
+            } else if split.indexes.is_empty() {
                 // unset the var
-                retval = parser.remove_var(split.varname, ParserEnvSetMode::new(mode));
+                let mode = ParserEnvSetMode::new(mode);
+                retval = parser.remove_var(split.varname, mode);
                 // When a non-existent-variable is unset, return NotFound as $status
                 // but do not emit any errors at the console as a compromise between user
                 // friendliness and correctness.
                 if retval != EnvStackSetResult::NotFound {
-                    handle_env_return(retval, cmd, split.varname, streams);
+                    handle_env_return(retval, cmd, split.varname, mode, streams, parser);
                 }
                 if retval == EnvStackSetResult::Ok && !opts.no_event {
                     event::fire(parser, Event::variable_erase(split.varname.to_owned()));
@@ -821,6 +1234,7 @@ fn erase(
                     opts,
                     split.varname,
                     mode,
+                    None,
                     result,
                     streams,
                     parser,
@@ -988,6 +1402,61 @@ fn set_internal(
         return Err(STATUS_INVALID_ARGS);
     }
 
+    // Associative variables can not be exported or path variables.
+    let sets_map = opts.associative || split.key.is_some();
+    if sets_map && opts.exportv {
+        err_str!("associative variables can not be exported")
+            .cmd(cmd)
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+    if sets_map && opts.pathvar {
+        err_str!("associative variables can not be path variables")
+            .cmd(cmd)
+            .full_trailer(parser)
+            .finish(streams);
+        return Err(STATUS_INVALID_ARGS);
+    }
+
+    if opts.associative {
+        if var_expr.contains('[') {
+            err_str!("Cannot use --associative when assigning to a key or slice")
+                .cmd(cmd)
+                .full_trailer(parser)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+        if argv.len() % 2 != 0 {
+            err_str!("expected key and value pairs")
+                .cmd(cmd)
+                .full_trailer(parser)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+    }
+
+    // Setting a key like `set map[key] ...` can only take one value.
+    if let Some(key) = split.key {
+        if opts.append || opts.prepend {
+            err_str!("Cannot use --append or --prepend when assigning to a slice")
+                .cmd(cmd)
+                .full_trailer(parser)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+        if argv.len() != 1 {
+            err_fmt!(
+                "expected one value for key '%s' but got %d",
+                key,
+                argv.len()
+            )
+            .cmd(cmd)
+            .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+    }
+
     // Setting with explicit indexes like `set foo[3] ...` has additional error handling.
     if !split.indexes.is_empty() {
         // Indexes must be > 0. (Note split_var_and_indexes negates negative values).
@@ -1022,17 +1491,48 @@ fn set_internal(
         }
     }
 
-    let new_values = if split.indexes.is_empty() {
+    let (new_keys, new_values) = if let Some(key) = split.key {
+        // Set a single key of an existing associative variable.
+        let (keys, values) = map_setting_keys(split.var.as_ref(), &[key, argv[0]]);
+        (Some(keys), values)
+    } else if opts.associative {
+        // When appending, insert into the existing map. Like for lists, this uses default scoping.
+        let existing = if opts.append {
+            parser.vars().get(split.varname)
+        } else {
+            None
+        };
+        if existing.as_ref().is_some_and(|var| !var.is_map()) {
+            err_fmt!("%s: not an associative variable", split.varname)
+                .cmd(cmd)
+                .full_trailer(parser)
+                .finish(streams);
+            return Err(STATUS_INVALID_ARGS);
+        }
+        let (keys, values) = map_setting_keys(existing.as_ref(), argv);
+        (Some(keys), values)
+    } else if split.indexes.is_empty() {
         // Handle the simple, common, case. Set the var to the specified values.
-        new_var_values(split.varname, opts, argv, parser.vars())
+        (
+            None,
+            new_var_values(split.varname, opts, argv, parser.vars()),
+        )
     } else {
         // Handle the uncommon case of setting specific slices of a var.
-        new_var_values_by_index(&split, argv)
+        (None, new_var_values_by_index(&split, argv))
     };
 
     // Set the value back in the variable stack and fire any events.
-    let retval =
-        env_set_reporting_errors(cmd, opts, split.varname, mode, new_values, streams, parser);
+    let retval = env_set_reporting_errors(
+        cmd,
+        opts,
+        split.varname,
+        mode,
+        new_keys,
+        new_values,
+        streams,
+        parser,
+    );
 
     if retval == EnvStackSetResult::Ok {
         warn_if_uvar_shadows_global(cmd, opts, split.varname, streams, parser);
@@ -1051,6 +1551,19 @@ pub fn set(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) ->
 
     let args = &args[optind..];
 
+    // Showing values and querying indexes look at the current values of computed variables.
+    if opts.show || opts.query {
+        for arg in args {
+            let (name, has_indexes) = match arg.find(L!("[")) {
+                Some(idx) => (&arg[..idx], true),
+                None => (*arg, false),
+            };
+            if (opts.show || has_indexes) && valid_var_name(name) {
+                parser.refresh_computed_var(name);
+            }
+        }
+    }
+
     let retval = if opts.query {
         query(cmd, &opts, parser, streams, args)
     } else if opts.erase {
@@ -1059,6 +1572,12 @@ pub fn set(parser: &mut Parser, streams: &mut IoStreams, args: &mut [&wstr]) ->
         list(&opts, parser, streams)
     } else if opts.show {
         show(cmd, parser, streams, args)
+    } else if opts.keys {
+        keys(cmd, &opts, parser, streams, args)
+    } else if opts.invalidate {
+        invalidate(cmd, &opts, parser, streams, args)
+    } else if opts.computed {
+        computed(cmd, &opts, parser, streams, args)
     } else if args.is_empty() && !(opts.append || opts.prepend) {
         list(&opts, parser, streams)
     } else {
@@ -239,7 +239,7 @@ fn write_stacktrace(&self, output: &mut OutputStream) {
         let Some(parser) = self.parser else {
             return;
         };
-        let stacktrace = parser.current_line();
+        let stacktrace = parser.current_line(output.is_terminal());
         if !stacktrace.is_empty() {
             output.append('\n');
             output.appendln(&stacktrace);
@@ -95,6 +95,8 @@ fn builtin_status_code(&self) -> c_int {
 pub const STATUS_READ_TOO_MUCH: c_int = 122;
 /// The status code when an expansion fails, for example, "$foo["
 pub const STATUS_EXPAND_ERROR: c_int = 121;
+/// The status code used when something is not allowed in restricted mode.
+pub const STATUS_RESTRICTED: c_int = 120;
 
 pub const STATUS_NO_VARIABLES_GIVEN: c_int = 255;
 
@@ -142,6 +144,10 @@ struct BuiltinData {
         name: L!("argparse"),
         func: argparse::argparse,
     },
+    BuiltinData {
+        name: L!("assert"),
+        func: assert::assert,
+    },
     BuiltinData {
         name: L!("begin"),
         func: builtin_generic,
@@ -246,10 +252,18 @@ struct BuiltinData {
         name: L!("fish_indent"),
         func: fish_indent::fish_indent,
     },
+    BuiltinData {
+        name: L!("fish_inline_image"),
+        func: fish_inline_image::fish_inline_image,
+    },
     BuiltinData {
         name: L!("fish_key_reader"),
         func: fish_key_reader::fish_key_reader,
     },
+    BuiltinData {
+        name: L!("fish_test"),
+        func: fish_test::fish_test,
+    },
     BuiltinData {
         name: L!("for"),
         func: builtin_generic,
@@ -486,6 +500,7 @@ pub fn builtin_get_desc(name: &wstr) -> Option<&'static wstr> {
         _ if name == "abbr" => wgettext!("Manage abbreviations"),
         _ if name == "and" => wgettext!("Run command if last command succeeded"),
         _ if name == "argparse" => wgettext!("Parse options in fish script"),
+        _ if name == "assert" => wgettext!("Check a condition in a test"),
         _ if name == "begin" => wgettext!("Create a block of code"),
         _ if name == "bg" => wgettext!("Send job to background"),
         _ if name == "bind" => wgettext!("Handle fish key bindings"),
@@ -511,7 +526,9 @@ pub fn builtin_get_desc(name: &wstr) -> Option<&'static wstr> {
         _ if name == "exit" => wgettext!("Exit the shell"),
         _ if name == "false" => wgettext!("Return an unsuccessful result"),
         _ if name == "fg" => wgettext!("Send job to foreground"),
+        _ if name == "fish_inline_image" => wgettext!("Draw an image in the terminal"),
         _ if name == "fish_key_reader" => wgettext!("explore what characters keyboard keys send"),
+        _ if name == "fish_test" => wgettext!("Run tests written in fish"),
         _ if name == "for" => wgettext!("Perform a set of commands multiple times"),
         _ if name == "function" => wgettext!("Define a new function"),
         _ if name == "functions" => wgettext!("List or remove functions"),
@@ -632,7 +649,7 @@ pub fn builtin_unexpected_argument(
 /// Print the backtrace and call for help that we use at the end of error messages.
 pub fn builtin_print_error_trailer(parser: &Parser, b: &mut OutputStream, cmd: &wstr) {
     b.append('\n');
-    let stacktrace = parser.current_line();
+    let stacktrace = parser.current_line(b.is_terminal());
     // Don't print two empty lines if we don't have a stacktrace.
     if !stacktrace.is_empty() {
         b.appendln(&stacktrace);
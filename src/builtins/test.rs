@@ -1071,7 +1071,8 @@ pub fn test(parser: &mut Parser, streams: &mut IoStreams, argv: &mut [&wstr]) ->
         }
         // Add a backtrace but not the "see help" message
         // because this isn't about passing the wrong options.
-        streams.err.append(&parser.current_line());
+        let stacktrace = parser.current_line(streams.err.is_terminal());
+        streams.err.append(&stacktrace);
         return Err(STATUS_INVALID_ARGS);
     }
 
@@ -2,7 +2,7 @@
     abbrs::with_abbrs,
     ast::unescape_keyword,
     autoload::{Autoload, AutoloadResult},
-    builtins::{builtin_exists, builtin_get_desc, builtin_get_names},
+    builtins::{argparse, builtin_exists, builtin_get_desc, builtin_get_names},
     common::valid_var_name_char,
     env::{EnvMode, EnvStack, Environment},
     exec::exec_subshell,
@@ -13,6 +13,7 @@
     flog::{flog, flogf},
     function,
     history::{History, history_id},
+    io::IoChain,
     localization::{LocalizableString, localizable_string},
     operation_context::OperationContext,
     parse_constants::SourceRange,
@@ -35,10 +36,12 @@
     string_suffixes_string_case_insensitive, strip_executable_suffix,
 };
 use fish_widestring::{WExt as _, charptr2wcstring};
+use lru::LruCache;
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     mem,
+    num::NonZeroUsize,
     ops::{Deref, DerefMut},
     sync::{
         LazyLock, Mutex, MutexGuard,
@@ -104,6 +107,8 @@ pub struct CompleteFlags: u16 {
         const VARIABLE_NAME = 1 << 9;
         /// Suppress showing the pager prefix for this completion.
         const SUPPRESS_PAGER_PREFIX = 1 << 10;
+        /// This completes a path to a file relative to the working directory.
+        const FILE_PATH = 1 << 11;
     }
 }
 
@@ -315,6 +320,11 @@ pub fn size(&self) -> usize {
         self.completions.len()
     }
 
+    /// Returns whether we have reached our limit, so that adding more would fail.
+    pub fn is_full(&self) -> bool {
+        self.completions.len() >= self.limit
+    }
+
     /// Returns the list of completions.
     pub fn as_list(&self) -> &[Completion] {
         &self.completions
@@ -402,6 +412,8 @@ struct CompletionEntry {
     /// Order for when this completion was created. This aids in outputting completions sorted by
     /// time.
     order: usize,
+    /// How long the output of `--arguments` may be reused, if at all.
+    cache_ttl: Option<Duration>,
 }
 
 impl CompletionEntry {
@@ -409,6 +421,7 @@ pub fn new() -> Self {
         Self {
             options: vec![],
             order: COMPLETE_ORDER.fetch_add(1, atomic::Ordering::Relaxed),
+            cache_ttl: None,
         }
     }
 
@@ -442,10 +455,33 @@ struct CompletionEntryIndex {
 static COMPLETION_MAP: Mutex<CompletionEntryMap> = Mutex::new(BTreeMap::new());
 static COMPLETION_TOMBSTONES: Mutex<BTreeSet<WString>> = Mutex::new(BTreeSet::new());
 
+/// Functions whose completions are generated from their `argparse` call when first needed.
+static ARGPARSE_FUNCTIONS: Mutex<BTreeSet<WString>> = Mutex::new(BTreeSet::new());
+
 /// Completion "wrapper" support. The map goes from wrapping-command to wrapped-command-list.
 type WrapperMap = HashMap<WString, Vec<WString>>;
 static WRAPPER_MAP: LazyLock<Mutex<WrapperMap>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 
+/// Cached results of `--arguments` for commands with a `--cache-ttl`. Keyed by the working
+/// directory, the command line before the token being completed, and the arguments script.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ArgumentCacheKey {
+    pwd: WString,
+    commandline: WString,
+    arguments: WString,
+}
+struct ArgumentCacheEntry {
+    created: Instant,
+    completions: CompletionList,
+}
+type ArgumentCache = LruCache<ArgumentCacheKey, ArgumentCacheEntry>;
+const ARGUMENT_CACHE_SIZE: usize = 256;
+static ARGUMENT_CACHE: LazyLock<Mutex<ArgumentCache>> = LazyLock::new(|| {
+    Mutex::new(ArgumentCache::new(
+        NonZeroUsize::new(ARGUMENT_CACHE_SIZE).unwrap(),
+    ))
+});
+
 /// Clear the [`CompleteFlags::AUTO_SPACE`] flag, and set [`CompleteFlags::NO_SPACE`] appropriately
 /// depending on the suffix of the string.
 fn resolve_auto_space(comp: &wstr, mut flags: CompleteFlags) -> CompleteFlags {
@@ -597,6 +633,8 @@ struct Completer<'ctx, 'parser> {
     /// Table of completions conditions that have already been tested and the corresponding test
     /// results.
     condition_cache: HashMap<WString, bool>,
+    /// The command line before the argument being completed, for caching argument completions.
+    commandline_prefix: WString,
 }
 
 static COMPLETION_AUTOLOADER: LazyLock<Mutex<Autoload>> =
@@ -611,6 +649,7 @@ pub fn new(ctx: &'ctx mut OperationContext<'parser>, flags: CompletionRequestOpt
             completions: CompletionReceiver::new(expansion_limit),
             needs_load: vec![],
             condition_cache: HashMap::new(),
+            commandline_prefix: WString::new(),
         }
     }
 
@@ -784,6 +823,16 @@ fn perform_for_commandline_impl(&mut self, cmdline: WString) {
             }
         }
 
+        let mut prefix_end = if current_argument.is_empty() {
+            position_in_statement
+        } else {
+            cur_tok.offset()
+        };
+        while prefix_end > 0 && cmdline.char_at(prefix_end - 1) == ' ' {
+            prefix_end -= 1;
+        }
+        self.commandline_prefix = cmdline[..prefix_end].to_owned();
+
         let mut do_file = false;
         let mut handle_as_special_cd = false;
         if in_redirection {
@@ -1210,7 +1259,62 @@ fn complete_abbr(&mut self, cmd: &wstr) {
     /// - `args`: The list of option arguments to be evaluated.
     /// - `desc`: Description of the completion
     /// - `flags`: The flags
-    fn complete_from_args(&mut self, s: &wstr, args: &wstr, desc: &wstr, flags: CompleteFlags) {
+    /// - `cache_ttl`: How long the expanded arguments may be reused, if at all.
+    fn complete_from_args(
+        &mut self,
+        s: &wstr,
+        args: &wstr,
+        desc: &wstr,
+        flags: CompleteFlags,
+        cache_ttl: Option<Duration>,
+    ) {
+        let cache_key = cache_ttl.map(|_| ArgumentCacheKey {
+            pwd: self.ctx.vars().get_pwd_slash(),
+            commandline: self.commandline_prefix.clone(),
+            arguments: args.to_owned(),
+        });
+        let cached = cache_key.as_ref().and_then(|key| {
+            let mut cache = ARGUMENT_CACHE.lock().unwrap();
+            let entry = cache.get(key)?;
+            if entry.created.elapsed() < cache_ttl.unwrap() {
+                return Some(entry.completions.clone());
+            }
+            cache.pop(key);
+            None
+        });
+        let possible_comp = match cached {
+            Some(possible_comp) => {
+                flog!(complete, "Using cached arguments for", args);
+                possible_comp
+            }
+            None => {
+                let possible_comp = self.expand_args(args);
+                // Autosuggestions do not run command substitutions, so their results are incomplete.
+                if let Some(key) = cache_key {
+                    if !self.flags.autosuggestion && !self.ctx.check_cancel() {
+                        let entry = ArgumentCacheEntry {
+                            created: Instant::now(),
+                            completions: possible_comp.clone(),
+                        };
+                        ARGUMENT_CACHE.lock().unwrap().put(key, entry);
+                    }
+                }
+                possible_comp
+            }
+        };
+
+        // Allow leading dots - see #3707.
+        self.complete_strings(
+            &escape(s),
+            &const_desc(desc),
+            &possible_comp,
+            flags,
+            ExpandFlags::ALLOW_NONLITERAL_LEADING_DOT,
+        );
+    }
+
+    /// Evaluate the given `--arguments` script, without letting it affect `$status`.
+    fn expand_args(&mut self, args: &wstr) -> CompletionList {
         let is_autosuggest = self.flags.autosuggestion;
 
         let mut saved_statuses = None;
@@ -1232,15 +1336,7 @@ fn complete_from_args(&mut self, s: &wstr, args: &wstr, desc: &wstr, flags: Comp
             parser.set_last_statuses(saved_statuses.unwrap());
         }
         std::mem::drop(scope);
-
-        // Allow leading dots - see #3707.
-        self.complete_strings(
-            &escape(s),
-            &const_desc(desc),
-            &possible_comp,
-            flags,
-            ExpandFlags::ALLOW_NONLITERAL_LEADING_DOT,
-        );
+        possible_comp
     }
 
     /// complete_param: Given a command, find completions for the argument `s` of command `cmd_orig`
@@ -1291,7 +1387,7 @@ fn complete_param_for_command(
         }
 
         // Make a list of lists of all options that we care about.
-        let all_options: Vec<Vec<CompleteEntryOpt>> = COMPLETION_MAP
+        let all_options: Vec<(Vec<CompleteEntryOpt>, Option<Duration>)> = COMPLETION_MAP
             .lock()
             .unwrap()
             .iter()
@@ -1314,7 +1410,7 @@ fn complete_param_for_command(
                     let mut options = completion.get_options().to_vec();
                     // We have to copy them in reverse order to preserve legacy behavior (#9221).
                     options.reverse();
-                    Some(options)
+                    Some((options, completion.cache_ttl))
                 } else {
                     None
                 }
@@ -1323,7 +1419,7 @@ fn complete_param_for_command(
 
         // Now release the lock and test each option that we captured above. We have to do this outside
         // the lock because callouts (like the condition) may add or remove completions. See issue #2.
-        for options in all_options {
+        for (options, cache_ttl) in all_options {
             let short_opt_pos = self.short_option_pos(s, &options);
             // We want last_option_requires_param to default to false but distinguish between when
             // a previous completion has set it to false and when it has its default value.
@@ -1366,7 +1462,13 @@ fn complete_param_for_command(
                                 }
                                 let (arg_prefix, arg) = s.split_once(arg_offset);
                                 let first_new = self.completions.completions.len();
-                                self.complete_from_args(arg, &o.comp, o.desc.localize(), o.flags);
+                                self.complete_from_args(
+                                    arg,
+                                    &o.comp,
+                                    o.desc.localize(),
+                                    o.flags,
+                                    cache_ttl,
+                                );
                                 for compl in &mut self.completions.completions[first_new..] {
                                     if compl.replaces_token() {
                                         compl.completion.insert_utfstr(0, arg_prefix);
@@ -1397,7 +1499,13 @@ fn complete_param_for_command(
                             if o.result_mode.force_files {
                                 has_force = true;
                             }
-                            self.complete_from_args(s, &o.comp, o.desc.localize(), o.flags);
+                            self.complete_from_args(
+                                s,
+                                &o.comp,
+                                o.desc.localize(),
+                                o.flags,
+                                cache_ttl,
+                            );
                         }
                     }
 
@@ -1433,7 +1541,13 @@ fn complete_param_for_command(
                                 if o.result_mode.force_files {
                                     has_force = true;
                                 }
-                                self.complete_from_args(s, &o.comp, o.desc.localize(), o.flags);
+                                self.complete_from_args(
+                                    s,
+                                    &o.comp,
+                                    o.desc.localize(),
+                                    o.flags,
+                                    cache_ttl,
+                                );
                             }
                         }
                     }
@@ -1456,7 +1570,7 @@ fn complete_param_for_command(
                 if o.option.is_empty() {
                     use_files &= !o.result_mode.no_files;
                     has_force |= o.result_mode.force_files;
-                    self.complete_from_args(s, &o.comp, o.desc.localize(), o.flags);
+                    self.complete_from_args(s, &o.comp, o.desc.localize(), o.flags, cache_ttl);
                 }
 
                 if !use_switches || s.is_empty() {
@@ -2330,6 +2444,19 @@ pub fn complete_remove(cmd: WString, cmd_is_path: bool, option: &wstr, typ: Comp
     }
 }
 
+/// Sets how long the results of `--arguments` for the given command may be reused.
+/// `None` disables caching.
+pub fn complete_set_cache_ttl(cmd: WString, cmd_is_path: bool, ttl: Option<Duration>) {
+    let mut completion_map = COMPLETION_MAP.lock().expect("mutex poisoned");
+    let c = completion_map
+        .entry(CompletionEntryIndex {
+            name: cmd,
+            is_path: cmd_is_path,
+        })
+        .or_insert_with(CompletionEntry::new);
+    c.cache_ttl = ttl;
+}
+
 /// Removes all completions for a given command.
 pub fn complete_remove_all(cmd: WString, cmd_is_path: bool, explicit: bool) {
     let mut completion_map = COMPLETION_MAP.lock().expect("mutex poisoned");
@@ -2464,6 +2591,14 @@ pub fn complete_load(cmd: &wstr, parser: &mut Parser) -> bool {
         loaded_new |= complete_wrap_map().get(cmd).is_some();
     }
 
+    let from_argparse = ARGPARSE_FUNCTIONS.lock().unwrap().remove(cmd);
+    if from_argparse {
+        for command in argparse::function_completion_commands(parser, cmd) {
+            parser.eval(&command, &IoChain::new());
+        }
+        loaded_new = true;
+    }
+
     // It's important to NOT hold the lock around completion loading.
     // We need to take the lock to decide what to load, drop it to perform the load, then reacquire
     // it.
@@ -2511,6 +2646,17 @@ pub fn complete_print(cmd: &wstr) -> WString {
         for o in entry.get_options().iter().rev() {
             out.push_utfstr(&completion2string(key, o));
         }
+        if let Some(ttl) = entry.cache_ttl {
+            out.push_utfstr(L!("complete"));
+            if key.is_path {
+                append_switch_short_arg(&mut out, 'p', &key.name);
+            } else {
+                out.push(' ');
+                out.push_utfstr(&escape(&key.name));
+            }
+            append_switch_long_arg(&mut out, L!("cache-ttl"), &ttl.as_secs().to_wstring());
+            out.push('\n');
+        }
     }
 
     // Append wraps.
@@ -2545,6 +2691,17 @@ pub fn complete_invalidate_path() {
     }
 }
 
+/// Observes that the working directory has changed, which may change the results of any
+/// cached `--arguments`.
+pub fn complete_invalidate_argument_cache() {
+    ARGUMENT_CACHE.lock().expect("mutex poisoned").clear();
+}
+
+/// Generate the completions of a function from its `argparse` call, the next time it is completed.
+pub fn complete_add_from_argparse(function: WString) {
+    ARGPARSE_FUNCTIONS.lock().unwrap().insert(function);
+}
+
 /// Adds a "wrap target." A wrap target is a command that completes like another command.
 pub fn complete_add_wrapper(command: WString, new_target: WString) -> bool {
     if command.is_empty() || new_target.is_empty() {
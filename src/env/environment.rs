@@ -4,13 +4,14 @@
 };
 use crate::{
     abbrs::{Abbreviation, Position, abbrs_get_set},
-    builtins::{BuiltinResult, SUCCESS},
+    builtins::{BuiltinResult, STATUS_RESTRICTED, SUCCESS},
     env::{
-        EnvMode, EnvSetMode, EnvVar, Statuses,
+        ComputedVar, EnvMode, EnvSetMode, EnvVar, Statuses,
         config_paths::{ConfigPaths, PREFIX},
         r#impl::is_electric_var,
     },
     env_dispatch::{VarChangeMilieu, env_dispatch_init, env_dispatch_var_change},
+    env_universal_common::{UvarLayer, UvarMetadata},
     event::Event,
     flog::flog,
     global_safety::RelaxedAtomicBool,
@@ -50,10 +51,11 @@
 pub enum EnvStackSetResult {
     #[default]
     Ok, // The variable was set successfully.
-    Perm,     // The variable is read-only.
-    Scope,    // Variable cannot be set in the given scope.
-    Invalid,  // The variable's value is invalid (e.g. umask).
-    NotFound, // The variable was not found (only possible when removing a variable).
+    Perm,       // The variable is read-only.
+    Scope,      // Variable cannot be set in the given scope.
+    Invalid,    // The variable's value is invalid (e.g. umask).
+    NotFound,   // The variable was not found (only possible when removing a variable).
+    Restricted, // Universal variables and search paths cannot be changed in restricted mode.
 }
 
 impl From<EnvStackSetResult> for BuiltinResult {
@@ -64,6 +66,7 @@ fn from(r: EnvStackSetResult) -> Self {
             EnvStackSetResult::Scope => Err(2),
             EnvStackSetResult::Invalid => Err(3),
             EnvStackSetResult::NotFound => Err(4),
+            EnvStackSetResult::Restricted => Err(STATUS_RESTRICTED),
         }
     }
 }
@@ -240,6 +243,35 @@ pub fn set(&self, key: &wstr, mode: EnvSetMode, mut vals: Vec<WString>) -> EnvSt
         }
 
         let ret: ModResult = self.lock().set(key, mode, vals);
+        self.finish_set(key, mode, ret)
+    }
+
+    /// Sets the associative variable with the specified name, where `vals[i]` is the value of
+    /// `keys[i]`.
+    pub fn set_map(
+        &self,
+        key: &wstr,
+        mode: EnvSetMode,
+        keys: Vec<WString>,
+        vals: Vec<WString>,
+    ) -> EnvStackSetResult {
+        let ret: ModResult = self.lock().set_map(key, mode, keys, vals);
+        self.finish_set(key, mode, ret)
+    }
+
+    /// Sets the computed variable with the specified name.
+    pub fn set_computed(
+        &self,
+        key: &wstr,
+        mode: EnvSetMode,
+        computed: Arc<ComputedVar>,
+    ) -> EnvStackSetResult {
+        let ret: ModResult = self.lock().set_computed(key, mode, computed);
+        self.finish_set(key, mode, ret)
+    }
+
+    /// Dispatch the changes after setting a variable, and return the status.
+    fn finish_set(&self, key: &wstr, mode: EnvSetMode, ret: ModResult) -> EnvStackSetResult {
         if ret.status == EnvStackSetResult::Ok {
             // Dispatch changes if we modified the global state or have 'dispatches_var_changes' set.
             // Important to not hold the lock here.
@@ -347,6 +379,30 @@ pub fn pop(&self, is_repainting: bool) {
         }
     }
 
+    /// Replace the global variables with a copy of them, until the matching [`Self::pop_sandbox`].
+    /// Universal variables are not changed while in a sandbox; setting one sets a global variable
+    /// in the sandbox instead. This is used to run tests without side effects.
+    pub fn push_sandbox(&self) {
+        assert!(self.can_push_pop, "push/pop not allowed on global stack");
+        self.lock().push_sandbox();
+    }
+
+    /// Throw away the global variables of the innermost sandbox.
+    pub fn pop_sandbox(&self, is_repainting: bool) {
+        assert!(self.can_push_pop, "push/pop not allowed on global stack");
+        let changed = self.lock().pop_sandbox();
+        for key in changed {
+            env_dispatch_var_change(
+                VarChangeMilieu {
+                    is_repainting,
+                    global_or_universal: true,
+                },
+                &key,
+                self,
+            );
+        }
+    }
+
     /// Returns an array containing all exported variables in a format suitable for execv.
     pub fn export_array(&self) -> Arc<OwningNullTerminatedArray> {
         self.lock().base.export_array()
@@ -562,6 +618,23 @@ fn setup_path(global_exported_mode: EnvSetMode) {
 /// This is a simple key->value map and not e.g. cut into paths.
 pub static INHERITED_VARS: OnceLock<HashMap<WString, WString>> = OnceLock::new();
 
+/// Return which layer of the universal variable files the value of `name` comes from, or `None`
+/// if there is no such universal variable.
+pub fn universal_var_layer(name: &wstr) -> Option<UvarLayer> {
+    uvars().get_layer(name)
+}
+
+/// Return when and where the universal variable `name` was last changed, if known.
+pub fn universal_var_metadata(name: &wstr) -> Option<UvarMetadata> {
+    uvars().get_metadata(name)
+}
+
+/// Return the path of the system defaults file for universal variables, or an empty string if
+/// there is none.
+pub fn universal_defaults_path() -> WString {
+    uvars().defaults_path().to_owned()
+}
+
 pub fn env_init(paths: Option<&ConfigPaths>, no_config: bool) {
     let vars = EnvStack::globals();
 
@@ -727,8 +800,14 @@ pub fn env_init(paths: Option<&ConfigPaths>, no_config: bool) {
         return;
     }
 
-    // Set up universal variables using the default path.
-    let callbacks = uvars().initialize().unwrap_or_default();
+    // Set up universal variables using the default path, on top of the system defaults.
+    let callbacks = {
+        let mut uvars_locked = uvars();
+        if let Some(paths) = paths {
+            uvars_locked.set_defaults_path(osstr2wcstring(paths.sysconf.join("fish_variables")));
+        }
+        uvars_locked.initialize().unwrap_or_default()
+    };
     for callback in callbacks {
         env_dispatch_var_change(
             VarChangeMilieu {
@@ -870,6 +949,73 @@ fn test_env_snapshot() {
         parser.popd(pushed_dirs);
     }
 
+    #[test]
+    #[serial]
+    fn test_env_sandbox() {
+        test_init();
+        let parser = TestParser::new();
+        let vars = parser.vars();
+        let global = EnvSetMode::new(EnvMode::GLOBAL, false);
+        let universal = EnvSetMode::new(EnvMode::UNIVERSAL, false);
+        vars.set_one(L!("test_env_sandbox_var"), global, L!("before").to_owned());
+
+        vars.push_sandbox();
+        assert_eq!(
+            vars.get(L!("test_env_sandbox_var")).unwrap().as_string(),
+            L!("before")
+        );
+        vars.set_one(L!("test_env_sandbox_var"), global, L!("inside").to_owned());
+        vars.set_one(
+            L!("test_env_sandbox_var_2"),
+            global,
+            L!("inside").to_owned(),
+        );
+        vars.set_one(
+            L!("test_env_sandbox_uvar"),
+            universal,
+            L!("inside").to_owned(),
+        );
+        assert_eq!(
+            vars.get(L!("test_env_sandbox_var")).unwrap().as_string(),
+            L!("inside")
+        );
+        // The sandbox covers the global variables seen without a parser.
+        assert_eq!(
+            EnvStack::globals()
+                .get(L!("test_env_sandbox_var"))
+                .unwrap()
+                .as_string(),
+            L!("inside")
+        );
+        // Universal variables become global ones.
+        assert_eq!(
+            vars.getf(L!("test_env_sandbox_uvar"), EnvMode::GLOBAL)
+                .unwrap()
+                .as_string(),
+            L!("inside")
+        );
+        assert_eq!(
+            vars.getf(L!("test_env_sandbox_uvar"), EnvMode::UNIVERSAL),
+            None
+        );
+        vars.pop_sandbox(false);
+
+        assert_eq!(
+            vars.get(L!("test_env_sandbox_var")).unwrap().as_string(),
+            L!("before")
+        );
+        assert_eq!(vars.get(L!("test_env_sandbox_var_2")), None);
+        assert_eq!(vars.get(L!("test_env_sandbox_uvar")), None);
+        assert_eq!(
+            EnvStack::globals()
+                .get(L!("test_env_sandbox_var"))
+                .unwrap()
+                .as_string(),
+            L!("before")
+        );
+        vars.remove(L!("test_env_sandbox_var"), global);
+    }
+
     // Can't push/pop from globals.
     #[test]
     #[should_panic]
@@ -1,4 +1,4 @@
-use super::var::{ELECTRIC_VARIABLES, ElectricVar, is_read_only};
+use super::var::{ComputedVar, ELECTRIC_VARIABLES, ElectricVar, is_read_only};
 use crate::env::{
     EnvMode, EnvSetMode, EnvStackSetResult, EnvVar, EnvVarFlags, PATH_ARRAY_SEP, Statuses, VarTable,
 };
@@ -418,7 +418,9 @@ pub fn getf(&self, key: &wstr, mode: EnvMode) -> Option<EnvVar> {
         if result.is_some() && !query.pathvar_matches(result.as_ref().unwrap()) {
             result = None;
         }
-        result
+        // Variables from `set --computed` have the values they were last computed to. The parser
+        // computes them when they are expanded.
+        result.map(EnvVar::resolving_computed)
     }
 
     pub fn get_names(&self, flags: EnvMode) -> Vec<WString> {
@@ -610,6 +612,16 @@ struct VarFlags {
     pub parent_exports: bool,
 }
 
+/// The kind of variable being set.
+enum VarKind {
+    /// A plain list of values.
+    List,
+    /// An associative variable with these keys, one per value.
+    Map(Vec<WString>),
+    /// A computed variable, whose values come from running its source.
+    Computed(Arc<ComputedVar>),
+}
+
 #[derive(Copy, Clone, Default)]
 pub struct ModResult {
     /// The publicly visible status of the set call.
@@ -639,6 +651,9 @@ pub struct EnvStackImpl {
 
     /// The scopes of caller functions, which are currently shadowed.
     shadowed_locals: Vec<EnvNodeRef>,
+
+    /// The global variables to restore when leaving a sandbox, see [`EnvStackImpl::push_sandbox`].
+    sandboxed_globals: Vec<VarTable>,
 }
 
 impl EnvStackImpl {
@@ -650,12 +665,55 @@ pub fn new() -> EnvMutex<EnvStackImpl> {
         EnvMutex::new(EnvStackImpl {
             base,
             shadowed_locals: Vec::new(),
+            sandboxed_globals: Vec::new(),
         })
     }
 
     /// Set a variable under the name `key`, using the given `mode`, setting its value to `val`.
-    pub fn set(&mut self, key: &wstr, mode: EnvSetMode, mut val: Vec<WString>) -> ModResult {
+    pub fn set(&mut self, key: &wstr, mode: EnvSetMode, val: Vec<WString>) -> ModResult {
+        self.set_with_kind(key, mode, VarKind::List, val)
+    }
+
+    /// Set an associative variable under the name `key`, using the given `mode`, where `val[i]` is
+    /// the value of `keys[i]`.
+    pub fn set_map(
+        &mut self,
+        key: &wstr,
+        mode: EnvSetMode,
+        keys: Vec<WString>,
+        val: Vec<WString>,
+    ) -> ModResult {
+        self.set_with_kind(key, mode, VarKind::Map(keys), val)
+    }
+
+    /// Set a computed variable under the name `key`, using the given `mode`.
+    pub fn set_computed(
+        &mut self,
+        key: &wstr,
+        mode: EnvSetMode,
+        computed: Arc<ComputedVar>,
+    ) -> ModResult {
+        self.set_with_kind(key, mode, VarKind::Computed(computed), vec![])
+    }
+
+    fn set_with_kind(
+        &mut self,
+        key: &wstr,
+        mode: EnvSetMode,
+        kind: VarKind,
+        mut val: Vec<WString>,
+    ) -> ModResult {
         let query = Query::from(mode);
+        if !matches!(kind, VarKind::List) {
+            // Electric variables are always lists.
+            if let Some(ev) = ElectricVar::for_name(key) {
+                return ModResult::new(if query.user && ev.readonly() {
+                    EnvStackSetResult::Perm
+                } else {
+                    EnvStackSetResult::Invalid
+                });
+            }
+        }
         // Handle electric and read-only variables.
         if let Some(ret) = self.try_set_electric(key, &query, &mut val) {
             return ModResult::new(ret);
@@ -677,23 +735,32 @@ pub fn set(&mut self, key: &wstr, mode: EnvSetMode, mut val: Vec<WString>) -> Mo
         if query.has_pathvar_unpathvar {
             flags.pathvar = Some(query.pathvar);
         }
+        // Associative and computed variables can not be exported, and are never split on colons.
+        if !matches!(kind, VarKind::List) {
+            flags.exports = Some(false);
+            flags.pathvar = Some(false);
+        }
+        let can_be_universal = !matches!(kind, VarKind::Computed(_));
 
         let mut result = ModResult::new(EnvStackSetResult::Ok);
         if query.has_scope {
             // The user requested a particular scope.
             // If we don't have uvars, fall back to using globals.
-            if query.universal && !UVAR_SCOPE_IS_GLOBAL.load() {
-                self.set_universal(key, val, query);
+            if query.universal && !self.universal_scope_is_global() {
+                if !can_be_universal {
+                    return ModResult::new(EnvStackSetResult::Scope);
+                }
+                self.set_universal(key, val, kind, query);
                 result.uvar_modified = true;
-            } else if query.global || (query.universal && UVAR_SCOPE_IS_GLOBAL.load()) {
-                Self::set_in_node(&mut self.base.globals, key, val, flags);
+            } else if query.global || query.universal {
+                Self::set_in_node(&mut self.base.globals, key, val, kind, flags);
                 result.global_modified = true;
             } else if query.local {
                 assert!(
                     !self.base.locals.ptr_eq(&self.base.globals),
                     "Locals should not be globals"
                 );
-                Self::set_in_node(&mut self.base.locals, key, val, flags);
+                Self::set_in_node(&mut self.base.locals, key, val, kind, flags);
             } else if query.function {
                 // "Function" scope is:
                 // Either the topmost local scope of the nearest function,
@@ -711,25 +778,32 @@ pub fn set(&mut self, key: &wstr, mode: EnvSetMode, mut val: Vec<WString>) -> Mo
                         break;
                     }
                 }
-                Self::set_in_node(&mut node, key, val, flags);
+                Self::set_in_node(&mut node, key, val, kind, flags);
             } else {
                 panic!("Unknown scope");
             }
         } else if let Some(mut node) = Self::find_in_chain(&self.base.locals, key) {
             // Existing local variable.
-            Self::set_in_node(&mut node, key, val, flags);
+            Self::set_in_node(&mut node, key, val, kind, flags);
         } else if let Some(mut node) = Self::find_in_chain(&self.base.globals, key) {
             // Existing global variable.
-            Self::set_in_node(&mut node, key, val, flags);
+            Self::set_in_node(&mut node, key, val, kind, flags);
             result.global_modified = true;
         } else if !UVAR_SCOPE_IS_GLOBAL.load() && uvars().get(key).is_some() {
-            // Existing universal variable.
-            self.set_universal(key, val, query);
-            result.uvar_modified = true;
+            if self.sandboxed_globals.is_empty() && can_be_universal {
+                // Existing universal variable.
+                self.set_universal(key, val, kind, query);
+                result.uvar_modified = true;
+            } else {
+                // Shadow the universal variable with a global one in the sandbox, or if the new
+                // variable can't be universal.
+                Self::set_in_node(&mut self.base.globals, key, val, kind, flags);
+                result.global_modified = true;
+            }
         } else {
             // Unspecified scope with no existing variables.
             let mut node = self.resolve_unspecified_scope();
-            Self::set_in_node(&mut node, key, val, flags);
+            Self::set_in_node(&mut node, key, val, kind, flags);
             result.global_modified = node.ptr_eq(&self.base.globals);
         }
         result
@@ -755,7 +829,10 @@ fn remove_from_chain(node: &mut EnvNodeRef, key: &wstr) -> EnvStackSetResult {
         let mut result = ModResult::new(EnvStackSetResult::Ok);
         if query.has_scope {
             // The user requested erasing from a particular scope.
-            if query.universal {
+            if query.universal && !self.sandboxed_globals.is_empty() {
+                result.status = remove_from_chain(&mut self.base.globals, key);
+                result.global_modified = true;
+            } else if query.universal {
                 if uvars().remove(key) {
                     result.status = EnvStackSetResult::Ok;
                 } else {
@@ -784,7 +861,7 @@ fn remove_from_chain(node: &mut EnvNodeRef, key: &wstr) -> EnvStackSetResult {
             // pass
         } else if Self::remove_from_chain(&mut self.base.globals, key) {
             result.global_modified = true;
-        } else if uvars().remove(key) {
+        } else if self.sandboxed_globals.is_empty() && uvars().remove(key) {
             result.uvar_modified = true;
         } else {
             result.status = EnvStackSetResult::NotFound;
@@ -838,6 +915,46 @@ pub fn pop(&mut self) -> Vec<WString> {
         var_names
     }
 
+    /// Save the global variables, to be restored by the matching [`Self::pop_sandbox`].
+    /// The global scope is shared by every variable stack, including the one from
+    /// [`EnvStack::globals`](crate::env::EnvStack::globals), so they all see changes made inside
+    /// the sandbox. Setting universal variables sets global ones instead.
+    pub fn push_sandbox(&mut self) {
+        let saved = self.base.globals.borrow().env.clone();
+        self.sandboxed_globals.push(saved);
+    }
+
+    /// Restore the global variables from before the innermost sandbox.
+    /// Return the names of the variables which differ between the two.
+    pub fn pop_sandbox(&mut self) -> Vec<WString> {
+        let saved = self
+            .sandboxed_globals
+            .pop()
+            .expect("Attempt to pop without a sandbox");
+        let mut globals = self.base.globals.borrow_mut();
+        let sandbox = mem::replace(&mut globals.env, saved);
+        globals.changed_exported();
+        let mut changed: Vec<WString> = sandbox
+            .iter()
+            .filter(|(key, val)| globals.env.get(*key) != Some(*val))
+            .map(|(key, _)| key.clone())
+            .collect();
+        changed.extend(
+            globals
+                .env
+                .keys()
+                .filter(|key| !sandbox.contains_key(*key))
+                .cloned(),
+        );
+        changed
+    }
+
+    /// Whether universal variables are set as global ones, because there are none or we are in a
+    /// sandbox.
+    fn universal_scope_is_global(&self) -> bool {
+        UVAR_SCOPE_IS_GLOBAL.load() || !self.sandboxed_globals.is_empty()
+    }
+
     /// Find the first node in the chain starting at `node` which contains the given key `key`.
     fn find_in_chain(node: &EnvNodeRef, key: &wstr) -> Option<EnvNodeRef> {
         #[allow(clippy::manual_find)]
@@ -919,19 +1036,26 @@ fn try_set_electric(
             parent_exports: ev.exports(),
             pathvar: Some(false),
         };
-        Self::set_in_node(&mut self.base.globals, key, val, flags);
+        Self::set_in_node(&mut self.base.globals, key, val, VarKind::List, flags);
         Some(EnvStackSetResult::Ok)
     }
 
     /// Set a universal variable, inheriting as applicable from the given old variable.
-    fn set_universal(&mut self, key: &wstr, mut val: Vec<WString>, query: Query) {
+    fn set_universal(&mut self, key: &wstr, mut val: Vec<WString>, kind: VarKind, query: Query) {
+        let keys = match kind {
+            VarKind::List => None,
+            VarKind::Map(keys) => Some(keys),
+            VarKind::Computed(_) => unreachable!("computed variables are never universal"),
+        };
         let mut locked_uvars = uvars();
         let oldvar = locked_uvars.get(key);
         let oldvar = oldvar.as_ref();
 
-        // Resolve whether or not to export.
+        // Resolve whether or not to export. Associative variables are never exported.
         let mut exports = false;
-        if query.has_export_unexport {
+        if keys.is_some() {
+            exports = false;
+        } else if query.has_export_unexport {
             exports = query.exports;
         } else if let Some(v) = oldvar {
             exports = v.exports();
@@ -940,7 +1064,9 @@ fn set_universal(&mut self, key: &wstr, mut val: Vec<WString>, query: Query) {
         // Resolve whether to be a path variable.
         // Here we fall back to the auto-pathvar behavior.
         let pathvar;
-        if query.has_pathvar_unpathvar {
+        if keys.is_some() {
+            pathvar = false;
+        } else if query.has_pathvar_unpathvar {
             pathvar = query.pathvar;
         } else if let Some(v) = oldvar {
             pathvar = v.is_pathvar();
@@ -957,13 +1083,22 @@ fn set_universal(&mut self, key: &wstr, mut val: Vec<WString>, query: Query) {
         let mut varflags = EnvVarFlags::empty();
         varflags.set(EnvVarFlags::EXPORT, exports);
         varflags.set(EnvVarFlags::PATHVAR, pathvar);
-        let new_var = EnvVar::new_vec(val, varflags);
+        let new_var = match keys {
+            Some(keys) => EnvVar::new_map(keys, val, varflags),
+            None => EnvVar::new_vec(val, varflags),
+        };
 
         locked_uvars.set(key, new_var);
     }
 
     /// Set a variable in a given node `node`.
-    fn set_in_node(node: &mut EnvNodeRef, key: &wstr, mut val: Vec<WString>, flags: VarFlags) {
+    fn set_in_node(
+        node: &mut EnvNodeRef,
+        key: &wstr,
+        mut val: Vec<WString>,
+        kind: VarKind,
+        flags: VarFlags,
+    ) {
         // Read the var from the node. In C++ this was node->env[key] which establishes a default.
         let mut node_ref = node.borrow_mut();
         let var = node_ref.env.entry(key.to_owned()).or_default();
@@ -983,8 +1118,12 @@ fn set_in_node(node: &mut EnvNodeRef, key: &wstr, mut val: Vec<WString>, flags:
             val = colon_split(&val);
         }
 
-        *var = var
-            .setting_vals(val)
+        let new_var = match kind {
+            VarKind::List => var.setting_vals(val),
+            VarKind::Map(keys) => var.setting_map(keys, val),
+            VarKind::Computed(computed) => var.setting_computed(computed),
+        };
+        *var = new_var
             .setting_exports(res_exports)
             .setting_pathvar(res_pathvar);
 
@@ -5,7 +5,9 @@
 use fish_widestring::{L, WString, wstr};
 use libc::c_int;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// The character used to delimit path and non-path variables in exporting and in string expansion.
 pub const PATH_ARRAY_SEP: char = ':';
@@ -117,6 +119,111 @@ pub struct EnvVarFlags: u8 {
     }
 }
 
+/// The values of a computed variable, and when they were computed.
+#[derive(Debug)]
+struct ComputedCache {
+    values: Vec<WString>,
+    /// None if the values were invalidated.
+    computed_at: Option<Instant>,
+}
+
+/// The definition of a variable set with `set --computed`, whose value is the output of fish code.
+/// The code is run by the parser when the variable is expanded, and the output is cached until the
+/// TTL runs out or the cache is invalidated. All copies of the variable share the cache.
+#[derive(Debug)]
+pub struct ComputedVar {
+    source: WString,
+    ttl: Option<Duration>,
+    restricted_commands: Option<Vec<WString>>,
+    cache: Mutex<Option<ComputedCache>>,
+    evaluating: AtomicBool,
+}
+
+impl ComputedVar {
+    pub fn new(
+        source: WString,
+        ttl: Option<Duration>,
+        restricted_commands: Option<Vec<WString>>,
+    ) -> Self {
+        ComputedVar {
+            source,
+            ttl,
+            restricted_commands,
+            cache: Mutex::new(None),
+            evaluating: AtomicBool::new(false),
+        }
+    }
+
+    /// The fish code which computes the value.
+    pub fn source(&self) -> &wstr {
+        &self.source
+    }
+
+    /// How long a computed value is kept, or None to keep it until invalidated.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// If the variable was defined by restricted code, the external commands its code may run.
+    /// The code is always run restricted, see `eval --restricted`.
+    pub fn restricted_commands(&self) -> Option<&[WString]> {
+        self.restricted_commands.as_deref()
+    }
+
+    /// Return the last computed values, even if they are stale, or None if never computed.
+    pub fn cached_values(&self) -> Option<Vec<WString>> {
+        let cache = self.cache.lock().unwrap();
+        cache.as_ref().map(|cache| cache.values.clone())
+    }
+
+    /// Return whether the value has been computed and is not stale.
+    pub fn is_fresh(&self) -> bool {
+        let cache = self.cache.lock().unwrap();
+        let Some(computed_at) = cache.as_ref().and_then(|cache| cache.computed_at) else {
+            return false;
+        };
+        self.ttl.is_none_or(|ttl| computed_at.elapsed() < ttl)
+    }
+
+    /// Store newly computed values. Return whether they differ from the previous ones.
+    pub fn store(&self, values: Vec<WString>) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        let changed = cache.as_ref().is_none_or(|cache| cache.values != values);
+        *cache = Some(ComputedCache {
+            values,
+            computed_at: Some(Instant::now()),
+        });
+        changed
+    }
+
+    /// Mark the cached value as stale, so it is computed again when next needed.
+    pub fn invalidate(&self) {
+        if let Some(cache) = self.cache.lock().unwrap().as_mut() {
+            cache.computed_at = None;
+        }
+    }
+
+    /// Mark that the value is being computed. Return false if it already is, which happens if the
+    /// code refers to the variable itself.
+    pub fn begin_evaluating(&self) -> bool {
+        !self.evaluating.swap(true, Ordering::Relaxed)
+    }
+
+    pub fn end_evaluating(&self) {
+        self.evaluating.store(false, Ordering::Relaxed);
+    }
+}
+
+impl PartialEq for ComputedVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+            && self.ttl == other.ttl
+            && self.restricted_commands == other.restricted_commands
+    }
+}
+
+impl Eq for ComputedVar {}
+
 /// EnvVar is an immutable value-type data structure representing the value of an environment
 /// variable.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -124,6 +231,11 @@ pub struct EnvVar {
     /// The list of values in this variable.
     /// Arc allows for cheap copying
     values: Arc<[WString]>,
+    /// For associative variables, the key of each value in `values`, in insertion order.
+    /// None for ordinary list variables.
+    keys: Option<Arc<[WString]>>,
+    /// For computed variables, the code computing the value and its cache.
+    computed: Option<Arc<ComputedVar>>,
     /// The variable's flags.
     flags: EnvVarFlags,
 }
@@ -136,6 +248,8 @@ fn default() -> Self {
 
         EnvVar {
             values: Arc::clone(&*EMPTY_LIST),
+            keys: None,
+            computed: None,
             flags: EnvVarFlags::empty(),
         }
     }
@@ -151,10 +265,31 @@ pub fn new(value: WString, flags: EnvVarFlags) -> Self {
     pub fn new_vec(values: Vec<WString>, flags: EnvVarFlags) -> Self {
         EnvVar {
             values: values.into(),
+            keys: None,
+            computed: None,
+            flags,
+        }
+    }
+
+    /// Creates a new associative `EnvVar`, where `values[i]` is the value of `keys[i]`.
+    pub fn new_map(keys: Vec<WString>, values: Vec<WString>, flags: EnvVarFlags) -> Self {
+        assert_eq!(keys.len(), values.len(), "Every key should have a value");
+        EnvVar {
+            values: values.into(),
+            keys: Some(keys.into()),
+            computed: None,
             flags,
         }
     }
 
+    /// Creates a new computed `EnvVar`, which has no values until computed.
+    pub fn new_computed(computed: Arc<ComputedVar>, flags: EnvVarFlags) -> Self {
+        EnvVar {
+            computed: Some(computed),
+            ..Self::new_vec(vec![], flags)
+        }
+    }
+
     /// Creates a new `EnvVar`, inferring the flags from the variable name.
     pub fn new_from_name_vec(name: &wstr, values: Vec<WString>) -> Self {
         Self::new_vec(values, Self::flags_for(name))
@@ -185,6 +320,41 @@ pub fn is_read_only(&self) -> bool {
         self.flags.contains(EnvVarFlags::READ_ONLY)
     }
 
+    /// Returns whether the variable is associative, i.e. maps keys to values.
+    pub fn is_map(&self) -> bool {
+        self.keys.is_some()
+    }
+
+    /// Returns the keys of an associative variable, or None for a list.
+    pub fn map_keys(&self) -> Option<&[WString]> {
+        self.keys.as_deref()
+    }
+
+    /// Returns the value for `key` if this is an associative variable which contains it.
+    pub fn map_get(&self, key: &wstr) -> Option<&WString> {
+        let keys = self.keys.as_ref()?;
+        let idx = keys.iter().position(|k| k == key)?;
+        Some(&self.values[idx])
+    }
+
+    /// Returns the definition of a computed variable, or None for other variables.
+    pub fn computed(&self) -> Option<&Arc<ComputedVar>> {
+        self.computed.as_ref()
+    }
+
+    /// Returns a computed variable with its last computed values, if any. Other variables are
+    /// returned unchanged.
+    pub fn resolving_computed(self) -> Self {
+        let values = match &self.computed {
+            Some(computed) => computed.cached_values().unwrap_or_default(),
+            None => return self,
+        };
+        EnvVar {
+            values: values.into(),
+            ..self
+        }
+    }
+
     /// Returns the variable's flags.
     pub fn flags(&self) -> EnvVarFlags {
         self.flags
@@ -195,7 +365,8 @@ pub fn as_string(&self) -> WString {
         join_strings(&self.values, self.delimiter())
     }
 
-    /// Returns the variable's values.
+    /// Returns the variable's values. For associative variables, these are the values in key
+    /// insertion order.
     pub fn as_list(&self) -> &[WString] {
         &self.values
     }
@@ -209,20 +380,34 @@ pub fn delimiter(&self) -> char {
         }
     }
 
-    /// Returns a copy of the variable with new values.
+    /// Returns a copy of the variable with new values. The result is always a list.
     pub fn setting_vals(&self, values: Vec<WString>) -> Self {
         EnvVar {
             values: values.into(),
+            keys: None,
+            computed: None,
             flags: self.flags,
         }
     }
 
+    /// Returns a copy of the variable as an associative variable with new keys and values.
+    pub fn setting_map(&self, keys: Vec<WString>, values: Vec<WString>) -> Self {
+        Self::new_map(keys, values, self.flags)
+    }
+
+    /// Returns a copy of the variable as a computed variable.
+    pub fn setting_computed(&self, computed: Arc<ComputedVar>) -> Self {
+        Self::new_computed(computed, self.flags)
+    }
+
     /// Returns a copy of the variable with the export flag changed.
     pub fn setting_exports(&self, export: bool) -> Self {
         let mut flags = self.flags;
         flags.set(EnvVarFlags::EXPORT, export);
         EnvVar {
             values: self.values.clone(),
+            keys: self.keys.clone(),
+            computed: self.computed.clone(),
             flags,
         }
     }
@@ -233,6 +418,8 @@ pub fn setting_pathvar(&self, pathvar: bool) -> Self {
         flags.set(EnvVarFlags::PATHVAR, pathvar);
         EnvVar {
             values: self.values.clone(),
+            keys: self.keys.clone(),
+            computed: self.computed.clone(),
             flags,
         }
     }
@@ -253,7 +440,7 @@ pub fn flags_for(name: &wstr) -> EnvVarFlags {
 
 #[cfg(test)]
 mod tests {
-    use super::{EnvMode, EnvVar, EnvVarFlags};
+    use super::{ComputedVar, EnvMode, EnvVar, EnvVarFlags};
     use crate::env::EnvSetMode;
     use crate::env::environment::{EnvStack, Environment as _};
     use crate::prelude::*;
@@ -261,7 +448,8 @@ mod tests {
     use assert_matches::assert_matches;
     use std::{
         mem::MaybeUninit,
-        time::{SystemTime, UNIX_EPOCH},
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
     };
 
     /// Helper for test_timezone_env_vars().
@@ -319,4 +507,63 @@ fn test_env_vars() {
         assert_ne!(v1, v3);
         assert_ne!(v1, v4);
     }
+
+    #[test]
+    fn test_env_var_map() {
+        let keys = vec![L!("b").to_owned(), L!("a").to_owned()];
+        let values = vec![L!("1").to_owned(), L!("2").to_owned()];
+        let map = EnvVar::new_map(keys.clone(), values.clone(), EnvVarFlags::empty());
+        assert!(map.is_map());
+        assert_eq!(map.map_keys(), Some(&keys[..]));
+        assert_eq!(map.map_get(L!("a")), Some(&L!("2").to_owned()));
+        assert_eq!(map.map_get(L!("c")), None);
+        // The values keep the insertion order of the keys.
+        assert_eq!(map.as_list(), &values[..]);
+        assert_eq!(map.as_string(), "1 2");
+
+        // A map is not equal to a list with the same values.
+        let list = EnvVar::new_vec(values.clone(), EnvVarFlags::empty());
+        assert_ne!(map, list);
+        assert!(!list.is_map());
+        assert_eq!(list.map_get(L!("a")), None);
+
+        // Setting new values turns it back into a list; changing flags keeps the keys.
+        assert_eq!(map.setting_vals(values), list);
+        assert!(map.setting_exports(false).is_map());
+    }
+
+    #[test]
+    fn test_env_var_computed() {
+        let computed = Arc::new(ComputedVar::new(L!("echo hi").to_owned(), None, None));
+        let var = EnvVar::new_computed(Arc::clone(&computed), EnvVarFlags::empty());
+        assert!(!computed.is_fresh());
+        assert!(var.clone().resolving_computed().as_list().is_empty());
+
+        let values = vec![L!("hi").to_owned()];
+        assert!(computed.store(values.clone()));
+        assert!(computed.is_fresh());
+        assert_eq!(var.clone().resolving_computed().as_list(), &values[..]);
+
+        // Invalidating keeps the stale values, and storing them again is not a change.
+        computed.invalidate();
+        assert!(!computed.is_fresh());
+        assert_eq!(computed.cached_values(), Some(values.clone()));
+        assert!(!computed.store(values.clone()));
+        assert!(computed.store(vec![]));
+
+        // Values expire after the TTL.
+        let expiring = ComputedVar::new(L!("echo hi").to_owned(), Some(Duration::ZERO), None);
+        expiring.store(values.clone());
+        assert!(!expiring.is_fresh());
+
+        // Evaluation doesn't nest.
+        assert!(computed.begin_evaluating());
+        assert!(!computed.begin_evaluating());
+        computed.end_evaluating();
+        assert!(computed.begin_evaluating());
+
+        // Setting new values turns it back into a list.
+        assert_eq!(var.setting_vals(values.clone()).computed(), None);
+        assert!(var.setting_exports(false).computed().is_some());
+    }
 }
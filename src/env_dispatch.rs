@@ -1,16 +1,18 @@
 use crate::common::init_special_chars_once;
-use crate::complete::complete_invalidate_path;
+use crate::complete::{complete_invalidate_argument_cache, complete_invalidate_path};
 use crate::env::{DEFAULT_READ_BYTE_LIMIT, READ_BYTE_LIMIT};
 use crate::env::{EnvMode, EnvStack, Environment as _, setenv_lock, unsetenv_lock};
 use crate::flog::flog;
 use crate::function;
-use crate::input::{update_wait_on_escape_ms, update_wait_on_sequence_key_ms};
+use crate::input::{
+    update_sequence_key_hint_delay_ms, update_wait_on_escape_ms, update_wait_on_sequence_key_ms,
+};
 use crate::locale::{invalidate_numeric_locale, set_libc_locales};
 use crate::prelude::*;
 use crate::reader::{
     reader_change_cursor_end_mode, reader_change_cursor_selection_mode, reader_change_history,
-    reader_current_data, reader_schedule_prompt_repaint, reader_set_autosuggestion_enabled,
-    reader_set_transient_prompt,
+    reader_current_data, reader_schedule_prompt_repaint, reader_set_abbr_preview,
+    reader_set_autosuggestion_enabled, reader_set_transient_prompt,
 };
 use crate::screen::{IS_DUMB, ONLY_GRAYSCALE, screen_set_midnight_commander_hack};
 use crate::terminal::ColorSupport;
@@ -60,6 +62,10 @@ macro_rules! vars {
             L!("fish_sequence_key_delay_ms"),
             vars!(update_wait_on_sequence_key_ms),
         );
+        table.add_anon(
+            L!("fish_sequence_key_hint_delay_ms"),
+            vars!(update_sequence_key_hint_delay_ms),
+        );
         table.add_anon(L!("fish_emoji_width"), vars!(handle_emoji_width));
         table.add_anon(
             L!("fish_ambiguous_width"),
@@ -68,7 +74,13 @@ macro_rules! vars {
         table.add_anon(L!("LINES"), vars!(handle_term_size_change));
         table.add_anon(L!("COLUMNS"), vars!(handle_term_size_change));
         table.add_anon(L!("fish_complete_path"), vars!(handle_complete_path_change));
+        table.add_anon(L!("PWD"), vars!(handle_pwd_change));
         table.add_anon(L!("fish_function_path"), vars!(handle_function_path_change));
+        #[cfg(feature = "localize-messages")]
+        table.add_anon(
+            L!("fish_translation_path"),
+            vars!(handle_translation_path_change),
+        );
         table.add_anon(L!("fish_read_limit"), vars!(handle_read_limit_change));
         table.add_anon(L!("fish_history"), vars!(handle_fish_history_change));
         table.add_anon(
@@ -79,6 +91,7 @@ macro_rules! vars {
             L!("fish_transient_prompt"),
             vars!(handle_transient_prompt_change),
         );
+        table.add_anon(L!("fish_abbr_preview"), vars!(handle_abbr_preview_change));
         table.add_anon(
             L!("fish_use_posix_spawn"),
             vars!(handle_fish_use_posix_spawn_change),
@@ -277,6 +290,10 @@ fn handle_transient_prompt_change(vars: &EnvStack) {
     reader_set_transient_prompt(vars);
 }
 
+fn handle_abbr_preview_change(vars: &EnvStack) {
+    reader_set_abbr_preview(vars);
+}
+
 fn handle_function_path_change(_: &EnvStack) {
     function::invalidate_path();
 }
@@ -285,6 +302,15 @@ fn handle_complete_path_change(_: &EnvStack) {
     complete_invalidate_path();
 }
 
+#[cfg(feature = "localize-messages")]
+fn handle_translation_path_change(_: &EnvStack) {
+    crate::localization::invalidate_translation_catalogs();
+}
+
+fn handle_pwd_change(_: &EnvStack) {
+    complete_invalidate_argument_cache();
+}
+
 fn handle_tz_change(var_name: &wstr, vars: &EnvStack) {
     handle_timezone(var_name, vars);
 }
@@ -364,6 +390,7 @@ fn run_inits(vars: &EnvStack) {
     handle_emoji_width(vars);
     update_wait_on_escape_ms(vars);
     update_wait_on_sequence_key_ms(vars);
+    update_sequence_key_hint_delay_ms(vars);
     handle_read_limit_change(vars);
     handle_fish_use_posix_spawn_change(vars);
     handle_fish_trace(vars);
@@ -7,14 +7,17 @@
 use crate::wutil::{FileId, INVALID_FILE_ID, file_id_for_file, file_id_for_path_narrow, wrealpath};
 use fish_common::{UnescapeFlags, UnescapeStringStyle, unescape_string};
 use fish_wcstringutil::{LineIterator, join_strings};
-use fish_widestring::{decode_byte_from_char, wcs2zstring};
+use fish_widestring::{decode_byte_from_char, osstr2wcstring, wcs2osstring, wcs2zstring};
 use itertools::Itertools as _;
-use std::collections::HashSet;
+use nix::unistd::gethostname;
+use std::cell::OnceCell;
 use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::fs::File;
 use std::io::{Read as _, Write as _};
 use std::mem::MaybeUninit;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Callback data, reflecting a change in universal variables.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -34,18 +37,64 @@ pub struct CallbackData {
 enum UvarFormat {
     Fish_2_x,
     Fish_3_0,
+    Fish_3_1,
     Future,
 }
 
+/// When and where a universal variable was last changed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UvarMetadata {
+    /// Seconds since the epoch.
+    pub mtime: i64,
+    /// The host name of the machine that changed it.
+    pub host: WString,
+}
+
+pub type UvarMetadataTable = HashMap<WString, UvarMetadata>;
+
+/// Where the value of a universal variable comes from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UvarLayer {
+    /// The read-only system defaults file.
+    System,
+    /// The user's own file.
+    User,
+    /// The user's own file, which overrides a value from the system defaults file.
+    UserOverridingSystem,
+}
+
 /// Class representing universal variables.
+///
+/// Variables come in two layers: the user's own file, which is read and written, and an optional
+/// system defaults file, which is only read. Values in the user's file take precedence.
 pub struct EnvUniversal {
     // Path that we save to. This is set in initialize(). If empty, initialize has not been called.
     vars_path: WString,
     narrow_vars_path: CString,
 
-    // The table of variables.
+    // The table of variables from the user's file.
     vars: VarTable,
 
+    // When and where the variables from the user's file were last changed, if known.
+    // Keys that are not in vars were erased at that time, so an older value from another instance
+    // does not bring them back.
+    metadata: UvarMetadataTable,
+
+    // Path of the read-only system defaults file, or empty if there is none.
+    defaults_path: WString,
+    narrow_defaults_path: CString,
+
+    // The table of variables from the system defaults file.
+    defaults: VarTable,
+    defaults_metadata: UvarMetadataTable,
+
+    // Both tables merged, see get_table(). This is only computed when needed, and reset whenever
+    // either table changes.
+    merged: OnceCell<VarTable>,
+
+    // File id of the system defaults file when we last read it.
+    defaults_file_id: FileId,
+
     // Keys that have been modified, and need to be written. A value here that is not present in
     // vars indicates a deleted value.
     modified: HashSet<WString>,
@@ -61,11 +110,15 @@ pub struct EnvUniversal {
     // File id from which we last read.
     // Only update if ok_to_save is updated as well.
     last_read_file_id: FileId,
+
+    // Our host name, recorded with each change.
+    hostname: WString,
 }
 
 struct UniversalReadUpdate {
     export_generation_increment: u64,
     new_vars: VarTable,
+    new_metadata: UvarMetadataTable,
     callbacks: CallbackDataList,
     ok_to_save: bool,
 }
@@ -77,21 +130,59 @@ pub fn new() -> Self {
             vars_path: Default::default(),
             narrow_vars_path: Default::default(),
             vars: Default::default(),
+            metadata: Default::default(),
+            defaults_path: Default::default(),
+            narrow_defaults_path: Default::default(),
+            defaults: Default::default(),
+            defaults_metadata: Default::default(),
+            merged: OnceCell::new(),
+            defaults_file_id: INVALID_FILE_ID,
             modified: Default::default(),
             export_generation: 1,
             ok_to_save: true,
             last_read_file_id: INVALID_FILE_ID,
+            hostname: gethostname().map_or_else(|_| WString::new(), osstr2wcstring),
         }
     }
     // Get the value of the variable with the specified name.
     pub fn get(&self, name: &wstr) -> Option<EnvVar> {
-        self.vars.get(name).cloned()
+        self.vars
+            .get(name)
+            .or_else(|| self.defaults.get(name))
+            .cloned()
     }
     // Return flags from the variable with the given name.
     pub fn get_flags(&self, name: &wstr) -> Option<EnvVarFlags> {
-        self.vars.get(name).map(|var| var.flags())
+        self.vars
+            .get(name)
+            .or_else(|| self.defaults.get(name))
+            .map(|var| var.flags())
+    }
+    // Return which layer the value of the variable with the given name comes from.
+    pub fn get_layer(&self, name: &wstr) -> Option<UvarLayer> {
+        match (
+            self.vars.contains_key(name),
+            self.defaults.contains_key(name),
+        ) {
+            (true, true) => Some(UvarLayer::UserOverridingSystem),
+            (true, false) => Some(UvarLayer::User),
+            (false, true) => Some(UvarLayer::System),
+            (false, false) => None,
+        }
+    }
+    // Return when and where the variable with the given name was last changed, if known.
+    pub fn get_metadata(&self, name: &wstr) -> Option<UvarMetadata> {
+        if self.vars.contains_key(name) {
+            self.metadata.get(name).cloned()
+        } else {
+            self.defaults_metadata.get(name).cloned()
+        }
     }
-    // Sets a variable.
+    // Return the path of the system defaults file, or an empty string if there is none.
+    pub fn defaults_path(&self) -> &wstr {
+        &self.defaults_path
+    }
+    // Sets a variable in the user's file.
     pub fn set(&mut self, key: &wstr, var: EnvVar) {
         let exports = var.exports();
         match self.vars.entry(key.to_owned()) {
@@ -105,27 +196,42 @@ pub fn set(&mut self, key: &wstr, var: EnvVar) {
                 entry.insert(var);
             }
         }
-        self.modified.insert(key.to_owned());
+        self.merged.take();
+        self.mark_modified(key);
         if exports {
             self.export_generation += 1;
         }
     }
-    // Removes a variable. Returns true if it was found, false if not.
+    // Removes a variable from the user's file. Returns true if it was found, false if not.
+    // A value from the system defaults file becomes visible again, but cannot be removed.
     pub fn remove(&mut self, key: &wstr) -> bool {
         if let Some(var) = self.vars.remove(key) {
+            self.merged.take();
             if var.exports() {
                 self.export_generation += 1;
             }
-            self.modified.insert(key.to_owned());
+            self.mark_modified(key);
             return true;
         }
         false
     }
 
+    // Record that we changed the variable with the given name just now.
+    fn mark_modified(&mut self, key: &wstr) {
+        self.metadata.insert(
+            key.to_owned(),
+            UvarMetadata {
+                mtime: now_seconds(),
+                host: self.hostname.clone(),
+            },
+        );
+        self.modified.insert(key.to_owned());
+    }
+
     // Gets variable names.
     pub fn get_names(&self, show_exported: bool, show_unexported: bool) -> Vec<WString> {
         let mut result = vec![];
-        for (key, var) in &self.vars {
+        for (key, var) in self.get_table() {
             if (var.exports() && show_exported) || (!var.exports() && show_unexported) {
                 result.push(key.clone());
             }
@@ -133,9 +239,25 @@ pub fn get_names(&self, show_exported: bool, show_unexported: bool) -> Vec<WStri
         result
     }
 
-    /// Get a view on the universal variable table.
+    /// Get the universal variable table, with values from the user's file taking precedence over
+    /// the system defaults.
     pub fn get_table(&self) -> &VarTable {
-        &self.vars
+        self.merged
+            .get_or_init(|| Self::merge_layers(&self.defaults, &self.vars))
+    }
+
+    fn merge_layers(defaults: &VarTable, vars: &VarTable) -> VarTable {
+        let mut result = defaults.clone();
+        result.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+        result
+    }
+
+    /// Set the path of the read-only system defaults file.
+    /// This should be called before initialize().
+    pub fn set_defaults_path(&mut self, path: WString) {
+        assert!(!self.initialized(), "Already initialized");
+        self.narrow_defaults_path = wcs2zstring(&path);
+        self.defaults_path = path;
     }
 
     /// Initialize this uvars for the default path.
@@ -152,10 +274,12 @@ fn initialize_at_path(&mut self, path: WString) -> Option<CallbackDataList> {
         assert!(!self.initialized(), "Already initialized");
         self.vars_path = path;
 
-        self.load_from_path()
+        let default_callbacks = self.load_defaults();
+        merge_callbacks(default_callbacks, self.load_from_path())
     }
 
-    /// Reads and writes variables at the correct path.
+    /// Reads and writes variables at the correct path, and rereads the system defaults if they
+    /// changed.
     /// If there is an existing variables file with an unknown format, parsing it is attempted,
     /// but it will not be overwritten.
     /// Returns whether data was read, and the callbacks.
@@ -163,7 +287,42 @@ pub fn sync(&mut self) -> (bool, Option<CallbackDataList>) {
         if !self.initialized() {
             return (false, None);
         }
+        let default_callbacks = self.load_defaults();
+        let (changed, callbacks) = self.sync_user_file();
+        (changed, merge_callbacks(default_callbacks, callbacks))
+    }
+
+    /// Read the system defaults file if it changed since we last read it.
+    /// Returns callbacks for the variables whose value changed as a result.
+    fn load_defaults(&mut self) -> CallbackDataList {
+        if self.defaults_path.is_empty() {
+            return vec![];
+        }
+        let file_id = file_id_for_path_narrow(&self.narrow_defaults_path);
+        if file_id == self.defaults_file_id {
+            return vec![];
+        }
+        flog!(uvar_file, "universal log reading system defaults");
+        self.defaults_file_id = file_id;
+        let mut new_defaults = VarTable::new();
+        let mut new_defaults_metadata = UvarMetadataTable::new();
+        if let Ok(file) = File::open(wcs2osstring(&self.defaults_path)) {
+            Self::read_message_internal(&file, &mut new_defaults, &mut new_defaults_metadata);
+        }
+        let old_table = self
+            .merged
+            .take()
+            .unwrap_or_else(|| Self::merge_layers(&self.defaults, &self.vars));
+        self.defaults = new_defaults;
+        self.defaults_metadata = new_defaults_metadata;
+        self.merged.take();
+        let (export_generation_increment, callbacks) =
+            diff_var_tables(&old_table, self.get_table(), &self.modified);
+        self.export_generation += export_generation_increment;
+        callbacks
+    }
 
+    fn sync_user_file(&mut self) -> (bool, Option<CallbackDataList>) {
         flog!(uvar_file, "universal log sync");
         // If we have no changes, just load.
         if self.modified.is_empty() {
@@ -180,7 +339,10 @@ pub fn sync(&mut self) -> (bool, Option<CallbackDataList>) {
             match self.load_from_file(old_file, file_id_for_file(old_file)) {
                 Some(potential_update) => {
                     if potential_update.do_save {
-                        let contents = Self::serialize_with_vars(&potential_update.data.new_vars);
+                        let contents = Self::serialize_with_vars(
+                            &potential_update.data.new_vars,
+                            &potential_update.data.new_metadata,
+                        );
                         tmp_file.write_all(&contents)?;
                     }
                     Ok(PotentialUpdate {
@@ -190,7 +352,7 @@ pub fn sync(&mut self) -> (bool, Option<CallbackDataList>) {
                 }
                 None => {
                     if self.ok_to_save {
-                        let contents = Self::serialize_with_vars(&self.vars);
+                        let contents = Self::serialize_with_vars(&self.vars, &self.metadata);
                         tmp_file.write_all(&contents)?;
                     }
                     Ok(PotentialUpdate {
@@ -211,12 +373,15 @@ pub fn sync(&mut self) -> (bool, Option<CallbackDataList>) {
                     Some(UniversalReadUpdate {
                         export_generation_increment,
                         new_vars,
+                        new_metadata,
                         callbacks,
                         ok_to_save,
                     }) => {
                         assert_eq!(potential_update.do_save, ok_to_save);
                         self.export_generation += export_generation_increment;
                         self.vars = new_vars;
+                        self.merged.take();
+                        self.metadata = new_metadata;
                         (true, Some(callbacks))
                     }
                     None => (true, None),
@@ -231,7 +396,11 @@ pub fn sync(&mut self) -> (bool, Option<CallbackDataList>) {
 
     /// Populate a variable table `out_vars` from a `s` string.
     /// Return the format of the file that we read.
-    fn populate_variables(s: &[u8], out_vars: &mut VarTable) -> UvarFormat {
+    fn populate_variables(
+        s: &[u8],
+        out_vars: &mut VarTable,
+        out_metadata: &mut UvarMetadataTable,
+    ) -> UvarFormat {
         // Decide on the format.
         let format = Self::format_for_contents(s);
 
@@ -239,8 +408,8 @@ fn populate_variables(s: &[u8], out_vars: &mut VarTable) -> UvarFormat {
         let mut wide_line = WString::new();
         let mut storage = WString::new();
         for line in iter {
-            // Skip empties and constants.
-            if line.is_empty() || line[0] == b'#' {
+            // Skip empties and constants, except for records of erased variables.
+            if line.is_empty() || (line[0] == b'#' && !line.starts_with(fish3_uvars::ERASEUVAR)) {
                 continue;
             }
 
@@ -256,12 +425,24 @@ fn populate_variables(s: &[u8], out_vars: &mut VarTable) -> UvarFormat {
                 UvarFormat::Fish_2_x => {
                     Self::parse_message_2x_internal(&wide_line, out_vars, &mut storage);
                 }
-                UvarFormat::Fish_3_0 => {
-                    Self::parse_message_30_internal(&wide_line, out_vars, &mut storage);
+                // 3.1 only adds options for metadata and associative variables, and records of
+                // erased variables.
+                UvarFormat::Fish_3_0 | UvarFormat::Fish_3_1 => {
+                    Self::parse_message_30_internal(
+                        &wide_line,
+                        out_vars,
+                        out_metadata,
+                        &mut storage,
+                    );
                 }
                 // For future formats, just try with the most recent one.
                 UvarFormat::Future => {
-                    Self::parse_message_30_internal(&wide_line, out_vars, &mut storage);
+                    Self::parse_message_30_internal(
+                        &wide_line,
+                        out_vars,
+                        out_metadata,
+                        &mut storage,
+                    );
                 }
             }
         }
@@ -298,10 +479,13 @@ fn format_for_contents(s: &[u8]) -> UvarFormat {
 
             // Try reading the version.
             let versionbuf = unsafe { versionbuf.assume_init() };
-            return if versionbuf.starts_with(UVARS_VERSION_3_0)
-                && versionbuf[UVARS_VERSION_3_0.len()] == b'\0'
-            {
+            let is_version = |version: &[u8]| {
+                versionbuf.starts_with(version) && versionbuf[version.len()] == b'\0'
+            };
+            return if is_version(UVARS_VERSION_3_0) {
                 UvarFormat::Fish_3_0
+            } else if is_version(UVARS_VERSION_3_1) {
+                UvarFormat::Fish_3_1
             } else {
                 UvarFormat::Future
             };
@@ -311,11 +495,29 @@ fn format_for_contents(s: &[u8]) -> UvarFormat {
     }
 
     /// Serialize a variable list.
-    fn serialize_with_vars(vars: &VarTable) -> Vec<u8> {
+    fn serialize_with_vars(vars: &VarTable, metadata: &UvarMetadataTable) -> Vec<u8> {
+        // Recently erased variables are recorded, so concurrent instances don't bring them back.
+        let now = now_seconds();
+        let erased: Vec<(&WString, &UvarMetadata)> = metadata
+            .iter()
+            .filter(|(k, m)| {
+                !vars.contains_key(*k) && now.saturating_sub(m.mtime) < ERASURE_LIFETIME
+            })
+            .sorted_by_key(|(k, _)| *k)
+            .collect();
+
         let mut contents = vec![];
         contents.extend_from_slice(SAVE_MSG);
         contents.extend_from_slice(b"# VERSION: ");
-        contents.extend_from_slice(UVARS_VERSION_3_0);
+        // Only use the newer version if needed, so older fish versions can still write the file.
+        if erased.is_empty()
+            && !vars.keys().any(|k| metadata.contains_key(k))
+            && !vars.values().any(EnvVar::is_map)
+        {
+            contents.extend_from_slice(UVARS_VERSION_3_0);
+        } else {
+            contents.extend_from_slice(UVARS_VERSION_3_1);
+        }
         contents.push(b'\n');
 
         vars.iter()
@@ -324,9 +526,20 @@ fn serialize_with_vars(vars: &VarTable) -> Vec<u8> {
             .for_each(|(k, v)| {
                 // Append the entry. Note that append_file_entry may fail,
                 // but that only affects one variable; soldier on.
-                append_file_entry(v.flags(), k, &encode_serialized(v.as_list()), &mut contents);
+                append_file_entry(
+                    v.flags(),
+                    v.is_map(),
+                    metadata.get(k),
+                    k,
+                    &encode_var(v),
+                    &mut contents,
+                );
             });
 
+        for (k, m) in erased {
+            append_erasure_entry(m, k, &mut contents);
+        }
+
         contents
     }
 
@@ -369,12 +582,15 @@ fn load_from_path_narrow(&mut self) -> Option<CallbackDataList> {
                 Some(UniversalReadUpdate {
                     export_generation_increment,
                     new_vars,
+                    new_metadata,
                     callbacks,
                     ok_to_save,
                 }),
             )) => {
                 self.export_generation += export_generation_increment;
                 self.vars = new_vars;
+                self.merged.take();
+                self.metadata = new_metadata;
                 self.ok_to_save = ok_to_save;
                 self.last_read_file_id = file_id;
                 Some(callbacks)
@@ -410,23 +626,29 @@ fn load_from_file(
         } else {
             // Read a variables table from the file.
             let mut new_vars = VarTable::new();
-            let format = Self::read_message_internal(file, &mut new_vars);
+            let mut new_metadata = UvarMetadataTable::new();
+            let format = Self::read_message_internal(file, &mut new_vars, &mut new_metadata);
 
             // Hacky: if the read format is in the future, avoid overwriting the file: never try to
             // save.
             let do_save = format != UvarFormat::Future;
 
+            // Our changes conflict with the file if it was changed concurrently. Keep whichever
+            // change was made last.
+            let keep_ours = self.changes_to_keep(&new_metadata);
+
             // Announce changes and update our exports generation.
             let (export_generation_increment, callbacks) =
-                self.generate_callbacks_and_update_exports(&new_vars);
+                self.generate_callbacks_and_update_exports(&new_vars, &keep_ours);
 
             // Acquire the new variables.
-            self.acquire_variables(&mut new_vars);
+            self.acquire_variables(&keep_ours, &mut new_vars, &mut new_metadata);
             Some(PotentialUpdate {
                 do_save,
                 data: UniversalReadUpdate {
                     export_generation_increment,
                     new_vars,
+                    new_metadata,
                     callbacks,
                     ok_to_save: do_save,
                 },
@@ -434,65 +656,50 @@ fn load_from_file(
         }
     }
 
+    /// Return the modified keys whose change should be kept over the contents of a file with
+    /// the given metadata: all of them, except those that the file says were changed later.
+    fn changes_to_keep(&self, file_metadata: &UvarMetadataTable) -> HashSet<WString> {
+        self.modified
+            .iter()
+            .filter(|key| {
+                let ours = self.metadata.get(*key).map(|m| m.mtime);
+                let theirs = file_metadata.get(*key).map(|m| m.mtime);
+                match (ours, theirs) {
+                    (Some(ours), Some(theirs)) => ours >= theirs,
+                    _ => true,
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
     /// Given a variable table, generate callbacks representing the difference between our vars and
-    /// the new vars.
+    /// the new vars, except for the keys in `keep_ours`.
     /// Returns by how much the exports generation count should be incremented, as well as a
     /// callback list.
     fn generate_callbacks_and_update_exports(
         &self,
         new_vars: &VarTable,
+        keep_ours: &HashSet<WString>,
     ) -> (u64, CallbackDataList) {
-        let mut export_generation_increment = 0;
-        let mut callbacks = CallbackDataList::new();
-        // Construct callbacks for erased values.
-        for (key, value) in &self.vars {
-            // Skip modified values.
-            if self.modified.contains(key) {
-                continue;
-            }
-
-            // If the value is not present in new_vars, it has been erased.
-            if !new_vars.contains_key(key) {
-                callbacks.push(CallbackData {
-                    key: key.clone(),
-                    val: None,
-                });
-                if value.exports() {
-                    export_generation_increment += 1;
-                }
-            }
-        }
-
-        // Construct callbacks for newly inserted or changed values.
-        for (key, new_entry) in new_vars {
-            // Skip modified values.
-            if self.modified.contains(key) {
-                continue;
-            }
-
-            let existing = self.vars.get(key);
-
-            // See if the value has changed.
-            let old_exports = existing.is_some_and(|v| v.exports());
-            let export_changed = old_exports != new_entry.exports();
-            let value_changed = existing.is_some_and(|v| v != new_entry);
-            if export_changed || value_changed {
-                export_generation_increment += 1;
-            }
-            if existing.is_none() || export_changed || value_changed {
-                // Value is set for the first time, or has changed.
-                callbacks.push(CallbackData {
-                    key: key.clone(),
-                    val: Some(new_entry.clone()),
-                });
-            }
-        }
-        (export_generation_increment, callbacks)
+        // Compare what is visible, so a value from the system defaults that is no longer
+        // overridden does not count as erased.
+        diff_var_tables(
+            self.get_table(),
+            &Self::merge_layers(&self.defaults, new_vars),
+            keep_ours,
+        )
     }
 
-    /// Copy modified values from existing vars to `vars_to_acquire`.
-    fn acquire_variables(&self, vars_to_acquire: &mut VarTable) {
-        for key in &self.modified {
+    /// Copy the values and metadata for the keys in `keep_ours` from existing vars to
+    /// `vars_to_acquire` and `metadata_to_acquire`.
+    fn acquire_variables(
+        &self,
+        keep_ours: &HashSet<WString>,
+        vars_to_acquire: &mut VarTable,
+        metadata_to_acquire: &mut UvarMetadataTable,
+    ) {
+        for key in keep_ours {
             match self.vars.get(key) {
                 None => {
                     /* The value has been deleted. */
@@ -503,12 +710,18 @@ fn acquire_variables(&self, vars_to_acquire: &mut VarTable) {
                     vars_to_acquire.insert(key.clone(), src.clone());
                 }
             }
+            // Keep the time of an erasure as well, to record it in the file.
+            match self.metadata.get(key) {
+                Some(metadata) => metadata_to_acquire.insert(key.clone(), metadata.clone()),
+                None => metadata_to_acquire.remove(key),
+            };
         }
     }
 
     fn populate_1_variable(
         input: &wstr,
         flags: EnvVarFlags,
+        is_map: bool,
         vars: &mut VarTable,
         storage: &mut WString,
     ) -> bool {
@@ -526,7 +739,22 @@ fn populate_1_variable(
             return false;
         };
         *storage = unescaped;
-        let var = EnvVar::new_vec(decode_serialized(&*storage), flags);
+        let values = decode_serialized(&*storage);
+        let var = if is_map {
+            // Keys and values are interleaved.
+            if values.len() % 2 != 0 {
+                return false;
+            }
+            let (keys, values) = values
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .unzip();
+            // Associative variables are never exported.
+            let flags = flags - EnvVarFlags::EXPORT - EnvVarFlags::PATHVAR;
+            EnvVar::new_map(keys, values, flags)
+        } else {
+            EnvVar::new_vec(values, flags)
+        };
 
         // Parse out the key and write into the map.
         *storage = s[..colon].to_owned();
@@ -535,9 +763,15 @@ fn populate_1_variable(
         true
     }
     /// Parse message msg per fish 3.0 format.
-    fn parse_message_30_internal(msg: &wstr, vars: &mut VarTable, storage: &mut WString) {
+    fn parse_message_30_internal(
+        msg: &wstr,
+        vars: &mut VarTable,
+        metadata: &mut UvarMetadataTable,
+        storage: &mut WString,
+    ) {
         use fish3_uvars as f3;
         if msg.starts_with(L!("#")) {
+            Self::parse_erasure_30_internal(msg, metadata);
             return;
         }
 
@@ -548,6 +782,9 @@ fn parse_message_30_internal(msg: &wstr, vars: &mut VarTable, storage: &mut WStr
         }
         // Parse out flags.
         let mut flags = EnvVarFlags::default();
+        let mut is_map = false;
+        let mut mtime = None;
+        let mut host = WString::new();
         loop {
             cursor = skip_spaces(cursor);
             if cursor.char_at(0) != '-' {
@@ -557,6 +794,14 @@ fn parse_message_30_internal(msg: &wstr, vars: &mut VarTable, storage: &mut WStr
                 flags |= EnvVarFlags::EXPORT;
             } else if r#match(&mut cursor, f3::PATH) {
                 flags |= EnvVarFlags::PATHVAR;
+            } else if r#match(&mut cursor, f3::MAP) {
+                is_map = true;
+            } else if let Some(value) = match_option_value(&mut cursor, f3::MTIME) {
+                mtime = value.to_string().parse::<i64>().ok();
+            } else if let Some(value) = match_option_value(&mut cursor, f3::HOST) {
+                host =
+                    unescape_string(value, UnescapeStringStyle::Script(UnescapeFlags::default()))
+                        .unwrap_or_default();
             } else {
                 // Skip this unknown flag, for future proofing.
                 while !cursor.is_empty() && !matches!(cursor.char_at(0), ' ' | '\t') {
@@ -566,8 +811,49 @@ fn parse_message_30_internal(msg: &wstr, vars: &mut VarTable, storage: &mut WStr
         }
 
         // Populate the variable with these flags.
-        if !Self::populate_1_variable(cursor, flags, vars, storage) {
+        if !Self::populate_1_variable(cursor, flags, is_map, vars, storage) {
             flogf!(warning, PARSE_ERR, msg);
+            return;
+        }
+        // On success, storage holds the variable name.
+        if let Some(mtime) = mtime {
+            metadata.insert(storage.clone(), UvarMetadata { mtime, host });
+        }
+    }
+
+    /// Parse a record of an erased variable, which older versions of fish skip as a comment.
+    fn parse_erasure_30_internal(msg: &wstr, metadata: &mut UvarMetadataTable) {
+        use fish3_uvars as f3;
+        let mut cursor = msg;
+        if !r#match(&mut cursor, f3::ERASEUVAR) {
+            return;
+        }
+        let mut mtime = None;
+        let mut host = WString::new();
+        loop {
+            cursor = skip_spaces(cursor);
+            if cursor.char_at(0) != '-' {
+                break;
+            }
+            if let Some(value) = match_option_value(&mut cursor, f3::MTIME) {
+                mtime = value.to_string().parse::<i64>().ok();
+            } else if let Some(value) = match_option_value(&mut cursor, f3::HOST) {
+                host =
+                    unescape_string(value, UnescapeStringStyle::Script(UnescapeFlags::default()))
+                        .unwrap_or_default();
+            } else {
+                // Skip this unknown flag, for future proofing.
+                while !cursor.is_empty() && !matches!(cursor.char_at(0), ' ' | '\t') {
+                    cursor = &cursor[1..];
+                }
+            }
+        }
+        let key = skip_spaces(cursor);
+        match mtime {
+            Some(mtime) if valid_var_name(key) => {
+                metadata.insert(key.to_owned(), UvarMetadata { mtime, host });
+            }
+            _ => flogf!(warning, PARSE_ERR, msg),
         }
     }
 
@@ -588,12 +874,16 @@ fn parse_message_2x_internal(msg: &wstr, vars: &mut VarTable, storage: &mut WStr
             return;
         }
 
-        if !Self::populate_1_variable(cursor, flags, vars, storage) {
+        if !Self::populate_1_variable(cursor, flags, false, vars, storage) {
             flogf!(warning, PARSE_ERR, msg);
         }
     }
 
-    fn read_message_internal(file: &File, vars: &mut VarTable) -> UvarFormat {
+    fn read_message_internal(
+        file: &File,
+        vars: &mut VarTable,
+        metadata: &mut UvarMetadataTable,
+    ) -> UvarFormat {
         let mut contents = vec![];
         // Read everything from the file. Put a sane limit on it.
         // TODO: Ideally, the cast should be checked at compile time.
@@ -612,10 +902,86 @@ fn read_message_internal(file: &File, vars: &mut VarTable) -> UvarFormat {
             contents.truncate(newline);
         }
 
-        Self::populate_variables(&contents, vars)
+        Self::populate_variables(&contents, vars, metadata)
     }
 }
 
+/// Generate callbacks representing the difference between `old_vars` and `new_vars`, ignoring
+/// the keys in `skip`.
+/// Returns by how much the exports generation count should be incremented, as well as a
+/// callback list.
+fn diff_var_tables(
+    old_vars: &VarTable,
+    new_vars: &VarTable,
+    skip: &HashSet<WString>,
+) -> (u64, CallbackDataList) {
+    let mut export_generation_increment = 0;
+    let mut callbacks = CallbackDataList::new();
+    // Construct callbacks for erased values.
+    for (key, value) in old_vars {
+        // Skip modified values.
+        if skip.contains(key) {
+            continue;
+        }
+
+        // If the value is not present in new_vars, it has been erased.
+        if !new_vars.contains_key(key) {
+            callbacks.push(CallbackData {
+                key: key.clone(),
+                val: None,
+            });
+            if value.exports() {
+                export_generation_increment += 1;
+            }
+        }
+    }
+
+    // Construct callbacks for newly inserted or changed values.
+    for (key, new_entry) in new_vars {
+        // Skip modified values.
+        if skip.contains(key) {
+            continue;
+        }
+
+        let existing = old_vars.get(key);
+
+        // See if the value has changed.
+        let old_exports = existing.is_some_and(|v| v.exports());
+        let export_changed = old_exports != new_entry.exports();
+        let value_changed = existing.is_some_and(|v| v != new_entry);
+        if export_changed || value_changed {
+            export_generation_increment += 1;
+        }
+        if existing.is_none() || export_changed || value_changed {
+            // Value is set for the first time, or has changed.
+            callbacks.push(CallbackData {
+                key: key.clone(),
+                val: Some(new_entry.clone()),
+            });
+        }
+    }
+    (export_generation_increment, callbacks)
+}
+
+/// Append `callbacks` to `default_callbacks`, treating no callbacks as an empty list.
+fn merge_callbacks(
+    mut default_callbacks: CallbackDataList,
+    callbacks: Option<CallbackDataList>,
+) -> Option<CallbackDataList> {
+    if default_callbacks.is_empty() {
+        return callbacks;
+    }
+    default_callbacks.extend(callbacks.unwrap_or_default());
+    Some(default_callbacks)
+}
+
+/// Return the current time in seconds since the epoch.
+fn now_seconds() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| i64::try_from(d.as_secs()).unwrap_or(i64::MAX))
+}
+
 /// Return the default variable path, or an empty string on failure.
 pub fn default_vars_path() -> WString {
     let ValidatedPath { path, ok } = path_get_config();
@@ -636,6 +1002,15 @@ pub fn default_vars_path() -> WString {
 /// Version for fish 3.0
 const UVARS_VERSION_3_0: &[u8] = b"3.0";
 
+/// Version that adds when and where each variable was last changed, erased variables and
+/// associative variables.
+/// Older versions of fish read it like 3.0, but do not rewrite it, so this information is kept.
+const UVARS_VERSION_3_1: &[u8] = b"3.1";
+
+/// How long, in seconds, erased variables are recorded in the file. This only needs to cover
+/// instances that have not synced since, so it can be short.
+const ERASURE_LIFETIME: i64 = 7 * 24 * 60 * 60;
+
 // Maximum file size we'll read.
 const MAX_READ_SIZE: usize = 16 * 1024 * 1024;
 
@@ -648,8 +1023,15 @@ mod fish2x_uvars {
 // Fields used in fish 3.0 uvars
 mod fish3_uvars {
     pub const SETUVAR: &[u8] = b"SETUVAR";
+    // A comment, so older versions of fish skip it.
+    pub const ERASEUVAR: &[u8] = b"# ERASEUVAR";
     pub const EXPORT: &[u8] = b"--export";
     pub const PATH: &[u8] = b"--path";
+    // An associative variable, since version 3.1.
+    pub const MAP: &[u8] = b"--map";
+    // When and where a variable was last changed, since version 3.1.
+    pub const MTIME: &[u8] = b"--mtime=";
+    pub const HOST: &[u8] = b"--host=";
 }
 
 /// Test if the message msg contains the command cmd.
@@ -672,6 +1054,27 @@ fn r#match(inout_cursor: &mut &wstr, cmd: &[u8]) -> bool {
     true
 }
 
+/// Test if the message msg starts with the option opt, which ends in "=".
+/// On success, updates the cursor to just past the option's value and returns the value.
+fn match_option_value<'a>(inout_cursor: &mut &'a wstr, opt: &[u8]) -> Option<&'a wstr> {
+    let cursor = *inout_cursor;
+    if !opt
+        .iter()
+        .copied()
+        .map(char::from)
+        .eq(cursor.chars().take(opt.len()))
+    {
+        return None;
+    }
+    let rest = &cursor[opt.len()..];
+    let len = rest
+        .chars()
+        .position(|c| matches!(c, ' ' | '\t'))
+        .unwrap_or(rest.len());
+    *inout_cursor = &rest[len..];
+    Some(&rest[..len])
+}
+
 /// The universal variable format has some funny escaping requirements; here we try to be safe.
 fn is_universal_safe_to_encode_directly(c: char) -> bool {
     if !(32..=128).contains(&u32::from(c)) {
@@ -711,6 +1114,8 @@ fn append_utf8(input: &wstr, receiver: &mut Vec<u8>) {
 /// Returns true on success. storage may be used for temporary storage, to avoid allocations.
 fn append_file_entry(
     flags: EnvVarFlags,
+    is_map: bool,
+    metadata: Option<&UvarMetadata>,
     key_in: &wstr,
     val_in: &wstr,
     result: &mut Vec<u8>,
@@ -734,6 +1139,20 @@ fn append_file_entry(
         result.extend_from_slice(f3::PATH);
         result.push(b' ');
     }
+    if is_map {
+        result.extend_from_slice(f3::MAP);
+        result.push(b' ');
+    }
+    if let Some(metadata) = metadata {
+        result.extend_from_slice(f3::MTIME);
+        result.extend_from_slice(metadata.mtime.to_string().as_bytes());
+        result.push(b' ');
+        if !metadata.host.is_empty() {
+            result.extend_from_slice(f3::HOST);
+            append_utf8(&full_escape(&metadata.host), result);
+            result.push(b' ');
+        }
+    }
 
     // Append variable name like "fish_color_cwd".
     if !valid_var_name(key_in) {
@@ -768,6 +1187,28 @@ fn append_file_entry(
     success
 }
 
+/// Creates a record of an erased variable like "# ERASEUVAR --mtime=1700000000 fish_color_cwd".
+/// Appends the result to *result (as UTF8).
+fn append_erasure_entry(metadata: &UvarMetadata, key: &wstr, result: &mut Vec<u8>) {
+    use fish3_uvars as f3;
+    if !valid_var_name(key) {
+        flogf!(error, "Illegal variable name: '%s'", key);
+        return;
+    }
+    result.extend_from_slice(f3::ERASEUVAR);
+    result.push(b' ');
+    result.extend_from_slice(f3::MTIME);
+    result.extend_from_slice(metadata.mtime.to_string().as_bytes());
+    result.push(b' ');
+    if !metadata.host.is_empty() {
+        result.extend_from_slice(f3::HOST);
+        append_utf8(&full_escape(&metadata.host), result);
+        result.push(b' ');
+    }
+    append_utf8(key, result);
+    result.push(b'\n');
+}
+
 /// Encoding of a null string.
 const ENV_NULL: &wstr = L!("\x1d");
 
@@ -791,6 +1232,21 @@ fn encode_serialized(vals: &[WString]) -> WString {
     join_strings(vals, UVAR_ARRAY_SEP)
 }
 
+/// Encode the values of a variable, interleaving the keys of associative variables.
+fn encode_var(var: &EnvVar) -> WString {
+    match var.map_keys() {
+        Some(keys) => {
+            let pairs: Vec<WString> = keys
+                .iter()
+                .zip(var.as_list())
+                .flat_map(|(key, value)| [key.clone(), value.clone()])
+                .collect();
+            encode_serialized(&pairs)
+        }
+        None => encode_serialized(var.as_list()),
+    }
+}
+
 fn skip_spaces(mut s: &wstr) -> &wstr {
     while s.starts_with(L!(" ")) || s.starts_with(L!("\t")) {
         s = &s[1..];
@@ -802,7 +1258,9 @@ fn skip_spaces(mut s: &wstr) -> &wstr {
 mod tests {
     use crate::{
         env::{EnvVar, EnvVarFlags, VarTable},
-        env_universal_common::{EnvUniversal, UvarFormat},
+        env_universal_common::{
+            EnvUniversal, UvarFormat, UvarLayer, UvarMetadata, UvarMetadataTable,
+        },
         prelude::*,
         tests::prelude::*,
         wutil::{INVALID_FILE_ID, file_id_for_path},
@@ -925,7 +1383,7 @@ fn test_universal_output() {
             ),
         );
 
-        let text = EnvUniversal::serialize_with_vars(&vars);
+        let text = EnvUniversal::serialize_with_vars(&vars, &UvarMetadataTable::new());
         let expected = concat!(
             "# This file contains fish universal variable definitions.\n",
             "# VERSION: 3.0\n",
@@ -988,10 +1446,167 @@ fn test_universal_parsing() {
         );
 
         let mut parsed_vars = VarTable::new();
-        EnvUniversal::populate_variables(input, &mut parsed_vars);
+        EnvUniversal::populate_variables(input, &mut parsed_vars, &mut UvarMetadataTable::new());
         assert_eq!(vars, parsed_vars);
     }
 
+    #[test]
+    #[serial]
+    fn test_universal_metadata() {
+        test_init();
+        let input = concat!(
+            "# This file contains fish universal variable definitions.\n",
+            "# VERSION: 3.1\n",
+            "SETUVAR --export --mtime=1700000000 --host=box\\x2dA varA:ValA1\n",
+            "SETUVAR varB:ValB1\n",
+        )
+        .as_bytes();
+
+        let mut vars = VarTable::new();
+        let mut metadata = UvarMetadataTable::new();
+        EnvUniversal::populate_variables(input, &mut vars, &mut metadata);
+        assert_eq!(
+            vars.get(L!("varA")),
+            Some(&EnvVar::new(L!("ValA1").to_owned(), EnvVarFlags::EXPORT))
+        );
+        assert_eq!(
+            metadata.get(L!("varA")),
+            Some(&UvarMetadata {
+                mtime: 1700000000,
+                host: L!("box-A").to_owned(),
+            })
+        );
+        assert!(vars.contains_key(L!("varB")));
+        assert_eq!(metadata.get(L!("varB")), None);
+
+        // Metadata survives a round trip.
+        assert_eq!(EnvUniversal::serialize_with_vars(&vars, &metadata), input);
+
+        // Without metadata, the file can still be written in the format older fish writes.
+        let text = EnvUniversal::serialize_with_vars(&vars, &UvarMetadataTable::new());
+        assert!(text.starts_with(
+            b"# This file contains fish universal variable definitions.\n# VERSION: 3.0\n"
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn test_universal_map() {
+        test_init();
+        let input = concat!(
+            "# This file contains fish universal variable definitions.\n",
+            "# VERSION: 3.1\n",
+            "SETUVAR --map varA:k1\\x1ev1\\x1ek\\x20two\\x1e\n",
+            "SETUVAR --map varB:\\x1d\n",
+            "SETUVAR varC:ValC1\n",
+        )
+        .as_bytes();
+
+        let mut vars = VarTable::new();
+        EnvUniversal::populate_variables(input, &mut vars, &mut UvarMetadataTable::new());
+        assert_eq!(
+            vars.get(L!("varA")),
+            Some(&EnvVar::new_map(
+                vec![L!("k1").to_owned(), L!("k two").to_owned()],
+                vec![L!("v1").to_owned(), WString::new()],
+                EnvVarFlags::empty(),
+            ))
+        );
+        assert_eq!(
+            vars.get(L!("varB")),
+            Some(&EnvVar::new_map(vec![], vec![], EnvVarFlags::empty()))
+        );
+        assert!(!vars.get(L!("varC")).unwrap().is_map());
+
+        // Maps survive a round trip.
+        assert_eq!(
+            EnvUniversal::serialize_with_vars(&vars, &UvarMetadataTable::new()),
+            input
+        );
+
+        // An odd number of elements is not a valid map, and maps are never exported.
+        let input = concat!(
+            "# VERSION: 3.1\n",
+            "SETUVAR --map varA:k1\n",
+            "SETUVAR --export --map varB:k1\\x1ev1\n",
+        )
+        .as_bytes();
+        let mut vars = VarTable::new();
+        EnvUniversal::populate_variables(input, &mut vars, &mut UvarMetadataTable::new());
+        assert_eq!(vars.get(L!("varA")), None);
+        assert!(!vars.get(L!("varB")).unwrap().exports());
+    }
+
+    #[test]
+    fn test_universal_last_writer_wins() {
+        test_init();
+        let (_test_dir, test_path) = make_test_uvar_path().unwrap();
+        let mut uvars1 = EnvUniversal::new();
+        let mut uvars2 = EnvUniversal::new();
+        uvars1.initialize_at_path(test_path.clone());
+        uvars2.initialize_at_path(test_path);
+
+        let noflags = EnvVarFlags::empty();
+        uvars1.set(L!("alpha"), EnvVar::new(L!("1").to_owned(), noflags));
+        uvars1.set(L!("beta"), EnvVar::new(L!("1").to_owned(), noflags));
+        uvars1.sync();
+
+        // uvars2 changed alpha before uvars1 did, so it loses. Its change to beta is newer.
+        uvars2.set(L!("alpha"), EnvVar::new(L!("2").to_owned(), noflags));
+        uvars2.metadata.get_mut(L!("alpha")).unwrap().mtime = 0;
+        uvars2.set(L!("beta"), EnvVar::new(L!("2").to_owned(), noflags));
+        uvars2.metadata.get_mut(L!("beta")).unwrap().mtime = i64::MAX;
+        let (_, callbacks) = uvars2.sync();
+        let callbacks = callbacks.unwrap_or_default();
+        assert_eq!(callbacks.len(), 1);
+        assert_eq!(callbacks[0].key, L!("alpha"));
+        assert_eq!(uvars2.get(L!("alpha")).unwrap().as_string(), L!("1"));
+        assert_eq!(uvars2.get(L!("beta")).unwrap().as_string(), L!("2"));
+
+        uvars1.sync();
+        assert_eq!(uvars1.get(L!("alpha")).unwrap().as_string(), L!("1"));
+        assert_eq!(uvars1.get(L!("beta")).unwrap().as_string(), L!("2"));
+        assert_eq!(uvars1.get_metadata(L!("beta")).unwrap().mtime, i64::MAX);
+    }
+
+    #[test]
+    fn test_universal_erasure_wins() {
+        test_init();
+        let (_test_dir, test_path) = make_test_uvar_path().unwrap();
+        let mut uvars1 = EnvUniversal::new();
+        let mut uvars2 = EnvUniversal::new();
+        uvars1.initialize_at_path(test_path.clone());
+        uvars2.initialize_at_path(test_path.clone());
+
+        let noflags = EnvVarFlags::empty();
+        uvars1.set(L!("alpha"), EnvVar::new(L!("1").to_owned(), noflags));
+        uvars1.sync();
+        uvars2.sync();
+
+        // uvars2 changed alpha before uvars1 erased it, so the erasure wins.
+        uvars2.set(L!("alpha"), EnvVar::new(L!("2").to_owned(), noflags));
+        uvars2.metadata.get_mut(L!("alpha")).unwrap().mtime = 0;
+        assert!(uvars1.remove(L!("alpha")));
+        uvars1.sync();
+        let (_, callbacks) = uvars2.sync();
+        let callbacks = callbacks.unwrap_or_default();
+        assert_eq!(callbacks.len(), 1);
+        assert_eq!(callbacks[0].key, L!("alpha"));
+        assert_eq!(callbacks[0].val, None);
+        assert_eq!(uvars2.get(L!("alpha")), None);
+
+        // The erasure is recorded in a comment, in the newer version of the format.
+        let contents = std::fs::read(wcs2osstring(&test_path)).unwrap();
+        let contents = String::from_utf8(contents).unwrap();
+        assert!(contents.contains("\n# VERSION: 3.1\n"));
+        assert!(contents.contains("\n# ERASEUVAR --mtime="));
+        assert!(!contents.contains(" alpha:"));
+        let mut uvars3 = EnvUniversal::new();
+        uvars3.initialize_at_path(test_path);
+        assert_eq!(uvars3.get(L!("alpha")), None);
+        assert!(uvars3.metadata.contains_key(L!("alpha")));
+    }
+
     #[test]
     #[serial]
     fn test_universal_parsing_legacy() {
@@ -1017,7 +1632,7 @@ fn test_universal_parsing_legacy() {
         );
 
         let mut parsed_vars = VarTable::new();
-        EnvUniversal::populate_variables(input, &mut parsed_vars);
+        EnvUniversal::populate_variables(input, &mut parsed_vars, &mut UvarMetadataTable::new());
         assert_eq!(vars, parsed_vars);
     }
 
@@ -1105,7 +1720,8 @@ macro_rules! validate {
         validate!(b"# blah\n# VERSION: 3.0", UvarFormat::Fish_3_0);
         validate!(b"# blah\n#VERSION: 3.0", UvarFormat::Fish_3_0);
         validate!(b"# blah\n#VERSION:3.0", UvarFormat::Fish_3_0);
-        validate!(b"# blah\n#VERSION:3.1", UvarFormat::Future);
+        validate!(b"# blah\n#VERSION:3.1", UvarFormat::Fish_3_1);
+        validate!(b"# blah\n#VERSION:3.3", UvarFormat::Future);
     }
 
     #[test]
@@ -1135,4 +1751,49 @@ fn test_universal_ok_to_save() {
         let after_id = file_id_for_path(&test_path);
         assert_eq!(before_id, after_id, "test_path should not have changed",);
     }
+
+    #[test]
+    fn test_universal_defaults() {
+        test_init();
+        let (test_dir, test_path) = make_test_uvar_path().unwrap();
+        let defaults_path = osstr2wcstring(test_dir.path().join("defaults.txt"));
+        std::fs::write(
+            wcs2osstring(&defaults_path),
+            b"# VERSION: 3.0\nSETUVAR org_color:blue\nSETUVAR shared:system\n",
+        )
+        .unwrap();
+
+        let mut uvars = EnvUniversal::new();
+        uvars.set_defaults_path(defaults_path.clone());
+        let callbacks = uvars
+            .initialize_at_path(test_path.clone())
+            .unwrap_or_default();
+        assert_eq!(callbacks.len(), 2);
+        assert_eq!(uvars.get(L!("shared")).unwrap().as_string(), L!("system"));
+        assert_eq!(uvars.get_layer(L!("shared")), Some(UvarLayer::System));
+
+        // Overriding only writes the user's file.
+        let noflags = EnvVarFlags::empty();
+        uvars.set(L!("shared"), EnvVar::new(L!("user").to_owned(), noflags));
+        uvars.set(L!("mine"), EnvVar::new(L!("1").to_owned(), noflags));
+        uvars.sync();
+        assert_eq!(uvars.get(L!("shared")).unwrap().as_string(), L!("user"));
+        assert_eq!(
+            uvars.get_layer(L!("shared")),
+            Some(UvarLayer::UserOverridingSystem)
+        );
+        assert_eq!(uvars.get_layer(L!("mine")), Some(UvarLayer::User));
+        assert_eq!(uvars.get_table().len(), 3);
+        let contents = std::fs::read(wcs2osstring(&test_path)).unwrap();
+        let contents = String::from_utf8(contents).unwrap();
+        assert!(contents.contains(" shared:user\n"));
+        assert!(!contents.contains("org_color"));
+
+        // Defaults can be overridden, but not erased.
+        assert!(uvars.remove(L!("shared")));
+        assert!(!uvars.remove(L!("org_color")));
+        uvars.sync();
+        assert_eq!(uvars.get(L!("shared")).unwrap().as_string(), L!("system"));
+        assert_eq!(uvars.get_layer(L!("org_color")), Some(UvarLayer::System));
+    }
 }
@@ -3,11 +3,16 @@
 // Some of the code in this file is based on code from the Glibc manual, though the changes
 // performed have been massive.
 
+use crate::ast::Node as _;
 use crate::builtins::{
     ErrorCode, STATUS_CMD_ERROR, STATUS_CMD_UNKNOWN, STATUS_NOT_EXECUTABLE, STATUS_READ_TOO_MUCH,
     builtin_run,
 };
-use crate::env::{EnvMode, EnvSetMode, EnvStack, Environment as _, READ_BYTE_LIMIT, Statuses};
+use crate::common::get_program_name;
+use crate::env::config_paths::{FishPath, get_fish_path};
+use crate::env::{
+    EnvMode, EnvSetMode, EnvStack, EnvVar, Environment as _, READ_BYTE_LIMIT, Statuses,
+};
 #[cfg(have_posix_spawn)]
 use crate::env_dispatch::use_posix_spawn;
 use crate::fds::{
@@ -31,23 +36,27 @@
 use crate::nix::isatty;
 use crate::null_terminated_array::OwningNullTerminatedArray;
 use crate::parser::{Block, BlockId, BlockType, EvalRes, Parser, ParserEnvSetMode};
+use crate::path::path_get_path;
 use crate::prelude::*;
 use crate::proc::{
-    InternalProc, Job, JobGroupRef, Pid, ProcStatus, Process, ProcessType, hup_jobs,
-    is_interactive_session, jobs_requiring_warning_on_exit, no_exec, print_exit_warning_for_jobs,
+    InternalProc, Job, JobControl, JobGroupRef, Pid, ProcStatus, Process, ProcessType,
+    get_job_control_mode, hup_jobs, is_interactive_session, jobs_requiring_warning_on_exit,
+    no_exec, print_exit_warning_for_jobs,
 };
 use crate::reader::{reader_run_count, restore_term_mode};
 use crate::redirection::{Dup2List, dup2_list_resolve_chain};
 use crate::signal::RawSignal;
-use crate::threads::{ThreadPool, is_forked_child};
+use crate::threads::{ThreadPool, assert_is_background_thread, is_forked_child};
 use crate::trace::trace_if_enabled_with_args;
 use crate::tty_handoff::TtyHandoff;
 use crate::wutil::{fish_wcstol, perror_io};
 use errno::{errno, set_errno};
-use fish_common::{ScopeGuard, exit_without_destructors, truncate_at_nul, write_loop};
-use fish_widestring::{ToWString as _, bytes2wcstring, wcs2bytes, wcs2zstring};
+use fish_common::{ScopeGuard, escape, exit_without_destructors, truncate_at_nul, write_loop};
+use fish_feature_flags::{FeatureFlag, METADATA, feature_test};
+use fish_wcstringutil::join_strings;
+use fish_widestring::{ToWString as _, bytes2wcstring, wcs2bytes, wcs2osstring, wcs2zstring};
 use libc::{
-    EACCES, ENOENT, ENOEXEC, ENOTDIR, EPIPE, EXIT_FAILURE, EXIT_SUCCESS, SIGINT, SIGQUIT,
+    EACCES, ENOENT, ENOEXEC, ENOTDIR, EPIPE, EXIT_FAILURE, EXIT_SUCCESS, SIGINT, SIGPIPE, SIGQUIT,
     STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
 };
 use nix::{
@@ -57,15 +66,17 @@
 };
 use std::sync::LazyLock;
 use std::{
-    ffi::CStr,
+    ffi::{CStr, CString, OsStr},
     io::{Read as _, Write as _},
     mem::MaybeUninit,
     num::NonZeroU32,
     os::fd::{AsRawFd as _, FromRawFd as _, OwnedFd, RawFd},
+    os::unix::{ffi::OsStrExt as _, process::CommandExt as _},
+    process::Stdio,
     slice,
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
 };
 
@@ -695,6 +706,20 @@ pub enum PgroupPolicy {
     Lead,              // The new process is the leader of a new pgroup.
 }
 
+impl PgroupPolicy {
+    /// Decide on pgroups for a process we are about to fork: either we stay in fish's pgroup, or
+    /// we set the pgroup from our leader, or we become the leader.
+    fn for_process(j: &Job, p: &Process) -> Self {
+        if p.leads_pgrp {
+            PgroupPolicy::Lead
+        } else if let Some(pgid) = j.group().pgid() {
+            PgroupPolicy::Join(pgid.as_pid_t())
+        } else {
+            PgroupPolicy::Inherit
+        }
+    }
+}
+
 /// Call fork() as part of executing a process in a job. Set up process groups.
 /// Execute `child_action` in the context of the child.
 fn fork_child_for_process(
@@ -786,7 +811,7 @@ fn fork_child_for_process(
         "Fork #%d, pid %d fork external command for '%s'",
         count,
         pid,
-        p.argv0().unwrap()
+        p.argv0().unwrap_or_default()
     );
     Ok(())
 }
@@ -881,15 +906,7 @@ fn exec_external_command(
     // Convert our IO chain to a dup2 sequence.
     let dup2s = dup2_list_resolve_chain(proc_io_chain);
 
-    // Decide on pgroups - either we stay in fish's pgroup, or we set the pgroup from our leader,
-    // or we become the leader.
-    let pgroup_policy = if p.leads_pgrp {
-        PgroupPolicy::Lead
-    } else if let Some(pgid) = j.group().pgid() {
-        PgroupPolicy::Join(pgid.as_pid_t())
-    } else {
-        PgroupPolicy::Inherit
-    };
+    let pgroup_policy = PgroupPolicy::for_process(j, p);
 
     // Ensure that stdin is blocking before we hand it off (see issue #176).
     // Note this will also affect stdout and stderr if they refer to the same tty.
@@ -966,14 +983,15 @@ fn function_prepare_environment(
         argv.clone(),
         props.shadow_scope,
     ));
-    let vars = parser.vars();
 
     // Setup the environment for the function. There are three components of the environment:
     // 1. named arguments
     // 2. inherited variables
     // 3. argv
+    // The first two go through the parser, so restricted code can't use them to change search
+    // paths or exported variables. The caller reports that before running the function.
 
-    let mode = parser.convert_env_set_mode(ParserEnvSetMode::user(EnvMode::LOCAL));
+    let mode = ParserEnvSetMode::user(EnvMode::LOCAL);
 
     let mut overwrite_argv = false;
     for (idx, named_arg) in props.named_arguments.iter().enumerate() {
@@ -981,9 +999,9 @@ fn function_prepare_environment(
             overwrite_argv = true;
         }
         if idx < argv.len() {
-            vars.set_one(named_arg, mode, argv[idx].clone());
+            parser.set_one(named_arg, mode, argv[idx].clone());
         } else {
-            vars.set_empty(named_arg, mode);
+            parser.set_empty(named_arg, mode);
         }
     }
 
@@ -991,11 +1009,11 @@ fn function_prepare_environment(
         if key == L!("argv") {
             overwrite_argv = true;
         }
-        vars.set(key, mode, value.clone());
+        parser.set_var(key, mode, value.clone());
     }
 
     if !overwrite_argv {
-        vars.set_argv(argv, mode.is_repainting);
+        parser.vars().set_argv(argv, parser.is_repainting());
     }
     fb
 }
@@ -1059,6 +1077,10 @@ fn get_performer_for_function(
     };
     let argv = p.argv().clone();
     Ok(Box::new(move |parser: &mut Parser, _out, _err| {
+        let _restricted = props
+            .restricted_commands
+            .clone()
+            .map(|allowed| parser.push_restriction(allowed));
         // Pull out the job list from the function.
         let fb = function_prepare_environment(parser, argv, &props);
         let body_node = props.func_node.child_ref(|n| &n.jobs);
@@ -1103,7 +1125,26 @@ fn exec_block_or_func_process(
         // Note this may fail if the function was erased.
         get_performer_for_function(p, j, &io_chain)?
     };
-    let status = performer(parser, None, None);
+
+    // With concurrent pipelines, if we write directly into a pipe (because we are the deferred
+    // process), stop once the reader goes away instead of running to completion.
+    let streams_into_pipe = !p.is_last_in_job
+        && !piped_output_needs_buffering
+        && feature_test(FeatureFlag::ConcurrentPipelines);
+    let broken_pipe = Arc::new(AtomicBool::new(false));
+    let saved_broken_pipe = streams_into_pipe.then(|| {
+        std::mem::replace(
+            &mut parser.libdata_mut().broken_pipe,
+            Some(broken_pipe.clone()),
+        )
+    });
+    let mut status = performer(parser, None, None);
+    if let Some(saved_broken_pipe) = saved_broken_pipe {
+        parser.libdata_mut().broken_pipe = saved_broken_pipe;
+        if broken_pipe.load(Ordering::Relaxed) {
+            status = ProcStatus::from_signal(RawSignal::new(SIGPIPE));
+        }
+    }
     p.status.set(status);
     // Match the behavior of handle_child_status to cancel a job group.
     if status.signal_exited() {
@@ -1134,6 +1175,310 @@ fn exec_block_or_func_process(
     Ok(())
 }
 
+/// Run a function or block process in a child fish. This is used for background jobs, and for
+/// processes which pipe into another process.
+/// Unlike [`exec_block_or_func_process`], this does not buffer the output. The child runs
+/// concurrently with the rest of the job, like an external command, and is reaped like one.
+/// It is sent a script which recreates our variables and functions before running the process, so
+/// any changes it makes to them are not seen by us.
+/// An error return here indicates that the process failed to launch, and the rest of the pipeline
+/// should be cancelled.
+fn exec_block_or_func_in_child(
+    parser: &mut Parser,
+    j: &Job,
+    p: &Process,
+    io_chain: &IoChain,
+) -> LaunchResult {
+    assert!(p.is_block_node() || p.is_function());
+    let Some(script) = child_fish_script(parser, p) else {
+        flog!(
+            error,
+            wgettext_fmt!("Unknown function '%s'", p.argv0().unwrap())
+        );
+        return Err(());
+    };
+
+    let Some(fish_path) = child_fish_path(parser) else {
+        flog!(
+            error,
+            wgettext!("Could not find fish to run a function or block in a pipeline")
+        );
+        return Err(());
+    };
+
+    // The script is sent over a pipe, since it may be too large for an argument.
+    let Ok(script_pipes) = make_autoclose_pipes() else {
+        flog!(warning, wgettext!(PIPE_ERROR));
+        return Err(());
+    };
+    let script_fd = script_pipes.read.as_raw_fd();
+    let mut dup2s = dup2_list_resolve_chain(io_chain);
+    // Clear CLOEXEC, so the child inherits the read end.
+    dup2s.add_dup2(script_fd, script_fd);
+
+    let mut argv = vec![wcs2zstring(get_program_name())];
+    argv.extend(child_fish_options().iter().map(|arg| wcs2zstring(arg)));
+    argv.push(c"-c".to_owned());
+    argv.push(wcs2zstring(&sprintf!(
+        "source /dev/fd/%d %d<&-",
+        script_fd,
+        script_fd
+    )));
+    let argv = OwningNullTerminatedArray::new(argv);
+    let envv = parser.vars().export_array();
+
+    fork_child_for_process(j, p, &dup2s, PgroupPolicy::for_process(j, p), |p| {
+        signal_safe_launch_process(p, &fish_path, &argv, &envv)
+    })?;
+    drop(script_pipes.read);
+
+    // Write the script in the background, as the child may not read it all before we launch the
+    // rest of the pipeline.
+    let script_write = script_pipes.write;
+    let script = wcs2bytes(&script);
+    exec_thread_pool().perform(move || {
+        // The child reports any error, e.g. if it failed to launch.
+        let _ = write_loop(&script_write, &script);
+    });
+    Ok(())
+}
+
+/// Return the path of the fish to run functions or blocks in, or None if it can't be found.
+fn child_fish_path(parser: &Parser) -> Option<CString> {
+    match get_fish_path() {
+        FishPath::Absolute(path) => CString::new(path.as_os_str().as_bytes()).ok(),
+        FishPath::LookUpInPath => {
+            path_get_path(get_program_name(), parser.vars()).map(|path| wcs2zstring(&path))
+        }
+    }
+}
+
+/// The options for a child fish which runs a script from [`child_fish_script`].
+fn child_fish_options() -> Vec<WString> {
+    // Pass on our features, so the child parses and runs the script as we would.
+    let features: Vec<WString> = METADATA
+        .iter()
+        .filter(|md| !md.read_only)
+        .map(|md| {
+            if feature_test(md.flag) {
+                md.name.to_owned()
+            } else {
+                sprintf!("no-%s", md.name)
+            }
+        })
+        .collect();
+    vec![
+        L!("--no-config").to_owned(),
+        sprintf!("--features=%s", join_strings(&features, ',')),
+    ]
+}
+
+/// The options to `fish` or `eval` which restrict code to running the external commands in
+/// `allowed`.
+fn restriction_args(allowed: &[WString]) -> Vec<WString> {
+    let mut args = vec![L!("--restricted").to_owned()];
+    for cmd in allowed {
+        args.push(sprintf!("--allow-command=%s", cmd));
+    }
+    args
+}
+
+/// Add the `definition` of a function or computed variable, or a command to run, to `script`. If
+/// it comes from restricted code, it is run in restricted mode, so it stays restricted.
+fn push_definition(script: &mut WString, definition: &wstr, restricted: Option<&[WString]>) {
+    let Some(allowed) = restricted else {
+        script.push_utfstr(definition);
+        return;
+    };
+    script.push_str("eval");
+    for arg in restriction_args(allowed) {
+        script.push(' ');
+        script.push_utfstr(&escape(&arg));
+    }
+    script.push_str(" -- ");
+    script.push_utfstr(&escape(definition));
+    script.push('\n');
+}
+
+/// Add a `set` command to `script` which recreates `var` in the scope given by the `set` flag
+/// `scope`.
+fn push_variable(script: &mut WString, scope: &str, name: &wstr, var: &EnvVar) {
+    if let Some(computed) = var.computed() {
+        let mut definition = L!("set -").to_owned();
+        definition.push_str(scope);
+        definition.push_str(" --computed ");
+        if let Some(ttl) = computed.ttl() {
+            let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+            definition.push_utfstr(&sprintf!("--ttl %dms ", millis));
+        }
+        definition.push_utfstr(name);
+        definition.push(' ');
+        definition.push_utfstr(&escape(computed.source()));
+        definition.push('\n');
+        push_definition(script, &definition, computed.restricted_commands());
+        return;
+    }
+    if let Some(keys) = var.map_keys() {
+        script.push_str("set -");
+        script.push_str(scope);
+        script.push_str("A ");
+        script.push_utfstr(name);
+        for (key, value) in keys.iter().zip(var.as_list()) {
+            script.push(' ');
+            script.push_utfstr(&escape(key));
+            script.push(' ');
+            script.push_utfstr(&escape(value));
+        }
+        script.push('\n');
+        return;
+    }
+    script.push_str("set -");
+    script.push_str(scope);
+    if var.exports() {
+        script.push('x');
+    }
+    script.push_str(if var.is_pathvar() {
+        " --path "
+    } else {
+        " --unpath "
+    });
+    script.push_utfstr(name);
+    for value in var.as_list() {
+        script.push(' ');
+        script.push_utfstr(&escape(value));
+    }
+    script.push('\n');
+}
+
+/// Return a script which recreates our variables and functions in a new fish.
+fn child_fish_setup(parser: &Parser) -> WString {
+    let vars = parser.vars();
+    let mut script = WString::new();
+    // Carry over the job control mode, so the child's jobs get pgroups as ours would.
+    script.push_str(match get_job_control_mode() {
+        JobControl::All => "status job-control full\n",
+        JobControl::Interactive => "status job-control interactive\n",
+        JobControl::None => "status job-control none\n",
+    });
+    // Recreate variables in their own scope: universal ones first (a child without config keeps
+    // them as globals), then globals and locals, so each shadows the ones before it like here.
+    let scopes = [
+        (EnvMode::UNIVERSAL, "U"),
+        (EnvMode::GLOBAL, "g"),
+        (EnvMode::LOCAL, "l"),
+    ];
+    for (mode, scope) in scopes {
+        for name in vars.get_names(mode) {
+            if crate::env::is_read_only(&name) {
+                continue;
+            }
+            let Some(var) = vars.getf(&name, mode) else {
+                continue;
+            };
+            push_variable(&mut script, scope, &name, &var);
+        }
+    }
+
+    // Autoloaded functions will be autoloaded by the child as well.
+    // Event handlers are left out, they belong to us.
+    for name in function::get_names(true, vars) {
+        let Some(props) = function::get_props(&name) else {
+            continue;
+        };
+        if props.is_autoload.load() {
+            continue;
+        }
+        let definition = props.definition_without_handlers(&name);
+        push_definition(
+            &mut script,
+            &definition,
+            props.restricted_commands.as_deref(),
+        );
+    }
+    script
+}
+
+/// Return a script which recreates our variables and functions in a new fish, and then runs the
+/// function or block process `p`. Return None if `p` calls a function which no longer exists.
+fn child_fish_script(parser: &Parser, p: &Process) -> Option<WString> {
+    let mut command = match &p.typ {
+        ProcessType::BlockNode(node) => node.source(node.source_str()).to_owned(),
+        ProcessType::Function => {
+            let argv0 = p.argv0().unwrap();
+            function::get_props(argv0)?;
+            let argv: Vec<WString> = p.argv().iter().map(|arg| escape(arg)).collect();
+            join_strings(&argv, ' ')
+        }
+        _ => panic!("Expected a block node or function process"),
+    };
+    command.push('\n');
+    let mut script = child_fish_setup(parser);
+    // Restricted code stays restricted in the child.
+    push_definition(
+        &mut script,
+        &command,
+        parser.restricted_commands().as_deref(),
+    );
+    Some(script)
+}
+
+/// A command to run in a new fish which has our variables and functions, so it can run while we do
+/// something else. Any changes it makes to variables and functions are not seen by us.
+pub struct ChildFishCommand {
+    fish_path: CString,
+    options: Vec<WString>,
+    script: WString,
+}
+
+impl ChildFishCommand {
+    /// Return None if fish could not be found.
+    pub fn new(parser: &Parser, command: &wstr) -> Option<Self> {
+        let mut command = command.to_owned();
+        command.push('\n');
+        let mut script = child_fish_setup(parser);
+        push_definition(
+            &mut script,
+            &command,
+            parser.restricted_commands().as_deref(),
+        );
+        Some(Self {
+            fish_path: child_fish_path(parser)?,
+            options: child_fish_options(),
+            script,
+        })
+    }
+
+    /// Run the command and return the lines it printed, or None if fish could not be launched.
+    /// This waits for the command to finish, so it must not be called on the main thread.
+    pub fn output_lines(self) -> Option<Vec<WString>> {
+        assert_is_background_thread();
+        let mut child = std::process::Command::new(OsStr::from_bytes(self.fish_path.as_bytes()))
+            .arg0(wcs2osstring(get_program_name()))
+            .args(self.options.iter().map(|arg| wcs2osstring(arg)))
+            .args(["-c", "source -"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        // `source` reads the entire script before running any of it, so the child's output can't
+        // fill up its pipe while we are still writing.
+        let mut stdin = child.stdin.take().unwrap();
+        let _ = stdin.write_all(&wcs2bytes(&self.script));
+        drop(stdin);
+        let output = child.wait_with_output().ok()?;
+        let mut lines: Vec<WString> = output
+            .stdout
+            .split(|&c| c == b'\n')
+            .map(bytes2wcstring)
+            .collect();
+        if lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        Some(lines)
+    }
+}
+
 fn get_performer_for_builtin(p: &Process, j: &Job, io_chain: &IoChain) -> Box<ProcPerformer> {
     assert!(p.is_builtin(), "Process must be a builtin");
 
@@ -1225,6 +1570,13 @@ fn exec_builtin_process(
     let performer = get_performer_for_builtin(p, j, io_chain);
     let status = performer(parser, Some(&mut out), Some(&mut err));
     p.status.set(status);
+    // If we wrote into the pipe of an enclosing function or block and its reader went away, tell
+    // it to stop.
+    if p.is_last_in_job && (out.saw_broken_pipe() || err.saw_broken_pipe()) {
+        if let Some(broken_pipe) = &parser.libdata().broken_pipe {
+            broken_pipe.store(true, Ordering::Relaxed);
+        }
+    }
     handle_builtin_output(parser, j, p, io_chain, &out, &err);
     Ok(())
 }
@@ -1354,6 +1706,15 @@ fn exec_process_in_job(
     // Execute the process.
     p.check_generations_before_launch();
     match p.typ {
+        ProcessType::Function | ProcessType::BlockNode(_)
+            if j.is_initially_background()
+                || (piped_output_needs_buffering
+                    && feature_test(FeatureFlag::ConcurrentPipelines)) =>
+        {
+            exec_block_or_func_in_child(parser, j, p, &process_net_io_chain)?;
+            parser.mut_wait_handles().remove_by_pid(p.pid().unwrap());
+            Ok(())
+        }
         ProcessType::Function | ProcessType::BlockNode(_) => exec_block_or_func_process(
             parser,
             j,
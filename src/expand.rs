@@ -16,7 +16,7 @@
     history::{History, history_id},
     operation_context::OperationContext,
     parse_constants::{ParseError, ParseErrorCode, ParseErrorList, SOURCE_LOCATION_UNKNOWN},
-    parse_util::{expand_variable_error, locate_cmdsubst_range},
+    parse_util::{expand_variable_error, locate_cmdsubst_range, unescaped_slice_length},
     path::path_apply_working_directory,
     prelude::*,
     wildcard::{WildcardResult, wildcard_expand_string, wildcard_has_internal},
@@ -507,6 +507,37 @@ fn parse_slice(
     Ok(pos)
 }
 
+/// Return whether the end of `prefix` is inside the brackets after an associative variable, like
+/// `$map[key`. Since variables are expanded from the end, variables in a key are expanded before
+/// the associative variable is looked up.
+fn in_map_subscript(prefix: &wstr, vars: &dyn Environment) -> bool {
+    let chars = prefix.as_char_slice();
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().rev() {
+        match c {
+            ']' => depth += 1,
+            '[' if depth > 0 => depth -= 1,
+            '[' => {
+                let name_start = chars[..i]
+                    .iter()
+                    .rposition(|&c| !valid_var_name_char(c))
+                    .map_or(0, |pos| pos + 1);
+                if name_start == 0
+                    || name_start == i
+                    || ![VARIABLE_EXPAND, VARIABLE_EXPAND_SINGLE].contains(&chars[name_start - 1])
+                {
+                    return false;
+                }
+                return vars
+                    .get(&prefix[name_start..i])
+                    .is_some_and(|var| var.is_map());
+            }
+            _ => (),
+        }
+    }
+    false
+}
+
 /// Expand all environment variables in the string *ptr.
 ///
 /// This function is slow, fragile and complicated. There are lots of little corner cases, like
@@ -610,8 +641,26 @@ fn expand_variables(
     let mut all_values = true;
     let slice_start = var_name_stop;
     let mut var_idx_list = vec![];
+    let mut map_key = None;
 
-    if instr.as_char_slice().get(slice_start) == Some(&'[') {
+    if instr.as_char_slice().get(slice_start) == Some(&'[')
+        && var.as_ref().is_some_and(EnvVar::is_map)
+    {
+        // Associative variables are indexed by a single key instead of a slice.
+        all_values = false;
+        let Some(slice_len) = unescaped_slice_length(&instr[slice_start..]) else {
+            append_syntax_error!(errors, slice_start + 1, "Invalid index value");
+            return ExpandResult::make_error(STATUS_EXPAND_ERROR);
+        };
+        // Any variables in the key have already been expanded, each value separately, see
+        // `in_map_subscript`. The separators only mark where they were.
+        let key: WString = instr[slice_start + 1..slice_start + slice_len - 1]
+            .chars()
+            .filter(|&c| c != INTERNAL_SEPARATOR && c != VARIABLE_EXPAND_EMPTY)
+            .collect();
+        map_key = Some(key);
+        var_name_and_slice_stop = slice_start + slice_len;
+    } else if instr.as_char_slice().get(slice_start) == Some(&'[') {
         all_values = false;
         // If a variable is missing, behave as though we have one value, so that $var[1] always
         // works.
@@ -689,6 +738,11 @@ fn expand_variables(
                     var_item_list.push(item.clone());
                 }
             }
+        } else if let Some(key) = map_key {
+            // A missing key expands to nothing, like an index out of bounds.
+            if let Some(value) = var.as_ref().unwrap().map_get(&key) {
+                var_item_list.push(value.clone());
+            }
         } else {
             let all_var_items = var.as_ref().unwrap().as_list();
             for item_index in var_idx_list {
@@ -704,6 +758,18 @@ fn expand_variables(
     }
 
     if is_single {
+        // A quoted expansion in the key of an associative variable, like "$map[$key]", would join
+        // several values into one key.
+        if var_item_list.len() > 1 && in_map_subscript(&instr[..varexp_char_idx], vars) {
+            append_syntax_error!(
+                errors,
+                varexp_char_idx,
+                "Expected a single key for an associative variable, but $%s has %d values",
+                var_name,
+                var_item_list.len()
+            );
+            return ExpandResult::make_error(STATUS_EXPAND_ERROR);
+        }
         // Quoted expansion. Here we expect the variable's delimiter.
         // Note history always has a space delimiter.
         let delimit = if history.is_some() {
@@ -1337,6 +1403,15 @@ fn stage_variables(&mut self, input: WString, out: &mut CompletionReceiver) -> E
             }
             ExpandResult::ok()
         } else {
+            // Computed variables are only evaluated where command substitutions may run.
+            if !self
+                .flags
+                .intersects(ExpandFlags::SKIP_CMDSUBST | ExpandFlags::FAIL_ON_CMDSUBST)
+            {
+                if let Some(parser) = self.ctx.maybe_parser() {
+                    parser.refresh_computed_vars(&next);
+                }
+            }
             let size = next.len();
             expand_variables(next, out, size, self.ctx.vars(), self.errors)
         }
@@ -1432,6 +1507,17 @@ fn stage_wildcards(
                 }
             }
 
+            // Completing a recursive wildcard may skip what the user's ignore files exclude.
+            let ignore_files = if for_completions && path_to_expand.contains(ANY_STRING_RECURSIVE) {
+                self.ctx
+                    .vars()
+                    .get(L!("fish_complete_ignore_files"))
+                    .map(|var| var.as_list().to_owned())
+                    .unwrap_or_default()
+            } else {
+                vec![]
+            };
+
             result = ExpandResult::new(ExpandResultCode::WildcardNoMatch);
             let mut expanded_recv = out.subreceiver();
             for effective_working_dir in effective_working_dirs {
@@ -1441,6 +1527,7 @@ fn stage_wildcards(
                     self.flags,
                     &*self.ctx.cancel_checker,
                     &mut expanded_recv,
+                    &ignore_files,
                 );
                 match expand_res {
                     WildcardResult::Match => result = ExpandResult::ok(),
@@ -1866,6 +1953,38 @@ macro_rules! expand_test {
             "Wrong fuzzy matching 6 - shouldn't remove valid directory names (#3211)"
         );
 
+        // Recursive wildcards complete to the full paths they match.
+        expand_test!(
+            "test/fish_expand_test/**/q",
+            ExpandFlags::FOR_COMPLETIONS,
+            "test/fish_expand_test/lol/nub/q",
+            "Recursive wildcard completion did the wrong thing 1"
+        );
+
+        expand_test!(
+            "test/fish_expand_test/**/x",
+            ExpandFlags::FOR_COMPLETIONS,
+            (
+                "test/fish_expand_test/aaa2/x",
+                "test/fish_expand_test/bax/xxx",
+                "test/fish_expand_test/baz/xxx",
+                "test/fish_expand_test/bb/x"
+            ),
+            "Recursive wildcard completion did the wrong thing 2"
+        );
+
+        expand_test!(
+            "test/fish_expand_test/l**",
+            ExpandFlags::FOR_COMPLETIONS,
+            (
+                "test/fish_expand_test/lol/",
+                "test/fish_expand_test/lol/nub/",
+                "test/fish_expand_test/lol/nub/q",
+                "test/fish_expand_test/lol/nub/zzz"
+            ),
+            "Recursive wildcard completion did the wrong thing 3"
+        );
+
         // Dotfiles
         expand_test!(
             "test/fish_expand_test/.*",
@@ -1973,7 +2092,7 @@ fn test_abbreviations() {
 
         // Helper to expand an abbreviation, enforcing we have no more than one result.
         let abbr_expand_1 = |token, pos| -> Option<WString> {
-            let result = with_abbrs(|abbrset| abbrset.r#match(token, pos, L!("")));
+            let result = with_abbrs(|abbrset| abbrset.r#match(token, pos, L!(""), L!("/")));
             if result.is_empty() {
                 return None;
             }
@@ -58,6 +58,10 @@ pub struct FunctionProperties {
 
     /// The 1-based line number where the specified function was copied.
     pub copy_definition_lineno: Option<NonZeroU32>,
+
+    /// If the function was defined by restricted code, the external commands it may run.
+    /// It is always run restricted, see `eval --restricted`.
+    pub restricted_commands: Option<Vec<WString>>,
 }
 
 /// FunctionProperties are safe to share between threads.
@@ -416,10 +420,24 @@ pub fn copy_definition_lineno(&self) -> u32 {
     /// targets. This is to support the 'functions' builtin.
     /// Note callers must provide the function name, since the function does not know its own name.
     pub fn annotated_definition(&self, name: &wstr) -> WString {
+        self.definition(name, true)
+    }
+
+    /// Like [`Self::annotated_definition`], but without event handlers.
+    /// This is used to recreate the function in a child fish, where its handlers must not fire.
+    pub fn definition_without_handlers(&self, name: &wstr) -> WString {
+        self.definition(name, false)
+    }
+
+    fn definition(&self, name: &wstr, with_handlers: bool) -> WString {
         let mut out = WString::new();
         let desc = self.description.localize();
         let def = get_function_body_source(self);
-        let handlers = event::get_function_handlers(name);
+        let handlers = if with_handlers {
+            event::get_function_handlers(name)
+        } else {
+            Default::default()
+        };
 
         out.push_str("function ");
         // Typically we prefer to specify the function name first, e.g. "function foo --description bar"
@@ -277,7 +277,9 @@ fn command_kind(
     }
 
     // Abbreviations
-    if abbreviation_ok && with_abbrs(|set| set.has_match(cmd, abbrs::Position::Command, L!(""))) {
+    if abbreviation_ok
+        && with_abbrs(|set| set.has_match(cmd, abbrs::Position::Command, L!(""), working_directory))
+    {
         return Some(Plain);
     }
 
@@ -1246,6 +1248,9 @@ fn get_highlight_var_name(role: HighlightRole) -> &'static wstr {
         HighlightRole::PagerSelectedPrefix => L!("fish_pager_color_selected_prefix"),
         HighlightRole::PagerSelectedCompletion => L!("fish_pager_color_selected_completion"),
         HighlightRole::PagerSelectedDescription => L!("fish_pager_color_selected_description"),
+        HighlightRole::PagerPreview => L!("fish_pager_color_preview"),
+        HighlightRole::PagerMarked => L!("fish_pager_color_marked"),
+        HighlightRole::PagerMatch => L!("fish_pager_color_match"),
     }
 }
 
@@ -1282,10 +1287,12 @@ fn get_fallback(role: HighlightRole) -> HighlightRole {
         HighlightRole::PagerSecondaryCompletion | HighlightRole::PagerSelectedCompletion => {
             HighlightRole::PagerCompletion
         }
-        HighlightRole::PagerSecondaryDescription | HighlightRole::PagerSelectedDescription => {
-            HighlightRole::PagerDescription
-        }
+        HighlightRole::PagerSecondaryDescription
+        | HighlightRole::PagerSelectedDescription
+        | HighlightRole::PagerPreview => HighlightRole::PagerDescription,
         HighlightRole::PagerSelectedBackground => HighlightRole::SearchMatch,
+        HighlightRole::PagerMarked => HighlightRole::Selection,
+        HighlightRole::PagerMatch => HighlightRole::SearchMatch,
     }
 }
 
@@ -1328,6 +1335,9 @@ pub enum HighlightRole {
     PagerSelectedPrefix,
     PagerSelectedCompletion,
     PagerSelectedDescription,
+    PagerPreview,
+    PagerMarked,
+    PagerMatch,
 }
 
 /// Simple value type describing how a character should be highlighted.
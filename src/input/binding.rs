@@ -7,6 +7,7 @@
     prelude::*,
     reader::{Reader, reader_reset_interrupted},
     threads::assert_is_main_thread,
+    tty_handoff::set_report_key_event_types,
 };
 use fish_common::{FilenameRef, Named, assert_sorted_by_name, escape, get_by_sorted_name};
 use std::{
@@ -139,6 +140,18 @@ fn is_generic(&self) -> bool {
     }
 }
 
+/// A possible continuation of a partially typed key sequence, shown as a hint while fish waits for
+/// the next key.
+#[derive(Debug, Clone)]
+pub struct SequenceKeyHint {
+    /// The keys typed so far.
+    pub typed: Vec<Key>,
+    /// The keys that still need to be typed to trigger the binding.
+    pub remaining: Vec<Key>,
+    /// Commands of the binding.
+    pub commands: Vec<WString>,
+}
+
 /// Input function metadata. This list should be kept in sync with the key code list in
 /// input_common.rs.
 struct InputFunctionMetadata {
@@ -270,6 +283,7 @@ const fn make_md(name: &'static wstr, code: ReadlineCmd) -> InputFunctionMetadat
     ("kill-word-vi", KillWordVi),
     ("nextd-or-forward-word", NextdOrForwardWordEmacs),
     ("or", FuncOr),
+    ("pager-toggle-mark", PagerToggleMark),
     ("pager-toggle-search", PagerToggleSearch),
     ("prevd-or-backward-word", PrevdOrBackwardWord),
     ("redo", Redo),
@@ -299,6 +313,10 @@ const fn make_md(name: &'static wstr, code: ReadlineCmd) -> InputFunctionMetadat
 pub struct BindingSet {
     bindings: Vec<Binding>,
     preset_bindings: Vec<Binding>,
+    /// Bindings without a key sequence, which receive keys that no other binding matches.
+    /// There is at most one per mode.
+    fallbacks: Vec<Binding>,
+    preset_fallbacks: Vec<Binding>,
 }
 
 impl BindingSet {
@@ -306,6 +324,8 @@ const fn new() -> Self {
         Self {
             bindings: Vec::new(),
             preset_bindings: Vec::new(),
+            fallbacks: Vec::new(),
+            preset_fallbacks: Vec::new(),
         }
     }
 }
@@ -387,6 +407,47 @@ pub fn add(
         binding_insert_sorted(ml, new_binding);
     }
 
+    /// Sets the fallback binding for a mode, replacing any existing one.
+    pub fn set_fallback(
+        &mut self,
+        commands: Vec<WString>,
+        mode: WString,
+        sets_mode: Option<WString>,
+        user: bool,
+        definition_file: Option<FilenameRef>,
+    ) {
+        let ml = if user {
+            &mut self.fallbacks
+        } else {
+            &mut self.preset_fallbacks
+        };
+        ml.retain(|m| m.mode != mode);
+        ml.push(Binding::new(
+            vec![],
+            commands,
+            mode,
+            sets_mode,
+            KeyNameStyle::Plain,
+            definition_file,
+        ));
+        self.update_key_event_types();
+    }
+
+    /// Ask the terminal for key release events only while a fallback binding can receive them.
+    fn update_key_event_types(&self) {
+        let receives_releases = self
+            .fallbacks
+            .iter()
+            .chain(&self.preset_fallbacks)
+            .any(|fallback| {
+                fallback
+                    .commands
+                    .iter()
+                    .any(|cmd| input_function_get_code(cmd).is_none())
+            });
+        set_report_key_event_types(receives_releases);
+    }
+
     // Like add(), but takes a single command.
     #[allow(clippy::too_many_arguments)]
     fn add1(
@@ -530,6 +591,7 @@ fn next(&mut self) -> CharEvent {
     /// If `escaped` is set, then return false if this (or any other) character had a timeout.
     fn next_is_char(
         &mut self,
+        ip: &BindingSet,
         style: &KeyNameStyle,
         key: Key,
         escaped: bool,
@@ -556,7 +618,18 @@ fn next_is_char(
                 }
             } else {
                 flog!(reader, "readch timed sequence key");
-                match self.event_queue.readch_timed_sequence_key() {
+                let typed: Vec<KeyEvent> = if self.subidx == 0 {
+                    self.peeked[..self.idx]
+                        .iter()
+                        .filter_map(|evt| evt.get_key().map(|kevt| kevt.key))
+                        .collect()
+                } else {
+                    vec![]
+                };
+                let bind_mode = self.event_queue.get_bind_mode();
+                match self.event_queue.readch_timed_sequence_key_with_hint(&|| {
+                    ip.sequence_key_hints(&bind_mode, &typed)
+                }) {
                     Some(evt) => evt,
                     None => {
                         self.had_timeout = true;
@@ -575,7 +648,7 @@ fn next_is_char(
             self.idx += 1;
             self.subidx = 0;
             flog!(reader, "matched delayed escape prefix in alt sequence");
-            return self.next_is_char(style, Key::from_raw(key.codepoint), true);
+            return self.next_is_char(ip, style, Key::from_raw(key.codepoint), true);
         }
         if *style == KeyNameStyle::Plain {
             let result = match_key_event_to_key(&kevt.key, &key);
@@ -611,7 +684,7 @@ fn next_is_char(
                     self.idx += 1;
                     self.subidx = 0;
                     flog!(reader, "matched escape prefix in raw escape sequence");
-                    return self.next_is_char(style, Key::from_raw(key.codepoint), true);
+                    return self.next_is_char(ip, style, Key::from_raw(key.codepoint), true);
                 } else if actual_seq
                     .get(self.subidx + 1)
                     .copied()
@@ -658,6 +731,7 @@ fn restart(&mut self) {
     /// Return true if this `peeker` matches a given sequence of char events given by `str`.
     fn try_peek_sequence(
         &mut self,
+        ip: &BindingSet,
         style: &KeyNameStyle,
         seq: &[Key],
         quality: &mut Vec<KeyMatchQuality>,
@@ -671,7 +745,7 @@ fn try_peek_sequence(
             // If we just read an escape, we need to add a timeout for the next char,
             // to distinguish between the actual escape key and an "alt"-modifier.
             let escaped = *style != KeyNameStyle::Plain && prev == Some(Key::from_raw(key::ESCAPE));
-            let Some(spec) = self.next_is_char(style, *key, escaped) else {
+            let Some(spec) = self.next_is_char(ip, style, *key, escaped) else {
                 return false;
             };
             quality.push(spec);
@@ -724,7 +798,7 @@ struct MatchedBinding<'a> {
             }
 
             // flog!(reader, "trying binding", format!("{:?}", m));
-            if self.try_peek_sequence(&m.key_name_style, &m.seq, &mut quality) {
+            if self.try_peek_sequence(ip, &m.key_name_style, &m.seq, &mut quality) {
                 // // A binding for just escape should also be deferred
                 // // so escape sequences take precedence.
                 let is_escape = m.seq == vec![Key::from_raw(key::ESCAPE)];
@@ -784,6 +858,14 @@ pub fn read_char(&mut self) -> CharEvent {
 
         // Search for sequence in binding tables.
         loop {
+            let released_keys = std::mem::take(&mut self.get_input_data_mut().released_keys);
+            if !released_keys.is_empty() {
+                // Each release pushes its commands to the front, so go backwards to keep the order.
+                for key in released_keys.into_iter().rev() {
+                    self.binding_execute_release(key);
+                }
+                continue;
+            }
             let evt = self.readch();
             match evt {
                 CharEvent::Readline(ref readline_event) => match readline_event.cmd {
@@ -869,12 +951,52 @@ fn binding_execute_matching_or_generic(&mut self) {
         let mut peeker = EventQueuePeeker::new(self);
         // Check for ordinary bindings.
         let bindings = bindings();
-        if let Some(binding) = peeker.find_binding(&bindings) {
+        let found = peeker.find_binding(&bindings);
+        if let Some(binding) = found.as_ref().filter(|binding| !binding.is_generic()) {
             flog!(
                 reader,
                 format!("Found binding {:?} from {:?}", &binding, &peeker.peeked)
             );
+            let key = peeker.peeked[..peeker.idx]
+                .iter()
+                .rev()
+                .find_map(|evt| evt.get_key().map(|kevt| kevt.key));
             peeker.consume();
+            self.get_input_data_mut().binding_key = key;
+            self.binding_execute(binding);
+            return;
+        }
+
+        // Nothing specific matched. A fallback takes the key before the generic binding.
+        if !peeker.char_sequence_interrupted() {
+            let bind_mode = peeker.event_queue.get_bind_mode();
+            if let Some(fallback) = bindings.fallback(&bind_mode) {
+                peeker.restart();
+                if let Some(key) = peeker.next().get_key().map(|kevt| kevt.key) {
+                    // Execute it as if it was bound to this key, so self-insert inserts the key.
+                    let mut binding = fallback.clone();
+                    binding.seq = vec![key.key];
+                    flog!(reader, format!("Found fallback {:?}", &binding));
+                    peeker.consume();
+                    self.get_input_data_mut().binding_key = Some(key);
+                    self.binding_execute(&binding);
+                    return;
+                }
+                peeker.restart();
+            }
+        }
+
+        if let Some(binding) = found {
+            flog!(
+                reader,
+                format!("Found binding {:?} from {:?}", &binding, &peeker.peeked)
+            );
+            let key = peeker
+                .peeked
+                .first()
+                .and_then(|evt| evt.get_key().map(|kevt| kevt.key));
+            peeker.consume();
+            self.get_input_data_mut().binding_key = key;
             self.binding_execute(&binding);
             return;
         }
@@ -895,6 +1017,23 @@ fn binding_execute_matching_or_generic(&mut self) {
         peeker.consume();
     }
 
+    /// Handle the release of a key. It must not act like another press of the key, so it only
+    /// runs the commands of a fallback binding that are not input functions.
+    fn binding_execute_release(&mut self, key: KeyEvent) {
+        let bind_mode = self.get_bind_mode();
+        let Some(mut binding) = bindings().fallback(&bind_mode).cloned() else {
+            return;
+        };
+        binding
+            .commands
+            .retain(|cmd| input_function_get_code(cmd).is_none());
+        if binding.commands.is_empty() {
+            return;
+        }
+        self.get_input_data_mut().binding_key = Some(key);
+        self.binding_execute(&binding);
+    }
+
     /// Pick through the queue of incoming characters until we get to one that matches.
     fn read_character_matching(&mut self, predicate: impl Fn(&CharEvent) -> bool) -> CharEvent {
         let mut saved_events = std::mem::take(&mut self.get_input_data_mut().event_storage);
@@ -1010,15 +1149,55 @@ pub fn get_names(&self, user: bool) -> Vec<BindingName> {
             .collect()
     }
 
-    /// Erase all bindings.
+    /// Erase all bindings, including the fallbacks.
     pub fn clear(&mut self, mode: Option<&wstr>, user: bool) {
-        let ml = if user {
-            &mut self.bindings
+        let (ml, fallbacks) = if user {
+            (&mut self.bindings, &mut self.fallbacks)
         } else {
-            &mut self.preset_bindings
+            (&mut self.preset_bindings, &mut self.preset_fallbacks)
         };
         let should_erase = |m: &Binding| mode.is_none_or(|x| x == m.mode);
         ml.retain(|m| !should_erase(m));
+        fallbacks.retain(|m| !should_erase(m));
+        self.update_key_event_types();
+    }
+
+    /// Erase the fallback binding for the specified mode.
+    pub fn erase_fallback(&mut self, mode: &wstr, user: bool) -> bool {
+        let ml = if user {
+            &mut self.fallbacks
+        } else {
+            &mut self.preset_fallbacks
+        };
+        let len = ml.len();
+        ml.retain(|m| m.mode != mode);
+        let erased = ml.len() != len;
+        self.update_key_event_types();
+        erased
+    }
+
+    /// Returns the fallback bindings, sorted by the order in which they were specified.
+    /// If bind_mode is None, then fallbacks from all modes are returned.
+    pub fn get_fallbacks(&self, bind_mode: Option<&wstr>, user: bool) -> Vec<&Binding> {
+        let ml = if user {
+            &self.fallbacks
+        } else {
+            &self.preset_fallbacks
+        };
+        let mut fallbacks: Vec<_> = ml
+            .iter()
+            .filter(|m| bind_mode.is_none_or(|mode| mode == m.mode))
+            .collect();
+        fallbacks.sort_unstable_by_key(|m| m.specification_order);
+        fallbacks
+    }
+
+    /// Returns the fallback binding that applies in `mode`, preferring the user's over the preset.
+    fn fallback(&self, mode: &wstr) -> Option<&Binding> {
+        self.fallbacks
+            .iter()
+            .chain(self.preset_fallbacks.iter())
+            .find(|m| m.mode == mode)
     }
 
     /// Erase binding for specified key sequence.
@@ -1039,6 +1218,44 @@ pub fn erase(&mut self, sequence: &[Key], mode: &wstr, user: bool) -> bool {
         result
     }
 
+    /// Returns the bindings in `mode` that could still be triggered after the keys in `typed`,
+    /// for hinting. User bindings shadow preset bindings for the same sequence.
+    pub fn sequence_key_hints(&self, mode: &wstr, typed: &[KeyEvent]) -> Vec<SequenceKeyHint> {
+        if typed.is_empty() {
+            return vec![];
+        }
+        let mut seen: Vec<&[Key]> = vec![];
+        let mut hints = vec![];
+        for m in self.bindings.iter().chain(self.preset_bindings.iter()) {
+            if m.mode != mode
+                || m.key_name_style != KeyNameStyle::Plain
+                || m.seq.len() <= typed.len()
+                || seen.contains(&m.seq.as_slice())
+            {
+                continue;
+            }
+            let matches = typed
+                .iter()
+                .zip(&m.seq)
+                .all(|(evt, key)| match_key_event_to_key(evt, key).is_some());
+            if !matches {
+                continue;
+            }
+            seen.push(&m.seq);
+            hints.push((
+                m.specification_order,
+                SequenceKeyHint {
+                    typed: m.seq[..typed.len()].to_vec(),
+                    remaining: m.seq[typed.len()..].to_vec(),
+                    commands: m.commands.clone(),
+                },
+            ));
+        }
+        // Show them in the order they were defined, like `bind` does.
+        hints.sort_by_key(|(order, _)| *order);
+        hints.into_iter().map(|(_, hint)| hint).collect()
+    }
+
     /// Returns the command bound to the specified bind mode.
     ///
     /// If bind_mode is None, then binds from all modes are returned.
@@ -1127,7 +1344,7 @@ mod tests {
         match_key_event_to_key,
     };
     use crate::input::{CharEvent, InputEventQueuer as _, KeyEvent};
-    use crate::key::{Key, Modifiers};
+    use crate::key::{Key, Modifiers, ctrl};
     use crate::prelude::*;
 
     #[test]
@@ -1257,4 +1474,114 @@ fn test_input() {
         assert_eq!(binding.unwrap().commands, ["down-line"]);
         peeker.restart();
     }
+
+    #[test]
+    fn test_sequence_key_hints() {
+        let mode = L!("default");
+        let add = |bindings: &mut BindingSet, seq: &[Key], command: &wstr, user: bool| {
+            bindings.add1(
+                seq.to_vec(),
+                KeyNameStyle::Plain,
+                command.to_owned(),
+                mode.to_owned(),
+                None,
+                user,
+                None,
+            );
+        };
+        let ctrl_x = ctrl('x');
+        let mut bindings = BindingSet::default();
+        add(
+            &mut bindings,
+            &[ctrl_x, ctrl('e')],
+            L!("edit_command_buffer"),
+            false,
+        );
+        add(
+            &mut bindings,
+            &[ctrl_x, ctrl('u')],
+            L!("upcase-word"),
+            false,
+        );
+        add(&mut bindings, &[ctrl_x, ctrl('e')], L!("my_editor"), true);
+        add(&mut bindings, &[ctrl_x], L!("fish_clipboard_copy"), false);
+        add(&mut bindings, &[ctrl('y'), ctrl('e')], L!("yank"), false);
+
+        let hints = bindings.sequence_key_hints(mode, &[KeyEvent::from(ctrl_x)]);
+        let hints: Vec<_> = hints
+            .iter()
+            .map(|hint| (hint.remaining.clone(), hint.commands.clone()))
+            .collect();
+        // The user binding comes first and shadows the preset one.
+        assert_eq!(
+            hints,
+            [
+                (vec![ctrl('e')], vec![L!("my_editor").to_owned()]),
+                (vec![ctrl('u')], vec![L!("upcase-word").to_owned()]),
+            ]
+        );
+
+        assert!(
+            bindings
+                .sequence_key_hints(L!("insert"), &[KeyEvent::from(ctrl_x)])
+                .is_empty()
+        );
+        assert!(bindings.sequence_key_hints(mode, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_fallback() {
+        let mode = L!("default");
+        let commands = |bindings: &BindingSet, mode: &wstr| {
+            bindings
+                .fallback(mode)
+                .map(|fallback| fallback.commands.clone())
+        };
+        let mut bindings = BindingSet::default();
+        assert_eq!(commands(&bindings, mode), None);
+
+        bindings.set_fallback(
+            vec![L!("preset_handler").to_owned()],
+            mode.to_owned(),
+            None,
+            false,
+            None,
+        );
+        assert_eq!(
+            commands(&bindings, mode),
+            Some(vec![L!("preset_handler").to_owned()])
+        );
+
+        // A user fallback takes precedence over the preset one, and replaces an earlier one.
+        bindings.set_fallback(
+            vec![L!("first").to_owned()],
+            mode.to_owned(),
+            None,
+            true,
+            None,
+        );
+        bindings.set_fallback(
+            vec![L!("second").to_owned()],
+            mode.to_owned(),
+            None,
+            true,
+            None,
+        );
+        assert_eq!(
+            commands(&bindings, mode),
+            Some(vec![L!("second").to_owned()])
+        );
+        assert_eq!(bindings.get_fallbacks(None, true).len(), 1);
+        assert_eq!(commands(&bindings, L!("insert")), None);
+
+        assert!(bindings.erase_fallback(mode, true));
+        assert!(!bindings.erase_fallback(mode, true));
+        assert_eq!(
+            commands(&bindings, mode),
+            Some(vec![L!("preset_handler").to_owned()])
+        );
+
+        bindings.clear(None, false);
+        assert_eq!(commands(&bindings, mode), None);
+    }
 }
@@ -1,8 +1,9 @@
 use super::{
     binding::match_key_event_to_key,
     input::{
-        CharEvent, ImplicitEvent, InputEventQueuer, InputEventTrigger, KeyEvent, QueryResponse,
-        QueryResultEvent, is_event_blocked_when_querying, next_input_event, stop_query,
+        CharEvent, ImplicitEvent, InputEventQueuer, InputEventTrigger, KeyEvent, KeyEventKind,
+        QueryResponse, QueryResultEvent, is_event_blocked_when_querying, next_input_event,
+        stop_query,
     },
 };
 use crate::{
@@ -14,8 +15,10 @@
         canonicalize_keyed_control_char, char_to_symbol, function_key, shift,
     },
     tty_handoff::{
-        SCROLL_CONTENT_UP_TERMINFO_CODE, TERMINAL_OS_NAME, XTGETTCAP_QUERY_OS_NAME, XTVERSION,
+        KITTY_GRAPHICS_QUERY_ID, SCROLL_CONTENT_UP_TERMINFO_CODE, TERMINAL_OS_NAME,
+        XTGETTCAP_QUERY_OS_NAME, XTVERSION, maybe_set_kitty_graphics_capability,
         maybe_set_kitty_keyboard_capability, maybe_set_scroll_content_up_capability,
+        maybe_set_sixel_capability, set_cell_size,
     },
 };
 use fish_widestring::{WString, bytes2wcstring, encode_byte_to_char, fish_reserved_codepoint};
@@ -172,6 +175,7 @@ fn parse_escape_sequence(
                 b'O' => self.parse_ss3(buffer),
                 b']' => self.parse_osc(buffer),
                 b'P' => self.parse_dcs(buffer),
+                b'_' => self.parse_apc(buffer),
                 _ => {
                     return EscapeSequenceParseResult {
                         state: Incomplete,
@@ -273,13 +277,20 @@ fn parse_csi(&mut self, buffer: &mut Vec<u8>) -> Option<CharEvent> {
             if caps_lock && modifiers == Modifiers::SHIFT && !key.to_uppercase().eq(Some(key)) {
                 modifiers.shift = false;
             }
-            KeyEvent::new_with(
+            let mut evt = KeyEvent::new_with(
                 modifiers,
                 explicit_modifiers,
                 key,
                 shifted_key,
                 base_layout_key,
-            )
+            );
+            // The event type is the first sub-parameter of the modifiers.
+            evt.kind = match params[1][1] {
+                2 => KeyEventKind::Repeat,
+                3 => KeyEventKind::Release,
+                _ => KeyEventKind::Press,
+            };
+            evt
         };
         let masked_key = |key: char| kitty_key(key, None, None);
 
@@ -342,6 +353,18 @@ fn parse_csi(&mut self, buffer: &mut Vec<u8>) -> Option<CharEvent> {
                 }
                 return Some(CharEvent::Implicit(ImplicitEvent::MouseLeft(position)));
             }
+            b't' if params[0][0] == 6 => {
+                // The response to the cell size query, with the height and width in pixels.
+                let (Ok(height), Ok(width)) =
+                    (usize::try_from(params[1][0]), usize::try_from(params[2][0]))
+                else {
+                    return invalid_sequence(buffer);
+                };
+                if width != 0 && height != 0 {
+                    set_cell_size(width, height);
+                }
+                return None;
+            }
             b't' => {
                 flog!(reader, "mouse event");
                 // VT200 button released in mouse highlighting mode at valid text location. 5 chars.
@@ -425,6 +448,10 @@ fn parse_csi(&mut self, buffer: &mut Vec<u8>) -> Option<CharEvent> {
             },
             b'c' if private_mode == Some(b'?') => {
                 flog!(reader, "Received Primary Device Attribute response");
+                // The first parameter is the terminal class; attribute 4 among the rest means sixel.
+                if params[1..].iter().any(|param| param[0] == 4) {
+                    maybe_set_sixel_capability();
+                }
                 return Some(query_response(QueryResponse::PrimaryDeviceAttribute));
             }
             b'n' if private_mode == Some(b'?') && params[0] == [997, 0, 0, 0] => {
@@ -599,6 +626,39 @@ fn parse_osc(&mut self, buffer: &mut Vec<u8>) -> Option<CharEvent> {
         Some(query_response(QueryResponse::BackgroundColor(c)))
     }
 
+    fn parse_apc(&mut self, buffer: &mut Vec<u8>) -> Option<CharEvent> {
+        assert_eq!(buffer, b"\x1b_");
+        let Some(kind) = self.read_sequence_byte(buffer) else {
+            return char_event(buffer, KeyEvent::from(alt('_')));
+        };
+        if kind != b'G' {
+            return None;
+        }
+        self.read_until_sequence_terminator(buffer, false)?;
+        // \e _ G i=ID ; OK ST
+        let buffer = &buffer[3..];
+        let mut buffer = buffer.splitn(2, |&c| c == b';');
+        let keys = buffer.next().unwrap();
+        let message = buffer.next()?;
+        flog!(
+            reader,
+            format!(
+                "Received kitty graphics response: {}; {}",
+                bytes2wcstring(keys),
+                bytes2wcstring(message)
+            )
+        );
+        let expected_id = format!("i={}", KITTY_GRAPHICS_QUERY_ID);
+        if message == b"OK"
+            && keys
+                .split(|&c| c == b',')
+                .any(|key| key == expected_id.as_bytes())
+        {
+            maybe_set_kitty_graphics_capability();
+        }
+        None
+    }
+
     fn parse_dcs(&mut self, buffer: &mut Vec<u8>) -> Option<CharEvent> {
         assert_eq!(buffer, b"\x1bP");
         let Some(success) = self.read_sequence_byte(buffer) else {
@@ -817,7 +877,7 @@ mod tests {
     use super::parse_hex;
     use crate::{
         input::{
-            CharEvent, KeyEvent, MockInputEventQueuer, QueryResponse,
+            CharEvent, KeyEvent, KeyEventKind, MockInputEventQueuer, QueryResponse,
             decode::{InputEventQueuerExt as _, query_response},
         },
         key::{self, Key, Modifiers, alt},
@@ -857,6 +917,11 @@ fn e(kevt: impl Into<KeyEvent>, seq: &str) -> CharEvent {
         validate!(b"\x1b\x1b\x1b", &[legacy_escape(), legacy_alt_escape()]);
         validate!(b"\x1bP", &[e(alt('P'), "\x1bP")]);
         validate!(b"\x1b\x1bP", &[legacy_escape(), e(alt('P'), "\x1bP")]);
+        validate!(b"\x1b_", &[e(alt('_'), "\x1b_")]);
+        // Kitty graphics responses are consumed silently.
+        validate!(b"\x1b_Gi=1;ENOENT:no such image\x1b\\", &[]);
+        // So is the cell size.
+        validate!(b"\x1b[6;20;10t", &[]);
         validate!(b"\x1b\x1b[D", &[e(alt(key::LEFT), "\x1b\x1b[D")]);
         validate!(b"\x1b\x1bOD", &[e(alt(key::LEFT), "\x1b\x1bOD")]);
         validate!(
@@ -867,6 +932,9 @@ fn e(kevt: impl Into<KeyEvent>, seq: &str) -> CharEvent {
             b"\x1b\x1b[97;3u",
             &[legacy_escape(), e(kitty_alt_a, "\x1b[97;3u")]
         );
+        let mut kitty_a_release = kitty_a;
+        kitty_a_release.kind = KeyEventKind::Release;
+        validate!(b"\x1b[97;1:3u", &[e(kitty_a_release, "\x1b[97;1:3u")]);
         validate!(
             b"\x1b\x1b]11;rgb:ffff/ffff/ffff\x1b\\",
             &[
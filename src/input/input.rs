@@ -1,5 +1,5 @@
 use super::{
-    binding::ReadlineCmd,
+    binding::{ReadlineCmd, SequenceKeyHint},
     decode::{DisplayBytes, LONG_READ_TIMEOUT, on_byte_read},
 };
 use crate::{
@@ -20,7 +20,7 @@
     collections::VecDeque,
     os::fd::{BorrowedFd, RawFd},
     sync::atomic::{AtomicUsize, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -172,12 +172,34 @@ pub fn from_check_exit() -> CharEvent {
     }
 }
 
+/// Whether a key was pressed, held down or released.
+/// Only terminals that report event types via the kitty keyboard protocol send repeats and
+/// releases; everything else is a press.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum KeyEventKind {
+    #[default]
+    Press,
+    Repeat,
+    Release,
+}
+
+impl KeyEventKind {
+    pub fn name(self) -> &'static wstr {
+        match self {
+            KeyEventKind::Press => L!("press"),
+            KeyEventKind::Repeat => L!("repeat"),
+            KeyEventKind::Release => L!("release"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct KeyEvent {
     pub key: Key,
     pub(crate) explicit_modifiers: bool,
     pub shifted_codepoint: char,
     pub base_layout_codepoint: char,
+    pub kind: KeyEventKind,
 }
 
 impl KeyEvent {
@@ -196,6 +218,7 @@ pub(super) fn new_with(
             explicit_modifiers,
             shifted_codepoint: shifted_key.unwrap_or_default(),
             base_layout_codepoint: base_layout_key.unwrap_or_default(),
+            kind: KeyEventKind::Press,
         }
     }
     pub(super) fn from_raw(codepoint: char) -> Self {
@@ -270,6 +293,11 @@ pub enum CharInputStyle {
 const WAIT_ON_SEQUENCE_KEY_INFINITE: usize = usize::MAX;
 static WAIT_ON_SEQUENCE_KEY_MS: AtomicUsize = AtomicUsize::new(WAIT_ON_SEQUENCE_KEY_INFINITE);
 
+/// Time in milliseconds to wait for the next key of a sequence before showing the possible
+/// continuations. By default, no hint is shown.
+const SEQUENCE_KEY_HINT_DISABLED: usize = usize::MAX;
+static SEQUENCE_KEY_HINT_DELAY_MS: AtomicUsize = AtomicUsize::new(SEQUENCE_KEY_HINT_DISABLED);
+
 // Update the wait_on_escape_ms value in response to the fish_escape_delay_ms user variable being
 // set.
 pub fn update_wait_on_escape_ms(vars: &EnvStack) {
@@ -320,6 +348,31 @@ pub fn update_wait_on_sequence_key_ms(vars: &EnvStack) {
     }
 }
 
+// Update the sequence_key_hint_delay_ms value in response to the fish_sequence_key_hint_delay_ms
+// user variable being set.
+pub fn update_sequence_key_hint_delay_ms(vars: &EnvStack) {
+    let hint_delay_ms = vars.get_unless_empty(L!("fish_sequence_key_hint_delay_ms"));
+    let Some(hint_delay_ms) = hint_delay_ms else {
+        SEQUENCE_KEY_HINT_DELAY_MS.store(SEQUENCE_KEY_HINT_DISABLED, Ordering::Relaxed);
+        return;
+    };
+    let hint_delay_ms = hint_delay_ms.as_string();
+    match fish_wcstol(&hint_delay_ms) {
+        Ok(val) if (10..5000).contains(&val) => {
+            SEQUENCE_KEY_HINT_DELAY_MS.store(val.try_into().unwrap(), Ordering::Relaxed);
+        }
+        _ => {
+            eprintf!(
+                concat!(
+                    "ignoring fish_sequence_key_hint_delay_ms: value '%s' ",
+                    "is not an integer or is < 10 or >= 5000 ms\n"
+                ),
+                hint_delay_ms
+            );
+        }
+    }
+}
+
 // A data type used by the input machinery.
 #[derive(Default)]
 pub struct InputData {
@@ -346,6 +399,12 @@ pub struct InputData {
 
     // If set, events will be buffered until the query finishes.
     pub blocking_query: Option<TerminalQuery>,
+
+    // The key event that triggered the most recently executed binding.
+    pub binding_key: Option<KeyEvent>,
+
+    // Keys released since the reader last handled releases.
+    pub released_keys: Vec<KeyEvent>,
 }
 
 impl InputData {
@@ -360,6 +419,8 @@ pub fn new(in_fd: RawFd, blocking_query_timeout: Option<Duration>) -> Self {
             event_storage: Vec::new(),
             blocking_query_timeout,
             blocking_query: None,
+            binding_key: None,
+            released_keys: Vec::new(),
         }
     }
 
@@ -430,13 +491,23 @@ pub(super) fn is_event_blocked_when_querying(evt: &CharEvent) -> bool {
 /// Note this is conceptually a "base class" with override points.
 pub trait InputEventQueuer {
     /// Return the next event in the queue, or none if the queue is empty.
+    /// Key releases are handed to key_released() instead, so they never take part in a key
+    /// sequence or end up as the argument of an input function.
     fn try_pop(&mut self) -> Option<CharEvent> {
-        if self.is_blocked_querying()
-            && is_event_blocked_when_querying(self.get_input_data().queue.front()?)
-        {
-            return None;
+        loop {
+            if self.is_blocked_querying()
+                && is_event_blocked_when_querying(self.get_input_data().queue.front()?)
+            {
+                return None;
+            }
+            let evt = self.get_input_data_mut().queue.pop_front()?;
+            match evt {
+                CharEvent::Key(kevt) if kevt.key.kind == KeyEventKind::Release => {
+                    self.key_released(kevt.key);
+                }
+                _ => return Some(evt),
+            }
         }
-        self.get_input_data_mut().queue.pop_front()
     }
 
     /// Read the next event, such as a UTF-8-encoded codepoint.
@@ -455,39 +526,48 @@ fn readch(&mut self) -> CharEvent {
                 return mevt;
             }
 
-            match next_input_event(
-                self.get_in_fd(),
-                self.get_ioport_fd(),
-                if self.is_blocked_querying() {
-                    Timeout::Duration(self.get_input_data().blocking_query_timeout.unwrap())
-                } else {
-                    Timeout::Forever
-                },
-            ) {
-                InputEventTrigger::Eof => {
-                    return CharEvent::Implicit(ImplicitEvent::Eof);
-                }
+            if let Some(mevt) = self.wait_for_input_event() {
+                return mevt;
+            }
+        }
+    }
 
-                InputEventTrigger::Interrupted => {
-                    self.select_interrupted();
-                }
+    /// Block until something happens on one of our fds, and handle it.
+    /// Return an event if it must be returned right away instead of being enqueued.
+    fn wait_for_input_event(&mut self) -> Option<CharEvent> {
+        match next_input_event(
+            self.get_in_fd(),
+            self.get_ioport_fd(),
+            if self.is_blocked_querying() {
+                Timeout::Duration(self.get_input_data().blocking_query_timeout.unwrap())
+            } else {
+                Timeout::Forever
+            },
+        ) {
+            InputEventTrigger::Eof => {
+                return Some(CharEvent::Implicit(ImplicitEvent::Eof));
+            }
 
-                InputEventTrigger::UvarNotified => {
-                    self.uvar_change_notified();
-                }
+            InputEventTrigger::Interrupted => {
+                self.select_interrupted();
+            }
 
-                InputEventTrigger::IOPortNotified => {
-                    self.ioport_notified();
-                }
+            InputEventTrigger::UvarNotified => {
+                self.uvar_change_notified();
+            }
 
-                InputEventTrigger::Byte(read_byte) => {
-                    on_byte_read(self, read_byte);
-                }
-                InputEventTrigger::TimeoutElapsed => {
-                    return CharEvent::QueryResult(QueryResultEvent::Timeout);
-                }
+            InputEventTrigger::IOPortNotified => {
+                self.ioport_notified();
+            }
+
+            InputEventTrigger::Byte(read_byte) => {
+                on_byte_read(self, read_byte);
+            }
+            InputEventTrigger::TimeoutElapsed => {
+                return Some(CharEvent::QueryResult(QueryResultEvent::Timeout));
             }
         }
+        None
     }
 
     fn readch_timed_esc(&mut self) -> Option<CharEvent> {
@@ -502,19 +582,55 @@ fn readch_timed_sequence_key(&mut self) -> Option<CharEvent> {
         self.readch_timed(wait_on_sequence_key_ms)
     }
 
+    /// Like readch_timed_sequence_key(), but if no event arrives within
+    /// `fish_sequence_key_hint_delay_ms`, show the hints computed by `hints` until one does.
+    fn readch_timed_sequence_key_with_hint(
+        &mut self,
+        hints: &dyn Fn() -> Vec<SequenceKeyHint>,
+    ) -> Option<CharEvent> {
+        let hint_delay_ms = SEQUENCE_KEY_HINT_DELAY_MS.load(Ordering::Relaxed);
+        let wait_on_sequence_key_ms = WAIT_ON_SEQUENCE_KEY_MS.load(Ordering::Relaxed);
+        if hint_delay_ms == SEQUENCE_KEY_HINT_DISABLED || hint_delay_ms >= wait_on_sequence_key_ms {
+            return self.readch_timed_sequence_key();
+        }
+        if let Some(evt) = self.readch_timed(hint_delay_ms) {
+            return Some(evt);
+        }
+        let hints = hints();
+        if !hints.is_empty() {
+            self.show_sequence_key_hint(&hints);
+        }
+        let evt = if wait_on_sequence_key_ms == WAIT_ON_SEQUENCE_KEY_INFINITE {
+            Some(self.readch())
+        } else {
+            self.readch_timed(wait_on_sequence_key_ms - hint_delay_ms)
+        };
+        if !hints.is_empty() {
+            self.hide_sequence_key_hint();
+        }
+        evt
+    }
+
     /// Like readch(), except it will wait at most wait_time_ms milliseconds for a
     /// character to be available for reading.
     /// Return None on timeout, the event on success.
     fn readch_timed(&mut self, wait_time_ms: usize) -> Option<CharEvent> {
-        if let Some(evt) = self.try_pop() {
-            return Some(evt);
+        let deadline = Instant::now() + Duration::from_millis(u64::try_from(wait_time_ms).unwrap());
+        loop {
+            if let Some(evt) = self.try_pop() {
+                return Some(evt);
+            }
+            if !check_fd_readable(
+                unsafe { BorrowedFd::borrow_raw(self.get_in_fd()) },
+                deadline.saturating_duration_since(Instant::now()),
+            ) {
+                return None;
+            }
+            // What we read may have been a key release only, which does not end the wait.
+            if let Some(evt) = self.wait_for_input_event() {
+                return Some(evt);
+            }
         }
-
-        check_fd_readable(
-            unsafe { BorrowedFd::borrow_raw(self.get_in_fd()) },
-            Duration::from_millis(u64::try_from(wait_time_ms).unwrap()),
-        )
-        .then(|| self.readch())
     }
 
     /// Return the fd from which to read.
@@ -640,6 +756,10 @@ fn is_blocked_querying(&self) -> bool {
     /// nothing.
     fn prepare_to_select(&mut self) {}
 
+    /// Override point for the release of a key, which has been taken out of the queue.
+    /// The default drops it.
+    fn key_released(&mut self, _key: KeyEvent) {}
+
     /// Called when select() is interrupted by a signal.
     fn select_interrupted(&mut self) {}
 
@@ -668,6 +788,14 @@ fn uvar_change_notified(&mut self) {}
     /// The default does nothing.
     fn ioport_notified(&mut self) {}
 
+    /// Override point for showing the possible continuations of a partially typed key sequence.
+    /// The default does nothing.
+    fn show_sequence_key_hint(&mut self, _hints: &[SequenceKeyHint]) {}
+
+    /// Override point for hiding what show_sequence_key_hint() showed.
+    /// The default does nothing.
+    fn hide_sequence_key_hint(&mut self) {}
+
     /// Get the function status.
     fn function_status(&self) -> bool {
         self.get_input_data().function_status
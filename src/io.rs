@@ -13,7 +13,7 @@
 use errno::Errno;
 use fish_util::perror;
 use fish_widestring::{bytes2wcstring, wcs2bytes};
-use libc::{EAGAIN, EINTR, ENOENT, ENOTDIR, EWOULDBLOCK, STDOUT_FILENO};
+use libc::{EAGAIN, EINTR, ENOENT, ENOTDIR, EPIPE, EWOULDBLOCK, STDOUT_FILENO};
 use nix::{fcntl::OFlag, sys::stat::Mode};
 use std::{
     fs::File,
@@ -659,6 +659,11 @@ pub enum OutputStream {
 }
 
 impl OutputStream {
+    /// Return whether this writes directly to a terminal.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OutputStream::Fd(stream) if isatty(stream.fd))
+    }
+
     /// Return any internally buffered contents.
     /// This is only implemented for a string_output_stream; others flush data to their underlying
     /// receiver (fd, or separated buffer) immediately and so will return an empty string here.
@@ -690,6 +695,14 @@ pub fn flush_and_check_error(&mut self) -> libc::c_int {
         }
     }
 
+    /// Return whether a write failed because the reading end of a pipe was closed.
+    pub fn saw_broken_pipe(&self) -> bool {
+        match self {
+            OutputStream::Fd(stream) => stream.broken_pipe,
+            OutputStream::Null | OutputStream::String(_) | OutputStream::Buffered(_) => false,
+        }
+    }
+
     /// Append the given characters.
     pub fn append(&mut self, s: impl IntoCharIter) -> bool {
         match self {
@@ -750,12 +763,19 @@ pub struct FdOutputStream {
 
     /// Whether we have received an error.
     errored: bool,
+
+    /// Whether the error was a write to a pipe whose reader has gone away.
+    broken_pipe: bool,
 }
 impl FdOutputStream {
     /// Construct from a file descriptor, which must be nonegative.
     pub fn new(fd: RawFd) -> Self {
         assert!(fd >= 0, "Invalid fd");
-        FdOutputStream { fd, errored: false }
+        FdOutputStream {
+            fd,
+            errored: false,
+            broken_pipe: false,
+        }
     }
 
     fn append(&mut self, s: impl IntoCharIter) -> bool {
@@ -764,6 +784,7 @@ fn append(&mut self, s: impl IntoCharIter) -> bool {
         }
         if unescape_bytes_and_write_to_fd(s, self.fd).is_none() {
             self.errored = true;
+            self.broken_pipe = errno::errno().0 == EPIPE;
         }
         !self.errored
     }
@@ -0,0 +1,201 @@
+//! Translation catalogs which are not part of fish, loaded at runtime for `_ --domain`.
+//!
+//! Each directory in `$fish_translation_path` may contain one subdirectory per domain, which holds
+//! one catalog per language, named after the language.
+//! For example, `~/.config/fish/translations/myplugin/de.mo` or `.../myplugin/pt_BR.ftl`.
+//! MO files are looked up by the untranslated message, FTL files by message ID.
+//! If a domain has catalogs for the same language in several directories, the first one wins.
+
+use super::settings::{find_best_matches, requested_languages};
+use crate::flog::flog;
+use fish_gettext_mo_file_parser::parse_mo_file;
+use fish_widestring::{WString, osstr2wcstring, wcs2osstring, wstr};
+use fluent::{FluentResource, concurrent::FluentBundle};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+enum Catalog {
+    /// Compiled gettext catalog, mapping messages to their translations.
+    Mo(HashMap<String, String>),
+    /// Fluent catalog, mapping message IDs to their translations.
+    Ftl(FluentBundle<FluentResource>),
+}
+
+impl Catalog {
+    fn load(path: &Path) -> Option<Self> {
+        let data = std::fs::read(path).ok()?;
+        match path.extension()?.to_str()? {
+            "mo" => {
+                let map = match parse_mo_file(&data) {
+                    Ok(map) => map,
+                    Err(e) => {
+                        flog!(config, "Failed to parse", osstr2wcstring(path), e);
+                        return None;
+                    }
+                };
+                let map = map
+                    .into_iter()
+                    // The empty message maps to the catalog's metadata.
+                    .filter(|(msgid, _)| !msgid.is_empty())
+                    .map(|(msgid, msgstr)| {
+                        (
+                            String::from_utf8_lossy(msgid).into_owned(),
+                            String::from_utf8_lossy(msgstr).into_owned(),
+                        )
+                    })
+                    .collect();
+                Some(Self::Mo(map))
+            }
+            "ftl" => {
+                let source = String::from_utf8(data).ok()?;
+                // Use whatever could be parsed, so a single broken message does not lose the
+                // whole catalog.
+                let resource = FluentResource::try_new(source).unwrap_or_else(|(resource, _)| {
+                    flog!(config, "Errors parsing", osstr2wcstring(path));
+                    resource
+                });
+                let mut bundle = FluentBundle::new_concurrent(vec![]);
+                // See fish_fluent for why isolation marks are disabled.
+                bundle.set_use_isolating(false);
+                let _ = bundle.add_resource(resource);
+                Some(Self::Ftl(bundle))
+            }
+            _ => None,
+        }
+    }
+
+    fn translate(&self, message: &str) -> Option<String> {
+        match self {
+            Self::Mo(map) => map.get(message).cloned(),
+            Self::Ftl(bundle) => {
+                let pattern = bundle.get_message(message)?.value()?;
+                let mut errors = vec![];
+                let value = bundle.format_pattern(pattern, None, &mut errors);
+                errors.is_empty().then(|| value.into_owned())
+            }
+        }
+    }
+}
+
+/// All catalogs of one domain, by language.
+#[derive(Default)]
+struct Domain {
+    catalogs: HashMap<String, Catalog>,
+    languages: HashSet<String>,
+}
+
+impl Domain {
+    fn load(dirs: &[WString], domain: &wstr) -> Self {
+        let mut result = Self::default();
+        for dir in dirs {
+            let mut domain_dir = dir.clone();
+            domain_dir.push('/');
+            domain_dir.push_utfstr(domain);
+            let Ok(entries) = std::fs::read_dir(wcs2osstring(&domain_dir)) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Some(language) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                if result.languages.contains(language) {
+                    continue;
+                }
+                let Some(catalog) = Catalog::load(&path) else {
+                    continue;
+                };
+                result.languages.insert(language.to_owned());
+                result.catalogs.insert(language.to_owned(), catalog);
+            }
+        }
+        result
+    }
+
+    fn translate(&self, message: &str) -> Option<String> {
+        let mut seen = HashSet::new();
+        requested_languages()
+            .iter()
+            .flat_map(|lang| find_best_matches(lang, &self.languages))
+            .filter(|lang| seen.insert(lang.clone()))
+            .find_map(|lang| self.catalogs[&lang].translate(message))
+    }
+}
+
+/// The loaded domains, by name and the directories they were loaded from.
+static DOMAINS: Mutex<Option<HashMap<(WString, Vec<WString>), Arc<Domain>>>> = Mutex::new(None);
+
+/// Translate `message` using the catalogs of `domain` found in `dirs`, which should be the value
+/// of `$fish_translation_path`. Returns `None` if no catalog for the current languages has a
+/// translation.
+pub fn translate_in_domain(dirs: &[WString], domain: &wstr, message: &wstr) -> Option<WString> {
+    let domain = {
+        let mut domains = DOMAINS.lock().unwrap();
+        let domains = domains.get_or_insert_with(HashMap::new);
+        Arc::clone(
+            domains
+                .entry((domain.to_owned(), dirs.to_vec()))
+                .or_insert_with(|| Arc::new(Domain::load(dirs, domain))),
+        )
+    };
+    domain
+        .translate(&message.to_string())
+        .map(|s| WString::from_str(&s))
+}
+
+/// Call this when `$fish_translation_path` changes, so catalogs are loaded again on next use.
+pub fn invalidate_translation_catalogs() {
+    *DOMAINS.lock().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Catalog, DOMAINS, Domain, translate_in_domain};
+    use fish_widestring::{L, WString};
+
+    #[test]
+    fn test_ftl_catalog() {
+        let dir = fish_tempfile::new_dir().unwrap();
+        let domain_dir = dir.path().join("myplugin");
+        std::fs::create_dir(&domain_dir).unwrap();
+        std::fs::write(domain_dir.join("de.ftl"), "greeting = Hallo Welt\n").unwrap();
+        std::fs::write(domain_dir.join("README"), "not a catalog").unwrap();
+
+        let catalog = Catalog::load(&domain_dir.join("de.ftl")).unwrap();
+        assert_eq!(catalog.translate("greeting").as_deref(), Some("Hallo Welt"));
+        assert_eq!(catalog.translate("farewell"), None);
+
+        let dirs = [WString::from_str(dir.path().to_str().unwrap())];
+        let domain = Domain::load(&dirs, L!("myplugin"));
+        assert_eq!(domain.languages.len(), 1);
+        assert!(domain.languages.contains("de"));
+    }
+
+    #[test]
+    fn test_domains_by_directory() {
+        let dir_a = fish_tempfile::new_dir().unwrap();
+        let dir_b = fish_tempfile::new_dir().unwrap();
+        for (dir, language) in [(&dir_a, "de.ftl"), (&dir_b, "fr.ftl")] {
+            let domain_dir = dir.path().join("myplugin");
+            std::fs::create_dir(&domain_dir).unwrap();
+            std::fs::write(domain_dir.join(language), "greeting = Hallo\n").unwrap();
+        }
+        let dirs_a = vec![WString::from_str(dir_a.path().to_str().unwrap())];
+        let dirs_b = vec![WString::from_str(dir_b.path().to_str().unwrap())];
+
+        // The same domain in other directories is loaded separately.
+        translate_in_domain(&dirs_a, L!("myplugin"), L!("greeting"));
+        translate_in_domain(&dirs_b, L!("myplugin"), L!("greeting"));
+        let domains = DOMAINS.lock().unwrap();
+        let domains = domains.as_ref().unwrap();
+        let has_language = |dirs: &Vec<WString>, language: &str| {
+            domains[&(L!("myplugin").to_owned(), dirs.clone())]
+                .languages
+                .contains(language)
+        };
+        assert!(has_language(&dirs_a, "de"));
+        assert!(!has_language(&dirs_a, "fr"));
+        assert!(has_language(&dirs_b, "fr"));
+    }
+}
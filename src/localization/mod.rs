@@ -3,6 +3,10 @@
     LocalizableString, localizable_consts, localizable_string, wgettext, wgettext_fmt,
 };
 #[cfg(feature = "localize-messages")]
+mod catalogs;
+#[cfg(feature = "localize-messages")]
+pub use catalogs::{invalidate_translation_catalogs, translate_in_domain};
+#[cfg(feature = "localize-messages")]
 mod settings;
 #[cfg(feature = "localize-messages")]
 pub use settings::{
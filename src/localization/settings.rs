@@ -3,7 +3,9 @@
 use fish_localization::{Language, LocalizationLanguage};
 use fish_widestring::{L, WString, wstr};
 use itertools::{Itertools as _, chain};
+use std::borrow::Borrow;
 use std::collections::HashSet;
+use std::hash::Hash;
 use std::sync::Mutex;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -136,12 +138,16 @@ pub fn display_all(&self) -> WString {
 
 struct LocalizationState {
     precedence_origin: LanguagePrecedenceOrigin,
+    /// The languages which were asked for, in order, before matching them against the catalogs
+    /// fish ships. Catalogs loaded at runtime are matched against this list.
+    requested_languages: Vec<String>,
 }
 
 impl LocalizationState {
     const fn new() -> Self {
         Self {
             precedence_origin: LanguagePrecedenceOrigin::Default,
+            requested_languages: vec![],
         }
     }
 
@@ -152,14 +158,14 @@ const fn new() -> Self {
     /// If a country code is present (`ll_CC`), only the catalog named `ll` will be considered as a fallback.
     /// If no country code is present (`ll`), all catalogs whose names start with `ll_` will be used in
     /// arbitrary order.
-    fn find_best_matches<'a, LL: LocalizationLanguage>(
+    fn find_best_matches<'a, LL: Borrow<str> + Clone + Eq + Hash>(
         language: Language<'a>,
         available_languages: &HashSet<LL>,
     ) -> Vec<LL> {
         // Try the exact name first.
         // If there already is a corresponding catalog return the language.
-        if let Some(&lang) = available_languages.get(*language) {
-            return vec![lang];
+        if let Some(lang) = available_languages.get(*language) {
+            return vec![lang.clone()];
         }
         let language_without_country_code = language
             .split_once('_')
@@ -170,17 +176,17 @@ fn find_best_matches<'a, LL: LocalizationLanguage>(
             // fall back to `llx_CC`, where `llx` is a 3-letter language identifier.
             let ll_prefix = format!("{language}_");
             let mut lang_catalogs = vec![];
-            for &lang in available_languages.iter() {
-                if lang.as_ref().starts_with(&ll_prefix) {
-                    lang_catalogs.push(lang);
+            for lang in available_languages.iter() {
+                if Borrow::<str>::borrow(lang).starts_with(&ll_prefix) {
+                    lang_catalogs.push(lang.clone());
                 }
             }
             lang_catalogs
         } else {
             // If `language` contained a country code, we only try to fall back to a catalog
             // without a country code.
-            if let Some(&lang) = available_languages.get(*language_without_country_code) {
-                vec![lang]
+            if let Some(lang) = available_languages.get(*language_without_country_code) {
+                vec![lang.clone()]
             } else {
                 vec![]
             }
@@ -208,6 +214,7 @@ fn is_c_locale(locale: &str) -> bool {
                     LanguagePrecedenceOrigin::LocaleVariable(*precedence_origin);
                 fish_fluent::set_language_precedence(&[]);
                 fish_gettext::set_language_precedence(&[]);
+                self.requested_languages.clear();
                 return;
             }
         }
@@ -268,6 +275,7 @@ fn update_precedence<LL: LocalizationLanguage>(
             fish_gettext::set_language_precedence,
         );
         self.precedence_origin = precedence_origin;
+        self.requested_languages = language_list;
     }
 
     fn update_from_status_language_builtin<'a, 'b: 'a, S: AsRef<str> + 'a>(
@@ -323,6 +331,7 @@ fn update_precedence<'a, LL: LocalizationLanguage>(
         );
 
         self.precedence_origin = LanguagePrecedenceOrigin::StatusLanguage;
+        self.requested_languages = unique_lang_strs.iter().map(|l| l.to_string()).collect();
 
         let mut seen_non_existing = HashSet::new();
         let non_existing: Vec<Language<'a>> = langs()
@@ -388,6 +397,25 @@ pub fn unset_from_status_language_builtin(env: &EnvStack) {
     localization_state.update_from_env(LocalizationVariables::from_env(env));
 }
 
+/// Matches `language` against `available_languages` the same way our own catalogs are matched.
+/// See [`update_from_env`] for the rules.
+pub(super) fn find_best_matches<LL: Borrow<str> + Clone + Eq + Hash>(
+    language: &str,
+    available_languages: &HashSet<LL>,
+) -> Vec<LL> {
+    LocalizationState::find_best_matches(Language(language), available_languages)
+}
+
+/// The languages requested via the environment or `status language`, in order of precedence.
+/// Unlike the precedence lists of our own catalogs, this is not filtered by availability.
+pub fn requested_languages() -> Vec<String> {
+    LOCALIZATION_STATE
+        .lock()
+        .unwrap()
+        .requested_languages
+        .clone()
+}
+
 pub fn status_language() -> WString {
     let localization_state = LOCALIZATION_STATE.lock().unwrap();
     let mut result = WString::new();
@@ -5,16 +5,22 @@
     editable_line::EditableLine,
     highlight::{HighlightRole, HighlightSpec, highlight_shell},
     operation_context::OperationContext,
+    path::path_apply_working_directory,
     prelude::*,
-    screen::{CharOffset, Line, ScreenData, wcswidth_rendered, wcwidth_rendered},
+    screen::{
+        CharOffset, Line, ScreenData, escape_code_length, wcswidth_rendered, wcwidth_rendered,
+    },
+    terminal::file_url,
     termsize::Termsize,
+    wutil::lwstat,
 };
 use fish_common::{EscapeFlags, EscapeStringStyle, escape_string};
-use fish_wcstringutil::string_fuzzy_match_string;
+use fish_wcstringutil::{fuzzy_score, string_fuzzy_match_string};
 use fish_widestring::{ELLIPSIS_CHAR, decoded_width};
 use std::{
     borrow::Cow,
     collections::{HashMap, hash_map::Entry},
+    sync::Arc,
 };
 
 /// Represents rendering from the pager.
@@ -78,6 +84,9 @@ pub enum SelectionMotion {
 /// Width of the search field.
 const PAGER_SEARCH_FIELD_WIDTH: usize = 12;
 
+/// The preview never takes more than this fraction (1/N) of the height available to the pager.
+const PAGER_PREVIEW_HEIGHT_DIVISOR: usize = 3;
+
 localizable_consts!(
     /// Text we use for the search field.
     SEARCH_FIELD_PROMPT
@@ -118,6 +127,22 @@ pub struct Pager {
 
     // Extra text to display at the bottom of the pager.
     pub extra_progress_text: WString,
+
+    // Lines produced by the preview function for the selected completion, with escape sequences
+    // removed.
+    preview: Vec<WString>,
+
+    // If set, completions of files are hyperlinks to them.
+    hyperlink_base: Option<HyperlinkBase>,
+}
+
+/// Where the files named by completions are, so they can be hyperlinks.
+#[derive(Clone)]
+pub struct HyperlinkBase {
+    /// The absolute path that completions which extend the token are appended to.
+    pub prefix: WString,
+    /// The directory that completions which replace the token are relative to.
+    pub working_directory: WString,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -372,7 +397,11 @@ fn measure_completion_infos(&mut self) {
     }
 
     // Indicates if the given completion info passes any filtering we have.
-    fn completion_info_passes_filter(&self, info: &PagerComp) -> bool {
+    // Also records how well, and where, it matched.
+    fn completion_info_passes_filter(&self, info: &mut PagerComp) -> bool {
+        info.score = None;
+        info.match_positions.clear();
+
         // If we have no filter, everything passes.
         if !self.search_field_shown || self.search_field_line.is_empty() {
             return true;
@@ -380,24 +409,25 @@ fn completion_info_passes_filter(&self, info: &PagerComp) -> bool {
 
         let needle = self.search_field_line.text();
 
-        // Match against the description.
-        if string_fuzzy_match_string(needle, &info.desc, false).is_some() {
+        // Rank against the completion strings, keeping the best one.
+        for (i, candidate) in info.comp.iter().enumerate() {
+            let haystack = self.prefix.clone().into_owned() + &candidate[..];
+            let Some(m) = fuzzy_score(needle, &haystack) else {
+                continue;
+            };
+            if info.score.is_none_or(|score| m.score > score) {
+                info.score = Some(m.score);
+                info.match_comp_idx = i;
+                info.match_positions = m.positions;
+            }
+        }
+        if info.score.is_some() {
             return true;
         }
 
-        // Match against the completion strings.
-        for candidate in &info.comp {
-            if string_fuzzy_match_string(
-                needle,
-                &(self.prefix.clone().into_owned() + &candidate[..]),
-                false,
-            )
-            .is_some()
-            {
-                return true;
-            }
-        }
-        false // no match
+        // Match against the description.
+        // These are not ranked, so they sort after everything that matched by completion.
+        string_fuzzy_match_string(needle, &info.desc, false).is_some()
     }
 
     /// Print the specified part of the completion list, using the specified column offsets and quoting
@@ -524,10 +554,30 @@ fn completion_print_item(
             }),
             bg_role,
         );
-        let comp_col =
-            HighlightSpec::with_fg_bg(modify_role(HighlightRole::PagerCompletion), bg_role);
+        let comp_col = if c.marked {
+            HighlightSpec::with_fg_bg(
+                HighlightRole::PagerMarked,
+                if selected {
+                    bg_role
+                } else {
+                    HighlightRole::PagerMarked
+                },
+            )
+        } else {
+            HighlightSpec::with_fg_bg(modify_role(HighlightRole::PagerCompletion), bg_role)
+        };
+        let prefix_col = if c.marked { comp_col } else { prefix_col };
         let desc_col =
             HighlightSpec::with_fg_bg(modify_role(HighlightRole::PagerDescription), bg_role);
+        let match_col = HighlightSpec::with_fg_bg(HighlightRole::PagerMatch, bg_role);
+        // Whether the character at this offset into the prefix plus the given completion string
+        // matched the search field. The selection is rendered in reverse video, so leave it be.
+        let is_match = |comp_idx: usize, offset: usize| {
+            !selected
+                && c.match_comp_idx == comp_idx
+                && c.match_positions.binary_search(&offset).is_ok()
+        };
+        let prefix_len = self.prefix.len();
 
         // Print the completion part
         let mut comp_remaining = comp_width;
@@ -543,22 +593,33 @@ fn completion_print_item(
                 );
             }
 
+            let comp_start = line_data.len();
             if let Some(prefix) = prefix {
-                comp_remaining -= print_max(
+                comp_remaining -= print_max_impl(
                     offset_in_cmdline,
                     prefix.chars(),
-                    prefix_col,
+                    |j| {
+                        if is_match(i, j) {
+                            match_col
+                        } else {
+                            prefix_col
+                        }
+                    },
                     comp_remaining,
                     !comp.is_empty(),
                     &mut line_data,
                 );
             }
+            let comp_idx = i;
             comp_remaining -= print_max_impl(
                 offset_in_cmdline,
                 comp.chars(),
                 |i| {
-                    if c.colors.is_empty() {
-                        return comp_col; // Not a shell command.
+                    if is_match(comp_idx, prefix_len + i) {
+                        return match_col;
+                    }
+                    if c.colors.is_empty() || c.marked {
+                        return comp_col; // Not a shell command, or marked.
                     }
                     if selected {
                         // Rendered in reverse video, so avoid highlighting.
@@ -570,6 +631,9 @@ fn completion_print_item(
                 i + 1 < c.comp.len(),
                 &mut line_data,
             );
+            if let Some(hyperlink) = &c.hyperlink {
+                line_data.set_hyperlink(comp_start, hyperlink);
+            }
         }
 
         let mut desc_remaining = col.width - comp_width + comp_remaining;
@@ -660,17 +724,34 @@ fn completion_print_item(
         line_data
     }
 
+    // Sets where the files named by the next completions are, to make them hyperlinks.
+    pub fn set_hyperlink_base(&mut self, base: Option<HyperlinkBase>) {
+        self.hyperlink_base = base;
+    }
+
     // Sets the set of completions.
     pub fn set_completions(&mut self, raw_completions: &[Completion], enable_refilter: bool) {
         self.selected_completion_idx = None;
+        self.preview.clear();
         // Get completion infos out of it.
         self.unfiltered_completion_infos = process_completions_into_infos(raw_completions);
 
+        if let Some(base) = &self.hyperlink_base {
+            for comp in &mut self.unfiltered_completion_infos {
+                comp.hyperlink = completion_hyperlink(base, &comp.representative);
+            }
+        }
+
         // Maybe join them.
         if *self.prefix == "-" {
             join_completions(&mut self.unfiltered_completion_infos);
         }
 
+        // Remember where each one lives, so marks survive refiltering.
+        for (i, comp) in self.unfiltered_completion_infos.iter_mut().enumerate() {
+            comp.unfiltered_idx = i;
+        }
+
         // Compute their various widths.
         self.measure_completion_infos();
 
@@ -678,11 +759,72 @@ pub fn set_completions(&mut self, raw_completions: &[Completion], enable_refilte
         if enable_refilter {
             self.refilter_completions();
         } else {
-            self.completion_infos = self.unfiltered_completion_infos.clone();
+            // Still highlight what matches the search field.
+            let mut infos = self.unfiltered_completion_infos.clone();
+            for info in &mut infos {
+                self.completion_info_passes_filter(info);
+            }
+            self.completion_infos = infos;
         }
         self.have_unrendered_completions = true;
     }
 
+    // Sets the preview lines for the selected completion.
+    pub fn set_preview(&mut self, lines: Vec<WString>) {
+        self.preview = lines
+            .into_iter()
+            .map(|line| {
+                let mut result = WString::with_capacity(line.len());
+                let mut i = 0;
+                while i < line.len() {
+                    if let Some(len) = escape_code_length(&line[i..]) {
+                        i += len;
+                        continue;
+                    }
+                    result.push(line.char_at(i));
+                    i += 1;
+                }
+                result
+            })
+            .collect();
+    }
+
+    // Removes any preview.
+    pub fn clear_preview(&mut self) {
+        self.preview.clear();
+    }
+
+    // Returns how many of the given rows the preview should use.
+    // The preview only takes space that the completions do not need to stay usable.
+    pub fn preview_height(&self, available_height: usize) -> usize {
+        if self.preview.is_empty() || self.is_empty() {
+            return 0;
+        }
+        let max_height = available_height / PAGER_PREVIEW_HEIGHT_DIVISOR;
+        if available_height - max_height < PAGER_MIN_HEIGHT {
+            return 0;
+        }
+        std::cmp::min(self.preview.len(), max_height)
+    }
+
+    // Produces a rendering of the preview, using at most the given number of rows.
+    pub fn render_preview(&self, term_width: usize, height: usize) -> ScreenData {
+        let mut screen_data = ScreenData::default();
+        let spec = HighlightSpec::with_both(HighlightRole::PagerPreview);
+        for text in self.preview.iter().take(height) {
+            let line = screen_data.add_line();
+            print_max(
+                CharOffset::None,
+                text.chars(),
+                spec,
+                term_width,
+                /*has_more=*/ false,
+                line,
+            );
+        }
+        screen_data
+    }
+
     // Sets the prefix.
     pub fn set_prefix(&mut self, prefix: Cow<'static, wstr>, highlight: bool /* = true */) {
         self.prefix = prefix;
@@ -897,6 +1039,34 @@ pub fn selected_completion(&'_ self, rendering: &PageRendering) -> Option<&'_ Co
             .map(|idx| &self.completion_infos[idx].representative)
     }
 
+    // Toggles the mark on the currently selected completion. Returns true if there was a selected
+    // completion.
+    pub fn toggle_selected_mark(&mut self, rendering: &PageRendering) -> bool {
+        let Some(idx) = self.visual_selected_completion_index(rendering.rows, rendering.cols)
+        else {
+            return false;
+        };
+        let comp = &mut self.completion_infos[idx];
+        comp.marked = !comp.marked;
+        let (unfiltered_idx, marked) = (comp.unfiltered_idx, comp.marked);
+        self.unfiltered_completion_infos[unfiltered_idx].marked = marked;
+        self.have_unrendered_completions = true;
+        true
+    }
+
+    // Returns the marked completions, in the order in which they are listed.
+    pub fn marked_completions(&self) -> impl Iterator<Item = &Completion> {
+        self.unfiltered_completion_infos
+            .iter()
+            .filter(|comp| comp.marked)
+            .map(|comp| &comp.representative)
+    }
+
+    // Indicates if any completion is marked.
+    pub fn has_marked_completions(&self) -> bool {
+        self.marked_completions().next().is_some()
+    }
+
     pub fn selected_completion_index(&self) -> Option<usize> {
         self.selected_completion_idx
     }
@@ -1029,17 +1199,23 @@ pub fn clear(&mut self) {
         self.fully_disclosed = false;
         self.search_field_shown = false;
         self.extra_progress_text.clear();
+        self.preview.clear();
         self.suggested_row_start = 0;
+        self.hyperlink_base = None;
     }
 
     // Updates the completions list per the filter.
     pub fn refilter_completions(&mut self) {
         self.completion_infos.clear();
         for comp in &self.unfiltered_completion_infos {
-            if self.completion_info_passes_filter(comp) {
-                self.completion_infos.push(comp.clone());
+            let mut comp = comp.clone();
+            if self.completion_info_passes_filter(&mut comp) {
+                self.completion_infos.push(comp);
             }
         }
+        // Show the best matches first. The sort is stable, so equal matches keep their order.
+        self.completion_infos
+            .sort_by_key(|comp| std::cmp::Reverse(comp.score));
     }
 
     // Sets whether the search field is shown.
@@ -1094,6 +1270,18 @@ pub struct PagerComp {
     pub comp_width: usize,
     /// On-screen width of the description information.
     pub desc_width: usize,
+    /// Whether the user marked this entry for insertion.
+    pub marked: bool,
+    /// The index of this entry in the unfiltered list.
+    unfiltered_idx: usize,
+    /// How well this entry matches the search field, if it matched by completion string.
+    score: Option<i32>,
+    /// The completion string that matched the search field best.
+    match_comp_idx: usize,
+    /// The matched characters, as indices into the prefix followed by that completion string.
+    match_positions: Vec<usize>,
+    /// The URL of the file this completes, if it is shown as a hyperlink.
+    hyperlink: Option<Arc<WString>>,
 }
 
 impl PagerComp {
@@ -1263,6 +1451,24 @@ fn join_completions(comps: &mut Vec<PagerComp>) {
     }
 }
 
+/// Return the URL of the existing file that a completion names.
+fn completion_hyperlink(base: &HyperlinkBase, comp: &Completion) -> Option<Arc<WString>> {
+    if !comp.flags.contains(CompleteFlags::FILE_PATH) {
+        return None;
+    }
+    let path = if comp.replaces_token() {
+        // Tildes were not expanded in this case.
+        if comp.completion.starts_with('~') {
+            return None;
+        }
+        path_apply_working_directory(&comp.completion, &base.working_directory)
+    } else {
+        base.prefix.clone() + &comp.completion[..]
+    };
+    lwstat(&path).ok()?;
+    Some(Arc::new(file_url(&path, &base.working_directory)))
+}
+
 /// Generate a list of comp_t structures from a list of completions.
 fn process_completions_into_infos(lst: &[Completion]) -> Vec<PagerComp> {
     // Make the list of the correct size up-front.
@@ -1310,8 +1516,10 @@ fn process_completions_into_infos(lst: &[Completion]) -> Vec<PagerComp> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Pager, SelectionMotion};
+    use super::{PageRendering, Pager, SelectionMotion};
     use crate::complete::{CompleteFlags, Completion};
+    use crate::editable_line::Edit;
+    use crate::highlight::HighlightRole;
     use crate::prelude::*;
     use crate::termsize::Termsize;
     use crate::tests::prelude::*;
@@ -1657,4 +1865,183 @@ macro_rules! validate {
         pager.set_completions(&completions(&[("Hello", "")]), true);
         validate!(&mut pager, 30, L!("{\\␊Hello")); // }
     }
+
+    #[test]
+    #[serial]
+    fn test_pager_search_ranking() {
+        test_init();
+        let completions = ["xbar", "foobar", "foo-bar", "zzz"]
+            .iter()
+            .map(|c| {
+                Completion::new(
+                    WString::from(*c),
+                    WString::new(),
+                    StringFuzzyMatch::exact_match(),
+                    CompleteFlags::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let shown = |pager: &Pager| {
+            pager
+                .completion_infos
+                .iter()
+                .map(|c| c.comp[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let matched_chars = |render: &PageRendering| {
+            let mut result = WString::new();
+            for i in 0..render.screen_data.line_count() {
+                let line = render.screen_data.line(i);
+                for j in 0..line.len() {
+                    if line.color_at(j).foreground == HighlightRole::PagerMatch {
+                        result.push(line.char_at(j));
+                    }
+                }
+            }
+            result
+        };
+
+        let mut pager = Pager::default();
+        pager.set_completions(&completions, true);
+        pager.set_term_size(&Termsize::defaults());
+        pager.set_search_field_shown(true);
+        pager
+            .search_field_line
+            .push_edit(Edit::new(0..0, L!("bar").to_owned()), false);
+        pager.refilter_completions();
+
+        // The match at a word boundary comes first, the rest keep their order.
+        assert_eq!(
+            shown(&pager),
+            &[
+                WString::from("foo-bar"),
+                WString::from("xbar"),
+                WString::from("foobar")
+            ]
+        );
+        let mut render = pager.render();
+        assert_eq!(matched_chars(&render), L!("barbarbar"));
+
+        // The selected completion is not highlighted.
+        pager.set_selected_completion_index(Some(0));
+        pager.update_rendering(&mut render);
+        assert_eq!(matched_chars(&render), L!("barbar"));
+
+        // Without a search, the original order is restored.
+        pager.search_field_line.clear();
+        pager.refilter_completions();
+        assert_eq!(shown(&pager).len(), completions.len());
+        assert_eq!(shown(&pager)[0], L!("xbar"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_pager_marks() {
+        test_init();
+        let completions = ["alpha", "beta", "gamma"]
+            .iter()
+            .map(|c| {
+                Completion::new(
+                    WString::from(*c),
+                    WString::new(),
+                    StringFuzzyMatch::exact_match(),
+                    CompleteFlags::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let marked = |pager: &Pager| {
+            pager
+                .marked_completions()
+                .map(|c| c.completion.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut pager = Pager::default();
+        pager.set_completions(&completions, true);
+        pager.set_term_size(&Termsize::defaults());
+        let mut render = pager.render();
+
+        // Nothing to mark without a selection.
+        assert!(!pager.toggle_selected_mark(&render));
+        assert!(!pager.has_marked_completions());
+
+        pager.select_next_completion_in_direction(SelectionMotion::Next, &render);
+        pager.update_rendering(&mut render);
+        assert!(pager.toggle_selected_mark(&render));
+        pager.select_next_completion_in_direction(SelectionMotion::Next, &render);
+        pager.update_rendering(&mut render);
+        pager.select_next_completion_in_direction(SelectionMotion::Next, &render);
+        pager.update_rendering(&mut render);
+        assert!(pager.toggle_selected_mark(&render));
+        assert_eq!(
+            marked(&pager),
+            &[WString::from("alpha"), WString::from("gamma")]
+        );
+
+        // Marks survive filtering.
+        pager.set_search_field_shown(true);
+        pager
+            .search_field_line
+            .push_edit(Edit::new(0..0, L!("gam").to_owned()), false);
+        pager.refilter_completions();
+        pager.set_selected_completion_index(Some(0));
+        pager.update_rendering(&mut render);
+        assert!(pager.toggle_selected_mark(&render));
+        assert_eq!(marked(&pager), &[WString::from("alpha")]);
+        pager.search_field_line.clear();
+        pager.refilter_completions();
+        assert_eq!(marked(&pager), &[WString::from("alpha")]);
+
+        // New completions have no marks.
+        pager.set_completions(&completions, true);
+        assert!(!pager.has_marked_completions());
+    }
+
+    #[test]
+    #[serial]
+    fn test_pager_preview() {
+        test_init();
+        let mut pager = Pager::default();
+        let completions = ["alpha", "beta"]
+            .iter()
+            .map(|c| {
+                Completion::new(
+                    WString::from(*c),
+                    WString::new(),
+                    StringFuzzyMatch::exact_match(),
+                    CompleteFlags::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // No completions, no preview.
+        pager.set_preview(vec![L!("ignored").to_owned()]);
+        assert_eq!(pager.preview_height(30), 0);
+
+        pager.set_completions(&completions, true);
+        pager.set_preview(vec![
+            L!("\x1b[31mred\x1b[m text").to_owned(),
+            L!("a much longer line of preview").to_owned(),
+        ]);
+        assert_eq!(pager.preview_height(30), 2);
+        // At most a third of the height.
+        assert_eq!(pager.preview_height(6), 2);
+        assert_eq!(pager.preview_height(5), 1);
+        // Never at the expense of the completions.
+        assert_eq!(pager.preview_height(3), 0);
+
+        let rendering = pager.render_preview(10, 2);
+        let lines = (0..rendering.line_count())
+            .map(|i| rendering.line(i))
+            .map(|line| WString::from(Vec::from_iter((0..line.len()).map(|i| line.char_at(i)))))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            &[WString::from("red text"), WString::from("a much lo…")]
+        );
+
+        // New completions invalidate the preview.
+        pager.set_completions(&completions, true);
+        assert_eq!(pager.preview_height(30), 0);
+    }
 }
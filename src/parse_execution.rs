@@ -7,8 +7,8 @@
     },
     builtins::{
         self, Error, STATUS_CMD_ERROR, STATUS_CMD_OK, STATUS_CMD_UNKNOWN, STATUS_EXPAND_ERROR,
-        STATUS_ILLEGAL_CMD, STATUS_INVALID_ARGS, STATUS_NOT_EXECUTABLE, STATUS_UNMATCHED_WILDCARD,
-        builtin_exists,
+        STATUS_ILLEGAL_CMD, STATUS_INVALID_ARGS, STATUS_NOT_EXECUTABLE, STATUS_RESTRICTED,
+        STATUS_UNMATCHED_WILDCARD, builtin_exists,
     },
     common::valid_var_name,
     complete::CompletionList,
@@ -23,6 +23,7 @@
     function,
     io::{IoChain, IoStreams, OutputStream, StringOutputStream},
     job_group::JobGroup,
+    nix::isatty,
     operation_context::OperationContext,
     parse_constants::{
         CALL_STACK_LIMIT_EXCEEDED_ERR_MSG, ERROR_TIME_BACKGROUND,
@@ -52,8 +53,12 @@
 };
 use fish_common::{ScopeGuard, escape, help_section, truncate_at_nul};
 use fish_widestring::WExt as _;
-use libc::{ENOTDIR, EXIT_SUCCESS, STDERR_FILENO, STDOUT_FILENO, c_int};
-use std::{io::ErrorKind, rc::Rc, sync::Arc};
+use libc::{ENOTDIR, EXIT_SUCCESS, SIGPIPE, STDERR_FILENO, STDOUT_FILENO, c_int};
+use std::{
+    io::ErrorKind,
+    rc::Rc,
+    sync::{Arc, atomic::Ordering},
+};
 
 /// An eval_result represents evaluation errors including wildcards which failed to match, syntax
 /// errors, or other expansion errors. It also tracks when evaluation was skipped due to signal
@@ -241,7 +246,9 @@ fn report_errors(
             }
 
             // Get a backtrace.
-            let backtrace_and_desc = ctx.parser().get_backtrace(&self.pstree().src, error_list);
+            let backtrace_and_desc =
+                ctx.parser()
+                    .get_backtrace(&self.pstree().src, error_list, isatty(STDERR_FILENO));
 
             // Print it.
             if !ctx.parser().test_only_suppress_stderr() {
@@ -819,6 +826,55 @@ fn populate_plain_process(
             }
         }
 
+        // Restricted code may not change the directory, replace the shell, or run external commands
+        // that were not allowed.
+        if ctx.parser().is_restricted() {
+            let denied = match &process_type {
+                ProcessType::Exec => Some(L!("exec")),
+                ProcessType::Builtin if cmd_args[0] == "cd" => Some(L!("cd")),
+                ProcessType::External
+                    if !ctx.parser().is_command_allowed(&cmd_args[0], &external_cmd) =>
+                {
+                    Some(cmd_args[0].as_utfstr())
+                }
+                _ => None,
+            };
+            if let Some(denied) = denied {
+                return report_error!(
+                    self,
+                    ctx,
+                    STATUS_RESTRICTED,
+                    &statement.command,
+                    "'%s' is not allowed in restricted mode",
+                    denied
+                );
+            }
+            // Nor may it call a function whose named arguments or inherited variables would change
+            // search paths or exported variables.
+            if matches!(process_type, ProcessType::Function) {
+                if let Some(props) = function::get_props(&cmd_args[0]) {
+                    let local_mode = ParserEnvSetMode::user(EnvMode::LOCAL);
+                    let names = props
+                        .named_arguments
+                        .iter()
+                        .chain(props.inherit_vars().iter().map(|(name, _)| name));
+                    for name in names {
+                        if let Some(restricted) = ctx.parser().restricted_change(name, local_mode) {
+                            return report_error!(
+                                self,
+                                ctx,
+                                STATUS_RESTRICTED,
+                                &statement.command,
+                                "%s: %s",
+                                cmd_args[0].as_utfstr(),
+                                restricted.message(name)
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
         // Populate the process.
         proc.typ = process_type;
         proc.set_argv(cmd_args);
@@ -929,9 +985,23 @@ fn run_for_statement(
             );
         }
 
+        // Restricted code may not loop over search paths or exported variables.
+        let local_mode = ParserEnvSetMode::user(EnvMode::LOCAL);
+        if let Some(restricted) = ctx.parser().restricted_change(&for_var_name, local_mode) {
+            return report_error!(
+                self,
+                ctx,
+                STATUS_RESTRICTED,
+                header.var_name,
+                "%s: %s",
+                "for",
+                restricted.message(&for_var_name)
+            );
+        }
+
         let retval = ctx.parser().set_var(
             &for_var_name,
-            ParserEnvSetMode::user(EnvMode::LOCAL),
+            local_mode,
             var.map_or(vec![], |var| var.as_list().to_owned()),
         );
         assert_eq!(retval, EnvStackSetResult::Ok);
@@ -949,11 +1019,21 @@ fn run_for_statement(
                 break;
             }
 
-            let retval = ctx.parser().set_var(
-                &for_var_name,
-                ParserEnvSetMode::user(EnvMode::empty()),
-                vec![val],
-            );
+            let loop_mode = ParserEnvSetMode::user(EnvMode::empty());
+            if let Some(restricted) = ctx.parser().restricted_change(&for_var_name, loop_mode) {
+                // The loop body may have exported the variable.
+                ret = report_error!(
+                    self,
+                    ctx,
+                    STATUS_RESTRICTED,
+                    header.var_name,
+                    "%s: %s",
+                    "for",
+                    restricted.message(&for_var_name)
+                );
+                break;
+            }
+            let retval = ctx.parser().set_var(&for_var_name, loop_mode, vec![val]);
             assert_eq!(
                 retval,
                 EnvStackSetResult::Ok,
@@ -1506,6 +1586,24 @@ fn determine_redirections(
                     &spec.target
                 );
             }
+
+            // Restricted code may read files, but only write to /dev/null.
+            if ctx.parser().is_restricted()
+                && matches!(
+                    spec.mode,
+                    RedirectionMode::Overwrite | RedirectionMode::Append | RedirectionMode::NoClob
+                )
+                && spec.target != "/dev/null"
+            {
+                return report_error!(
+                    self,
+                    ctx,
+                    STATUS_RESTRICTED,
+                    redir_node,
+                    "Redirecting to '%s' is not allowed in restricted mode",
+                    &spec.target
+                );
+            }
             out_redirections.push(spec);
 
             if oper.stderr_merge {
@@ -1684,6 +1782,18 @@ fn run_1_job(
             if self.cancel_signal.is_none() {
                 self.cancel_signal = job.group().get_cancel_signal();
             }
+
+            // If the job was killed writing into the pipe of an enclosing function or block, stop
+            // that as well.
+            let last = job.processes().last().unwrap();
+            if last.is_completed()
+                && last.status().signal_exited()
+                && last.status().signal_code() == SIGPIPE
+            {
+                if let Some(broken_pipe) = &ctx.parser().libdata().broken_pipe {
+                    broken_pipe.store(true, Ordering::Relaxed);
+                }
+            }
         }
 
         finalize_profile_item(
@@ -1880,7 +1990,11 @@ fn setup_group(&self, ctx: &mut OperationContext<'_>, j: &mut Job) {
             return;
         }
 
-        if j.processes()[0].is_internal() || !self.use_job_control(ctx) {
+        // Functions and blocks in a background job run in a child fish, which can lead a pgroup.
+        let first = &j.processes()[0];
+        let first_is_internal =
+            first.is_builtin() || (first.is_internal() && !j.is_initially_background());
+        if first_is_internal || !self.use_job_control(ctx) {
             // This job either doesn't have a pgroup (e.g. a simple block), or lives in fish's pgroup.
             j.group = Some(JobGroup::create(j.command().to_owned(), j.wants_job_id()));
         } else {
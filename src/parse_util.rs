@@ -38,6 +38,16 @@
 /// Return the length of the slice starting at `in`, or 0 if there is no slice, or None on error.
 /// This never accepts incomplete slices.
 pub fn slice_length(input: &wstr) -> Option<usize> {
+    slice_length_impl(input, true)
+}
+
+/// Like [`slice_length`], but for a string which has already been unescaped, so quotes and
+/// backslashes have no special meaning.
+pub fn unescaped_slice_length(input: &wstr) -> Option<usize> {
+    slice_length_impl(input, false)
+}
+
+fn slice_length_impl(input: &wstr, escaped_input: bool) -> Option<usize> {
     let openc = '[';
     let closec = ']';
     let mut escaped = false;
@@ -53,7 +63,7 @@ pub fn slice_length(input: &wstr) -> Option<usize> {
     while let Some(c) = chars.next() {
         pos += 1;
         if !escaped {
-            if ['\'', '"'].contains(&c) {
+            if escaped_input && ['\'', '"'].contains(&c) {
                 let oldpos = pos;
                 pos = quote_end(input, pos, c)?;
                 // We need to advance the iterator as well
@@ -74,7 +84,7 @@ pub fn slice_length(input: &wstr) -> Option<usize> {
                 }
             }
         }
-        if c == '\\' {
+        if escaped_input && c == '\\' {
             escaped = !escaped;
         } else {
             escaped = false;
@@ -516,6 +526,16 @@ pub fn lineno(s: &wstr, offset: usize) -> usize {
     count_newlines(&s[..end]) + 1
 }
 
+/// Get the column at the specified character offset, starting with 1.
+pub fn column(s: &wstr, offset: usize) -> usize {
+    let end = offset.min(s.len());
+    let line_start = s.as_char_slice()[..end]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |pos| pos + 1);
+    end - line_start + 1
+}
+
 /// Calculate the line number of the specified cursor position.
 pub fn get_line_from_offset(s: &wstr, pos: usize) -> isize {
     // Return the line pos is on, or -1 if it's after the end.
@@ -1896,8 +1916,8 @@ pub fn expand_variable_error(
 #[cfg(test)]
 mod tests {
     use super::{
-        BOOL_AFTER_BACKGROUND_ERROR_MSG, compute_indents, detect_parse_errors, get_cmdsubst_extent,
-        get_process_extent, slice_length,
+        BOOL_AFTER_BACKGROUND_ERROR_MSG, column, compute_indents, detect_parse_errors,
+        get_cmdsubst_extent, get_process_extent, slice_length, unescaped_slice_length,
     };
     use crate::parse_constants::{
         ERROR_BAD_VAR_CHAR1, ERROR_BRACKETED_VARIABLE_QUOTED1, ERROR_BRACKETED_VARIABLE1,
@@ -2000,6 +2020,16 @@ macro_rules! validate {
         validate!("begin; echo; end", 12, 12..16);
     }
 
+    #[test]
+    fn test_column() {
+        let s = L!("echo a\n  echo b");
+        assert_eq!(column(s, 0), 1);
+        assert_eq!(column(s, 5), 6);
+        assert_eq!(column(s, 7), 1);
+        assert_eq!(column(s, 9), 3);
+        assert_eq!(column(s, 100), 9);
+    }
+
     #[test]
     #[serial]
     fn test_get_cmdsubst_extent() {
@@ -2026,6 +2056,15 @@ fn test_slice_length() {
         assert_eq!(slice_length(L!("[\"foo\"")), None);
     }
 
+    #[test]
+    fn test_unescaped_slice_length() {
+        test_init();
+        assert_eq!(unescaped_slice_length(L!("[a[1]]x")), Some(6));
+        assert_eq!(unescaped_slice_length(L!("[it's]")), Some(6));
+        assert_eq!(unescaped_slice_length(L!("[a\\]")), Some(4));
+        assert_eq!(unescaped_slice_length(L!("[a[1]")), None);
+    }
+
     #[test]
     #[serial]
     fn test_indents() {
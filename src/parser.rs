@@ -3,18 +3,20 @@
 use crate::{
     ast::{self, Node},
     builtins::STATUS_ILLEGAL_CMD,
-    common::{CancelChecker, PROFILING_ACTIVE},
+    common::{CancelChecker, PROFILING_ACTIVE, valid_var_name, valid_var_name_char},
     complete::CompletionList,
     env::{
-        EnvMode, EnvSetMode, EnvStack, EnvStackSetResult, Environment,
+        ComputedVar, EnvMode, EnvSetMode, EnvStack, EnvStackSetResult, Environment,
         FISH_TERMINAL_COLOR_THEME_VAR, Statuses,
     },
     event::{self, Event},
+    exec::exec_subshell,
     expand::{ExpandFlags, ExpandResultCode, expand_string, replace_home_directory_with_tilde},
     fds::{BEST_O_SEARCH, open_dir},
     flog, flogf, function,
     io::IoChain,
     job_group::MaybeJobId,
+    nix::isatty,
     operation_context::{EXPANSION_LIMIT_DEFAULT, OperationContext},
     parse_constants::{
         FISH_MAX_EVAL_DEPTH, FISH_MAX_STACK_DEPTH, ParseError, ParseErrorList, ParseTreeFlags,
@@ -22,19 +24,22 @@
     },
     parse_execution::{EndExecutionReason, ExecutionContext},
     parse_tree::{NodeRef, ParsedSourceRef, SourceLineCache, parse_source},
+    parse_util::column,
+    path::path_apply_working_directory,
     prelude::*,
     proc::{InternalJobId, JobGroupRef, JobList, JobRef, Pid, ProcStatus, job_reap},
     signal::{RawSignal, signal_check_cancel, signal_clear_cancel},
+    terminal::{hyperlink_file, hyperlinks_enabled},
     wait_handle::WaitHandleStore,
-    wutil::perror_nix,
+    wutil::{normalize_path, perror_nix},
 };
 use assert_matches::assert_matches;
 use fish_common::{
     EscapeFlags, EscapeStringStyle, FilenameRef, ScopedCell, ScopedRefCell, escape_string,
 };
 use fish_util::get_time;
-use fish_widestring::{WExt as _, wcs2bytes};
-use libc::c_int;
+use fish_widestring::{VARIABLE_EXPAND, VARIABLE_EXPAND_SINGLE, WExt as _, wcs2bytes};
+use libc::{STDERR_FILENO, c_int};
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::Write as _;
@@ -43,6 +48,7 @@
 use std::os::fd::OwnedFd;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 pub enum BlockData {
@@ -96,6 +102,12 @@ pub fn wants_pop_env(&self) -> bool {
     pub fn src_lineno(&self, cache: &mut SourceLineCache) -> Option<NonZeroU32> {
         self.src_node.as_ref()?.lineno_with_cache(cache)
     }
+
+    /// Return the 1-based column of this block.
+    pub fn src_column(&self) -> Option<usize> {
+        let node = self.src_node.as_ref()?;
+        Some(column(node.source_str(), node.source_offset()?))
+    }
 }
 
 impl Block {
@@ -322,6 +334,18 @@ pub struct LibraryData {
     /// Note this only exits up to the "current script boundary." That is, a call to exit within a
     /// 'source' or 'read' command will only exit up to that command.
     pub exit_current_script: bool,
+
+    /// If set, the process we are running writes directly into a pipe, and this is set once a
+    /// write fails because the reader went away. Execution then stops as if by SIGPIPE.
+    pub broken_pipe: Option<Arc<AtomicBool>>,
+
+    /// If set, we are running restricted code, which may only run these external commands.
+    /// See `eval --restricted`.
+    pub restricted_commands: ScopedRefCell<Option<Vec<WString>>>,
+
+    /// The number of failed assertions from the 'assert' command.
+    /// This is used by 'fish_test' to tell whether a test failed.
+    pub assertion_failures: usize,
 }
 
 impl LibraryData {
@@ -539,7 +563,7 @@ pub fn eval_with(
         }
 
         // Get a backtrace. This includes the message.
-        let backtrace_and_desc = self.get_backtrace(cmd, &error_list);
+        let backtrace_and_desc = self.get_backtrace(cmd, &error_list, isatty(STDERR_FILENO));
 
         if !self.test_only_suppress_stderr() {
             // Print it.
@@ -597,7 +621,7 @@ pub fn eval_wstr(
             errored = detect_parse_errors_in_ast(&ast, &src, Some(&mut errors)).is_err();
         }
         if errored {
-            let sb = self.get_backtrace(&src, &errors);
+            let sb = self.get_backtrace(&src, &errors, false);
             return Err(sb);
         }
 
@@ -658,12 +682,20 @@ pub fn eval_node<T: Node>(
         // This includes both signals sent to fish (user hit control-C while fish is foreground) and
         // signals from the job group (e.g. some external job terminated with SIGQUIT).
         let jg = job_group.cloned();
+        let broken_pipe = self.libdata().broken_pipe.clone();
         let check_cancel_signal = move || {
             // Did fish itself get a signal?
             let sig = signal_check_cancel();
             if sig != 0 {
                 return Some(RawSignal::new(sig));
             }
+            // Did our output pipe break?
+            if broken_pipe
+                .as_ref()
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+            {
+                return Some(RawSignal::new(libc::SIGPIPE));
+            }
             // Has this job group been cancelled?
             jg.as_ref().and_then(|jg| jg.get_cancel_signal())
         };
@@ -756,7 +788,10 @@ pub fn expand_argument_list(
     /// LINE_NUMBER): LINE'. Example:
     ///
     /// init.fish (line 127): ls|grep pancake
-    pub fn current_line(&self) -> WString {
+    ///
+    /// If `to_terminal` is set and `$fish_hyperlinks` is enabled, file names are hyperlinks.
+    pub fn current_line(&self, to_terminal: bool) -> WString {
+        let hyperlinks = to_terminal && hyperlinks_enabled(self.vars());
         let lineno = self.lineno_for_display();
         let file = self.current_filename();
 
@@ -765,11 +800,21 @@ pub fn current_line(&self) -> WString {
         // If we are not going to print a stack trace, at least print the line number and filename.
         if !self.is_interactive() || self.is_function() {
             if let Some(file) = file {
-                prefix.push_utfstr(&wgettext_fmt!(
-                    "%s (line %d)",
-                    &user_presentable_path(&file, self.vars()),
-                    lineno
-                ));
+                let column = self.current_node.borrow().as_ref().map_or(1, |node| {
+                    node.source_offset()
+                        .map_or(1, |offset| column(node.source_str(), offset))
+                });
+                if let Some(location) =
+                    format_source_location(self.vars(), &file, lineno, column, hyperlinks)
+                {
+                    prefix.push_utfstr(&location);
+                } else {
+                    prefix.push_utfstr(&wgettext_fmt!(
+                        "%s (line %d)",
+                        &user_presentable_path(&file, self.vars(), hyperlinks),
+                        lineno
+                    ));
+                }
             } else if self.libdata().within_fish_init {
                 prefix.push_utfstr(&wgettext_fmt!("Startup (line %d)", lineno));
             } else {
@@ -804,7 +849,7 @@ pub fn current_line(&self) -> WString {
             line_info.push('\n');
         }
 
-        line_info.push_utfstr(&self.stack_trace());
+        line_info.push_utfstr(&self.stack_trace_impl(hyperlinks));
         line_info
     }
 
@@ -950,10 +995,168 @@ pub fn set_var_and_fire(
         res
     }
 
+    /// Cover of vars().set_map(), which also fires any returned event handlers.
+    pub fn set_map_and_fire(
+        &mut self,
+        key: &wstr,
+        mode: ParserEnvSetMode,
+        keys: Vec<WString>,
+        vals: Vec<WString>,
+    ) -> EnvStackSetResult {
+        let res = self.set_map(key, mode, keys, vals);
+        if res == EnvStackSetResult::Ok {
+            event::fire(self, Event::variable_set(key.to_owned()));
+        }
+        res
+    }
+
+    /// Compute the values of any stale computed variables that are expanded in `s`, which has
+    /// already been unescaped. If a value changes, fire the variable's event handlers.
+    pub fn refresh_computed_vars(&mut self, s: &wstr) {
+        let chars = s.as_char_slice();
+        let mut i = 0;
+        while i < chars.len() {
+            if ![VARIABLE_EXPAND, VARIABLE_EXPAND_SINGLE].contains(&chars[i]) {
+                i += 1;
+                continue;
+            }
+            // With `$$name`, the variables named by the values of `name` are expanded too.
+            let mut depth = 0;
+            while i < chars.len() && [VARIABLE_EXPAND, VARIABLE_EXPAND_SINGLE].contains(&chars[i]) {
+                depth += 1;
+                i += 1;
+            }
+            let start = i;
+            while i < chars.len() && valid_var_name_char(chars[i]) {
+                i += 1;
+            }
+            if i > start {
+                self.refresh_computed_var_indirect(&s[start..i], depth);
+            }
+        }
+    }
+
+    /// Refresh `name`, and if `depth` is more than 1, the variables it names, down to `depth`
+    /// levels of indirection.
+    fn refresh_computed_var_indirect(&mut self, name: &wstr, depth: usize) {
+        self.refresh_computed_var(name);
+        if depth <= 1 {
+            return;
+        }
+        let Some(var) = self.vars().get(name) else {
+            return;
+        };
+        for target in var.as_list() {
+            if valid_var_name(target) {
+                self.refresh_computed_var_indirect(target, depth - 1);
+            }
+        }
+    }
+
+    /// Compute the value of `name` if it is a computed variable whose value is stale. If the
+    /// value changes, fire the variable's event handlers.
+    pub fn refresh_computed_var(&mut self, name: &wstr) {
+        let Some(var) = self.vars().get(name) else {
+            return;
+        };
+        let Some(computed) = var.computed() else {
+            return;
+        };
+        // Don't recurse if the code refers to the variable itself.
+        if computed.is_fresh() || !computed.begin_evaluating() {
+            return;
+        }
+        let mut values = vec![];
+        {
+            let _restricted = computed
+                .restricted_commands()
+                .map(|allowed| self.push_restriction(allowed.to_vec()));
+            let _ = exec_subshell(computed.source(), self, Some(&mut values), false);
+        }
+        computed.end_evaluating();
+        if computed.store(values) {
+            event::fire(self, Event::variable_set(name.to_owned()));
+        }
+    }
+
     pub fn is_repainting(&self) -> bool {
         self.libdata().is_repaint
     }
 
+    /// Whether we are running restricted code, see `eval --restricted`.
+    pub fn is_restricted(&self) -> bool {
+        self.libdata().restricted_commands.borrow().is_some()
+    }
+
+    /// The external commands that restricted code may run, or None if we are not restricted.
+    pub fn restricted_commands(&self) -> Option<Vec<WString>> {
+        self.libdata().restricted_commands.borrow().clone()
+    }
+
+    /// Restrict the code that runs until the returned guard is dropped, allowing only the external
+    /// commands in `allowed`. If we are already restricted, commands that were not allowed before
+    /// stay forbidden.
+    pub fn push_restriction(&self, mut allowed: Vec<WString>) -> impl DerefMut + use<> {
+        let restricted_commands = &self.libdata().restricted_commands;
+        if let Some(outer) = &*restricted_commands.borrow() {
+            allowed.retain(|cmd| outer.contains(cmd));
+        }
+        restricted_commands.scoped_replace(Some(allowed))
+    }
+
+    /// Whether the external command `cmd`, found at `path`, may be run.
+    /// Allowed commands are given either by name, or by their full path.
+    pub fn is_command_allowed(&self, cmd: &wstr, path: &wstr) -> bool {
+        let Some(allowed) = &*self.libdata().restricted_commands.borrow() else {
+            return true;
+        };
+        allowed
+            .iter()
+            .any(|entry| entry == path || (!cmd.contains('/') && entry == cmd))
+    }
+
+    /// Lift any restriction until the returned guard is dropped.
+    /// This is used to load trusted code, like autoloaded functions, on behalf of restricted code.
+    pub fn lift_restriction(&self) -> impl DerefMut + use<> {
+        self.libdata().restricted_commands.scoped_replace(None)
+    }
+
+    /// If setting or erasing `key` with `mode` is not allowed because we are running restricted
+    /// code, return why.
+    pub fn restricted_change(
+        &self,
+        key: &wstr,
+        mode: ParserEnvSetMode,
+    ) -> Option<RestrictedChange> {
+        if !self.is_restricted() {
+            return None;
+        }
+        if is_search_path_var(key) {
+            return Some(RestrictedChange::SearchPath);
+        }
+        // Exported variables change what the allowed commands do, and outlive the restriction.
+        let vars = self.vars();
+        let exported = [EnvMode::LOCAL, EnvMode::GLOBAL, EnvMode::UNIVERSAL]
+            .into_iter()
+            .any(|scope| vars.getf(key, scope).is_some_and(|var| var.exports()));
+        if exported || (mode.user && mode.mode.contains(EnvMode::EXPORT)) {
+            return Some(RestrictedChange::Exported);
+        }
+        if mode.mode.contains(EnvMode::UNIVERSAL) {
+            return Some(RestrictedChange::Universal);
+        }
+        // Without a scope, an existing universal variable is changed unless it is shadowed.
+        let shadowing_scopes = EnvMode::LOCAL | EnvMode::FUNCTION | EnvMode::GLOBAL;
+        (!mode.mode.intersects(EnvMode::ANY_SCOPE)
+            && vars.getf(key, shadowing_scopes).is_none()
+            && vars.getf(key, EnvMode::UNIVERSAL).is_some())
+        .then_some(RestrictedChange::Universal)
+    }
+
+    fn is_restricted_change(&self, key: &wstr, mode: ParserEnvSetMode) -> bool {
+        self.restricted_change(key, mode).is_some()
+    }
+
     pub fn convert_env_set_mode(&self, mode: ParserEnvSetMode) -> EnvSetMode {
         EnvSetMode::new_with(mode.mode, mode.user, self.is_repainting())
     }
@@ -965,10 +1168,42 @@ pub fn set_var(
         mode: ParserEnvSetMode,
         vals: Vec<WString>,
     ) -> EnvStackSetResult {
+        if self.is_restricted_change(key, mode) {
+            return EnvStackSetResult::Restricted;
+        }
         let mode = self.convert_env_set_mode(mode);
         self.vars().set(key, mode, vals)
     }
 
+    /// Cover of vars().set_map(), without firing events
+    pub fn set_map(
+        &mut self,
+        key: &wstr,
+        mode: ParserEnvSetMode,
+        keys: Vec<WString>,
+        vals: Vec<WString>,
+    ) -> EnvStackSetResult {
+        if self.is_restricted_change(key, mode) {
+            return EnvStackSetResult::Restricted;
+        }
+        let mode = self.convert_env_set_mode(mode);
+        self.vars().set_map(key, mode, keys, vals)
+    }
+
+    /// Cover of vars().set_computed(), without firing events
+    pub fn set_computed(
+        &mut self,
+        key: &wstr,
+        mode: ParserEnvSetMode,
+        computed: Arc<ComputedVar>,
+    ) -> EnvStackSetResult {
+        if self.is_restricted_change(key, mode) {
+            return EnvStackSetResult::Restricted;
+        }
+        let mode = self.convert_env_set_mode(mode);
+        self.vars().set_computed(key, mode, computed)
+    }
+
     /// Cover of vars().set_one(), without firing events
     pub fn set_one(
         &mut self,
@@ -976,18 +1211,27 @@ pub fn set_one(
         mode: ParserEnvSetMode,
         val: WString,
     ) -> EnvStackSetResult {
+        if self.is_restricted_change(key, mode) {
+            return EnvStackSetResult::Restricted;
+        }
         let mode = self.convert_env_set_mode(mode);
         self.vars().set_one(key, mode, val)
     }
 
     /// Cover of vars().set_empty(), without firing events
     pub fn set_empty(&mut self, key: &wstr, mode: ParserEnvSetMode) -> EnvStackSetResult {
+        if self.is_restricted_change(key, mode) {
+            return EnvStackSetResult::Restricted;
+        }
         let mode = self.convert_env_set_mode(mode);
         self.vars().set_empty(key, mode)
     }
 
     /// Cover of vars().remove(), without firing events
     pub fn remove_var(&mut self, key: &wstr, mode: ParserEnvSetMode) -> EnvStackSetResult {
+        if self.is_restricted_change(key, mode) {
+            return EnvStackSetResult::Restricted;
+        }
         let mode = self.convert_env_set_mode(mode);
         self.vars().remove(key, mode)
     }
@@ -1141,7 +1385,10 @@ pub fn flush_profiling(&mut self, path: &OsStr) {
         profile_items.clear();
     }
 
-    pub fn get_backtrace(&self, src: &wstr, errors: &ParseErrorList) -> WString {
+    /// Describe the first of `errors` in `src`, followed by the stack trace.
+    /// If `to_terminal` is set and `$fish_hyperlinks` is enabled, file names are hyperlinks.
+    pub fn get_backtrace(&self, src: &wstr, errors: &ParseErrorList, to_terminal: bool) -> WString {
+        let hyperlinks = to_terminal && hyperlinks_enabled(self.vars());
         let Some(err) = errors.first() else {
             return WString::new();
         };
@@ -1164,14 +1411,27 @@ pub fn get_backtrace(&self, src: &wstr, errors: &ParseErrorList) -> WString {
         }
 
         let prefix = if let Some(filename) = self.current_filename() {
-            if which_line > 0 {
+            let location = (which_line > 0)
+                .then(|| {
+                    format_source_location(
+                        self.vars(),
+                        &filename,
+                        which_line.try_into().unwrap(),
+                        column(src, err.source_start),
+                        hyperlinks,
+                    )
+                })
+                .flatten();
+            if let Some(location) = location {
+                location
+            } else if which_line > 0 {
                 wgettext_fmt!(
                     "%s (line %u)",
-                    user_presentable_path(&filename, self.vars()),
+                    user_presentable_path(&filename, self.vars(), hyperlinks),
                     which_line
                 )
             } else {
-                user_presentable_path(&filename, self.vars())
+                user_presentable_path(&filename, self.vars(), hyperlinks)
             }
         } else {
             L!("fish").to_owned()
@@ -1181,7 +1441,7 @@ pub fn get_backtrace(&self, src: &wstr, errors: &ParseErrorList) -> WString {
         if !output.is_empty() {
             output.push('\n');
         }
-        output.push_utfstr(&self.stack_trace());
+        output.push_utfstr(&self.stack_trace_impl(hyperlinks));
         output
     }
 
@@ -1208,6 +1468,10 @@ pub fn is_interactive(&self) -> bool {
 
     /// Return a string representing the current stack trace.
     pub fn stack_trace(&self) -> WString {
+        self.stack_trace_impl(false)
+    }
+
+    fn stack_trace_impl(&self, hyperlinks: bool) -> WString {
         let mut line_cache = SourceLineCache::default();
         self.blocks_iter_rev()
             // Stop at event handler. No reason to believe that any other code is relevant.
@@ -1216,7 +1480,13 @@ pub fn stack_trace(&self) -> WString {
             // detect that.
             .take_while(|b| b.typ() != BlockType::Event)
             .fold(WString::new(), |mut trace, b| {
-                append_block_description_to_stack_trace(self, b, &mut trace, &mut line_cache);
+                append_block_description_to_stack_trace(
+                    self,
+                    b,
+                    &mut trace,
+                    &mut line_cache,
+                    hyperlinks,
+                );
                 trace
             })
     }
@@ -1294,9 +1564,103 @@ pub fn set_color_theme(&mut self, background_color: Option<&xterm_color::Color>)
     }
 }
 
+/// The kinds of variables that restricted code may not change, see `eval --restricted`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RestrictedChange {
+    /// A variable used to find commands, functions or completions.
+    SearchPath,
+    /// A universal variable.
+    Universal,
+    /// An exported variable, or a variable that would be exported.
+    Exported,
+}
+
+impl RestrictedChange {
+    /// The error message for trying to change the variable `key`.
+    pub fn message(self, key: &wstr) -> WString {
+        match self {
+            RestrictedChange::SearchPath => {
+                wgettext_fmt!(
+                    "Tried to change the search path '%s' in restricted mode",
+                    key
+                )
+            }
+            RestrictedChange::Universal => wgettext_fmt!(
+                "Tried to change the universal variable '%s' in restricted mode",
+                key
+            ),
+            RestrictedChange::Exported => wgettext_fmt!(
+                "Tried to change the exported variable '%s' in restricted mode",
+                key
+            ),
+        }
+    }
+}
+
+/// Whether `key` is one of the variables used to find commands, functions or completions.
+/// Restricted code may not change these, as that would change what the allowed commands run.
+pub fn is_search_path_var(key: &wstr) -> bool {
+    [
+        L!("PATH"),
+        L!("fish_user_paths"),
+        L!("fish_function_path"),
+        L!("fish_complete_path"),
+    ]
+    .contains(&key)
+}
+
 // Given a file path, return something nicer. Currently we just "unexpand" tildes.
-fn user_presentable_path(path: &wstr, vars: &dyn Environment) -> WString {
-    replace_home_directory_with_tilde(path, vars)
+// If `hyperlink` is set, the result links to the file.
+fn user_presentable_path(path: &wstr, vars: &dyn Environment, hyperlink: bool) -> WString {
+    let text = replace_home_directory_with_tilde(path, vars);
+    if hyperlink {
+        hyperlink_file(&text, path, &vars.get_pwd_slash())
+    } else {
+        text
+    }
+}
+
+/// Describe a location in a file as configured by `$fish_error_location_format`, where `%f` is
+/// replaced by the absolute path, `%l` by the line and `%c` by the column.
+/// Return None if the variable is empty, so the caller can use its usual wording.
+fn format_source_location(
+    vars: &dyn Environment,
+    path: &wstr,
+    line: u32,
+    column: usize,
+    hyperlink: bool,
+) -> Option<WString> {
+    let format = vars
+        .get_unless_empty(L!("fish_error_location_format"))?
+        .as_string();
+    let pwd = vars.get_pwd_slash();
+    let mut result = WString::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => result.push_utfstr(&normalize_path(
+                &path_apply_working_directory(path, &pwd),
+                false,
+            )),
+            Some('l') => result.push_utfstr(&line.to_wstring()),
+            Some('c') => result.push_utfstr(&column.to_wstring()),
+            Some('%') => result.push('%'),
+            Some(other) => {
+                result.push('%');
+                result.push(other);
+            }
+            None => result.push('%'),
+        }
+    }
+    Some(if hyperlink {
+        hyperlink_file(&result, path, &pwd)
+    } else {
+        result
+    })
 }
 
 /// Print profiling information to the specified stream.
@@ -1359,6 +1723,7 @@ fn append_block_description_to_stack_trace(
     b: &Block,
     trace: &mut WString,
     line_cache: &mut SourceLineCache,
+    hyperlinks: bool,
 ) {
     let mut print_source_location = false;
     match b.typ() {
@@ -1404,7 +1769,7 @@ fn append_block_description_to_stack_trace(
             let source_dest = file;
             trace.push_utfstr(&wgettext_fmt!(
                 "from sourcing file %s",
-                &user_presentable_path(source_dest, parser.vars())
+                &user_presentable_path(source_dest, parser.vars(), hyperlinks)
             ));
             trace.push('\n');
             print_source_location = true;
@@ -1430,11 +1795,22 @@ fn append_block_description_to_stack_trace(
 
     if print_source_location {
         if let Some(file) = b.src_filename.as_ref() {
-            trace.push_utfstr(&sprintf!(
-                "\tcalled on line %d of file %s\n",
-                b.src_lineno(line_cache).map_or(0, |n| n.get()),
-                user_presentable_path(file, parser.vars())
-            ));
+            let lineno = b.src_lineno(line_cache).map_or(0, |n| n.get());
+            if let Some(location) = format_source_location(
+                parser.vars(),
+                file,
+                lineno,
+                b.src_column().unwrap_or(1),
+                hyperlinks,
+            ) {
+                trace.push_utfstr(&sprintf!("\tcalled at %s\n", location));
+            } else {
+                trace.push_utfstr(&sprintf!(
+                    "\tcalled on line %d of file %s\n",
+                    lineno,
+                    user_presentable_path(file, parser.vars(), hyperlinks)
+                ));
+            }
         } else if parser.libdata().within_fish_init {
             trace.push_str("\tcalled during startup\n");
         }
@@ -559,11 +559,14 @@ pub fn is_completed(&self) -> bool {
     pub fn make_wait_handle(&self, jid: InternalJobId) -> Option<WaitHandleRef> {
         let pid = self.pid()?;
         if self.wait_handle.borrow().is_none() {
-            self.wait_handle.replace(Some(WaitHandle::new(
-                pid,
-                jid,
-                wbasename(&self.actual_cmd.clone()).to_owned(),
-            )));
+            // Functions run in a child fish have no command path, use their name instead.
+            let base_name = if self.is_function() {
+                self.argv0().unwrap_or_default().to_owned()
+            } else {
+                wbasename(&self.actual_cmd.clone()).to_owned()
+            };
+            self.wait_handle
+                .replace(Some(WaitHandle::new(pid, jid, base_name)));
         }
         self.wait_handle()
     }
@@ -736,7 +739,7 @@ pub fn wants_job_control(&self) -> bool {
     }
 
     pub fn entitled_to_terminal(&self) -> bool {
-        self.group().is_foreground() && self.processes().iter().any(|p| !p.is_internal())
+        self.group().is_foreground() && self.has_external_proc()
     }
 
     /// Return whether this job is initially going to run in the background, because & was
@@ -753,9 +756,12 @@ pub fn mark_constructed(&self) {
 
     /// Return whether we have internal or external procs, respectively.
     /// Internal procs are builtins, blocks, and functions.
-    /// External procs include exec and external.
+    /// External procs include exec and external, as well as blocks and functions run in a child
+    /// fish.
     pub fn has_external_proc(&self) -> bool {
-        self.processes().iter().any(|p| !p.is_internal())
+        self.processes()
+            .iter()
+            .any(|p| !p.is_internal() || p.has_pid())
     }
 
     /// Return whether this job, when run, will want a job ID.
@@ -1278,7 +1284,7 @@ fn process_mark_finished_children(parser: &mut Parser, block_ok: bool, block_io:
                 flogf!(
                     proc_reap_external,
                     "Reaped external process '%s' (pid %d, status %d)",
-                    proc.argv0().unwrap(),
+                    proc.argv0().unwrap_or_default(),
                     pid,
                     proc.status().status_value()
                 );
@@ -1289,7 +1295,7 @@ fn process_mark_finished_children(parser: &mut Parser, block_ok: bool, block_io:
                 flogf!(
                     proc_reap_external,
                     "External process '%s' (pid %d, %s)",
-                    proc.argv0().unwrap(),
+                    proc.argv0().unwrap_or_default(),
                     proc.pid().unwrap(),
                     if proc.status().stopped() {
                         "stopped"
@@ -1490,7 +1496,7 @@ fn summary_command(j: &Job, p: Option<&Process>) -> WString {
                 buffer += &sprintf!(" %s", pid)[..];
 
                 buffer.push(' ');
-                buffer += &escape(p.argv0().unwrap())[..];
+                buffer += &escape(p.argv0().unwrap_or_default())[..];
             }
         }
     }
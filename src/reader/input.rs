@@ -2,7 +2,10 @@
 use super::{Reader, reader_reading_interrupted, reader_schedule_prompt_repaint};
 use crate::{
     event,
-    input::{CharEvent, InputData, InputEventQueuer, ReadlineCmd, input_get_bind_mode},
+    input::{
+        CharEvent, InputData, InputEventQueuer, KeyEvent, ReadlineCmd, SequenceKeyHint,
+        input_get_bind_mode,
+    },
     proc::job_reap,
     signal::signal_clear_cancel,
 };
@@ -31,6 +34,11 @@ fn prepare_to_select(&mut self) {
         }
     }
 
+    fn key_released(&mut self, key: KeyEvent) {
+        // Handled by read_char() once the current key sequence is done.
+        self.input_data.released_keys.push(key);
+    }
+
     fn select_interrupted(&mut self) {
         // Readline commands may be bound to \cc which also sets the cancel flag.
         // See #6937, #8125.
@@ -77,6 +85,14 @@ fn paste_commit(&mut self) {
         )));
     }
 
+    fn show_sequence_key_hint(&mut self, hints: &[SequenceKeyHint]) {
+        self.show_sequence_key_hint_in_pager(hints);
+    }
+
+    fn hide_sequence_key_hint(&mut self) {
+        self.hide_sequence_key_hint_in_pager();
+    }
+
     fn get_bind_mode(&self) -> WString {
         input_get_bind_mode(self.parser.vars())
     }
@@ -25,6 +25,11 @@ pub(super) struct Debouncers {
     // Debounce history pager computations. This holds a callback, not a single value,
     // both to demonstrate the technique and because the callback can capture local variables.
     pub history_pager: Debounce<Callback>,
+    // Debounce pager previews. This produces a callback, which runs the preview function on the
+    // main thread once the selection has settled.
+    pub pager_preview: Debounce<Callback>,
+    // Run the conditions of abbreviations for each prompt.
+    pub abbr_conditions: Debounce<Callback>,
 }
 
 impl Debouncers {
@@ -36,10 +41,14 @@ pub fn new() -> Self {
         const HIGHLIGHT_TIMEOUT: Duration = Duration::from_millis(500);
         const HISTORY_PAGER_TIMEOUT: Duration = Duration::from_millis(500);
         const AUTOSUGGEST_TIMEOUT: Duration = Duration::from_millis(500);
+        const PAGER_PREVIEW_TIMEOUT: Duration = Duration::from_millis(500);
+        const ABBR_CONDITIONS_TIMEOUT: Duration = Duration::from_millis(500);
         Self {
             autosuggestions: Debounce::new(&pool, &event_signaller, AUTOSUGGEST_TIMEOUT),
             highlight: Debounce::new(&pool, &event_signaller, HIGHLIGHT_TIMEOUT),
             history_pager: Debounce::new(&pool, &event_signaller, HISTORY_PAGER_TIMEOUT),
+            pager_preview: Debounce::new(&pool, &event_signaller, PAGER_PREVIEW_TIMEOUT),
+            abbr_conditions: Debounce::new(&pool, &event_signaller, ABBR_CONDITIONS_TIMEOUT),
             event_signaller,
         }
     }
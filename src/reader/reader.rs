@@ -23,7 +23,7 @@
     word_motion::{MoveWordDir, MoveWordStateMachine, MoveWordStyle},
 };
 use crate::{
-    abbrs::{self, abbrs_match},
+    abbrs::{self, abbrs_match, with_abbrs},
     ast::{self, Kind, is_same_node},
     builtins::{ErrorCode, STATUS_CMD_ERROR, STATUS_CMD_OK},
     common::{get_program_name, shell_modes},
@@ -38,7 +38,7 @@
         handle_fish_cursor_selection_mode_change,
     },
     event,
-    exec::exec_subshell,
+    exec::{ChildFishCommand, exec_subshell},
     expand::{ExpandFlags, ExpandResultCode, expand_one, expand_string, expand_tilde},
     fd_readable_set::poll_fd_readable,
     fds::{make_fd_blocking, wopen_cloexec},
@@ -56,15 +56,15 @@
     input::{
         BackgroundColorQuery, CharEvent, CharInputStyle, CursorPositionQuery,
         CursorPositionQueryReason, ImplicitEvent, InputData, InputEventQueue,
-        InputEventQueuer as _, LONG_READ_TIMEOUT, QueryResponse, QueryResultEvent, ReadlineCmd,
-        RecurrentQuery, TerminalQuery, stop_query,
+        InputEventQueuer as _, KeyEvent, LONG_READ_TIMEOUT, QueryResponse, QueryResultEvent,
+        ReadlineCmd, RecurrentQuery, SequenceKeyHint, TerminalQuery, stop_query,
     },
     io::IoChain,
-    key::ViewportPosition,
+    key::{KEY_SEPARATOR, Key, ViewportPosition},
     kill::{kill_add, kill_replace, kill_yank, kill_yank_rotate},
     nix::isatty,
     operation_context::{OperationContext, get_bg_context},
-    pager::{PageRendering, Pager, SelectionMotion},
+    pager::{HyperlinkBase, PageRendering, Pager, SelectionMotion},
     panic::AT_EXIT,
     parse_constants::{ParseIssue, ParseTreeFlags, SourceRange},
     parse_util::{
@@ -74,6 +74,7 @@
         lineno, locate_cmdsubst_range,
     },
     parser::{BlockType, EvalRes, Parser, ParserEnvSetMode},
+    path::path_apply_working_directory,
     portable_atomic::AtomicU64,
     prelude::*,
     proc::{
@@ -91,10 +92,11 @@
         TerminalCommand::{
             self, ClearScreen, DecrstAlternateScreenBuffer, DecsetAlternateScreenBuffer,
             DecsetShowCursor, Osc0WindowTitle, Osc1TabTitle, Osc133CommandFinished,
-            Osc133CommandStart, QueryBackgroundColor, QueryCursorPosition,
-            QueryKittyKeyboardProgressiveEnhancements, QueryPrimaryDeviceAttribute, QueryXtgettcap,
-            QueryXtversion,
+            Osc133CommandStart, QueryBackgroundColor, QueryCellSize, QueryCursorPosition,
+            QueryKittyGraphics, QueryKittyKeyboardProgressiveEnhancements,
+            QueryPrimaryDeviceAttribute, QueryXtgettcap, QueryXtversion,
         },
+        hyperlinks_enabled,
     },
     termsize::{signal_safe_termsize_invalidate_tty, termsize_last, termsize_update},
     text_face::{TextFace, parse_text_face},
@@ -113,9 +115,9 @@
 use assert_matches::assert_matches;
 use errno::{Errno, errno};
 use fish_common::{
-    EscapeFlags, EscapeStringStyle, ScopeGuard, escape, escape_string, escape_string_with_quote,
-    exit_without_destructors, get_obfuscation_read_char, help_section,
-    restore_term_foreground_process_group_for_exit, write_loop,
+    EscapeFlags, EscapeStringStyle, ScopeGuard, UnescapeFlags, UnescapeStringStyle, escape,
+    escape_string, escape_string_with_quote, exit_without_destructors, get_obfuscation_read_char,
+    help_section, restore_term_foreground_process_group_for_exit, unescape_string, write_loop,
 };
 use fish_fallback::{fish_wcwidth, lowercase};
 use fish_feature_flags::FeatureFlag;
@@ -276,6 +278,7 @@ pub fn terminal_init(vars: &dyn Environment, inputfd: RawFd) -> TerminalInitResu
         out.write_command(QueryKittyKeyboardProgressiveEnhancements);
         out.write_command(QueryXtversion);
         out.write_command(QueryBackgroundColor);
+        out.write_command(QueryCellSize);
         query_capabilities_via_dcs(&mut out, vars);
         out.write_command(QueryPrimaryDeviceAttribute);
     }
@@ -479,6 +482,9 @@ pub struct ReaderConfig {
     /// Whether to expand abbreviations.
     pub expand_abbrev_ok: bool,
 
+    /// Whether to show the expansion of the abbreviation at the cursor.
+    pub abbr_preview: bool,
+
     /// Whether to exit on interrupt (^C).
     pub exit_on_interrupt: bool,
 
@@ -510,6 +516,8 @@ pub struct CommandlineState {
     pub search_field: Option<(WString, usize)>,
     /// pager is visible and search is active
     pub search_mode: bool,
+    /// the key event that triggered the binding being executed, if any
+    pub key_event: Option<KeyEvent>,
 }
 
 impl CommandlineState {
@@ -523,6 +531,7 @@ const fn new() -> Self {
             pager_fully_disclosed: false,
             search_field: None,
             search_mode: false,
+            key_event: None,
         }
     }
 }
@@ -707,6 +716,10 @@ pub struct ReaderData {
     history_search: ReaderHistorySearch,
     /// In-pager history search.
     history_pager: Option<Range<usize>>,
+    /// Whether the pager shows the continuations of a partially typed key sequence.
+    sequence_key_hint_shown: bool,
+    /// Whether the preview function took too long, so the pager shows no previews until closed.
+    pager_preview_over_budget: bool,
 
     /// The cursor selection mode.
     cursor_selection_mode: CursorSelectionMode,
@@ -725,6 +738,9 @@ pub struct ReaderData {
     cycle_command_line: WString,
     cycle_cursor_pos: usize,
 
+    /// The expansions of the abbreviations expanded in the current command line.
+    expanded_abbreviations: Vec<WString>,
+
     /// If set, a key binding or the 'exit' command has asked us to exit our read loop.
     exit_loop_requested: bool,
     /// If this is true, exit reader even if there are running jobs. This happens if we press e.g.
@@ -792,6 +808,12 @@ pub(super) fn service_debounced_results(&mut self) {
         if let Some(cb) = self.debouncers.history_pager.take_result() {
             cb(self);
         }
+        if let Some(cb) = self.debouncers.pager_preview.take_result() {
+            cb(self);
+        }
+        if let Some(cb) = self.debouncers.abbr_conditions.take_result() {
+            cb(self);
+        }
     }
 }
 
@@ -830,6 +852,7 @@ fn read_i(parser: &mut Parser) {
         expand_abbrev_ok: true,
         autosuggest_ok: check_bool_var(parser.vars(), L!("fish_autosuggestion_enabled"), true),
         transient_prompt: check_bool_var(parser.vars(), L!("fish_transient_prompt"), false),
+        abbr_preview: check_bool_var(parser.vars(), L!("fish_abbr_preview"), false),
         ..Default::default()
     };
 
@@ -884,7 +907,22 @@ fn read_i(parser: &mut Parser) {
         BufferedOutputter::new(Outputter::stdoutput()).write_command(Osc133CommandFinished {
             exit_status: reader.parser.last_status(),
         });
-        event::fire_generic(reader.parser, L!("fish_postexec").to_owned(), vec![command]);
+        event::fire_generic(
+            reader.parser,
+            L!("fish_postexec").to_owned(),
+            vec![command.clone()],
+        );
+        // Tell the user about an abbreviation they could have used instead.
+        let pwd = reader.parser.vars().get_pwd_slash();
+        if let Some((name, expansion)) = with_abbrs(|set| set.hint(&command, &pwd))
+            .filter(|(_, expansion)| !reader.data.expanded_abbreviations.contains(expansion))
+        {
+            event::fire_generic(
+                reader.parser,
+                L!("fish_abbr_hint").to_owned(),
+                vec![name, expansion],
+            );
+        }
         // Allow any pending history items to be returned in the history array.
         reader.history.resolve_pending();
 
@@ -1124,6 +1162,18 @@ pub fn reader_set_autosuggestion_enabled(vars: &dyn Environment) {
     }
 }
 
+/// Enable or disable the abbreviation preview based on the associated variable.
+pub fn reader_set_abbr_preview(vars: &dyn Environment) {
+    // We don't need to _change_ if we're not initialized yet.
+    if let Some(data) = current_data() {
+        let enable = check_bool_var(vars, L!("fish_abbr_preview"), false);
+        if data.conf.abbr_preview != enable {
+            data.conf.abbr_preview = enable;
+            data.schedule_prompt_repaint();
+        }
+    }
+}
+
 /// Enable or disable transient prompt based on the associated variable.
 pub fn reader_set_transient_prompt(vars: &dyn Environment) {
     // We don't need to _change_ if we're not initialized yet.
@@ -1316,6 +1366,17 @@ pub fn reader_status_count() -> u64 {
 /// The name of the function for getting the input mode indicator.
 const MODE_PROMPT_FUNCTION_NAME: &wstr = L!("fish_mode_prompt");
 
+/// The name of the function that prints a preview of the selected pager item.
+const PAGER_PREVIEW_FUNCTION_NAME: &wstr = L!("fish_pager_preview");
+
+/// How long the pager selection must stay put before we run the preview function, so that quickly
+/// scrolling through the pager does not run it for every item.
+const PAGER_PREVIEW_DELAY: Duration = Duration::from_millis(50);
+
+/// The preview function runs on the main thread, so typing waits for it. If it takes longer than
+/// this, no more previews are shown until the pager is closed.
+const PAGER_PREVIEW_BUDGET: Duration = Duration::from_millis(200);
+
 /// The default title for the reader. This is used by reader_readline.
 const DEFAULT_TITLE: &wstr = L!("echo (status current-command) \" \" $PWD");
 
@@ -1407,6 +1468,8 @@ fn new(
             history,
             history_search: Default::default(),
             history_pager: None,
+            sequence_key_hint_shown: false,
+            pager_preview_over_budget: false,
             cursor_selection_mode: CursorSelectionMode::Exclusive,
             cursor_end_mode: CursorEndMode::Exclusive,
             selection: Default::default(),
@@ -1415,6 +1478,7 @@ fn new(
             right_prompt_buff: Default::default(),
             cycle_command_line: Default::default(),
             cycle_cursor_pos: Default::default(),
+            expanded_abbreviations: Default::default(),
             exit_loop_requested: Default::default(),
             did_warn_for_bg_jobs: Default::default(),
             kill_item: Default::default(),
@@ -1534,6 +1598,7 @@ fn update_commandline_state(&self) {
         snapshot.selection = self.get_selection();
         snapshot.pager_mode = !self.pager.is_empty();
         snapshot.pager_fully_disclosed = self.current_page_rendering.remaining_to_disclose == 0;
+        snapshot.key_event = self.input_data.binding_key;
         if (snapshot.search_field.is_some() != self.pager.search_field_shown)
             || snapshot
                 .search_field
@@ -1833,6 +1898,20 @@ fn layout_and_repaint_before_execution(&mut self) {
     /// `reason` is used in flog to explain why.
     fn paint_layout(&mut self, reason: &wstr, is_final_rendering: bool) {
         flogf!(reader_render, "Repainting from %s", reason);
+        let abbr_preview = if self.conf.abbr_preview
+            && self.conf.expand_abbrev_ok
+            && !is_final_rendering
+            && self.active_edit_line_tag() == EditableLineTag::Commandline
+        {
+            let cmd_line = &self.data.command_line;
+            abbreviation_preview(
+                cmd_line.text(),
+                cmd_line.position(),
+                &self.vars().get_pwd_slash(),
+            )
+        } else {
+            None
+        };
         let cmd_line = &self.data.command_line;
 
         let (full_line, autosuggested_range) = if self.conf.in_silent_mode {
@@ -1856,6 +1935,14 @@ fn paint_layout(&mut self, reason: &wstr, is_final_rendering: bool) {
                 )),
                 Some(autosuggested_start..autosuggested_end),
             )
+        } else if let Some(preview) = abbr_preview {
+            // Show the expansion of the abbreviation at the cursor like an autosuggestion.
+            let pos = cmd_line.position();
+            let text = cmd_line.text();
+            (
+                Cow::Owned(text[..pos].to_owned() + &preview[..] + &text[pos..]),
+                Some(pos..pos + preview.len()),
+            )
         } else {
             (Cow::Borrowed(cmd_line.text()), None)
         };
@@ -2587,6 +2674,7 @@ fn readline(
         // The command line before completion.
         self.cycle_command_line.clear();
         self.cycle_cursor_pos = 0;
+        self.expanded_abbreviations.clear();
 
         self.history_search.reset();
 
@@ -2605,6 +2693,8 @@ fn readline(
         if !self.conf.event.is_empty() {
             event::fire_generic(self.parser, self.conf.event.to_owned(), vec![]);
         }
+        // Abbreviation conditions are evaluated again for each prompt.
+        self.schedule_abbr_conditions();
         self.exec_prompt(true, false);
 
         // Start out as initially dirty.
@@ -3003,6 +3093,8 @@ fn query_capabilities_via_dcs(out: &mut Outputter, vars: &dyn Environment) {
     out.write_command(DecsetAlternateScreenBuffer); // enable alternative screen buffer
     out.write_command(QueryXtgettcap(SCROLL_CONTENT_UP_TERMINFO_CODE));
     out.write_command(QueryXtgettcap(XTGETTCAP_QUERY_OS_NAME));
+    // Not a DCS query, but the reply comes back as an APC string, which needs the same care.
+    out.write_command(QueryKittyGraphics);
     out.write_command(DecrstAlternateScreenBuffer); // disable alternative screen buffer
 }
 
@@ -3212,6 +3304,11 @@ fn handle_readline_command(&mut self, c: ReadlineCmd) {
                     self.compute_and_apply_completions(c);
                 }
             }
+            rl::PagerToggleMark => {
+                if self.pager_toggle_mark() {
+                    self.select_completion_in_direction(SelectionMotion::Next, false);
+                }
+            }
             rl::PagerToggleSearch => {
                 if let Some(history_pager) = &self.history_pager {
                     if history_pager.start == 0 {
@@ -4473,6 +4570,12 @@ impl<'a> Reader<'a> {
     fn handle_execute(&mut self) -> bool {
         // Evaluate. If the current command is unfinished, or if the character is escaped
         // using a backslash, insert a newline.
+        // If the user marked completions, insert them all.
+        if self.history_pager.is_none() && self.pager.has_marked_completions() {
+            self.insert_marked_completions();
+            self.clear_pager();
+            return true;
+        }
         // If the user hits return while navigating the pager, it only clears the pager.
         if self.is_navigating_pager_contents() {
             let search_field = &self.data.pager.search_field_line;
@@ -4592,6 +4695,7 @@ impl ReaderData {
     // Ensure we have no pager contents.
     fn clear_pager(&mut self) {
         self.pager.clear();
+        self.pager_preview_over_budget = false;
         self.history_pager = None;
         self.clear(EditableLineTag::SearchField);
         self.command_line_transient_edit = None;
@@ -4695,6 +4799,73 @@ fn pager_selection_changed(&mut self) {
         } else {
             self.update_buff_pos(EditableLineTag::Commandline, None);
         }
+
+        self.schedule_pager_preview();
+    }
+
+    /// Return the argument for the preview function, given the current pager selection.
+    /// This is the full token (or command line, for the history pager) that the selection produces.
+    fn pager_preview_argument(&self) -> Option<WString> {
+        let completion = self
+            .pager
+            .selected_completion(&self.current_page_rendering)?;
+        if completion
+            .flags
+            .intersects(CompleteFlags::REPLACES_TOKEN | CompleteFlags::REPLACES_LINE)
+        {
+            return Some(completion.completion.clone());
+        }
+        let cursor = self.cycle_cursor_pos.min(self.cycle_command_line.len());
+        let (token_range, _) = get_token_extent(&self.cycle_command_line, cursor);
+        let token = &self.cycle_command_line[token_range.start.min(cursor)..cursor];
+        let mut result = unescape_string(
+            token,
+            UnescapeStringStyle::Script(UnescapeFlags::INCOMPLETE),
+        )
+        .unwrap_or_else(|| token.to_owned());
+        result.push_utfstr(&completion.completion);
+        Some(result)
+    }
+
+    /// Run the conditions of abbreviations for a new prompt in a child fish, so that slow
+    /// conditions don't block the reader. An abbreviation whose condition has not finished yet
+    /// runs it in this fish when it is expanded.
+    fn schedule_abbr_conditions(&mut self) {
+        let generation = abbrs::abbrs_clear_condition_cache();
+        let conditions = abbrs::abbrs_conditions();
+        if conditions.is_empty() {
+            return;
+        }
+        let script = abbrs::abbrs_conditions_script(&conditions);
+        let Some(command) = ChildFishCommand::new(self.parser, &script) else {
+            return;
+        };
+        let performer = move || -> iothreads::Callback {
+            let output = command.output_lines().unwrap_or_default();
+            Box::new(move |_r: &mut Reader| {
+                abbrs::abbrs_set_condition_results(generation, conditions, &output);
+            })
+        };
+        self.debouncers.abbr_conditions.perform(performer);
+    }
+
+    /// Kick off an update of the pager preview. Once the selection has settled, the preview
+    /// function runs, so quickly moving through the pager does not wait for it.
+    fn schedule_pager_preview(&mut self) {
+        let Some(argument) = self.pager_preview_argument() else {
+            self.pager.clear_preview();
+            return;
+        };
+        if self.pager_preview_over_budget {
+            return;
+        }
+        let performer = move || -> iothreads::Callback {
+            std::thread::sleep(PAGER_PREVIEW_DELAY);
+            Box::new(move |r: &mut Reader| {
+                r.run_pager_preview(argument);
+            })
+        };
+        self.debouncers.pager_preview.perform(performer);
     }
 
     /// Sets the command line contents, without clearing the pager.
@@ -4715,6 +4886,58 @@ fn set_buffer_maintaining_pager(&mut self, new_cmd_line: &wstr, pos: usize) {
         self.history_search.reset();
     }
 
+    /// Toggle the mark on the selected item of the completion pager.
+    /// Returns true if there was an item to mark.
+    fn pager_toggle_mark(&mut self) -> bool {
+        if self.history_pager.is_some() {
+            return false;
+        }
+        self.pager
+            .toggle_selected_mark(&self.current_page_rendering)
+    }
+
+    /// Replace the token being completed with all marked completions, each escaped as if it had
+    /// been inserted on its own.
+    fn insert_marked_completions(&mut self) {
+        // Go back to the command line before the pager modified it.
+        self.clear_transient_edit();
+        let mut cmdline = self.cycle_command_line.clone();
+        let mut cursor = self.cycle_cursor_pos.min(cmdline.len());
+        let (token_range, _) = get_token_extent(&cmdline, cursor);
+        let token_prefix = cmdline[token_range.start.min(cursor)..cursor].to_owned();
+
+        let marked: Vec<Completion> = self.pager.marked_completions().cloned().collect();
+        let mut ctx = OperationContext::background_interruptible(EnvStack::globals());
+        for (i, completion) in marked.iter().enumerate() {
+            if i > 0 {
+                // Start a new token, which has the same prefix as the one we completed.
+                let mut insertion = WString::new();
+                if cursor > 0 && cmdline.char_at(cursor - 1) != ' ' {
+                    insertion.push(' ');
+                }
+                if !completion.flags.contains(CompleteFlags::REPLACES_TOKEN) {
+                    insertion.push_utfstr(&token_prefix);
+                }
+                cmdline.insert_utfstr(cursor, &insertion);
+                cursor += insertion.len();
+                // Keep the new token apart from whatever follows it.
+                if !matches!(cmdline.char_at(cursor), ' ' | '\0') {
+                    cmdline.insert(cursor, ' ');
+                }
+            }
+            cmdline = completion_apply_to_command_line(
+                &mut ctx,
+                &completion.completion,
+                completion.flags,
+                &cmdline,
+                &mut cursor,
+                /*append_only=*/ false,
+                /*is_unique=*/ false,
+            );
+        }
+        self.set_buffer_maintaining_pager(&cmdline, cursor);
+    }
+
     fn select_completion_in_direction(
         &mut self,
         dir: SelectionMotion,
@@ -5987,6 +6210,98 @@ fn fill_history_pager_complete(
     }
 }
 
+/// Render a key sequence the way `bind` prints it.
+fn key_sequence_to_string(keys: &[Key]) -> WString {
+    let names: Vec<WString> = keys.iter().map(|&key| WString::from(key)).collect();
+    join_strings(&names, KEY_SEPARATOR)
+}
+
+impl<'a> Reader<'a> {
+    /// Show the possible continuations of a partially typed key sequence in the pager, unless the
+    /// pager is already in use.
+    pub(super) fn show_sequence_key_hint_in_pager(&mut self, hints: &[SequenceKeyHint]) {
+        if !self.pager.is_empty() || self.conf.in_silent_mode {
+            return;
+        }
+        let completions: Vec<Completion> = hints
+            .iter()
+            .map(|hint| {
+                let mut description = join_strings(&hint.commands, ' ');
+                // Add the description of a function bound on its own.
+                if let [command] = hint.commands.as_slice() {
+                    if let Some(props) = function::get_props(command) {
+                        let function_description = props.description.localize();
+                        if !function_description.is_empty() {
+                            description.push_str(": ");
+                            description.push_utfstr(function_description);
+                        }
+                    }
+                }
+                Completion::with_desc(key_sequence_to_string(&hint.remaining), description)
+            })
+            .collect();
+        let mut prefix = key_sequence_to_string(&hints[0].typed);
+        prefix.push(KEY_SEPARATOR);
+        self.pager.set_prefix(Cow::Owned(prefix), false);
+        self.pager.set_completions(&completions, false);
+        self.sequence_key_hint_shown = true;
+        self.layout_and_repaint(L!("sequence-key-hint"));
+    }
+
+    /// Remove what show_sequence_key_hint_in_pager() showed.
+    pub(super) fn hide_sequence_key_hint_in_pager(&mut self) {
+        if !std::mem::take(&mut self.sequence_key_hint_shown) {
+            return;
+        }
+        self.clear_pager();
+        self.layout_and_repaint(L!("sequence-key-hint"));
+    }
+}
+
+impl<'a> Reader<'a> {
+    /// Run the preview function for `argument` and show its output, unless the selection has
+    /// moved on in the meantime.
+    fn run_pager_preview(&mut self, argument: WString) {
+        if self.pager_preview_argument().as_ref() != Some(&argument) {
+            return; // The selection changed or the pager has been closed.
+        }
+        if self.pager_preview_over_budget
+            || !function::exists(PAGER_PREVIEW_FUNCTION_NAME, self.parser)
+        {
+            return;
+        }
+        let mut cmd = PAGER_PREVIEW_FUNCTION_NAME.to_owned();
+        cmd.push(' ');
+        cmd.push_utfstr(&escape(&argument));
+        cmd.push_str(" </dev/null 2>/dev/null");
+
+        let start = Instant::now();
+        let mut outputs = vec![];
+        {
+            let _not_interactive = self.parser.push_scope(|s| {
+                s.is_interactive = false;
+                s.readonly_commandline = true;
+            });
+            let _ = exec_subshell(
+                &cmd,
+                self.parser,
+                Some(&mut outputs),
+                /*apply_exit_status=*/ false,
+            );
+        }
+        let elapsed = start.elapsed();
+        if elapsed > PAGER_PREVIEW_BUDGET {
+            flog!(
+                reader,
+                format!("Pager preview took {elapsed:?}, no more previews until the pager closes")
+            );
+            self.pager_preview_over_budget = true;
+        }
+        self.pager.set_preview(outputs);
+        self.layout_and_repaint(L!("pager-preview"));
+    }
+}
+
 /// Expand an abbreviation replacer, which may mean running its function.
 /// Return the replacement, or none to skip it. This may run fish script!
 fn expand_replacer(
@@ -6120,6 +6435,81 @@ pub fn reader_expand_abbreviation_at_cursor(
     cursor_pos: usize,
     parser: &mut Parser,
 ) -> Option<abbrs::Replacement> {
+    let (range, position, cmd) = abbreviation_token_at_cursor(cmdline, cursor_pos)?;
+    let token_str = &cmdline[Range::<usize>::from(range)];
+    let pwd = parser.vars().get_pwd_slash();
+    let replacers = abbrs_match(token_str, position, cmd, &pwd);
+    for replacer in replacers {
+        if let Some(condition) = &replacer.condition {
+            if abbrs::abbrs_condition_cached(condition).is_none() {
+                run_abbr_condition(condition, parser);
+            }
+        }
+        if !abbrs::abbrs_condition_holds(replacer.condition.as_deref()) {
+            continue;
+        }
+        if let Some(replacement) = expand_replacer(range, token_str, &replacer, parser) {
+            return Some(replacement);
+        }
+    }
+    None
+}
+
+/// Run the abbreviation `condition`, whose result for this prompt is not known yet because it is
+/// still running in the background, and record its result for this prompt.
+fn run_abbr_condition(condition: &wstr, parser: &mut Parser) {
+    let generation = abbrs::abbrs_condition_generation();
+    let conditions = vec![condition.to_owned()];
+    let script = abbrs::abbrs_conditions_script(&conditions);
+    let _not_interactive = parser.push_scope(|s| {
+        s.is_interactive = false;
+        s.readonly_commandline = true;
+    });
+    let mut output = vec![];
+    if exec_subshell(
+        &script,
+        parser,
+        Some(&mut output),
+        /*apply_exit_status=*/ false,
+    )
+    .is_ok()
+    {
+        abbrs::abbrs_set_condition_results(generation, conditions, &output);
+    }
+}
+
+/// Return the text to preview the expansion of the abbreviation that ends at the cursor, if any.
+/// Only literal expansions are shown, since this must not run any fish script.
+fn abbreviation_preview(cmdline: &wstr, cursor_pos: usize, pwd: &wstr) -> Option<WString> {
+    let (range, position, cmd) = abbreviation_token_at_cursor(cmdline, cursor_pos)?;
+    if range.end() != cursor_pos {
+        return None;
+    }
+    let token_str = &cmdline[Range::<usize>::from(range)];
+    let replacer = abbrs_match(token_str, position, cmd, pwd)
+        .into_iter()
+        .find(|replacer| {
+            !replacer.is_function && abbrs::abbrs_condition_holds(replacer.condition.as_deref())
+        })?;
+    let expansion =
+        abbrs::Replacement::new(range, replacer.replacement, replacer.set_cursor_marker).text;
+    let mut preview = L!(" \u{2192} ").to_owned();
+    match expansion.find_char('\n') {
+        Some(newline) => {
+            preview.push_utfstr(&expansion[..newline]);
+            preview.push(ELLIPSIS_CHAR);
+        }
+        None => preview.push_utfstr(&expansion),
+    }
+    Some(preview)
+}
+
+/// Return the range of the token an abbreviation at the cursor would replace, the position of the
+/// token, and the command it is an argument to (or empty).
+fn abbreviation_token_at_cursor(
+    cmdline: &wstr,
+    cursor_pos: usize,
+) -> Option<(SourceRange, abbrs::Position, &wstr)> {
     // Find the token containing the cursor. Usually users edit from the end, so walk backwards.
     let tokens = extract_tokens(cmdline);
     let mut token: Option<_> = None;
@@ -6151,15 +6541,7 @@ pub fn reader_expand_abbreviation_at_cursor(
     } else {
         None
     };
-
-    let token_str = &cmdline[Range::<usize>::from(range)];
-    let replacers = abbrs_match(token_str, position, cmd.unwrap_or(L!("")));
-    for replacer in replacers {
-        if let Some(replacement) = expand_replacer(range, token_str, &replacer, parser) {
-            return Some(replacement);
-        }
-    }
-    None
+    Some((range, position, cmd.unwrap_or(L!(""))))
 }
 
 impl<'a> Reader<'a> {
@@ -6174,6 +6556,7 @@ fn expand_abbreviation_at_cursor(&mut self, cursor_backtrack: usize) -> bool {
             if let Some(replacement) =
                 reader_expand_abbreviation_at_cursor(el.text(), cursor_pos, self.parser)
             {
+                self.expanded_abbreviations.push(replacement.text.clone());
                 self.push_edit(elt, Edit::new(replacement.range.into(), replacement.text));
                 self.update_buff_pos(elt, replacement.cursor);
                 return true;
@@ -6426,7 +6809,7 @@ fn reader_shell_test(parser: &Parser, bstr: &wstr) -> Result<(), ParseIssue> {
     let res = detect_parse_errors(bstr, Some(&mut errors), /*accept_incomplete=*/ true);
 
     if res.is_err_and(|p| p.error) {
-        let mut error_desc = parser.get_backtrace(bstr, &errors);
+        let mut error_desc = parser.get_backtrace(bstr, &errors, isatty(STDERR_FILENO));
 
         // Ensure we end with a newline. Also add an initial newline, because it's likely the user
         // just hit enter and so there's junk on the current line.
@@ -7133,6 +7516,30 @@ fn handle_completions(&mut self, token_range: Range<usize>, mut comp: Vec<Comple
             }
         }
 
+        // The directory that appended completions are relative to, so the pager can link files.
+        let hyperlink_base = hyperlinks_enabled(self.vars()).then(|| {
+            let working_directory = self.vars().get_pwd_slash();
+            let mut prefix = if will_replace_token {
+                WString::new()
+            } else {
+                unescape_string(&tok, UnescapeStringStyle::Script(UnescapeFlags::INCOMPLETE))
+                    .unwrap_or_default()
+            };
+            if use_prefix {
+                prefix.push_utfstr(common_prefix);
+            }
+            expand_tilde(&mut prefix, self.vars());
+            let prefix = if prefix.is_empty() {
+                working_directory.clone()
+            } else {
+                path_apply_working_directory(&prefix, &working_directory)
+            };
+            HyperlinkBase {
+                prefix,
+                working_directory,
+            }
+        });
+
         // Print the completion list.
         let prefix = if will_replace_token && !use_prefix {
             Cow::Borrowed(L!(""))
@@ -7179,6 +7586,7 @@ fn handle_completions(&mut self, token_range: Range<usize>, mut comp: Vec<Comple
 
         // Update the pager data.
         self.pager.set_prefix(prefix, true);
+        self.pager.set_hyperlink_base(hyperlink_base);
         self.pager.set_completions(&comp, true);
         // Modify the command line to reflect the new pager.
         self.pager_selection_changed();
@@ -19,7 +19,7 @@
 use crate::terminal::SgrTerminalCommand::EnterDimMode;
 use crate::terminal::TerminalCommand::{
     self, ClearToEndOfLine, ClearToEndOfScreen, CursorDown, CursorLeft, CursorMove, CursorRight,
-    CursorUp, Osc133PromptEnd, Osc133PromptStart, ScrollContentUp,
+    CursorUp, Osc8Hyperlink, Osc133PromptEnd, Osc133PromptStart, ScrollContentUp,
 };
 use crate::terminal::{BufferedOutputter, CardinalDirection, Outputter};
 use crate::termsize::Termsize;
@@ -35,6 +35,7 @@
 use std::io::Write as _;
 use std::num::NonZeroU16;
 use std::ops::Range;
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::AtomicU32;
 use std::time::SystemTime;
@@ -54,6 +55,8 @@ pub struct HighlightedChar {
     character: char,
     // Logical offset within the command line.
     offset_in_cmdline: CharOffset,
+    // The URL of the hyperlink this character belongs to.
+    hyperlink: Option<Arc<WString>>,
 }
 
 /// A class representing a single line of a screen.
@@ -86,6 +89,7 @@ pub fn append(
             highlight,
             character: rendered_character(character),
             offset_in_cmdline,
+            hyperlink: None,
         });
     }
 
@@ -116,6 +120,18 @@ pub fn color_at(&self, idx: usize) -> HighlightSpec {
         self.text[idx].highlight
     }
 
+    /// Return the hyperlink at a char index.
+    pub fn hyperlink_at(&self, idx: usize) -> Option<&Arc<WString>> {
+        self.text[idx].hyperlink.as_ref()
+    }
+
+    /// Make the characters from `start` to the end of the line a hyperlink to `url`.
+    pub fn set_hyperlink(&mut self, start: usize, url: &Arc<WString>) {
+        for c in &mut self.text[start..] {
+            c.hyperlink = Some(Arc::clone(url));
+        }
+    }
+
     /// Return the logical offset corresponding to this cell
     pub fn offset_in_cmdline_at(&self, idx: usize) -> CharOffset {
         self.text[idx].offset_in_cmdline
@@ -506,16 +522,24 @@ fn saturating_sub(m: NonZeroU16, s: usize) -> NonZeroU16 {
             }
         };
 
+        // Reserve room for the preview of the selected completion, if any.
+        let preview_height = pager.preview_height(usize::from(pager_available_height.get()));
+
         // Re-render our completions page if necessary. Limit the term size of the pager to the true
-        // term size, minus the number of lines consumed by our string.
+        // term size, minus the number of lines consumed by our string and the preview.
         pager.set_term_size(&Termsize::new(
             curr_termsize.width_u16(),
-            pager_available_height,
+            saturating_sub(pager_available_height, preview_height),
         ));
 
         pager.update_rendering(page_rendering);
         // Append pager_data (none if empty).
         self.desired.append_lines(&page_rendering.screen_data);
+        // The preview goes below the completions.
+        if !page_rendering.screen_data.is_empty() {
+            self.desired
+                .append_lines(&pager.render_preview(screen_width, preview_height));
+        }
 
         self.scrolled = scrolled_cursor.scroll_amount != 0;
         self.desired.visible_prompt_lines =
@@ -1214,6 +1238,7 @@ fn s_line(zelf: &Screen, i: usize) -> &Line {
             }
 
             // Now actually output stuff.
+            let mut current_hyperlink = None;
             loop {
                 let done = j >= o_line(self, i).len();
                 // Clear the screen if we have not done so yet.
@@ -1240,6 +1265,11 @@ fn s_line(zelf: &Screen, i: usize) -> &Line {
 
                 self.handle_soft_wrap(current_width, i);
                 self.r#move(current_width, i);
+                let hyperlink = o_line(self, i).hyperlink_at(j).cloned();
+                if hyperlink != current_hyperlink {
+                    self.write_command(Osc8Hyperlink(hyperlink.as_deref().map(|url| &url[..])));
+                    current_hyperlink = hyperlink;
+                }
                 let color = o_line(self, i).color_at(j);
                 set_color(self, color);
                 let ch = o_line(self, i).char_at(j);
@@ -1249,6 +1279,10 @@ fn s_line(zelf: &Screen, i: usize) -> &Line {
                 j += 1;
             }
 
+            if current_hyperlink.is_some() {
+                self.write_command(Osc8Hyperlink(None));
+            }
+
             let mut clear_remainder = false;
             // Clear the remainder of the line if we need to clear and if we didn't write to the end of
             // the line. If we did write to the end of the line, the "sticky right edge" (as part of
@@ -1550,6 +1584,7 @@ pub fn escape_code_length(code: &wstr) -> Option<usize> {
 
     is_screen_name_escape_seq(code)
         .or_else(|| is_osc_escape_seq(code))
+        .or_else(|| is_string_escape_seq(code))
         .or_else(|| is_three_byte_escape_seq(code))
         .or_else(|| is_csi_style_escape_seq(code))
         .or_else(|| is_two_byte_escape_seq(code))
@@ -1640,6 +1675,17 @@ fn is_osc_escape_seq(code: &wstr) -> Option<usize> {
     None
 }
 
+/// Device Control String (DCS) and Application Program Command (APC) escape codes, which carry
+/// sixel and kitty graphics images: ESC followed by P or _, terminated by escape + backslash.
+/// Without a terminator, only the introducer is considered part of the sequence.
+fn is_string_escape_seq(code: &wstr) -> Option<usize> {
+    if !matches!(code.char_at(1), 'P' | '_') {
+        return None;
+    }
+    let pos = code[2..].find(L!("\x1B\\"))?;
+    Some(2 + pos + 2)
+}
+
 /// Generic VT100 three byte sequence: `CSI` followed by something in the range @ through _.
 fn is_three_byte_escape_seq(code: &wstr) -> Option<usize> {
     if code.char_at(1) == '[' && (code.char_at(2) >= '@' && code.char_at(2) <= '_') {
@@ -1785,7 +1831,10 @@ fn line_shared_prefix(a: &Line, b: &Line) -> usize {
         let bc = b.char_at(idx);
 
         // We're done if the text or colors are different.
-        if ac != bc || a.color_at(idx) != b.color_at(idx) {
+        if ac != bc
+            || a.color_at(idx) != b.color_at(idx)
+            || a.hyperlink_at(idx) != b.hyperlink_at(idx)
+        {
             if idx > 0 {
                 fn invisible(c: char) -> bool {
                     matches!(fish_wcwidth(c), None | Some(0))
@@ -2077,11 +2126,13 @@ mod tests {
     use crate::parse_util::compute_indents;
     use crate::prelude::*;
     use crate::screen::{
-        LayoutCache, PromptCacheEntry, PromptLayout, ScreenLayout, compute_layout,
+        CharOffset, LayoutCache, Line, PromptCacheEntry, PromptLayout, ScreenLayout,
+        compute_layout, line_shared_prefix,
     };
     use crate::tests::prelude::*;
     use fish_wcstringutil::join_strings;
     use fish_widestring::ELLIPSIS_CHAR;
+    use std::sync::Arc;
 
     #[test]
     #[serial]
@@ -2118,6 +2169,23 @@ fn test_escape_code_length() {
         );
         assert_eq!(lc.escape_code_length(L!("\x1B]blahblahblah\x1B\\")), 16);
         assert_eq!(lc.escape_code_length(L!("\x1B]blahblahblah\x07")), 15);
+
+        // Hyperlinks.
+        assert_eq!(
+            lc.escape_code_length(L!("\x1B]8;;file:///tmp\x1B\\tmp\x1B]8;;\x1B\\")),
+            18
+        );
+
+        // Inline images.
+        assert_eq!(
+            lc.escape_code_length(L!("\x1B_Ga=T,f=100;iVBORw0KGgo=\x1B\\NOT_PART_OF_SEQUENCE")),
+            27
+        );
+        assert_eq!(
+            lc.escape_code_length(L!("\x1BPq#0;2;0;0;0~~\x1B\\NOT_PART_OF_SEQUENCE")),
+            17
+        );
+        assert_eq!(lc.escape_code_length(L!("\x1B_unterminated")), 2);
     }
 
     #[test]
@@ -2531,4 +2599,23 @@ macro_rules! validate {
             )
         );
     }
+
+    #[test]
+    fn test_line_hyperlinks() {
+        let mut a = Line::new();
+        for c in "ls foo".chars() {
+            a.append(c, HighlightSpec::new(), CharOffset::None);
+        }
+        let mut b = a.clone();
+        assert_eq!(line_shared_prefix(&a, &b), 6);
+
+        // A hyperlink changes the line even if the text does not.
+        let url = Arc::new(L!("file:///foo").to_owned());
+        b.set_hyperlink(3, &url);
+        assert_eq!(b.hyperlink_at(2), None);
+        assert_eq!(b.hyperlink_at(5), Some(&url));
+        assert_eq!(line_shared_prefix(&a, &b), 3);
+        a.set_hyperlink(3, &url);
+        assert_eq!(line_shared_prefix(&a, &b), 6);
+    }
 }
@@ -1,16 +1,21 @@
-use crate::tty_handoff::XTVERSION;
+use crate::tty_handoff::{KITTY_GRAPHICS_QUERY_ID, XTVERSION};
 // Generic output functions.
-use crate::{flog, prelude::*, should_flog};
 use crate::{
+    env::Environment,
+    path::path_apply_working_directory,
     screen::{is_dumb, only_grayscale},
     text_face::{ResettableStyle, TextFace, TextStyling, UnderlineStyle},
     threads::MainThread,
+    wutil::normalize_path,
 };
+use crate::{flog, prelude::*, should_flog};
 use bitflags::bitflags;
 use fish_color::{Color, Color24};
 use fish_common::{EscapeStringStyle, escape_string, write_loop};
 use fish_feature_flags::FeatureFlag;
-use fish_widestring::{wcs2bytes, wcs2bytes_appending};
+use fish_wcstringutil::bool_from_string;
+use fish_widestring::{bytes2wcstring, osstr2wcstring, wcs2bytes, wcs2bytes_appending};
+use nix::unistd::gethostname;
 use std::sync::LazyLock;
 use std::{
     cell::{RefCell, RefMut},
@@ -87,12 +92,15 @@ pub(crate) enum TerminalCommand<'a> {
     QueryPrimaryDeviceAttribute,
     QueryXtversion,
     QueryXtgettcap(&'static str),
+    QueryKittyGraphics,
 
     DecsetAlternateScreenBuffer,
     DecrstAlternateScreenBuffer,
 
     // Keyboard protocols
     KittyKeyboardProgressiveEnhancementsEnable,
+    // Like the above, but also report repeat and release events.
+    KittyKeyboardProgressiveEnhancementsEnableWithEventTypes,
     KittyKeyboardProgressiveEnhancementsDisable,
     QueryKittyKeyboardProgressiveEnhancements,
 
@@ -104,7 +112,7 @@ pub(crate) enum TerminalCommand<'a> {
 
     // OSC sequences
     //
-    // Note that OSC 7 and OSC 52 are written from fish script, and OSC 8 is written in our
+    // Note that OSC 7 and OSC 52 are written from fish script, and OSC 8 is also written in our
     // man pages (via "man_show_urls").
     Osc0WindowTitle(&'a [WString]),
     Osc1TabTitle(&'a [WString]),
@@ -112,10 +120,13 @@ pub(crate) enum TerminalCommand<'a> {
     Osc133PromptEnd,
     Osc133CommandStart(&'a wstr),
     Osc133CommandFinished { exit_status: libc::c_int },
+    // Start a hyperlink to the given URL, or end the current one.
+    Osc8Hyperlink(Option<&'a wstr>),
 
     // Other terminal features
     QueryCursorPosition,
     QueryBackgroundColor,
+    QueryCellSize,
     ScrollContentUp { lines: usize },
 
     DecsetShowCursor,
@@ -152,6 +163,17 @@ fn query_xtgettcap(out: &mut Outputter, cap: &str) -> bool {
     true
 }
 
+fn query_kitty_graphics(out: &mut Outputter) -> bool {
+    // Ask the terminal to validate (but not display) a 1x1 RGB image.
+    // A supporting terminal replies with an APC response carrying the same ID.
+    write_to_output!(
+        out,
+        "\x1b_Gi={},s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\",
+        KITTY_GRAPHICS_QUERY_ID
+    );
+    true
+}
+
 struct DisplayAsHex<'a>(&'a str);
 
 impl<'a> std::fmt::Display for DisplayAsHex<'a> {
@@ -222,6 +244,49 @@ fn osc_133_command_finished(out: &mut Outputter, exit_status: libc::c_int) -> bo
     }
 }
 
+fn osc_8_hyperlink(out: &mut Outputter, url: Option<&wstr>) -> bool {
+    out.write_bytes(b"\x1b]8;;");
+    if let Some(url) = url {
+        out.write_bytes(&wcs2bytes(url));
+    }
+    out.write_bytes(b"\x1b\\");
+    true
+}
+
+/// Return whether paths should be written as hyperlinks, as requested by `$fish_hyperlinks`.
+pub fn hyperlinks_enabled(vars: &dyn Environment) -> bool {
+    !is_dumb()
+        && vars
+            .get_unless_empty(L!("fish_hyperlinks"))
+            .is_some_and(|v| bool_from_string(&v.as_string()))
+}
+
+/// Return the `file://` URL for a path, which is resolved against `working_directory`.
+pub fn file_url(path: &wstr, working_directory: &wstr) -> WString {
+    static HOSTNAME: LazyLock<WString> =
+        LazyLock::new(|| gethostname().map_or_else(|_| WString::new(), osstr2wcstring));
+    let path = normalize_path(
+        &path_apply_working_directory(path, working_directory),
+        false,
+    );
+    let mut url = WString::from_str("file://");
+    url.push_utfstr(&escape_string(&HOSTNAME, EscapeStringStyle::Url));
+    url.push_utfstr(&escape_string(&path, EscapeStringStyle::Url));
+    url
+}
+
+/// Wrap `text` in a hyperlink to the file at `path`, which is relative to `working_directory`.
+pub fn hyperlink_file(text: &wstr, path: &wstr, working_directory: &wstr) -> WString {
+    let mut out = Outputter::new_buffering();
+    out.write_command(TerminalCommand::Osc8Hyperlink(Some(&file_url(
+        path,
+        working_directory,
+    ))));
+    out.write_wstr(text);
+    out.write_command(TerminalCommand::Osc8Hyperlink(None));
+    bytes2wcstring(out.contents())
+}
+
 fn scroll_content_up(out: &mut Outputter, lines: usize) -> bool {
     write_to_output!(out, "\x1b[{}S", lines);
     true
@@ -302,9 +367,13 @@ fn write(out: &mut Outputter, sequence: &'static [u8]) -> bool {
             QueryPrimaryDeviceAttribute => write(self, b"\x1b[0c"),
             QueryXtversion => write(self, b"\x1b[>0q"),
             QueryXtgettcap(cap) => query_xtgettcap(self, cap),
+            QueryKittyGraphics => query_kitty_graphics(self),
             DecsetAlternateScreenBuffer => write(self, b"\x1b[?1049h"),
             DecrstAlternateScreenBuffer => write(self, b"\x1b[?1049l"),
             KittyKeyboardProgressiveEnhancementsEnable => write(self, b"\x1b[=5u"),
+            KittyKeyboardProgressiveEnhancementsEnableWithEventTypes => {
+                write(self, b"\x1b[=7u")
+            }
             KittyKeyboardProgressiveEnhancementsDisable => write(self, b"\x1b[=0u"),
             QueryKittyKeyboardProgressiveEnhancements => query_kitty_progressive_enhancements(self),
             ModifyOtherKeysEnable => write(self, b"\x1b[>4;1m"),
@@ -317,8 +386,10 @@ fn write(out: &mut Outputter, sequence: &'static [u8]) -> bool {
             Osc133PromptEnd => osc_133_prompt_end(self),
             Osc133CommandStart(command) => osc_133_command_start(self, command),
             Osc133CommandFinished { exit_status } => osc_133_command_finished(self, exit_status),
+            Osc8Hyperlink(url) => osc_8_hyperlink(self, url),
             QueryCursorPosition => write(self, b"\x1b[6n"),
             QueryBackgroundColor => write(self, b"\x1b]11;?\x1b\\"),
+            QueryCellSize => write(self, b"\x1b[16t"),
             ScrollContentUp { lines } => scroll_content_up(self, lines),
             DecsetShowCursor => write(self, b"\x1b[?25h"),
             DecsetFocusReporting => write(self, b"\x1b[?1004h"),
@@ -805,7 +876,9 @@ mod tests {
         Outputter,
         Paintable::{Background, Foreground, Underline},
         SgrTerminalCommand::ExitAttributeMode,
+        file_url, hyperlink_file,
     };
+    use crate::prelude::*;
 
     #[test]
     fn sgr_combining() {
@@ -949,4 +1022,20 @@ fn resettable_style_attribute() {
             )
         );
     }
+
+    #[test]
+    fn hyperlinks() {
+        let url = file_url(L!("../a b/./c"), L!("/tmp/dir/"));
+        assert!(url.starts_with("file://"));
+        assert!(url.ends_with("/tmp/a%20b/c"));
+        assert_eq!(url.chars().filter(|&c| c == '/').count(), 5);
+
+        let link = hyperlink_file(L!("c"), L!("/c"), L!("/"));
+        assert_eq!(
+            link,
+            L!("\x1b]8;;").to_owned()
+                + &file_url(L!("/c"), L!("/"))[..]
+                + L!("\x1b\\c\x1b]8;;\x1b\\")
+        );
+    }
 }
@@ -13,7 +13,9 @@
     self, ApplicationKeypadModeDisable, ApplicationKeypadModeEnable, DecrstBracketedPaste,
     DecrstColorThemeReporting, DecrstFocusReporting, DecsetBracketedPaste,
     DecsetColorThemeReporting, DecsetFocusReporting, KittyKeyboardProgressiveEnhancementsDisable,
-    KittyKeyboardProgressiveEnhancementsEnable, ModifyOtherKeysDisable, ModifyOtherKeysEnable,
+    KittyKeyboardProgressiveEnhancementsEnable,
+    KittyKeyboardProgressiveEnhancementsEnableWithEventTypes, ModifyOtherKeysDisable,
+    ModifyOtherKeysEnable,
 };
 use crate::threads::assert_is_main_thread;
 use crate::wutil::{perror_nix, wcstoi};
@@ -24,7 +26,7 @@
 use nix::unistd::getpgrp;
 use std::os::fd::BorrowedFd;
 use std::sync::{
-    OnceLock,
+    Mutex, OnceLock,
     atomic::{AtomicPtr, Ordering},
 };
 
@@ -36,6 +38,15 @@ pub fn maybe_set_kitty_keyboard_capability() {
     KITTY_KEYBOARD_SUPPORTED.get_or_init(|| true);
 }
 
+/// Whether to ask for key repeat and release events, which only fallback bindings receive.
+static REPORT_KEY_EVENT_TYPES: RelaxedAtomicBool = RelaxedAtomicBool::new(false);
+
+/// Set whether to ask for key repeat and release events. This takes effect the next time the
+/// TTY protocols are enabled.
+pub fn set_report_key_event_types(report: bool) {
+    REPORT_KEY_EVENT_TYPES.store(report);
+}
+
 pub(crate) static SCROLL_CONTENT_UP_SUPPORTED: OnceLock<bool> = OnceLock::new();
 pub(crate) const SCROLL_CONTENT_UP_TERMINFO_CODE: &str = "indn";
 
@@ -51,6 +62,47 @@ pub fn maybe_set_scroll_content_up_capability() {
     });
 }
 
+/// Whether the TTY can display images sent with the kitty graphics protocol.
+static KITTY_GRAPHICS_SUPPORTED: OnceLock<bool> = OnceLock::new();
+pub(crate) const KITTY_GRAPHICS_QUERY_ID: u32 = 31;
+
+pub fn maybe_set_kitty_graphics_capability() {
+    KITTY_GRAPHICS_SUPPORTED.get_or_init(|| {
+        flog!(reader, "Kitty graphics protocol is supported");
+        true
+    });
+}
+
+pub fn get_kitty_graphics_capability() -> Option<bool> {
+    KITTY_GRAPHICS_SUPPORTED.get().copied()
+}
+
+/// Whether the TTY can display sixel images, as advertised in its primary device attributes.
+static SIXEL_SUPPORTED: OnceLock<bool> = OnceLock::new();
+
+pub fn maybe_set_sixel_capability() {
+    SIXEL_SUPPORTED.get_or_init(|| {
+        flog!(reader, "Sixel graphics are supported");
+        true
+    });
+}
+
+pub fn get_sixel_capability() -> Option<bool> {
+    SIXEL_SUPPORTED.get().copied()
+}
+
+/// The width and height of a cell in pixels, as reported by the TTY.
+static CELL_SIZE: Mutex<Option<(usize, usize)>> = Mutex::new(None);
+
+pub fn set_cell_size(width: usize, height: usize) {
+    flog!(reader, "Cell size is", width, "by", height, "pixels");
+    *CELL_SIZE.lock().unwrap() = Some((width, height));
+}
+
+pub fn get_cell_size() -> Option<(usize, usize)> {
+    *CELL_SIZE.lock().unwrap()
+}
+
 pub static TERMINAL_OS_NAME: OnceLock<Option<WString>> = OnceLock::new();
 pub(crate) const XTGETTCAP_QUERY_OS_NAME: &str = "query-os-name";
 
@@ -107,6 +159,7 @@ enum ProtocolKind {
 // the full serialized command sequence as bytes.
 struct ProtocolBytes {
     kitty_keyboard: Box<[u8]>,
+    kitty_keyboard_with_event_types: Box<[u8]>,
     other: Box<[u8]>,
     wezterm_workaround: Box<[u8]>,
     none: Box<[u8]>,
@@ -131,6 +184,9 @@ fn get_commands(&self, enable: bool) -> &[u8] {
             &self.disablers
         };
         match protocol {
+            ProtocolKind::KittyKeyboard if REPORT_KEY_EVENT_TYPES.load() => {
+                &cmds.kitty_keyboard_with_event_types
+            }
             ProtocolKind::KittyKeyboard => &cmds.kitty_keyboard,
             ProtocolKind::Other => &cmds.other,
             ProtocolKind::WorkAroundWezTerm => &cmds.wezterm_workaround,
@@ -197,6 +253,9 @@ fn get_protocols(self) -> TtyProtocolsSet {
             kitty_keyboard: serialize_commands(
                 on_chain().chain([KittyKeyboardProgressiveEnhancementsEnable]),
             ),
+            kitty_keyboard_with_event_types: serialize_commands(
+                on_chain().chain([KittyKeyboardProgressiveEnhancementsEnableWithEventTypes]),
+            ),
             other: serialize_commands(on_chain().chain([
                 ModifyOtherKeysEnable,       // XTerm's modifyOtherKeys
                 ApplicationKeypadModeEnable, // set application keypad mode, so the keypad keys send unique codes
@@ -208,6 +267,9 @@ fn get_protocols(self) -> TtyProtocolsSet {
             kitty_keyboard: serialize_commands(
                 off_chain().chain([KittyKeyboardProgressiveEnhancementsDisable]),
             ),
+            kitty_keyboard_with_event_types: serialize_commands(
+                off_chain().chain([KittyKeyboardProgressiveEnhancementsDisable]),
+            ),
             other: serialize_commands(
                 off_chain().chain([ModifyOtherKeysDisable, ApplicationKeypadModeDisable]),
             ),
@@ -238,6 +300,8 @@ pub fn initialize_tty_protocols(vars: &dyn Environment) {
     // Default missing query responses.
     KITTY_KEYBOARD_SUPPORTED.get_or_init(|| false);
     SCROLL_CONTENT_UP_SUPPORTED.get_or_init(|| false);
+    KITTY_GRAPHICS_SUPPORTED.get_or_init(|| false);
+    SIXEL_SUPPORTED.get_or_init(|| false);
     TERMINAL_OS_NAME.get_or_init(|| None);
     let xtversion = XTVERSION.get_or_init(WString::new);
 
@@ -82,8 +82,7 @@ fn has_prefix_match(comps: &CompletionReceiver, first: usize) -> bool {
 /// Matches the string against the wildcard, and if the wildcard is a possible completion of the
 /// string, the remainder of the string is inserted into the out vector.
 ///
-/// We ignore ANY_STRING_RECURSIVE here. The consequence is that you cannot tab complete **
-/// wildcards. This is historic behavior.
+/// The string is a single path component, so ANY_STRING_RECURSIVE is treated like ANY_STRING.
 /// is_first_call is default false.
 fn wildcard_complete_internal(
     s: &wstr,
@@ -199,7 +198,7 @@ fn wildcard_complete_internal(
             }
             wildcard_complete_internal(s.slice_from(1), wc.slice_from(1), params, flags, out, false)
         }
-        ANY_STRING => {
+        ANY_STRING | ANY_STRING_RECURSIVE => {
             // Hackish. If this is the last character of the wildcard, then just complete with
             // the empty string. This fixes cases like "f*<tab>" -> "f*o".
             if wc.len() == 1 {
@@ -247,8 +246,6 @@ fn wildcard_complete_internal(
                 false => WildcardResult::NoMatch,
             }
         }
-        // We don't even try with this one.
-        ANY_STRING_RECURSIVE => WildcardResult::NoMatch,
         _ => unreachable!(),
     }
 }
@@ -324,6 +321,13 @@ fn wildcard_test_flags_then_complete(
     if expand_flags.contains(ExpandFlags::NO_SPACE_FOR_UNCLOSED_BRACE) {
         flags |= CompleteFlags::NO_SPACE;
     }
+    // Executables may have been found via $PATH instead of the working directory.
+    let path_flag = if executables_only {
+        CompleteFlags::empty()
+    } else {
+        CompleteFlags::FILE_PATH
+    };
+    flags |= path_flag;
     // Fast path: If we need directories, and we already know it is one,
     // and we don't need to do anything else, just return it.
     // This is a common case for cd completions, and removes the `stat` entirely in case the system
@@ -336,7 +340,7 @@ fn wildcard_test_flags_then_complete(
             Some(&|_| L!("").to_owned()),
             Some(out),
             expand_flags,
-            CompleteFlags::NO_SPACE,
+            CompleteFlags::NO_SPACE | path_flag,
         ) == WildcardResult::Match;
     }
     // Check if it will match before stat().
@@ -422,7 +426,7 @@ fn wildcard_test_flags_then_complete(
             desc_func,
             Some(out),
             expand_flags,
-            CompleteFlags::NO_SPACE,
+            CompleteFlags::NO_SPACE | path_flag,
         ) == WildcardResult::Match;
     }
 
@@ -436,6 +440,7 @@ mod expander {
         path::append_path_component,
         wutil::{DevInode, dir_iter::DirIter, normalize_path},
     };
+    use fish_widestring::{bytes2wcstring, wcs2osstring};
 
     use super::*;
 
@@ -446,6 +451,113 @@ mod expander {
     pub(super) struct ParentInfo {
         // Whether some parent expansion is fuzzy, and therefore completions always prepend their prefix.
         has_fuzzy_ancestor: bool,
+        // Whether we are completing a recursive wildcard. The matches may be spread over many
+        // directories, so completions replace the token with the path they were found at.
+        complete_full_paths: bool,
+    }
+
+    impl ParentInfo {
+        pub(super) fn new(complete_full_paths: bool) -> Self {
+            Self {
+                complete_full_paths,
+                ..Default::default()
+            }
+        }
+    }
+
+    /// A pattern read from an ignore file like .gitignore, see `$fish_complete_ignore_files`.
+    struct IgnoreRule {
+        /// The directory containing the ignore file. Like base_dir, this is empty or ends in a slash.
+        base_dir: WString,
+        /// The slash-separated components of the pattern, with wildcards unescaped.
+        components: Vec<WString>,
+        /// Whether the pattern is relative to base_dir, instead of matching names at any depth.
+        anchored: bool,
+        /// Whether the pattern only matches directories.
+        dir_only: bool,
+        /// Whether the pattern re-includes what earlier patterns excluded.
+        negated: bool,
+    }
+
+    impl IgnoreRule {
+        /// Parse a line of an ignore file. This supports the common subset of the gitignore syntax:
+        /// comments, negation with `!`, trailing slashes for directories, leading or inner slashes
+        /// to anchor the pattern, and the wildcards `*`, `?` and `**`.
+        fn parse(base_dir: &wstr, line: &wstr) -> Option<Self> {
+            let mut line = line.trim_matches(' ');
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let negated = line.starts_with('!');
+            if negated {
+                line = &line[1..];
+            }
+            let dir_only = line.ends_with('/');
+            if dir_only {
+                line = &line[..line.len() - 1];
+            }
+            let anchored = line.contains('/');
+            let line = line.trim_matches('/');
+            if line.is_empty() {
+                return None;
+            }
+
+            let components = line
+                .split('/')
+                .filter(|c| !c.is_empty())
+                .map(|component| {
+                    if component.as_char_slice() == ['*', '*'] {
+                        return WString::from_chars([ANY_STRING_RECURSIVE]);
+                    }
+                    let mut result = WString::new();
+                    let mut chars = component.chars();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '*' => result.push(ANY_STRING),
+                            '?' => result.push(ANY_CHAR),
+                            '\\' => result.extend(chars.next()),
+                            c => result.push(c),
+                        }
+                    }
+                    result
+                })
+                .collect();
+            Some(Self {
+                base_dir: base_dir.to_owned(),
+                components,
+                anchored,
+                dir_only,
+                negated,
+            })
+        }
+
+        /// Whether this rule matches the entry with the given name in base_dir, which is at or
+        /// beneath our own directory.
+        fn matches(&self, base_dir: &wstr, name: &wstr, is_dir: impl FnOnce() -> bool) -> bool {
+            let matched = if self.anchored {
+                let mut path: Vec<&wstr> = base_dir[self.base_dir.len()..]
+                    .split('/')
+                    .filter(|c| !c.is_empty())
+                    .collect();
+                path.push(name);
+                Self::match_components(&self.components, &path)
+            } else {
+                wildcard_match(name, &self.components[0], false)
+            };
+            matched && (!self.dir_only || is_dir())
+        }
+
+        fn match_components(pattern: &[WString], path: &[&wstr]) -> bool {
+            let Some((first, rest)) = pattern.split_first() else {
+                return path.is_empty();
+            };
+            if first.as_char_slice() == [ANY_STRING_RECURSIVE] {
+                return (0..=path.len()).any(|i| Self::match_components(rest, &path[i..]));
+            }
+            path.split_first().is_some_and(|(component, path)| {
+                wildcard_match(*component, first, false) && Self::match_components(rest, path)
+            })
+        }
     }
 
     // A stateful object for expanding wildcards.
@@ -468,6 +580,10 @@ pub(super) struct WildCardExpander<'e> {
         did_overflow: bool,
         /// Whether we have successfully added any completions.
         did_add: bool,
+        /// The names of ignore files to read in each directory when completing recursive wildcards.
+        ignore_files: &'e [WString],
+        /// The rules from the ignore files in the directories we are currently beneath.
+        ignore_rules: Vec<IgnoreRule>,
     }
 
     impl<'e> WildCardExpander<'e> {
@@ -476,6 +592,7 @@ pub fn new(
             flags: ExpandFlags,
             cancel_checker: &'e mut dyn FnMut() -> bool,
             resolved_completions: &'e mut CompletionReceiver,
+            ignore_files: &'e [WString],
         ) -> Self {
             Self {
                 cancel_checker,
@@ -490,7 +607,27 @@ pub fn new(
                 did_add: false,
                 did_interrupt: false,
                 did_overflow: false,
+                ignore_files,
+                ignore_rules: vec![],
+            }
+        }
+
+        /// Expand the wildcard in the given directory, see [`Self::expand_in_directory`]. When
+        /// completing recursive wildcards, this also applies the ignore files found here to
+        /// everything beneath.
+        pub fn expand(
+            &mut self,
+            base_dir: &wstr,
+            wc: &wstr,
+            effective_prefix: &wstr,
+            info: ParentInfo,
+        ) {
+            let rules_before = self.ignore_rules.len();
+            if info.complete_full_paths {
+                self.load_ignore_rules(base_dir);
             }
+            self.expand_in_directory(base_dir, wc, effective_prefix, info);
+            self.ignore_rules.truncate(rules_before);
         }
 
         /// The real implementation of wildcard expansion is in this function. Other functions are just
@@ -506,7 +643,7 @@ pub fn new(
         ///    This is usually the same thing as the original wildcard, but for fuzzy matching, we
         ///    expand intermediate segments. effective_prefix is always either empty, or ends with a slash
         /// info: the parent info for expansion of this path, such as whether we have a fuzzy ancestor.
-        pub fn expand(
+        fn expand_in_directory(
             &mut self,
             base_dir: &wstr,
             wc: &wstr,
@@ -688,7 +825,10 @@ fn expand_trailing_slash(&mut self, base_dir: &wstr, prefix: &wstr, info: Parent
                 if need_dir && !known_dir {
                     continue;
                 }
-                if !entry.name.is_empty() && !entry.name.starts_with('.') {
+                if !entry.name.is_empty()
+                    && !entry.name.starts_with('.')
+                    && !self.is_ignored(base_dir, entry)
+                {
                     self.try_add_completion_result(
                         &(base_dir.to_owned() + entry.name.as_utfstr()),
                         &entry.name,
@@ -724,10 +864,18 @@ fn expand_intermediate_segment(
                     // Doesn't match the wildcard for this segment, skip it.
                     continue;
                 }
-                if !entry.is_dir() {
+                if !entry.is_dir() || self.is_ignored(base_dir, entry) {
                     continue;
                 }
 
+                // The prefix for our children. When completing full paths, the wildcard is replaced
+                // by the directory we found.
+                let prefix: WString = if info.complete_full_paths {
+                    prefix.to_owned() + entry.name.as_utfstr() + L!("/")
+                } else {
+                    prefix.to_owned() + wc_segment + L!("/")
+                };
+
                 // Fast path: If this entry can't be a link (we know via d_type),
                 // we don't need to protect against symlink loops.
                 // This is *not* deduplication, we just don't want a loop.
@@ -736,8 +884,6 @@ fn expand_intermediate_segment(
                 // because we're a bit inconsistent on when we will enter loops.
                 if is_final && entry.is_possible_link() == Some(false) {
                     let full_path: WString = base_dir.to_owned() + entry.name.as_utfstr() + L!("/");
-                    let prefix: WString = prefix.to_owned() + wc_segment + L!("/");
-
                     self.expand(&full_path, wc_remainder, &prefix, info);
                     continue;
                 }
@@ -752,8 +898,6 @@ fn expand_intermediate_segment(
                 }
 
                 let full_path: WString = base_dir.to_owned() + entry.name.as_utfstr() + L!("/");
-                let prefix: WString = prefix.to_owned() + wc_segment + L!("/");
-
                 self.expand(&full_path, wc_remainder, &prefix, info);
 
                 // Now remove the visited file. This is for #2414: only directories "beneath" us should be
@@ -851,6 +995,9 @@ fn expand_last_segment(
                 }
 
                 if self.flags.contains(ExpandFlags::FOR_COMPLETIONS) {
+                    if self.is_ignored(base_dir, entry) {
+                        continue;
+                    }
                     self.try_add_completion_result(
                         &(base_dir.to_owned() + entry.name.as_utfstr()),
                         &entry.name,
@@ -957,6 +1104,10 @@ fn try_add_completion_result(
         ) {
             // This function is only for the completions case.
             assert!(self.flags.contains(ExpandFlags::FOR_COMPLETIONS));
+            // The walk for recursive wildcards may reach a path more than once.
+            if info.complete_full_paths && self.completion_set.contains(filepath) {
+                return;
+            }
             let mut abs_path = self.working_directory.to_owned();
             append_path_component(&mut abs_path, filepath);
 
@@ -980,9 +1131,18 @@ fn try_add_completion_result(
                 // Note that prepend_token_prefix is a no-op unless COMPLETE_REPLACES_TOKEN is set
                 let after = self.resolved_completions.len();
                 for c in self.resolved_completions[before..after].iter_mut() {
-                    if info.has_fuzzy_ancestor && !c.replaces_token() {
+                    if (info.has_fuzzy_ancestor || info.complete_full_paths) && !c.replaces_token()
+                    {
                         c.flags |= CompleteFlags::REPLACES_TOKEN;
-                        c.prepend_token_prefix(wildcard);
+                        if info.complete_full_paths && wildcard_has_internal(wildcard) {
+                            // The completion only extends the wildcard, use the name it matched.
+                            c.completion = filename.to_owned();
+                            if entry.is_dir() {
+                                c.completion.push('/');
+                            }
+                        } else {
+                            c.prepend_token_prefix(wildcard);
+                        }
                     }
                     c.prepend_token_prefix(prefix);
                 }
@@ -1003,8 +1163,58 @@ fn try_add_completion_result(
                     }
                 }
 
+                if info.complete_full_paths {
+                    self.completion_set.insert(filepath.to_owned());
+                }
                 self.did_add = true;
             }
+            // The walk for recursive wildcards may find far more than we can take, so stop at the
+            // limit like expansion does.
+            if info.complete_full_paths && self.resolved_completions.is_full() {
+                self.did_overflow = true;
+            }
+        }
+
+        /// Read the ignore files in the given directory, applying their rules beneath it.
+        fn load_ignore_rules(&mut self, base_dir: &wstr) {
+            // The #7222 hack expands the same directory again.
+            if self
+                .ignore_rules
+                .iter()
+                .any(|rule| rule.base_dir == base_dir)
+            {
+                return;
+            }
+            for name in self.ignore_files {
+                let mut path = self.working_directory.to_owned();
+                append_path_component(&mut path, base_dir);
+                append_path_component(&mut path, name);
+                let Ok(contents) = std::fs::read(wcs2osstring(&path)) else {
+                    continue;
+                };
+                let contents = bytes2wcstring(&contents);
+                self.ignore_rules.extend(
+                    contents
+                        .split('\n')
+                        .filter_map(|line| IgnoreRule::parse(base_dir, line)),
+                );
+            }
+        }
+
+        /// Whether the entry in base_dir is excluded by our ignore rules.
+        /// As in gitignore, the last matching rule wins.
+        fn is_ignored(&self, base_dir: &wstr, entry: &DirEntry) -> bool {
+            let mut ignored = false;
+            for rule in &self.ignore_rules {
+                // A rule that agrees with the current verdict can't change it.
+                if rule.negated != ignored {
+                    continue;
+                }
+                if rule.matches(base_dir, &entry.name, || entry.is_dir()) {
+                    ignored = !rule.negated;
+                }
+            }
+            ignored
         }
 
         // Helper to resolve using our prefix.
@@ -1043,6 +1253,8 @@ fn open_dir(&self, base_dir: &wstr, dotdot: bool) -> std::io::Result<DirIter> {
 /// executables_only
 /// \param cancel_checker A function to call to check for cancellation
 /// \param output The completion receiver to receive expanded wildcards
+/// \param ignore_files Names of gitignore-style files whose patterns exclude paths when completing
+/// recursive wildcards
 ///
 pub fn wildcard_expand_string<'closure>(
     wc: &wstr,
@@ -1050,6 +1262,7 @@ pub fn wildcard_expand_string<'closure>(
     flags: ExpandFlags,
     mut cancel_checker: impl FnMut() -> bool + 'closure,
     output: &mut CompletionReceiver,
+    ignore_files: &[WString],
 ) -> WildcardResult {
     use expander::{ParentInfo, WildCardExpander};
     // Fuzzy matching only if we're doing completions.
@@ -1072,11 +1285,16 @@ pub fn wildcard_expand_string<'closure>(
         return WildcardResult::NoMatch;
     }
 
-    // We do not support tab-completing recursive (**) wildcards. This is historic behavior.
-    // Do not descend any directories if there is a ** wildcard.
-    if flags.contains(ExpandFlags::FOR_COMPLETIONS) && wc.contains(ANY_STRING_RECURSIVE) {
-        return WildcardResult::NoMatch;
-    }
+    // Tab-completing recursive (**) wildcards walks the tree, and the matches replace the token
+    // with their full path.
+    let info = ParentInfo::new(
+        flags.contains(ExpandFlags::FOR_COMPLETIONS) && wc.contains(ANY_STRING_RECURSIVE),
+    );
+    let ignore_files = if info.complete_full_paths {
+        ignore_files
+    } else {
+        &[]
+    };
 
     // Compute the prefix and base dir. The prefix is what we prepend for filesystem operations
     // (i.e. the working directory), the base_dir is the part of the wildcard consumed thus far,
@@ -1092,8 +1310,9 @@ pub fn wildcard_expand_string<'closure>(
         (working_directory, L!(""), wc)
     };
 
-    let mut expander = WildCardExpander::new(prefix, flags, &mut cancel_checker, output);
-    expander.expand(base_dir, effective_wc, base_dir, ParentInfo::default());
+    let mut expander =
+        WildCardExpander::new(prefix, flags, &mut cancel_checker, output, ignore_files);
+    expander.expand(base_dir, effective_wc, base_dir, info);
     expander.status_code()
 }
 
@@ -235,6 +235,24 @@ abbr | grep __abbr_coexist
 abbr -e --command foo __abbr_coexist
 abbr -e --command bar __abbr_coexist_2
 
+# Conditions and directories round-trip through --show
+abbr --add __abbr_cond --condition 'test -d .git' --directory '/src/*' --directory /tmp 'git status'
+abbr | grep __abbr_cond
+# CHECK: abbr -a --condition 'test -d .git' --directory '/src/*' --directory /tmp -- __abbr_cond 'git status'
+abbr --add __abbr_dir --directory '~/src' 'make'
+abbr | grep __abbr_dir | string replace -- $HOME HOME
+# CHECK: abbr -a --directory HOME/src -- __abbr_dir make
+abbr --erase __abbr_cond __abbr_dir
+
+abbr --show --condition true
+# CHECKERR: abbr: --condition option requires --add
+
+abbr --show --directory /tmp
+# CHECKERR: abbr: --directory option requires --add
+
+abbr --add foo --condition true --condition false bar
+# CHECKERR: abbr: Cannot specify multiple conditions
+
 abbr --add foo --rename foo fuu --show --list --erase foo --query foo
 # CHECKERR: abbr: Cannot combine options add, rename, show, list, erase, query
 
@@ -842,5 +842,118 @@ begin
     set -l
 end
 
+# Check descriptions, --print-completions and --print-help
+begin
+    set -l specs 'h/help"Show help"' 'n/name=+"Name to greet"' l/loud 'q/quiet"Be quiet"' count=?
+    argparse --print-completions -n greet -x loud,q $specs --
+    # CHECK: complete -c greet -s h -l help -d 'Show help'
+    # CHECK: complete -c greet -s n -l name -r -d 'Name to greet'
+    # CHECK: complete -c greet -s l -l loud -n 'not __fish_seen_argument -s q -l quiet'
+    # CHECK: complete -c greet -s q -l quiet -n 'not __fish_seen_argument -s l -l loud' -d 'Be quiet'
+    # CHECK: complete -c greet -l count
+
+    argparse --print-help -n greet $specs --
+    # CHECK: Usage: greet [OPTIONS] [ARG ...]
+    # CHECK:
+    # CHECK: Options:
+    # CHECK:   -h, --help           Show help
+    # CHECK:   -n, --name=VALUE     Name to greet
+    # CHECK:   -l, --loud
+    # CHECK:   -q, --quiet          Be quiet
+    # CHECK:       --count[=VALUE]
+
+    argparse --print-help --max-args=0 -n greet --
+    # CHECK: Usage: greet [OPTIONS]
+
+    argparse --print-help --print-completions h --
+    # CHECKERR: argparse: --print-completions --print-help: options cannot be used together
+    # CHECKERR: {{.*}}checks/argparse.fish (line {{\d+}}):
+    # CHECKERR: argparse --print-help --print-completions h --
+    # CHECKERR: ^
+    # CHECKERR: (Type 'help argparse' for related documentation)
+
+    argparse 'h/help"Show help' --
+    # CHECKERR: argparse: Invalid option spec 'h/help"Show help' at char '"'
+    # CHECKERR: {{.*}}checks/argparse.fish (line {{\d+}}):
+    # CHECKERR: argparse 'h/help"Show help' --
+    # CHECKERR: ^
+    # CHECKERR: (Type 'help argparse' for related documentation)
+
+    # The description does not change how the options are parsed.
+    argparse 'h/help"Show help"' 'n/name="Name"' -- --help -n fish
+    echo $_flag_help $_flag_name
+    # CHECK: --help fish
+end
+
+# Check complete --from-argparse
+function argparse_greet
+    argparse 'h/help"Show help"' v/verbose $dynamic_specs 'q/quiet"Be quiet"' -- $argv
+    or return
+end
+complete --from-argparse argparse_greet
+complete -C'argparse_greet --'
+# CHECK: --help{{\t}}Show help
+# CHECK: --quiet{{\t}}Be quiet
+# CHECK: --verbose
+
+# Check subcommands
+begin
+    set -l specs v/verbose 'add:f/force"Overwrite files"' add:n/name= rm:r/recursive
+    argparse --subcommand 'add"Add a file"' --subcommand rm $specs -- -v add --force -n foo bar
+    echo $_subcommand $_flag_verbose $_flag_force $_flag_name
+    # CHECK: add -v --force foo
+    echo $argv
+    # CHECK: bar
+    echo $argv_opts
+    # CHECK: -v --force -n foo
+end
+
+begin
+    argparse --subcommand add --subcommand rm v/verbose add:f/force --
+    set -q _subcommand
+    or echo no subcommand
+    # CHECK: no subcommand
+
+    argparse --subcommand add --subcommand rm v/verbose add:f/force -- frob
+    # CHECKERR: argparse frob: invalid subcommand
+
+    argparse --subcommand add --subcommand rm v/verbose add:f/force -- -f add
+    # CHECKERR: argparse: -f: unknown option
+
+    argparse --subcommand add --subcommand rm v/verbose add:f/force -- add -v
+    # CHECKERR: argparse add: -v: unknown option
+
+    argparse --subcommand 'add"Add a file' --
+    # CHECKERR: argparse: Invalid subcommand spec 'add"Add a file'
+    # CHECKERR: {{.*}}checks/argparse.fish (line {{\d+}}):
+    # CHECKERR: argparse --subcommand 'add"Add a file' --
+    # CHECKERR: ^
+    # CHECKERR: (Type 'help argparse' for related documentation)
+
+    set -l specs h/help 'add:f/force"Overwrite files"' rm:r/recursive
+    argparse --print-completions -n tool --subcommand 'add"Add a file"' --subcommand rm $specs --
+    # CHECK: complete -c tool -n __fish_use_subcommand -s h -l help
+    # CHECK: complete -c tool -f -n __fish_use_subcommand -a add -d 'Add a file'
+    # CHECK: complete -c tool -f -n __fish_use_subcommand -a rm
+    # CHECK: complete -c tool -n '__fish_seen_subcommand_from add' -s f -l force -d 'Overwrite files'
+    # CHECK: complete -c tool -n '__fish_seen_subcommand_from rm' -s r -l recursive
+
+    argparse --print-help -n tool --subcommand 'add"Add a file"' --subcommand rm $specs --
+    # CHECK: Usage: tool [OPTIONS] SUBCOMMAND [ARG ...]
+    # CHECK:
+    # CHECK: Options:
+    # CHECK:   -h, --help
+    # CHECK:
+    # CHECK: Subcommands:
+    # CHECK:   add  Add a file
+    # CHECK:   rm
+    # CHECK:
+    # CHECK: Options for add:
+    # CHECK:   -f, --force  Overwrite files
+    # CHECK:
+    # CHECK: Options for rm:
+    # CHECK:   -r, --recursive
+end
+
 # Check that the argparse's are properly wrapped in begin blocks
 set -l
@@ -202,6 +202,28 @@ bind ctrl-q
 bind --user --preset ctrl-q 'echo preset'
 # CHECKERR: bind: --preset --user: options cannot be used together
 
+# Fallback bindings
+bind --fallback
+echo $status
+# CHECK: 1
+bind --fallback 'echo unbound'
+bind -M insert --fallback my_handler self-insert
+bind --fallback
+# CHECK: bind --fallback 'echo unbound'
+# CHECK: bind -M insert --fallback my_handler self-insert
+bind --fallback -M insert
+# CHECK: bind -M insert --fallback my_handler self-insert
+bind --fallback 'echo replaced'
+bind --fallback -M default
+# CHECK: bind --fallback 'echo replaced'
+bind --erase --fallback
+bind --erase --fallback -M insert
+bind --fallback
+echo $status
+# CHECK: 1
+bind --user --preset --fallback 'echo preset'
+# CHECKERR: bind: --preset --user: options cannot be used together
+
 fish_default_key_bindings
 
 exit 0
@@ -761,3 +761,38 @@ if string match -rq -- '^[a-z]+$' $USER
     string match -rq -- "$USER\t.*" (complete -C "echo ~$first_letter_wrong_case")
     or echo "`complete -C'echo ~$first_letter_wrong_case'` did not yield $USER"
 end
+
+# Argument completions can be cached.
+function cached-completions
+end
+set -g cached_completion_runs 0
+function __cached_completion_args
+    set -g cached_completion_runs (math $cached_completion_runs + 1)
+    echo run$cached_completion_runs
+end
+complete -c cached-completions -xa "(__cached_completion_args)" --cache-ttl 60
+complete -c cached-completions
+# CHECK: complete --exclusive cached-completions -a '(__cached_completion_args)'
+# CHECK: complete cached-completions --cache-ttl 60
+complete -C"cached-completions "
+# CHECK: run1
+complete -C"cached-completions "
+# CHECK: run1
+# The cache is keyed on the command line before the token.
+complete -C"cached-completions run1 "
+# CHECK: run2
+complete -C"cached-completions r"
+# CHECK: run1
+# Changing directory discards the cache.
+cd .
+complete -C"cached-completions "
+# CHECK: run3
+complete -c cached-completions --cache-ttl 0
+complete -C"cached-completions "
+# CHECK: run4
+complete -C"cached-completions "
+# CHECK: run5
+complete -c cached-completions --cache-ttl soon
+# CHECKERR: complete: soon: invalid integer
+complete -c cached-completions -e --cache-ttl 5
+# CHECKERR: complete: invalid option combination, '--erase' and '--cache-ttl'
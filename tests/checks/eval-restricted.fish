@@ -0,0 +1,186 @@
+# RUN: fish=%fish %fish %s
+
+set -l tmp (mktemp -d)
+cd $tmp
+
+# Builtins and functions are fine.
+eval --restricted 'echo hello | string upper; set -g restricted_global 1'
+echo $restricted_global
+# CHECK: HELLO
+# CHECK: 1
+
+eval --restricted 'builtin cd /'
+echo $status $PWD
+# CHECKERR: {{.*}} (line {{\d+}}): 'cd' is not allowed in restricted mode
+# CHECKERR: builtin cd /
+# CHECKERR:         ^^
+# CHECK: 120 {{.*}}
+
+eval --restricted 'exec true'
+echo $status
+# CHECKERR: {{.*}} (line {{\d+}}): 'exec' is not allowed in restricted mode
+# CHECKERR: exec true
+# CHECKERR:      ^~~^
+# CHECK: 120
+
+eval --restricted 'echo hi >file'
+echo $status
+# CHECKERR: {{.*}} (line {{\d+}}): Redirecting to 'file' is not allowed in restricted mode
+# CHECKERR: echo hi >file
+# CHECKERR:         ^~~~^
+# CHECK: 120
+test -e file
+or echo no file
+# CHECK: no file
+
+# Reading files and discarding output is fine.
+echo content >file
+eval --restricted 'read -l line <file; echo $line >/dev/null 2>&1; echo $line'
+# CHECK: content
+
+eval --restricted 'touch other'
+echo $status
+# CHECKERR: {{.*}} (line {{\d+}}): 'touch' is not allowed in restricted mode
+# CHECKERR: touch other
+# CHECKERR: ^~~~^
+# CHECK: 120
+
+eval --restricted --allow-command touch -- 'touch other'
+echo $status
+# CHECK: 0
+test -e other
+and echo other exists
+# CHECK: other exists
+
+# A command allowed by name may only be found via $PATH.
+eval --restricted --allow-command touch -- (command -s touch) third
+# CHECKERR: {{.*}} (line {{\d+}}): '{{.*}}/touch' is not allowed in restricted mode
+# CHECKERR: {{.*}}/touch third
+# CHECKERR: ^{{.*}}
+
+eval --restricted 'set -U restricted_uvar 1'
+echo $status
+# CHECKERR: set: Tried to change the universal variable 'restricted_uvar' in restricted mode
+# CHECK: 120
+set -q restricted_uvar
+or echo no uvar
+# CHECK: no uvar
+
+# Search paths can't be changed, so allowed names keep running the same commands.
+eval --restricted --allow-command touch -- 'set -gx PATH $PWD $PATH'
+echo $status
+# CHECKERR: set: Tried to change the search path 'PATH' in restricted mode
+# CHECK: 120
+eval --restricted 'set -l fish_function_path $PWD'
+# CHECKERR: set: Tried to change the search path 'fish_function_path' in restricted mode
+contains -- $PWD $PATH
+or echo PATH unchanged
+# CHECK: PATH unchanged
+
+# Nor as a loop variable, or through a function's arguments.
+eval --restricted 'for PATH in $PWD; touch sixth; end'
+echo $status
+# CHECKERR: {{.*}} (line {{\d+}}): for: Tried to change the search path 'PATH' in restricted mode
+# CHECKERR: for PATH in $PWD; touch sixth; end
+# CHECKERR:     ^~~^
+# CHECK: 120
+function path_arg -a PATH
+    echo $PATH
+end
+eval --restricted 'path_arg $PWD'
+echo $status
+# CHECKERR: {{.*}} (line {{\d+}}): path_arg: Tried to change the search path 'PATH' in restricted mode
+# CHECKERR: path_arg $PWD
+# CHECKERR: ^~~~~~~^
+# CHECK: 120
+eval --restricted 'function restricted_path_arg -a PATH; end'
+echo $status
+# CHECKERR: function: Tried to change the search path 'PATH' in restricted mode
+# CHECK: 120
+eval --restricted 'function restricted_inherit -V fish_function_path; end'
+# CHECKERR: function: Tried to change the search path 'fish_function_path' in restricted mode
+
+# Exported variables change what the allowed commands do.
+eval --restricted 'set -gx restricted_export 1'
+echo $status
+# CHECKERR: set: Tried to change the exported variable 'restricted_export' in restricted mode
+# CHECK: 120
+set -gx existing_export 1
+eval --restricted 'set -g existing_export 2; set -e existing_export'
+# CHECKERR: set: Tried to change the exported variable 'existing_export' in restricted mode
+# CHECKERR: set: Tried to change the exported variable 'existing_export' in restricted mode
+echo $existing_export
+# CHECK: 1
+
+# Code that runs later on its own can't be added.
+eval --restricted 'bind ctrl-g "touch bound"'
+echo $status
+# CHECKERR: bind: changing bindings is not allowed in restricted mode
+# CHECK: 120
+eval --restricted 'abbr -a gg "touch abbreviated"'
+# CHECKERR: abbr: changing abbreviations is not allowed in restricted mode
+eval --restricted 'complete -c restricted_cmd -a "(touch completed)"'
+# CHECKERR: complete: changing completions is not allowed in restricted mode
+eval --restricted 'function restricted_handler --on-event restricted_event; touch handled; end'
+# CHECKERR: function: event handlers are not allowed in restricted mode
+eval --restricted 'bind >/dev/null; complete -C "restricted_cmd " >/dev/null'
+and echo listing works
+# CHECK: listing works
+
+# Nor can files be written by builtins.
+eval --restricted 'fish_indent --write file'
+echo $status
+# CHECKERR: fish_indent: writing files is not allowed in restricted mode
+# CHECK: 120
+eval --restricted 'history clear'
+# CHECKERR: history: changing the history is not allowed in restricted mode
+
+# Restrictions nest, and inner ones can't allow more.
+eval --restricted --allow-command touch -- 'eval --restricted --allow-command touch --allow-command mkdir -- mkdir dir'
+# CHECKERR: {{.*}} (line {{\d+}}): 'mkdir' is not allowed in restricted mode
+# CHECKERR: mkdir dir
+# CHECKERR: ^~~~^
+
+# Functions defined by restricted code stay restricted.
+eval --restricted 'function restricted_fn; touch $argv; end'
+restricted_fn third
+echo $status
+# CHECKERR: {{.*}} (line {{\d+}}): 'touch' is not allowed in restricted mode
+# CHECKERR: touch $argv
+# CHECKERR: ^~~~^
+# CHECKERR: in function 'restricted_fn' with arguments 'third'
+# CHECKERR: called on line {{\d+}} of file {{.*}}/eval-restricted.fish
+# CHECK: 120
+
+eval --allow-command touch 'touch fourth'
+# CHECKERR: eval: --allow-command requires --restricted
+# CHECKERR: {{.*}}/eval-restricted.fish (line {{\d+}}):
+# CHECKERR: eval --allow-command touch 'touch fourth'
+# CHECKERR: ^{{.*}}
+# CHECKERR: (Type 'help eval' for related documentation)
+
+$fish --restricted -c 'command touch fifth'
+echo $status
+# CHECKERR: fish: 'touch' is not allowed in restricted mode
+# CHECKERR: command touch fifth
+# CHECKERR:         ^~~~^
+# CHECK: 120
+
+$fish --restricted --allow-command=touch -c 'touch fifth'
+and echo fifth exists
+# CHECK: fifth exists
+
+# Functions autoloaded by restricted code are not restricted afterwards.
+eval --restricted 'ls; cd /' 2>/dev/null
+mkdir sub
+cd sub
+echo $PWD
+# CHECK: {{.*}}/sub
+ls ..
+# CHECK: fifth
+# CHECK: file
+# CHECK: other
+# CHECK: sub
+
+cd /
+rm -r $tmp
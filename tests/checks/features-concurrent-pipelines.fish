@@ -0,0 +1,75 @@
+#RUN: %fish --features=concurrent-pipelines %s
+
+# Blocks in the middle of a pipeline run in a child fish, so variables do not leak out.
+set -g var before
+begin
+    set -g var inside
+    echo $var
+end | string upper
+#CHECK: INSIDE
+echo $var
+#CHECK: before
+
+# Functions see our variables and functions, and get their arguments.
+set -g greeting hello
+function greet
+    echo $greeting
+end
+function show_args
+    greet
+    printf '<%s>\n' $argv
+end
+show_args 'a b' c | string join ,
+#CHECK: hello,<a b>,<c>
+
+# Variables keep their scope, and locals still shadow globals.
+function scopes
+    set -l greeting hi
+    set -l only_local yes
+    begin
+        echo $greeting $only_local
+        set -ql only_local; and echo local
+        set -qg only_local; or echo not global
+    end | string join ' '
+end
+scopes
+#CHECK: hi yes local not global
+
+# Output is streamed, so an endless producer stops once its reader is done.
+begin
+    while true
+        echo y
+    end
+end | head -n 1 | string upper
+#CHECK: Y
+echo $pipestatus
+#CHECK: 141 0 0
+
+# A function writing into a pipe stops when the reader goes away.
+function yes_forever
+    while true
+        echo y
+    end
+end
+yes_forever | head -n 1
+echo $pipestatus
+#CHECK: y
+#CHECK: 141 0
+
+# Likewise for a block, even if the writes come from external commands.
+begin
+    while true
+        command echo y
+    end
+end | head -n 1
+echo $pipestatus
+#CHECK: y
+#CHECK: 141 0
+
+# The exit status is that of the child.
+function fail
+    return 3
+end
+fail | string length
+echo $pipestatus
+#CHECK: 3 1
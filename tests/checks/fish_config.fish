@@ -202,6 +202,7 @@ echo >$__fish_config_dir/themes/custom-from-userconf.theme \
     # CHECK: $fish_color_normal: set in universal scope, unexported, with 2 elements
     # CHECK: $fish_color_normal[1]: |yellow|
     # CHECK: $fish_color_normal[2]: |--theme=custom-from-userconf|
+    # CHECK: $fish_color_normal: last changed {{.*}}
 
     fish_config theme choose default --color-theme=unknown
     print-sample-colors
@@ -215,11 +216,13 @@ echo >$__fish_config_dir/themes/custom-from-userconf.theme \
     # CHECK: $fish_color_normal: set in universal scope, unexported, with 2 elements
     # CHECK: $fish_color_normal[1]: |yellow|
     # CHECK: $fish_color_normal[2]: |--theme=custom-from-userconf|
+    # CHECK: $fish_color_normal: last changed {{.*}}
 
     echo yes | fish_config theme save default
     set -S fish_color_normal
     # CHECK: $fish_color_normal: set in universal scope, unexported, with 1 elements
     # CHECK: $fish_color_normal[1]: |--reset|
+    # CHECK: $fish_color_normal: last changed {{.*}}
 }
 
 {
@@ -0,0 +1,36 @@
+#RUN: %fish %s
+
+set -l tmp (mktemp -d)
+
+fish_inline_image
+# CHECKERR: fish_inline_image: expected 1 arguments; got 0
+echo $status
+# CHECK: 2
+
+fish_inline_image $tmp/missing.png
+# CHECKERR: fish_inline_image: {{.*}}/missing.png: cannot read image: No such file or directory
+echo $status
+# CHECK: 1
+
+printf 'GIF89a' >$tmp/logo.gif
+fish_inline_image $tmp/logo.gif
+# CHECKERR: fish_inline_image: {{.*}}/logo.gif: unsupported image format, expected PNG or sixel
+
+printf '\x89PNG\r\n\x1a\n' >$tmp/logo.png
+fish_inline_image --width=none $tmp/logo.png
+# CHECKERR: fish_inline_image: none: invalid integer
+# CHECKERR: {{.*}}/fish_inline_image.fish (line {{\d+}}):
+# CHECKERR: fish_inline_image --width=none $tmp/logo.png
+# CHECKERR: ^{{.*}}
+# CHECKERR: (Type 'help fish_inline_image' for related documentation)
+
+# Without a terminal that reports support for images, nothing is drawn.
+set -l out (fish_inline_image --width=3 $tmp/logo.png)
+echo $status (count $out)
+# CHECK: 1 0
+
+fish_inline_image --query
+echo $status
+# CHECK: 1
+
+rm -r $tmp
@@ -0,0 +1,158 @@
+#RUN: %fish %s
+
+set -l tmp (mktemp -d)
+
+assert 1 -eq 1
+echo $status
+# CHECK: 0
+
+assert 1 -eq 2
+# CHECKERR: assert: assertion failed: 1 -eq 2
+# CHECKERR: {{.*}}/fish_test.fish (line {{\d+}}):
+# CHECKERR: assert 1 -eq 2
+# CHECKERR: ^{{.*}}
+echo $status
+# CHECK: 1
+
+assert --message "not the same" -- foo = 'bar baz'
+# CHECKERR: assert: not the same: foo = 'bar baz'
+# CHECKERR: {{.*}}/fish_test.fish (line {{\d+}}):
+# CHECKERR: assert --message "not the same" -- foo = 'bar baz'
+# CHECKERR: ^{{.*}}
+
+echo 'function test_pass
+    assert 1 -eq 1
+end
+
+function test_fail_status
+    echo some output
+    return 3
+end
+
+function test_fail_assert
+    assert -m "wrong answer" 1 -eq 2
+    set -U __fish_test_uvar changed
+    set -g __fish_test_global changed
+    true
+end' >$tmp/tests.fish
+
+# Only keep the interesting diagnostics, not the stack traces.
+fish_test $tmp/tests.fish | string match -rv '^# (?!exit status|failed assertions|some output|assert:)'
+echo $pipestatus[1]
+# CHECK: TAP version 13
+# CHECK: 1..3
+# CHECK: ok 1 - test_pass
+# CHECK: not ok 2 - test_fail_status
+# CHECK: # exit status: 3
+# CHECK: # some output
+# CHECK: not ok 3 - test_fail_assert
+# CHECK: # failed assertions: 1
+# CHECK: # assert: wrong answer: 1 -eq 2
+# CHECK: 1
+
+# Tests have no lasting effects.
+set -q __fish_test_uvar __fish_test_global
+or echo no variables
+# CHECK: no variables
+functions -q test_pass
+or echo no functions
+# CHECK: no functions
+
+# Changes to the function path inside a test are used for autoloading, and undone afterwards.
+mkdir $tmp/functions
+echo 'function __fish_test_autoloaded
+    echo autoloaded
+end' >$tmp/functions/__fish_test_autoloaded.fish
+echo "function test_function_path
+    set -g fish_function_path $tmp/functions \$fish_function_path
+    assert (__fish_test_autoloaded) = autoloaded
+end" >$tmp/path.fish
+fish_test $tmp/path.fish
+echo $status
+# CHECK: TAP version 13
+# CHECK: 1..1
+# CHECK: ok 1 - test_function_path
+# CHECK: 0
+contains -- $tmp/functions $fish_function_path
+or echo function path restored
+# CHECK: function path restored
+
+# The working directory and functions defined inside a test are restored afterwards.
+set -l oldpwd $PWD
+echo "function test_cd
+    cd $tmp
+    function __fish_test_defined
+    end
+end
+
+function test_after_cd
+    assert \$PWD = $oldpwd
+    assert (command pwd -P) = (builtin realpath $oldpwd)
+    not functions -q __fish_test_defined
+end" >$tmp/cd.fish
+fish_test $tmp/cd.fish
+echo $status
+# CHECK: TAP version 13
+# CHECK: 1..2
+# CHECK: ok 1 - test_cd
+# CHECK: ok 2 - test_after_cd
+# CHECK: 0
+test $PWD = $oldpwd
+and test (command pwd -P) = (builtin realpath $oldpwd)
+and echo cwd restored
+# CHECK: cwd restored
+functions -q __fish_test_defined
+or echo no defined function
+# CHECK: no defined function
+
+echo 'function test_pass
+    echo "<ok>"
+end' >$tmp/pass.fish
+fish_test --format=junit $tmp/pass.fish
+echo $status
+# CHECK: <?xml version="1.0" encoding="UTF-8"?>
+# CHECK: <testsuites tests="1" failures="0">
+# CHECK:   <testsuite name="{{.*}}/pass.fish" tests="1" failures="0">
+# CHECK:     <testcase name="test_pass" classname="{{.*}}/pass.fish" time="{{[\d.]+}}">
+# CHECK:       <system-out>&lt;ok&gt;
+# CHECK: </system-out>
+# CHECK:     </testcase>
+# CHECK:   </testsuite>
+# CHECK: </testsuites>
+# CHECK: 0
+
+fish_test $tmp/missing.fish
+echo $status
+# CHECKERR: fish_test: {{.*}}/missing.fish: cannot read file: No such file or directory
+# CHECK: TAP version 13
+# CHECK: 1..0
+# CHECK: 1
+
+# Without files, the defined test functions run in order of their names.
+function test_b
+    exit 4
+    echo unreachable
+end
+function test_a
+    # History is private.
+    assert -z "$fish_history"
+end
+fish_test
+echo $status
+# CHECK: TAP version 13
+# CHECK: 1..2
+# CHECK: ok 1 - test_a
+# CHECK: not ok 2 - test_b
+# CHECK: # exit status: 4
+# CHECK: 1
+echo still running
+# CHECK: still running
+
+fish_test --format=xml
+# CHECKERR: fish_test: xml: invalid report format
+# CHECKERR: {{.*}}/fish_test.fish (line {{\d+}}):
+# CHECKERR: fish_test --format=xml
+# CHECKERR: ^{{.*}}
+# CHECKERR: (Type 'help fish_test' for related documentation)
+
+rm -r $tmp
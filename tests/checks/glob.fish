@@ -89,6 +89,29 @@ string join \n **/bar | sort
 # CHECK: bar
 # CHECK: foo/bar
 
+# Recursive wildcards can be tab-completed, to the paths they match.
+mkdir -p build/out
+touch build/out/bar
+complete -C'ls **/ba' | sort
+# CHECK: bar
+# CHECK: build/out/bar
+# CHECK: foo/bar
+
+# Ignore files are only consulted when asked to.
+printf '%s\n' '# comment' build/ /bar >.gitignore
+complete -C'ls **/ba' | sort
+# CHECK: bar
+# CHECK: build/out/bar
+# CHECK: foo/bar
+set -g fish_complete_ignore_files .gitignore
+complete -C'ls **/ba' | sort
+# CHECK: foo/bar
+echo '!/bar' >>.gitignore
+complete -C'ls **/ba' | sort
+# CHECK: bar
+# CHECK: foo/bar
+set -e fish_complete_ignore_files
+
 # Clean up.
 cd $oldpwd
 rm -Rf $tmpdir
@@ -701,3 +701,65 @@ function __fish_print_help
 end
 PATH=hello fish_indent --help
 # CHECK: Help using PATH[1]=hello
+
+echo 'cat file.txt | grep pattern | sort | uniq -c' | $fish_indent --max-width=30
+# CHECK: {{^}}cat file.txt \
+# CHECK: {{^}}    | grep pattern \
+# CHECK: {{^}}    | sort \
+# CHECK: {{^}}    | uniq -c
+
+echo 'test -f config.fish && source config.fish || echo missing' | $fish_indent --max-width=30
+# CHECK: {{^}}test -f config.fish \
+# CHECK: {{^}}    && source config.fish \
+# CHECK: {{^}}    || echo missing
+
+echo 'test -d build; and make -C build; or echo no build dir' | $fish_indent --max-width=30
+# CHECK: {{^}}test -d build
+# CHECK: {{^}}and make -C build
+# CHECK: {{^}}or echo no build dir
+
+echo 'echo one two three four five six seven eight nine ten' | $fish_indent --max-width=20
+# CHECK: {{^}}echo one two three \
+# CHECK: {{^}}    four five six \
+# CHECK: {{^}}    seven eight nine \
+# CHECK: {{^}}    ten
+
+# Wrapping is idempotent.
+echo 'echo one two three four five six seven eight nine ten | cat' | $fish_indent --max-width=20 | $fish_indent --max-width=20 --check
+echo $status
+# CHECK: 0
+
+# Lines without a place to break are left alone.
+echo 'echo a-very-long-argument' | $fish_indent --max-width=10
+# CHECK: {{^}}echo a-very-long-argument
+
+$fish_indent --max-width=0 </dev/null
+# CHECKERR: fish_indent: 0: invalid integer
+
+printf '%s\n' 'echo  a' 'if true' 'echo   b' 'echo   c' 'end' 'echo  d' | $fish_indent --lines=3:3
+# CHECK: {{^}}echo  a
+# CHECK: {{^}}if true
+# CHECK: {{^}}    echo b
+# CHECK: {{^}}echo   c
+# CHECK: {{^}}end
+# CHECK: {{^}}echo  d
+
+# Selecting only part of a block without statements of its own formats the whole block.
+printf '%s\n' 'echo  a' 'if true' 'echo   b' 'echo   c' 'end' 'echo  d' | $fish_indent --lines=2:2
+# CHECK: {{^}}echo  a
+# CHECK: {{^}}if true
+# CHECK: {{^}}    echo b
+# CHECK: {{^}}    echo c
+# CHECK: {{^}}end
+# CHECK: {{^}}echo  d
+
+$fish_indent --lines=3 </dev/null
+# CHECKERR: fish_indent: 3: invalid line range
+
+echo 'echo hi' | $fish_indent --dump-ast=json | string match -r '^\{"kind":"job_list","start":0,"end":8'
+# CHECK: {"kind":"job_list","start":0,"end":8
+echo 'echo hi' | $fish_indent --dump-ast=json | string match -r '\{"kind":"argument"[^}]*\}'
+# CHECK: {"kind":"argument","start":5,"end":7,"children":[]}
+
+$fish_indent --dump-ast=xml </dev/null
+# CHECKERR: fish_indent: xml: invalid AST format
@@ -139,3 +139,26 @@ disown 252
 
 jobs %abc
 # CHECKERR: jobs: '%abc' is not a valid job ID
+
+# Functions and blocks can be backgrounded. They run in a child fish.
+function bg_greet
+    sleep 0.2
+    echo hello from $argv
+end
+bg_greet background &
+jobs --command
+#CHECK: Command
+#CHECK: bg_greet
+test $last_pid != $fish_pid || echo function has same pid as fish
+wait bg_greet
+#CHECK: hello from background
+
+set -g bg_var unchanged
+begin
+    set -g bg_var changed
+    echo block done
+end &
+wait
+#CHECK: block done
+echo $bg_var
+#CHECK: unchanged
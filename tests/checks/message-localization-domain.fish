@@ -0,0 +1,58 @@
+# RUN: %fish %s
+#REQUIRES: %fish -c 'status buildinfo | grep localize-messages'
+
+set --erase LC_ALL LC_MESSAGES
+set -lx LANG en_US.UTF-8
+
+set -l dir (mktemp -d)
+mkdir -p $dir/first/myplugin $dir/second/myplugin
+printf '%s\n' 'greeting = Hallo Welt' 'farewell = Tschüss' >$dir/first/myplugin/de.ftl
+printf '%s\n' 'greeting = Servus' 'only-here = Nur hier' >$dir/second/myplugin/de.ftl
+printf '%s\n' 'greeting = Olá mundo' >$dir/second/myplugin/pt_BR.ftl
+
+set -g fish_translation_path $dir/first $dir/second
+
+set -l LANGUAGE de_DE
+echo (_ --domain=myplugin greeting)
+# CHECK: Hallo Welt
+
+# Only the first catalog for a language is used.
+echo (_ -d myplugin only-here)
+# CHECK: only-here
+
+# Unknown messages and domains are echoed back.
+echo (_ --domain=myplugin untranslated)
+# CHECK: untranslated
+echo (_ --domain=nosuchdomain greeting)
+# CHECK: greeting
+
+# `pt` falls back to `pt_BR`.
+set -l LANGUAGE pt de
+echo (_ --domain=myplugin greeting)
+# CHECK: Olá mundo
+echo (_ --domain=myplugin farewell)
+# CHECK: Tschüss
+
+status language set de
+echo (_ --domain=myplugin greeting)
+# CHECK: Hallo Welt
+status language unset
+
+# Changing the path reloads the catalogs.
+set -g fish_translation_path $dir/second
+set -l LANGUAGE de
+echo (_ --domain=myplugin greeting)
+# CHECK: Servus
+
+set -l LANGUAGE
+echo (_ --domain=myplugin greeting)
+# CHECK: greeting
+
+_ --domain
+# CHECKERR: _: --domain: option requires an argument
+# CHECKERR: {{.*}}checks/message-localization-domain.fish (line 51):
+# CHECKERR: _ --domain
+# CHECKERR: ^
+# CHECKERR: (Type 'help _' for related documentation)
+
+rm -r $dir
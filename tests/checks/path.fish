@@ -487,3 +487,12 @@ path invalid-subcmd
 # CHECKERR: path invalid-subcmd
 # CHECKERR: ^
 # CHECKERR: (Type 'help path' for related documentation)
+
+# Hyperlinks are only written to a terminal.
+begin
+    set -l fish_hyperlinks 1
+    path resolve / | string escape
+    # CHECK: /
+    realpath / | string escape
+    # CHECK: /
+end
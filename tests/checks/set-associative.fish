@@ -0,0 +1,149 @@
+# RUN: fish=%fish %fish %s
+# Associative variables
+
+set -A color apple red banana yellow
+echo $color[banana]
+# CHECK: yellow
+echo $color
+# CHECK: red yellow
+count $color
+# CHECK: 2
+
+# Keys can come from other variables, and may contain spaces.
+set -l fruit apple
+echo $color[$fruit]
+# CHECK: red
+set color["green apple"] green
+echo "$color[green apple]"
+# CHECK: green
+
+# Each value of a variable in a key is looked up separately, so it can't be used in quotes.
+set -l fruits apple banana
+echo $color[$fruits]
+# CHECK: red yellow
+echo "$color[$fruits]"
+# CHECKERR: {{.*}}set-associative.fish (line {{\d+}}): Expected a single key for an associative variable, but $fruits has 2 values
+# CHECKERR: echo "$color[$fruits]"
+# CHECKERR: {{ *}}^
+
+# Keys may contain brackets.
+set color[a[1]] bracketed
+echo $color[a[1]]
+# CHECK: bracketed
+set -e color[a[1]]
+
+# Missing keys expand to nothing.
+echo x$color[cherry]x
+# CHECK:
+echo "x$color[cherry]x"
+# CHECK: xx
+
+set --keys color
+# CHECK: apple
+# CHECK: banana
+# CHECK: green apple
+set -l list a b c
+set --keys list
+# CHECK: 1
+# CHECK: 2
+# CHECK: 3
+set --keys nonexistent
+echo $status
+# CHECK: 1
+
+set --show color
+# CHECK: $color: set in global scope, unexported, associative with 3 keys
+# CHECK: $color[apple]: |red|
+# CHECK: $color[banana]: |yellow|
+# CHECK: $color[green\ apple]: |green|
+
+# Changing a key keeps its position.
+set color[apple] green
+echo $color
+# CHECK: green yellow green
+
+# Appending inserts or updates keys.
+set -A -a color banana brown cherry red
+set --keys color
+# CHECK: apple
+# CHECK: banana
+# CHECK: green apple
+# CHECK: cherry
+echo $color[banana]
+# CHECK: brown
+
+set -q color[cherry]
+echo $status
+# CHECK: 0
+set -q color[durian] color[apple] color[elderberry]
+echo $status
+# CHECK: 2
+
+set -e color[cherry]
+set -q color[cherry]
+echo $status
+# CHECK: 1
+set --keys color
+# CHECK: apple
+# CHECK: banana
+# CHECK: green apple
+
+# An empty map
+set -A empty
+set --show empty
+# CHECK: $empty: set in global scope, unexported, associative with 0 keys
+
+# Setting without -A makes it a list again.
+set color red
+set --show color
+# CHECK: $color: set in global scope, unexported, with 1 elements
+# CHECK: $color[1]: |red|
+
+# Errors
+set -A color apple
+# CHECKERR: set: expected key and value pairs
+# CHECKERR: {{.*}}set-associative.fish (line {{\d+}}):
+# CHECKERR: set -A color apple
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+set -Ax exported key value
+# CHECKERR: set: associative variables can not be exported
+# CHECKERR: {{.*}}set-associative.fish (line {{\d+}}):
+# CHECKERR: set -Ax exported key value
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+set -q exported
+or echo not set
+# CHECK: not set
+
+set -A ports http 80
+set ports[https] 443 8443
+# CHECKERR: set: expected one value for key 'https' but got 2
+
+set -A -a color key value
+# CHECKERR: set: color: not an associative variable
+# CHECKERR: {{.*}}set-associative.fish (line {{\d+}}):
+# CHECKERR: set -A -a color key value
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+set -A -e ports
+# CHECKERR: set: invalid option combination
+# CHECKERR: {{.*}}set-associative.fish (line {{\d+}}):
+# CHECKERR: set -A -e ports
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+# Maps are never exported to child processes.
+set -A ports http 80
+$fish -c 'set -q ports; or echo not inherited'
+# CHECK: not inherited
+
+# Universal associative variables survive in the variables file.
+set -UA umap one 1 "two words" 2
+$fish -c 'set --keys umap; echo $umap[two words]'
+# CHECK: one
+# CHECK: two words
+# CHECK: 2
+set -e -U umap
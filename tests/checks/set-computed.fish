@@ -0,0 +1,178 @@
+# RUN: fish=%fish %fish %s
+# Computed variables
+
+set -g calls 0
+set --computed answer 'set -g calls (math $calls + 1); echo 42'
+
+# Nothing runs until the variable is expanded.
+echo $calls
+# CHECK: 0
+echo $answer
+# CHECK: 42
+echo $answer $answer
+# CHECK: 42 42
+echo $calls
+# CHECK: 1
+
+set --show answer
+# CHECK: $answer: set in global scope, unexported, with 1 elements
+# CHECK: $answer: computed by 'set -g calls (math $calls + 1); echo 42'
+# CHECK: $answer[1]: |42|
+
+# Invalidating runs the code again on the next expansion.
+set --invalidate answer
+echo $status
+# CHECK: 0
+echo $calls
+# CHECK: 1
+echo "$answer"
+# CHECK: 42
+echo $calls
+# CHECK: 2
+
+set -l plain value
+set --invalidate plain nonexistent answer
+echo $status
+# CHECK: 2
+
+# The output is split into lines like a command substitution.
+set --computed lines 'printf "%s\n" a b c'
+count $lines
+# CHECK: 3
+
+# Values expire after the TTL.
+set -g stamp 0
+set --computed --ttl 200ms fresh 'set -g stamp (math $stamp + 1); echo $stamp'
+echo $fresh $fresh
+# CHECK: 1 1
+sleep 0.3
+echo $fresh
+# CHECK: 2
+
+set --computed --ttl 2m slow true
+set --show slow
+# CHECK: $slow: set in global scope, unexported, with 0 elements
+# CHECK: $slow: computed by true, cached for 2m
+
+# Variable handlers run when the computed value changes.
+set -g counter 0
+function on_counter --on-variable counter_value
+    echo counter_value changed to $counter_value
+end
+set --computed --no-event counter_value 'echo $counter'
+echo $counter_value
+# CHECK: counter_value changed to 0
+# CHECK: 0
+set --invalidate counter_value
+echo $counter_value
+# CHECK: 0
+set counter 1
+set --invalidate counter_value
+echo $counter_value
+# CHECK: counter_value changed to 1
+# CHECK: 1
+functions -e on_counter
+
+# A computed variable that refers to itself sees its previous value.
+set --computed self 'echo "x$self"'
+echo $self
+# CHECK: x
+set --invalidate self
+echo $self
+# CHECK: xx
+
+# Setting it normally makes it an ordinary variable.
+set answer 7
+set --invalidate answer
+echo $status
+# CHECK: 1
+echo $answer
+# CHECK: 7
+
+# Computed variables are local to functions like other variables.
+function f
+    set --computed -l inner 'echo inside'
+    echo $inner
+end
+f
+# CHECK: inside
+set -q inner
+or echo not set
+# CHECK: not set
+
+# Computed variables defined by restricted code stay restricted.
+set -l tmp (mktemp -d)
+cd $tmp
+$fish -c 'eval --restricted "set -g --computed sneaky \'touch sneaky; echo computed\'"; echo $sneaky' 2>/dev/null
+# CHECK: computed
+test -e sneaky
+or echo not touched
+# CHECK: not touched
+cd /
+rm -r $tmp
+
+# Indirect expansion computes the variable that is named.
+set --computed target 'echo via indirection'
+set -g pointer target
+echo $$pointer
+# CHECK: via indirection
+
+# `set --show` and `set --query` with indexes use the current value, `set --query` without
+# indexes does not run the code.
+set -g ran 0
+set --computed lines_to_query 'set -g ran 1; echo a; echo b'
+set -q lines_to_query
+and echo $ran
+# CHECK: 0
+set -q lines_to_query[2]
+and echo $ran
+# CHECK: 1
+set --computed shown 'echo shown value'
+set --show shown
+# CHECK: $shown: set in global scope, unexported, with 1 elements
+# CHECK: $shown: computed by 'echo shown value'
+# CHECK: $shown[1]: |shown value|
+
+# Other readers, like listing variables, see the last computed value.
+set --computed listed 'echo listed value'
+set | string match 'listed*'
+# CHECK: listed
+echo $listed >/dev/null
+set | string match 'listed*'
+# CHECK: listed 'listed value'
+
+# Errors
+set --computed -x exported true
+# CHECKERR: set: computed variables can not be exported
+# CHECKERR: {{.*}}set-computed.fish (line {{\d+}}):
+# CHECKERR: set --computed -x exported true
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+set --computed -U universal true
+# CHECKERR: set: computed variables can not be universal
+# CHECKERR: {{.*}}set-computed.fish (line {{\d+}}):
+# CHECKERR: set --computed -U universal true
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+set --computed toomany echo hi
+# CHECKERR: set: expected 2 arguments; got 3
+# CHECKERR: {{.*}}set-computed.fish (line {{\d+}}):
+# CHECKERR: set --computed toomany echo hi
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+set --ttl 1s nottl true
+# CHECKERR: set: invalid option combination
+# CHECKERR: {{.*}}set-computed.fish (line {{\d+}}):
+# CHECKERR: set --ttl 1s nottl true
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+set --computed --ttl soon later true
+# CHECKERR: set: soon: invalid duration
+# CHECKERR: {{.*}}set-computed.fish (line {{\d+}}):
+# CHECKERR: set --computed --ttl soon later true
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
@@ -550,6 +550,7 @@ set -U var1 hello
 set --show var1
 #CHECK: $var1: set in universal scope, unexported, with 1 elements
 #CHECK: $var1[1]: |hello|
+#CHECK: $var1: last changed {{\d{4}-\d\d-\d\d \d\d:\d\d:\d\d}}{{( on .*)?}}
 
 set -l var1
 set -g var1 goodbye "and don't come back"
@@ -560,6 +561,7 @@ set --show var1
 #CHECK: $var1[2]: |and don't come back|
 #CHECK: $var1: set in universal scope, unexported, with 1 elements
 #CHECK: $var1[1]: |hello|
+#CHECK: $var1: last changed {{\d{4}-\d\d-\d\d \d\d:\d\d:\d\d}}{{( on .*)?}}
 
 set -g var2
 set --show _unset_var var2
@@ -1051,6 +1053,7 @@ env XDG_CONFIG_HOME= HOME=$PWD/empty LC_ALL=en_US.UTF-8 $FISH -c 'set -Ux LC_ALL
 env XDG_CONFIG_HOME= HOME=$PWD/empty LC_ALL=en_US.UTF-8 $FISH -c 'set -S LC_ALL'
 # CHECK: $LC_ALL: set in universal scope, exported, with 1 elements
 # CHECK: $LC_ALL[1]: |en_US.UTF-8|
+# CHECK: $LC_ALL: last changed {{.*}}
 # CHECK: $LC_ALL: originally inherited as |en_US.UTF-8|
 
 # This used to crash
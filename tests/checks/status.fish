@@ -188,3 +188,26 @@ end
 
 status build-info other-arg
 # CHECKERR: status build-info: expected 0 arguments; got 1
+
+set -g fish_error_location_format '%f:%l:%c'
+printf "%s\n" (test-stack-trace-other | string replace \t '<TAB>')[1..4]
+# CHECK: in function 'test-stack-trace-main'
+# CHECK: <TAB>called at /{{.*}}/status.fish:{{\d+}}:5
+# CHECK: in function 'test-stack-trace-other'
+# CHECK: <TAB>called at /{{.*}}/status.fish:{{\d+}}:{{\d+}}
+
+path invalid-subcmd
+# CHECKERR: path invalid-subcmd: invalid subcommand
+# CHECKERR: /{{.*}}/status.fish:{{\d+}}:1:
+# CHECKERR: path invalid-subcmd
+# CHECKERR: ^
+# CHECKERR: (Type 'help path' for related documentation)
+
+set -g fish_error_location_format 'line %l in %f (%%c)'
+path invalid-subcmd
+# CHECKERR: path invalid-subcmd: invalid subcommand
+# CHECKERR: line {{\d+}} in /{{.*}}/status.fish (%c):
+# CHECKERR: path invalid-subcmd
+# CHECKERR: ^
+# CHECKERR: (Type 'help path' for related documentation)
+set -e fish_error_location_format
@@ -56,3 +56,20 @@ tmux-sleep
 # CHECK: prompt {{\d+}}>
 
 isolated-tmux capture-pane -p
+
+# Preview the expansion of the abbreviation at the cursor.
+isolated-tmux send-keys C-u 'set -g fish_abbr_preview 1' Enter C-l abbr-test
+tmux-sleep
+isolated-tmux capture-pane -p
+# CHECK: prompt {{\d+}}> abbr-test → abbr-test [expanded]
+
+# Hint at an abbreviation whose expansion was typed out.
+isolated-tmux send-keys C-u 'abbr -g ec "echo hello"' Enter \
+    'function hint --on-event fish_abbr_hint; echo "hint: $argv"; end' Enter C-l \
+    'echo hello' Enter
+tmux-sleep
+isolated-tmux capture-pane -p
+# CHECK: prompt {{\d+}}> echo hello
+# CHECK: hello
+# CHECK: hint: ec echo hello
+# CHECK: prompt {{\d+}}>
@@ -0,0 +1,40 @@
+#RUN: %fish %s
+#REQUIRES: command -v tmux
+
+isolated-tmux-start -C '
+    function record_key_event
+        set -ga key_events (commandline --key-event | string join /)
+    end
+    function show_key_events
+        __fish_echo string join " " -- $key_events
+    end
+    bind --fallback record_key_event self-insert
+    bind ctrl-g show_key_events
+    bind ctrl-x,ctrl-e "commandline -i multi"
+'
+
+# The fallback sees the key press, and inserts the key.
+isolated-tmux send-keys x
+tmux-sleep
+# A release only runs the commands that are not input functions, so nothing is inserted.
+# tmux does not report releases, so send the kitty keyboard protocol's sequence for one.
+isolated-tmux send-keys -H 1b 5b 31 32 30 3b 31 3a 33 75
+tmux-sleep
+isolated-tmux send-keys C-g
+tmux-sleep
+isolated-tmux capture-pane -p
+# CHECK: prompt 0> x
+# CHECK: x//press x//release
+# CHECK: prompt 0> x
+
+# A release in the middle of a key sequence does not break it up.
+isolated-tmux send-keys C-u C-l C-x
+isolated-tmux send-keys -H 1b 5b 31 32 30 3b 35 3a 33 75
+isolated-tmux send-keys C-e
+tmux-sleep
+isolated-tmux send-keys C-g
+tmux-sleep
+isolated-tmux capture-pane -p
+# CHECK: prompt 0> multi
+# CHECK: x//press x//release ctrl-x/ctrl/release
+# CHECK: prompt 0> multi
@@ -0,0 +1,31 @@
+#RUN: %fish %s
+#REQUIRES: command -v tmux
+
+isolated-tmux-start -C '
+    set -g fish_autosuggestion_enabled 0
+    function edit-it --description "Insert the word edited at the cursor position"
+        commandline -i edited
+    end
+    bind ctrl-x,ctrl-e edit-it
+    bind ctrl-x,ctrl-b "commandline -i bee" "commandline -f repaint"
+    set -g fish_sequence_key_hint_delay_ms 2000
+'
+
+# No hint before the delay has passed.
+isolated-tmux send-keys C-x
+tmux-sleep
+isolated-tmux capture-pane -p
+# CHECK: prompt 0>
+
+# After the delay, the hint lists the commands, and the description of a function bound on its own.
+sleep 2
+isolated-tmux capture-pane -p
+# CHECK: prompt 0>
+# CHECK: ctrl-x,ctrl-e{{ +}}(edit-it: Insert the word edited at the cursor position)
+# CHECK: ctrl-x,ctrl-b{{ +}}(commandline -i bee commandline -f repaint)
+
+# Finishing the sequence hides the hint and runs the binding.
+isolated-tmux send-keys C-e
+tmux-sleep
+isolated-tmux capture-pane -p
+# CHECK: prompt 0> edited
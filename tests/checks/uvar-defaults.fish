@@ -0,0 +1,45 @@
+#RUN: fish=%fish %fish %s
+#REQUIRES: %fish -c 'test -w $__fish_sysconf_dir; and not test -e $__fish_sysconf_dir/fish_variables'
+# Universal variables from the user's file on top of the system defaults file.
+
+set -l defaults $__fish_sysconf_dir/fish_variables
+echo '# VERSION: 3.0
+SETUVAR __fish_layered_shared:system
+SETUVAR __fish_layered_system:system' >$defaults
+
+# This fish started without defaults. They are read when it synchronizes, after an external
+# command.
+set -q __fish_layered_system
+or echo not yet
+# CHECK: not yet
+command true
+echo $__fish_layered_system
+# CHECK: system
+
+# The user's value takes precedence, and erasing it shows the default again.
+set -U __fish_layered_shared user
+echo $__fish_layered_shared $__fish_layered_system
+# CHECK: user system
+$fish -c 'echo $__fish_layered_shared $__fish_layered_system'
+# CHECK: user system
+set --show __fish_layered_shared | string match -e 'system default'
+# CHECK: $__fish_layered_shared: overrides the system default in {{.*}}/fish_variables
+set -e -U __fish_layered_shared
+echo $__fish_layered_shared
+# CHECK: system
+set --show __fish_layered_shared | string match -e 'system defaults'
+# CHECK: $__fish_layered_shared: from the system defaults in {{.*}}/fish_variables
+
+# Changes to the defaults file replace the old defaults.
+echo '# VERSION: 3.0
+SETUVAR __fish_layered_shared:changed' >$defaults
+command true
+echo $__fish_layered_shared
+# CHECK: changed
+set -q __fish_layered_system
+or echo gone
+# CHECK: gone
+set --names -U | string match '__fish_layered_*'
+# CHECK: __fish_layered_shared
+
+rm $defaults